@@ -16,9 +16,18 @@ enum DisplayMode {
     MODE_WEIGHT_USER2_FORECAST = 7
 };
 
+// EPBM v2 encoding byte values (v1 bitmaps are always raw)
+enum EpbmEncoding { EPBM_RAW = 0, EPBM_PACKED4 = 1, EPBM_RLE = 2 };
+
 // RTC memory to persist display mode across deep sleep
 RTC_DATA_ATTR DisplayMode current_mode = MODE_WEATHER;
 
+// Sleep recommended by the server with the last bitmap (X-Sleep-Seconds), 0 if none
+uint32_t server_sleep_sec = 0;
+
+// Time taken by the previous download, reported to the server on the next one
+RTC_DATA_ATTR uint32_t last_download_ms = 0;
+
 // Display object for reTerminal e1002 (7.3" color, GDEP073E01)
 GxEPD2_7C<GxEPD2_730c_GDEP073E01, GxEPD2_730c_GDEP073E01::HEIGHT> display(
     GxEPD2_730c_GDEP073E01(EPD_CS_PIN, EPD_DC_PIN, EPD_RES_PIN, EPD_BUSY_PIN));
@@ -31,6 +40,8 @@ void setup_wifi();
 bool sync_ntp_time();
 void setup_buttons();
 void check_wake_reason();
+const char* wake_reason_name();
+int get_battery_percentage(int* battery_mv);
 void download_and_render_image();
 uint32_t calculate_sleep_duration();
 void deep_sleep();
@@ -317,12 +328,32 @@ void check_wake_reason() {
 }
 
 /**
- * Parse EPBM bitmap header from 8-byte buffer
+ * Short name for the wake cause, sent to the server with each request
+ */
+const char* wake_reason_name() {
+    switch (esp_sleep_get_wakeup_cause()) {
+        case ESP_SLEEP_WAKEUP_EXT1:
+            return "button";
+        case ESP_SLEEP_WAKEUP_TIMER:
+            return "timer";
+        default:
+            return "reset";
+    }
+}
+
+/**
+ * Parse EPBM bitmap header from 8-byte buffer. The magic is "EPBM" for v1 and
+ * "EPB2" for v2, whose header continues with version and encoding bytes.
  * Returns true if valid, false otherwise
  */
-bool parse_bitmap_header(uint8_t* header, uint16_t* width, uint16_t* height) {
-    // Check magic number "EPBM"
-    if (memcmp(header, "EPBM", 4) != 0) {
+bool parse_bitmap_header(uint8_t* header, uint16_t* width, uint16_t* height,
+                         bool* is_v1) {
+    // Check magic number
+    if (memcmp(header, "EPBM", 4) == 0) {
+        *is_v1 = true;
+    } else if (memcmp(header, "EPB2", 4) == 0) {
+        *is_v1 = false;
+    } else {
         Serial.println("[Bitmap] Invalid magic number");
         return false;
     }
@@ -367,6 +398,20 @@ uint16_t map_epbm_color(uint8_t color) {
     }
 }
 
+/**
+ * Draw `count` pixels of one EPBM color in raster order, starting at
+ * *pixels_rendered. Stops at the end of the frame.
+ */
+void draw_pixel_run(int* pixels_rendered, int width, int total_pixels,
+                    uint8_t color_value, int count) {
+    uint16_t gxepd_color = map_epbm_color(color_value);
+    for (int i = 0; i < count && *pixels_rendered < total_pixels; i++) {
+        display.drawPixel(*pixels_rendered % width, *pixels_rendered / width,
+                          gxepd_color);
+        (*pixels_rendered)++;
+    }
+}
+
 /**
  * Display a white screen with red border to indicate error/stale data
  * Uses full screen refresh for reliability after deep sleep
@@ -393,10 +438,10 @@ void show_error_screen() {
 }
 
 /**
- * Get battery percentage from ESP32 ADC
+ * Get battery percentage from ESP32 ADC, and the battery voltage in mV
  * Returns 0-100, or -1 if unavailable
  */
-int get_battery_percentage() {
+int get_battery_percentage(int* battery_mv) {
     // Enable battery monitoring circuit
     pinMode(BATTERY_ENABLE_PIN, OUTPUT);
     digitalWrite(BATTERY_ENABLE_PIN, HIGH);
@@ -412,6 +457,7 @@ int get_battery_percentage() {
     // Voltage divider: Vbat -> R1(10k) -> ADC -> R2(10k) -> GND
     // ADC sees Vbat/2, so multiply by 2 to get actual battery voltage
     float voltage = (mv / 1000.0) * 2.0;
+    *battery_mv = mv * 2;
 
     // Convert voltage to percentage
     // LiPo battery: 4.2V = 100%, 3.0V = 0%
@@ -429,9 +475,33 @@ int get_battery_percentage() {
     return percentage;
 }
 
+/**
+ * Read exactly `count` bytes from the stream, giving up 5 seconds after
+ * `start_time`
+ */
+bool read_stream_bytes(WiFiClient* stream, uint8_t* buffer, int count,
+                       unsigned long start_time) {
+    int read = 0;
+    while (read < count) {
+        if (stream->available()) {
+            buffer[read++] = stream->read();
+        } else if (!stream->connected()) {
+            return false;
+        } else {
+            delay(1);
+        }
+        if (millis() - start_time > 5000) {
+            Serial.println("[Stream] Timeout reading header");
+            return false;
+        }
+    }
+    return true;
+}
+
 /**
  * Download and render raw bitmap image using streaming (no full buffer)
- * Format: EPBM header (8 bytes) + pixel data (1 byte per pixel)
+ * Format: EPBM header (8 bytes) + pixel data (1 byte per pixel), or the v2
+ * header (10 bytes) + packed 4-bit or run-length encoded pixel data
  * This streams the image directly to the display to avoid running out of memory
  */
 void download_and_render_image() {
@@ -459,18 +529,41 @@ void download_and_render_image() {
     }
 
     // Add battery percentage
-    int battery_pct = get_battery_percentage();
+    int battery_mv = 0;
+    int battery_pct = get_battery_percentage(&battery_mv);
     if (battery_pct >= 0) {
         url += (has_params ? "&" : "?");
         url += "battery_pct=" + String(battery_pct);
+        url += "&battery_mv=" + String(battery_mv);
+        has_params = true;
     }
 
+    // Health details for the server's telemetry log
+    url += (has_params ? "&" : "?");
+    url += "rssi=" + String(WiFi.RSSI());
+    url += "&wake_reason=" + String(wake_reason_name());
+    url += "&firmware=" FIRMWARE_VERSION;
+    if (last_download_ms > 0) {
+        url += "&download_ms=" + String(last_download_ms);
+    }
+    has_params = true;
+
+    // Ask for a compressed bitmap; older servers ignore this and send v1 raw
+    url += (has_params ? "&" : "?");
+    url += "format=" EPBM_FORMAT;
+
     Serial.printf("[Stream] Mode: %s\n", get_mode_name(current_mode));
     Serial.printf("[Stream] Fetching: %s\n", url.c_str());
 
     http.begin(url);
     http.setTimeout(30000);  // 30 second timeout
+    http.addHeader("X-Device-Id", DEVICE_ID);
+
+    // The server recommends when to wake next, based on when its data changes
+    const char* response_headers[] = {"X-Sleep-Seconds"};
+    http.collectHeaders(response_headers, 1);
 
+    unsigned long request_time = millis();
     int http_code = http.GET();
     Serial.printf("[Stream] HTTP response code: %d\n", http_code);
 
@@ -482,46 +575,59 @@ void download_and_render_image() {
         return;
     }
 
+    if (http.hasHeader("X-Sleep-Seconds")) {
+        long sleep_sec = http.header("X-Sleep-Seconds").toInt();
+        if (sleep_sec > 0 && sleep_sec <= MAX_SERVER_SLEEP_SEC) {
+            server_sleep_sec = (uint32_t)sleep_sec;
+        }
+    }
+
     int total_len = http.getSize();
     Serial.printf("[Stream] Content-Length: %d bytes\n", total_len);
 
-    // Expected size: 8 bytes header + 800*480 bytes data = 384008 bytes
-    int expected_size = 8 + (EPD_WIDTH * EPD_HEIGHT);
-    if (total_len != expected_size) {
-        Serial.printf("[Stream] Size mismatch: expected %d bytes\n",
-                      expected_size);
+    WiFiClient* stream = http.getStreamPtr();
+    unsigned long start_time = millis();
+
+    // Step 1: Read and parse header: 8 bytes, plus version + encoding for v2.
+    // The magic says which version this is; a v2 body can be as long as a v1
+    // one, so the length can't.
+    uint8_t header[10];
+    uint16_t width, height;
+    bool is_v1;
+    if (!read_stream_bytes(stream, header, 8, start_time) ||
+        !parse_bitmap_header(header, &width, &height, &is_v1) ||
+        (!is_v1 && !read_stream_bytes(stream, header + 8, 2, start_time))) {
+        Serial.println("[Stream] Invalid header");
+        http.end();
+        show_error_screen();
+        return;
+    }
+    int header_len = is_v1 ? 8 : 10;
+
+    // A v1 raw bitmap is exactly 8 bytes header + 800*480 bytes data = 384008
+    // bytes, and a v2 one is never longer than that plus its 2 extra header bytes
+    int raw_size = 8 + (EPD_WIDTH * EPD_HEIGHT);
+    if (total_len > 0 &&
+        (is_v1 ? total_len != raw_size : total_len > raw_size + 2)) {
+        Serial.printf("[Stream] Unexpected size: raw bitmaps are %d bytes\n",
+                      raw_size);
         http.end();
         show_error_screen();
         return;
     }
 
-    WiFiClient* stream = http.getStreamPtr();
-    unsigned long start_time = millis();
-
-    // Step 1: Read and parse header (8 bytes)
-    uint8_t header[8];
-    int header_bytes = 0;
-    while (http.connected() && header_bytes < 8) {
-        if (stream->available()) {
-            header[header_bytes++] = stream->read();
-        } else {
-            delay(1);
-        }
-        if (millis() - start_time > 5000) {
-            Serial.println("[Stream] Timeout reading header");
+    uint8_t encoding = EPBM_RAW;
+    if (!is_v1) {
+        if (header[8] != 2 || header[9] > EPBM_RLE) {
+            Serial.printf("[Stream] Unsupported EPBM version %d / encoding %d\n",
+                          header[8], header[9]);
             http.end();
             show_error_screen();
             return;
         }
+        encoding = header[9];
     }
-
-    uint16_t width, height;
-    if (!parse_bitmap_header(header, &width, &height)) {
-        Serial.println("[Stream] Invalid header");
-        http.end();
-        show_error_screen();
-        return;
-    }
+    Serial.printf("[Stream] Encoding: %d\n", encoding);
 
     // Step 2: Prepare display for streaming
     Serial.println("[Stream] Starting streaming render...");
@@ -542,7 +648,7 @@ void download_and_render_image() {
 
     int total_pixels = width * height;
     int pixels_rendered = 0;
-    int bytes_downloaded = 8;  // Already read header
+    int bytes_downloaded = header_len;  // Already read header
 
     Serial.print("[Stream] Progress: ");
     int last_pct = 0;
@@ -551,22 +657,35 @@ void download_and_render_image() {
         // Read chunk of pixel data
         size_t available = stream->available();
         if (available) {
-            int to_read =
-                min((int)available,
-                    min(STREAM_BUFFER_SIZE, total_pixels - pixels_rendered));
+            int to_read = min((int)available, STREAM_BUFFER_SIZE);
+            if (encoding == EPBM_RAW) {
+                // Don't read past the frame in case the body has trailing data
+                to_read = min(to_read, total_pixels - pixels_rendered);
+            }
             int chunk_size = stream->readBytes(pixel_buffer, to_read);
             bytes_downloaded += chunk_size;
 
             // Render pixels from this chunk
             for (int i = 0; i < chunk_size; i++) {
-                uint8_t color_value = pixel_buffer[i];
-                uint16_t gxepd_color = map_epbm_color(color_value);
-
-                int x = pixels_rendered % width;
-                int y = pixels_rendered / width;
-
-                display.drawPixel(x, y, gxepd_color);
-                pixels_rendered++;
+                uint8_t value = pixel_buffer[i];
+                switch (encoding) {
+                    case EPBM_PACKED4:
+                        // Two pixels per byte, first pixel in the high nibble
+                        draw_pixel_run(&pixels_rendered, width, total_pixels,
+                                       value >> 4, 1);
+                        draw_pixel_run(&pixels_rendered, width, total_pixels,
+                                       value & 0x0F, 1);
+                        break;
+                    case EPBM_RLE:
+                        // Run length - 1 in the top 5 bits, color in the low 3
+                        draw_pixel_run(&pixels_rendered, width, total_pixels,
+                                       value & 0x07, (value >> 3) + 1);
+                        break;
+                    default:
+                        draw_pixel_run(&pixels_rendered, width, total_pixels,
+                                       value, 1);
+                        break;
+                }
             }
 
             // Progress indicator every 10%
@@ -592,6 +711,7 @@ void download_and_render_image() {
 
     free(pixel_buffer);
     http.end();
+    last_download_ms = millis() - request_time;
 
     Serial.printf(
         " 100%%\n[Stream] Rendered %d pixels, downloaded %d bytes in %lu ms\n",
@@ -607,11 +727,17 @@ void download_and_render_image() {
 }
 
 /**
- * Calculate seconds until next scheduled wake time
- * Wake times: 6am, 12pm, 6pm, midnight (local time)
+ * Calculate seconds until next wake: the server's X-Sleep-Seconds if it sent
+ * one, else the next scheduled wake time (6am, 12pm, 6pm local time)
  * Returns: seconds to sleep, or FALLBACK_SLEEP_SEC if time not synced
  */
 uint32_t calculate_sleep_duration() {
+    if (server_sleep_sec > 0) {
+        Serial.printf("[Sleep] Server-directed: %u seconds / %.2f hours\n",
+                      server_sleep_sec, server_sleep_sec / 3600.0);
+        return server_sleep_sec;
+    }
+
     time_t now = time(nullptr);
 
     // Check if time is valid (synced)
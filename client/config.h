@@ -10,6 +10,11 @@
 // Server configuration
 #define SERVER_HOST SECRET_HOST
 #define SERVER_PORT 8080
+
+// Sent as X-Device-Id, so the server logs this panel's battery and signal
+// history (see /telemetry) and can serve its playlist from /device/{id}/next.bin
+#define DEVICE_ID "reterminal-1"
+#define FIRMWARE_VERSION "1.1"
 // Endpoint is selected dynamically from current display mode:
 //   - weather, stocks, fred
 //   - weight/velocity and weight/forecast (with user query param)
@@ -20,7 +25,8 @@
 #define TIMEZONE_OFFSET_SEC (-8 * 3600)  // Offset from UTC in seconds
 #define DST_OFFSET_SEC 3600               // Daylight saving adjustment (1 hour)
 
-// Scheduled wake times (24-hour format, local time)
+// Scheduled wake times (24-hour format, local time). Only used when the server
+// does not send X-Sleep-Seconds with the bitmap (older server or failed download).
 #define WAKE_HOUR_1 6   // 6:00 AM
 #define WAKE_HOUR_2 12  // 12:00 PM (noon)
 #define WAKE_HOUR_3 18  // 6:00 PM
@@ -28,6 +34,9 @@
 // Fallback sleep duration if NTP fails
 #define FALLBACK_SLEEP_SEC (3600 * 6)
 
+// Longest server-directed sleep accepted; anything else falls back to the schedule
+#define MAX_SERVER_SLEEP_SEC (3600 * 72)
+
 // === ePaper Display Pins (reTerminal e1002) ===
 // Using GxEPD2 with GxEPD2_730c_GDEP073E01 driver
 // 7.3" full-color e-paper: 800x480 pixels, 8 colors (black, white + 6 colors)
@@ -68,6 +77,10 @@
 #define BUTTON_KEY1 4  // Middle button - Switch to Stocks
 #define BUTTON_KEY2 5  // Left button - Switch to Weather
 
+// EPBM pixel encoding requested from the server: "raw", "packed4" or "rle".
+// RLE is ~10-30x smaller on the chart screens, which shortens the Wi-Fi window.
+#define EPBM_FORMAT "rle"
+
 // EPBM streaming buffer size
 // Used for downloading and rendering chunks on-the-fly
 #define STREAM_BUFFER_SIZE 8192  // 8KB chunks for efficient streaming
@@ -62,6 +62,16 @@ else
     echo "  Environment file found at ${INSTALL_DIR}/env.txt"
 fi
 
+# Step 5b: Check for config file
+if [ ! -f "${INSTALL_DIR}/config.toml" ]; then
+    echo "  Creating config.toml from template..."
+    sudo cp "${BUNDLE_DIR}/config.example.toml" "${INSTALL_DIR}/config.toml"
+    echo "Please edit ${INSTALL_DIR}/config.toml with your locations and screens."
+    echo
+else
+    echo "  Config file found at ${INSTALL_DIR}/config.toml"
+fi
+
 # Step 6: Install systemd system service
 echo "Step 3: Installing systemd system service..."
 sudo cp "${BUNDLE_DIR}/${SERVICE_NAME}.service" "${SYSTEM_SYSTEMD_DIR}/${SERVICE_NAME}.service"
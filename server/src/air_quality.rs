@@ -0,0 +1,977 @@
+//! Air quality and pollen: the US EPA Air Quality Index now and for the next few days,
+//! the main pollutants behind it, and pollen counts where the provider has them.
+//!
+//! OpenWeatherMap's Air Pollution API and Open-Meteo's air-quality API both give hourly
+//! pollutant concentrations, and the index is computed here from those with the EPA
+//! breakpoints so that both providers read the same. They are hourly values rather than
+//! the 8- and 24-hour averages of the official index, so a short spike reads a little
+//! high. Only Open-Meteo has pollen, and only in Europe.
+
+use crate::locale::Locale;
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use crate::upstream::{FetchError, RateLimit, Upstream};
+use crate::weather::wrap_text_lines;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::prelude::*;
+use reverse_geocoder::ReverseGeocoder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AirQualityData {
+    pub lat: f32,
+    pub lon: f32,
+    /// Seconds east of UTC at the location, for splitting the forecast into days
+    pub timezone_offset: i32,
+    pub current: Pollutants,
+    /// Hourly from the current hour, four or five days out
+    pub hourly: Vec<Pollutants>,
+    /// Current counts; `None` where the provider has no pollen data
+    pub pollen: Option<Pollen>,
+}
+
+/// Concentrations at one hour, in µg/m³
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Pollutants {
+    pub dt: i64,
+    pub pm2_5: f32,
+    pub pm10: f32,
+    pub o3: f32,
+    pub no2: f32,
+}
+
+/// Grains per m³, summed over the species of each group
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Pollen {
+    pub tree: f32,
+    pub grass: f32,
+    pub weed: f32,
+}
+
+/// Where a screen's air quality comes from
+pub enum AirQualitySource {
+    OpenWeather { api_key: String },
+    OpenMeteo,
+}
+
+impl AirQualitySource {
+    /// Fetches within `limit`, which should be the provider's
+    pub async fn fetch(
+        &self,
+        upstream: &Upstream,
+        limit: &RateLimit,
+        lat: &str,
+        lon: &str,
+    ) -> Result<AirQualityData, FetchError> {
+        match self {
+            AirQualitySource::OpenWeather { api_key } => {
+                let url = |endpoint: &str| {
+                    format!(
+                        "https://api.openweathermap.org/data/2.5/{}?lat={}&lon={}&appid={}",
+                        endpoint, lat, lon, api_key
+                    )
+                };
+                let current: AirPollution = upstream.get_json(&url("air_pollution"), limit).await?;
+                let forecast: AirPollution = upstream
+                    .get_json(&url("air_pollution/forecast"), limit)
+                    .await?;
+                // The API has no time zone, so the forecast days follow the server's
+                let timezone_offset = Local::now().offset().local_minus_utc();
+                air_pollution(current, forecast, timezone_offset)
+                    .ok_or_else(|| "Empty air pollution response".into())
+            }
+            AirQualitySource::OpenMeteo => {
+                let url = format!(
+                    "https://air-quality-api.open-meteo.com/v1/air-quality?latitude={}&longitude={}&current={},{}&hourly={}&timezone=auto&timeformat=unixtime&forecast_days=5",
+                    lat, lon, OPEN_METEO_POLLUTANTS, OPEN_METEO_POLLEN, OPEN_METEO_POLLUTANTS
+                );
+                let forecast: OpenMeteoAirQuality = upstream.get_json(&url, limit).await?;
+                Ok(forecast.into())
+            }
+        }
+    }
+}
+
+/// OpenWeatherMap `air_pollution` and `air_pollution/forecast` responses
+#[derive(Deserialize)]
+struct AirPollution {
+    coord: Coord,
+    list: Vec<AirPollutionHour>,
+}
+
+#[derive(Deserialize)]
+struct Coord {
+    lat: f32,
+    lon: f32,
+}
+
+/// OpenWeatherMap's own 1-5 index is ignored in favor of the EPA one
+#[derive(Deserialize)]
+struct AirPollutionHour {
+    dt: i64,
+    components: Components,
+}
+
+#[derive(Deserialize)]
+struct Components {
+    pm2_5: f32,
+    pm10: f32,
+    o3: f32,
+    no2: f32,
+}
+
+impl From<&AirPollutionHour> for Pollutants {
+    fn from(hour: &AirPollutionHour) -> Self {
+        let c = &hour.components;
+        Pollutants {
+            dt: hour.dt,
+            pm2_5: c.pm2_5,
+            pm10: c.pm10,
+            o3: c.o3,
+            no2: c.no2,
+        }
+    }
+}
+
+/// Current conditions and forecast, or `None` when there is no current hour
+fn air_pollution(
+    current: AirPollution,
+    forecast: AirPollution,
+    timezone_offset: i32,
+) -> Option<AirQualityData> {
+    Some(AirQualityData {
+        lat: current.coord.lat,
+        lon: current.coord.lon,
+        timezone_offset,
+        current: current.list.first()?.into(),
+        hourly: forecast.list.iter().map(Pollutants::from).collect(),
+        pollen: None,
+    })
+}
+
+const OPEN_METEO_POLLUTANTS: &str = "pm2_5,pm10,ozone,nitrogen_dioxide";
+const OPEN_METEO_POLLEN: &str =
+    "alder_pollen,birch_pollen,olive_pollen,grass_pollen,mugwort_pollen,ragweed_pollen";
+
+/// Open-Meteo air-quality response with `timeformat=unixtime`. Pollen is null outside
+/// Europe, and any value can be null at the end of the forecast.
+#[derive(Deserialize)]
+struct OpenMeteoAirQuality {
+    latitude: f32,
+    longitude: f32,
+    utc_offset_seconds: i32,
+    current: OpenMeteoCurrent,
+    hourly: OpenMeteoHourly,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoCurrent {
+    time: i64,
+    pm2_5: Option<f32>,
+    pm10: Option<f32>,
+    ozone: Option<f32>,
+    nitrogen_dioxide: Option<f32>,
+    alder_pollen: Option<f32>,
+    birch_pollen: Option<f32>,
+    olive_pollen: Option<f32>,
+    grass_pollen: Option<f32>,
+    mugwort_pollen: Option<f32>,
+    ragweed_pollen: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoHourly {
+    time: Vec<i64>,
+    pm2_5: Vec<Option<f32>>,
+    pm10: Vec<Option<f32>>,
+    ozone: Vec<Option<f32>>,
+    nitrogen_dioxide: Vec<Option<f32>>,
+}
+
+impl OpenMeteoCurrent {
+    fn pollen(&self) -> Option<Pollen> {
+        let sum = |species: &[Option<f32>]| -> Option<f32> {
+            species.iter().flatten().copied().reduce(|a, b| a + b)
+        };
+        let tree = sum(&[self.alder_pollen, self.birch_pollen, self.olive_pollen]);
+        let grass = self.grass_pollen;
+        let weed = sum(&[self.mugwort_pollen, self.ragweed_pollen]);
+        if tree.is_none() && grass.is_none() && weed.is_none() {
+            return None;
+        }
+        Some(Pollen {
+            tree: tree.unwrap_or(0.0),
+            grass: grass.unwrap_or(0.0),
+            weed: weed.unwrap_or(0.0),
+        })
+    }
+}
+
+impl From<OpenMeteoAirQuality> for AirQualityData {
+    fn from(forecast: OpenMeteoAirQuality) -> Self {
+        let c = &forecast.current;
+        let h = &forecast.hourly;
+        let at = |values: &[Option<f32>], i: usize| values.get(i).copied().flatten();
+        let hourly = h
+            .time
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &time)| {
+                Some(Pollutants {
+                    dt: time,
+                    pm2_5: at(&h.pm2_5, i)?,
+                    pm10: at(&h.pm10, i)?,
+                    o3: at(&h.ozone, i)?,
+                    no2: at(&h.nitrogen_dioxide, i)?,
+                })
+            })
+            .collect();
+        AirQualityData {
+            lat: forecast.latitude,
+            lon: forecast.longitude,
+            timezone_offset: forecast.utc_offset_seconds,
+            current: Pollutants {
+                dt: c.time,
+                pm2_5: c.pm2_5.unwrap_or(0.0),
+                pm10: c.pm10.unwrap_or(0.0),
+                o3: c.ozone.unwrap_or(0.0),
+                no2: c.nitrogen_dioxide.unwrap_or(0.0),
+            },
+            hourly,
+            pollen: c.pollen(),
+        }
+    }
+}
+
+/// The pollutants the index is computed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pollutant {
+    Pm2_5,
+    Pm10,
+    O3,
+    No2,
+}
+
+const POLLUTANTS: [Pollutant; 4] = [
+    Pollutant::Pm2_5,
+    Pollutant::Pm10,
+    Pollutant::O3,
+    Pollutant::No2,
+];
+
+/// µg/m³ per ppb at 25°C
+const O3_UG_PER_PPB: f32 = 1.962;
+const NO2_UG_PER_PPB: f32 = 1.881;
+
+impl Pollutant {
+    fn label(self) -> &'static str {
+        match self {
+            Pollutant::Pm2_5 => "PM2.5",
+            Pollutant::Pm10 => "PM10",
+            Pollutant::O3 => "Ozone",
+            Pollutant::No2 => "NO₂",
+        }
+    }
+
+    fn concentration(self, pollutants: &Pollutants) -> f32 {
+        match self {
+            Pollutant::Pm2_5 => pollutants.pm2_5,
+            Pollutant::Pm10 => pollutants.pm10,
+            Pollutant::O3 => pollutants.o3,
+            Pollutant::No2 => pollutants.no2,
+        }
+    }
+
+    /// EPA breakpoints as (low, high concentration, low, high index). Particulates are
+    /// in µg/m³ and the gases in ppb; ozone uses the 8-hour table, so it tops out at 300.
+    fn breakpoints(self) -> &'static [(f32, f32, f32, f32)] {
+        match self {
+            Pollutant::Pm2_5 => &[
+                (0.0, 9.0, 0.0, 50.0),
+                (9.1, 35.4, 51.0, 100.0),
+                (35.5, 55.4, 101.0, 150.0),
+                (55.5, 125.4, 151.0, 200.0),
+                (125.5, 225.4, 201.0, 300.0),
+                (225.5, 325.4, 301.0, 500.0),
+            ],
+            Pollutant::Pm10 => &[
+                (0.0, 54.0, 0.0, 50.0),
+                (55.0, 154.0, 51.0, 100.0),
+                (155.0, 254.0, 101.0, 150.0),
+                (255.0, 354.0, 151.0, 200.0),
+                (355.0, 424.0, 201.0, 300.0),
+                (425.0, 604.0, 301.0, 500.0),
+            ],
+            Pollutant::O3 => &[
+                (0.0, 54.0, 0.0, 50.0),
+                (55.0, 70.0, 51.0, 100.0),
+                (71.0, 85.0, 101.0, 150.0),
+                (86.0, 105.0, 151.0, 200.0),
+                (106.0, 200.0, 201.0, 300.0),
+            ],
+            Pollutant::No2 => &[
+                (0.0, 53.0, 0.0, 50.0),
+                (54.0, 100.0, 51.0, 100.0),
+                (101.0, 360.0, 101.0, 150.0),
+                (361.0, 649.0, 151.0, 200.0),
+                (650.0, 1249.0, 201.0, 300.0),
+                (1250.0, 2049.0, 301.0, 500.0),
+            ],
+        }
+    }
+
+    /// Concentration in the breakpoints' unit, truncated to their precision as the EPA does
+    fn epa_concentration(self, pollutants: &Pollutants) -> f32 {
+        let c = self.concentration(pollutants);
+        match self {
+            Pollutant::Pm2_5 => (c * 10.0).floor() / 10.0,
+            Pollutant::Pm10 => c.floor(),
+            Pollutant::O3 => (c / O3_UG_PER_PPB).floor(),
+            Pollutant::No2 => (c / NO2_UG_PER_PPB).floor(),
+        }
+    }
+
+    /// This pollutant's sub-index, linear within its breakpoint range
+    fn aqi(self, pollutants: &Pollutants) -> u16 {
+        let c = self.epa_concentration(pollutants).max(0.0);
+        let table = self.breakpoints();
+        let &(c_lo, c_hi, i_lo, i_hi) = table
+            .iter()
+            .find(|&&(_, c_hi, _, _)| c <= c_hi)
+            .unwrap_or(&table[table.len() - 1]);
+        let c = c.clamp(c_lo, c_hi);
+        ((i_hi - i_lo) / (c_hi - c_lo) * (c - c_lo) + i_lo).round() as u16
+    }
+}
+
+/// The overall index, which is the highest sub-index, and the pollutant behind it
+pub fn aqi(pollutants: &Pollutants) -> (u16, Pollutant) {
+    POLLUTANTS
+        .iter()
+        .map(|&pollutant| (pollutant.aqi(pollutants), pollutant))
+        .max_by_key(|&(aqi, _)| aqi)
+        .expect("POLLUTANTS is not empty")
+}
+
+/// EPA health categories, cleanest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AqiCategory {
+    Good,
+    Moderate,
+    UnhealthyForSensitiveGroups,
+    Unhealthy,
+    VeryUnhealthy,
+    Hazardous,
+}
+
+impl AqiCategory {
+    pub fn from_aqi(aqi: u16) -> Self {
+        match aqi {
+            0..=50 => AqiCategory::Good,
+            51..=100 => AqiCategory::Moderate,
+            101..=150 => AqiCategory::UnhealthyForSensitiveGroups,
+            151..=200 => AqiCategory::Unhealthy,
+            201..=300 => AqiCategory::VeryUnhealthy,
+            _ => AqiCategory::Hazardous,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            AqiCategory::Good => "Good",
+            AqiCategory::Moderate => "Moderate",
+            AqiCategory::UnhealthyForSensitiveGroups => "Unhealthy for Sensitive Groups",
+            AqiCategory::Unhealthy => "Unhealthy",
+            AqiCategory::VeryUnhealthy => "Very Unhealthy",
+            AqiCategory::Hazardous => "Hazardous",
+        }
+    }
+
+    /// EPA's color for the category, and a text color that reads on it
+    fn colors(self) -> (&'static str, &'static str) {
+        match self {
+            AqiCategory::Good => ("#00e400", "black"),
+            AqiCategory::Moderate => ("#ffff00", "black"),
+            AqiCategory::UnhealthyForSensitiveGroups => ("#ff7e00", "black"),
+            AqiCategory::Unhealthy => ("#ff0000", "white"),
+            AqiCategory::VeryUnhealthy => ("#8f3f97", "white"),
+            AqiCategory::Hazardous => ("#7e0023", "white"),
+        }
+    }
+
+    /// Warning icon in the nearest of the icon set's four colors
+    fn icon_file(self) -> &'static str {
+        match self {
+            AqiCategory::Good => "code-green.svg",
+            AqiCategory::Moderate => "code-yellow.svg",
+            AqiCategory::UnhealthyForSensitiveGroups => "code-orange.svg",
+            _ => "code-red.svg",
+        }
+    }
+}
+
+/// Pollen groups, each summing its species
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PollenKind {
+    Tree,
+    Grass,
+    Weed,
+}
+
+const POLLEN_KINDS: [PollenKind; 3] = [PollenKind::Tree, PollenKind::Grass, PollenKind::Weed];
+
+impl PollenKind {
+    fn label(self) -> &'static str {
+        match self {
+            PollenKind::Tree => "Trees",
+            PollenKind::Grass => "Grass",
+            PollenKind::Weed => "Weeds",
+        }
+    }
+
+    fn count(self, pollen: &Pollen) -> f32 {
+        match self {
+            PollenKind::Tree => pollen.tree,
+            PollenKind::Grass => pollen.grass,
+            PollenKind::Weed => pollen.weed,
+        }
+    }
+
+    fn icon_file(self) -> &'static str {
+        match self {
+            PollenKind::Tree => "pollen-tree.svg",
+            PollenKind::Grass => "pollen-grass.svg",
+            PollenKind::Weed => "pollen-flower.svg",
+        }
+    }
+
+    /// Lowest counts for low, moderate, high and very high, from the National Allergy
+    /// Bureau's scale
+    fn thresholds(self) -> [f32; 4] {
+        match self {
+            PollenKind::Tree => [1.0, 15.0, 90.0, 1500.0],
+            PollenKind::Grass => [1.0, 5.0, 20.0, 200.0],
+            PollenKind::Weed => [1.0, 10.0, 50.0, 500.0],
+        }
+    }
+
+    /// 0 (none) to 4 (very high)
+    fn level(self, pollen: &Pollen) -> usize {
+        let count = self.count(pollen);
+        self.thresholds().iter().filter(|&&t| count >= t).count()
+    }
+}
+
+const POLLEN_LEVELS: [&str; 5] = ["None", "Low", "Moderate", "High", "Very High"];
+
+/// Highest index of each local day in the forecast, today first
+fn daily_max(hourly: &[Pollutants], tz_offset: FixedOffset) -> Vec<(NaiveDate, u16)> {
+    let mut days: Vec<(NaiveDate, u16)> = Vec::new();
+    for hour in hourly {
+        let Some(time) = DateTime::from_timestamp(hour.dt, 0) else {
+            continue;
+        };
+        let date = time.with_timezone(&tz_offset).date_naive();
+        let (hour_aqi, _) = aqi(hour);
+        match days.last_mut() {
+            Some((day, max)) if *day == date => *max = (*max).max(hour_aqi),
+            _ => days.push((date, hour_aqi)),
+        }
+    }
+    days
+}
+
+/// Loads an icon from the static set as a base64-encoded data URI
+fn load_icon_as_data_uri(icon_filename: &str) -> Result<String, std::io::Error> {
+    let icon_path = format!("assets/static/fill-svg-static/{}", icon_filename);
+    let svg_content = fs::read(&icon_path)?;
+    let encoded = general_purpose::STANDARD.encode(&svg_content);
+    Ok(format!("data:image/svg+xml;base64,{}", encoded))
+}
+
+/// A bordered bar filled to `fill_percent` with a gradient, as on the weather screen
+fn gradient_bar_svg(
+    (x, y): (f64, f64),
+    (width, height): (f64, f64),
+    fill_percent: f64,
+    gradient_id: &str,
+    clip_id: &str,
+) -> String {
+    let inset = 2.0;
+    let fill_width = (width - inset * 2.0) * (fill_percent.clamp(0.0, 100.0) / 100.0);
+    let mut svg = format!(
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="white" stroke="black" stroke-width="2" rx="3"/>"#,
+        x, y, width, height
+    );
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <clipPath id="{}"><rect x="{}" y="{}" width="{}" height="{}" rx="2"/></clipPath>"#,
+        clip_id,
+        x + inset,
+        y + inset,
+        fill_width,
+        height - inset * 2.0
+    ));
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="url(#{})" clip-path="url(#{})" rx="2"/>"#,
+        x + inset,
+        y + inset,
+        width - inset * 2.0,
+        height - inset * 2.0,
+        gradient_id,
+        clip_id
+    ));
+    svg.push('\n');
+    svg
+}
+
+/// Bars span index 0 to 300, the bottom of Hazardous
+fn aqi_fill_percent(aqi: u16) -> f64 {
+    aqi as f64 / 300.0 * 100.0
+}
+
+/// The index as the headline, then its category, the pollen levels and the daily
+/// forecast
+fn compact_air_quality_svg(
+    air: &AirQualityData,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    locale: Locale,
+    tz_offset: FixedOffset,
+    canvas: Canvas,
+) -> String {
+    let coords = (air.lat as f64, air.lon as f64);
+    let mut card = CompactCard::new(
+        format!("Air quality · {}", geocoder.search(coords).record.name),
+        battery_pct,
+    );
+    // Red from the level at which sensitive groups should limit time outside
+    let color = |category: AqiCategory| {
+        if category >= AqiCategory::UnhealthyForSensitiveGroups {
+            Ink::Red
+        } else {
+            Ink::Black
+        }
+    };
+    let (current_aqi, main_pollutant) = aqi(&air.current);
+    let category = AqiCategory::from_aqi(current_aqi);
+    card.headline = Some(CardLine::new(format!("AQI {}", current_aqi)).colored(color(category)));
+    card.icon = load_icon_as_data_uri(category.icon_file()).ok();
+    card.lines.push(
+        CardLine::new(category.name())
+            .with_value(main_pollutant.label())
+            .colored(color(category)),
+    );
+    if let Some(pollen) = &air.pollen {
+        for kind in POLLEN_KINDS {
+            let level = kind.level(pollen);
+            let line =
+                CardLine::new(format!("{} pollen", kind.label())).with_value(POLLEN_LEVELS[level]);
+            card.lines.push(if level >= 3 {
+                line.colored(Ink::Red)
+            } else {
+                line
+            });
+        }
+    }
+    for (date, day_aqi) in daily_max(&air.hourly, tz_offset).into_iter().skip(1) {
+        card.lines.push(
+            CardLine::new(locale.weekday(date.weekday()))
+                .with_value(format!("AQI {}", day_aqi))
+                .colored(color(AqiCategory::from_aqi(day_aqi))),
+        );
+    }
+    card.svg(canvas)
+}
+
+/// Generates an SVG of the current air quality, its forecast and pollen
+///
+/// # Arguments
+/// * `air` - Pollutant concentrations and pollen counts
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `geocoder` - Reverse geocoder for the city name
+/// * `locale` - Language of the day names
+/// * `canvas` - Size to lay out for
+///
+/// # Returns
+/// A String containing the SVG markup
+pub fn generate_air_quality_svg(
+    air: &AirQualityData,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let (width, height) = (canvas.width, canvas.height);
+    let tz_offset =
+        chrono::FixedOffset::east_opt(air.timezone_offset).unwrap_or_else(|| chrono::Utc.fix());
+    if canvas.is_compact() {
+        return compact_air_quality_svg(air, battery_pct, geocoder, locale, tz_offset, canvas);
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    );
+    svg.push('\n');
+    svg.push_str(r#"  <defs>"#);
+    svg.push('\n');
+    // AQI gradient: the EPA category colors at 0, 50, 100, 150, 200 and 300
+    svg.push_str(r#"    <linearGradient id="aqiGradient" x1="0%" y1="0%" x2="100%" y2="0%">"#);
+    svg.push('\n');
+    for (offset, color) in [
+        (0.0, "#00e400"),
+        (16.7, "#ffff00"),
+        (33.3, "#ff7e00"),
+        (50.0, "#ff0000"),
+        (66.7, "#8f3f97"),
+        (100.0, "#7e0023"),
+    ] {
+        svg.push_str(&format!(
+            r#"      <stop offset="{}%" style="stop-color:{};stop-opacity:1" />"#,
+            offset, color
+        ));
+        svg.push('\n');
+    }
+    svg.push_str(r#"    </linearGradient>"#);
+    svg.push('\n');
+    // Pollen gradient: green (none) -> yellow -> orange -> red (very high)
+    svg.push_str(r#"    <linearGradient id="pollenGradient" x1="0%" y1="0%" x2="100%" y2="0%">"#);
+    svg.push('\n');
+    svg.push_str(r#"      <stop offset="0%" style="stop-color:green;stop-opacity:1" />"#);
+    svg.push('\n');
+    svg.push_str(r#"      <stop offset="33%" style="stop-color:yellow;stop-opacity:1" />"#);
+    svg.push('\n');
+    svg.push_str(r#"      <stop offset="66%" style="stop-color:orange;stop-opacity:1" />"#);
+    svg.push('\n');
+    svg.push_str(r#"      <stop offset="100%" style="stop-color:red;stop-opacity:1" />"#);
+    svg.push('\n');
+    svg.push_str(r#"    </linearGradient>"#);
+    svg.push('\n');
+    svg.push_str(svg_common::BATTERY_GRADIENT_DEF);
+    svg.push('\n');
+    svg.push_str(r#"  </defs>"#);
+    svg.push('\n');
+
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+    svg.push('\n');
+
+    // Left section: current air quality and pollen
+    let left_width = (width as f32 * 0.6).round();
+    svg.push_str(
+        r#"  <text x="20" y="35" font-family="Arial" font-size="28" font-weight="bold" fill="black">Air Quality</text>"#,
+    );
+    svg.push('\n');
+    let coords = (air.lat as f64, air.lon as f64);
+    svg.push_str(&format!(
+        r#"  <text x="20" y="58" font-family="Arial" font-size="16" fill="black">{}</text>"#,
+        svg_common::escape_xml_text(&geocoder.search(coords).record.name)
+    ));
+    svg.push('\n');
+
+    let (current_aqi, main_pollutant) = aqi(&air.current);
+    let category = AqiCategory::from_aqi(current_aqi);
+    if let Ok(data_uri) = load_icon_as_data_uri(category.icon_file()) {
+        svg.push_str(&format!(
+            r#"  <image x="350" y="2" width="80" height="80" href="{}"/>"#,
+            data_uri
+        ));
+        svg.push('\n');
+    }
+
+    // The index on its category color
+    let (fill, text_fill) = category.colors();
+    svg.push_str(&format!(
+        r#"  <rect x="20" y="80" width="150" height="100" fill="{}" stroke="black" stroke-width="2" rx="6"/>"#,
+        fill
+    ));
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <text x="95" y="142" text-anchor="middle" font-family="Arial" font-size="56" font-weight="bold" fill="{}">{}</text>"#,
+        text_fill, current_aqi
+    ));
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <text x="95" y="168" text-anchor="middle" font-family="Arial" font-size="16" fill="{}">US AQI</text>"#,
+        text_fill
+    ));
+    svg.push('\n');
+    let mut category_y = 105.0;
+    for line in wrap_text_lines(category.name(), 18) {
+        svg.push_str(&format!(
+            r#"  <text x="190" y="{}" font-family="Arial" font-size="24" font-weight="bold" fill="black">{}</text>"#,
+            category_y, line
+        ));
+        svg.push('\n');
+        category_y += 28.0;
+    }
+    svg.push_str(&format!(
+        r#"  <text x="190" y="{}" font-family="Arial" font-size="18" fill="black">Mainly {}</text>"#,
+        category_y + 4.0,
+        main_pollutant.label()
+    ));
+    svg.push('\n');
+
+    // Pollutant bars, each filled to its own sub-index
+    let bar_width = 180.0;
+    let bar_height = 20.0;
+    for (i, pollutant) in POLLUTANTS.iter().enumerate() {
+        let y = 205.0 + i as f64 * 32.0;
+        svg.push_str(&format!(
+            r#"  <text x="40" y="{}" font-family="Arial" font-size="20" fill="black">{}</text>"#,
+            y + 17.0,
+            pollutant.label()
+        ));
+        svg.push('\n');
+        svg.push_str(&gradient_bar_svg(
+            (130.0, y),
+            (bar_width, bar_height),
+            aqi_fill_percent(pollutant.aqi(&air.current)),
+            "aqiGradient",
+            &format!("pollutantClip{}", i),
+        ));
+        svg.push_str(&format!(
+            r#"  <text x="{}" y="{}" font-family="Arial" font-size="16" fill="black">{:.0} µg/m³ ({})</text>"#,
+            140.0 + bar_width,
+            y + 16.0,
+            pollutant.concentration(&air.current),
+            pollutant.aqi(&air.current)
+        ));
+        svg.push('\n');
+    }
+
+    // Pollen, side by side
+    let pollen_y = 345.0;
+    match &air.pollen {
+        Some(pollen) => {
+            for (i, kind) in POLLEN_KINDS.iter().enumerate() {
+                let x = 20.0 + i as f64 * 150.0;
+                if let Ok(data_uri) = load_icon_as_data_uri(kind.icon_file()) {
+                    svg.push_str(&format!(
+                        r#"  <image x="{}" y="{}" width="56" height="56" href="{}"/>"#,
+                        x - 6.0,
+                        pollen_y,
+                        data_uri
+                    ));
+                    svg.push('\n');
+                }
+                let level = kind.level(pollen);
+                svg.push_str(&format!(
+                    r#"  <text x="{}" y="{}" font-family="Arial" font-size="18" font-weight="bold" fill="black">{}</text>"#,
+                    x + 54.0,
+                    pollen_y + 22.0,
+                    kind.label()
+                ));
+                svg.push('\n');
+                svg.push_str(&format!(
+                    r#"  <text x="{}" y="{}" font-family="Arial" font-size="15" fill="black">{}</text>"#,
+                    x + 54.0,
+                    pollen_y + 42.0,
+                    POLLEN_LEVELS[level]
+                ));
+                svg.push('\n');
+                svg.push_str(&gradient_bar_svg(
+                    (x, pollen_y + 60.0),
+                    (130.0, 14.0),
+                    level as f64 / 4.0 * 100.0,
+                    "pollenGradient",
+                    &format!("pollenClip{}", i),
+                ));
+            }
+        }
+        None => {
+            svg.push_str(&format!(
+                r#"  <text x="40" y="{}" font-family="Arial" font-size="18" fill="black">No pollen data for this location</text>"#,
+                pollen_y + 35.0
+            ));
+            svg.push('\n');
+        }
+    }
+
+    // Vertical divider
+    svg.push_str(&format!(
+        r#"  <line x1="{}" y1="20" x2="{}" y2="{}" stroke="black" stroke-width="2"/>"#,
+        left_width,
+        left_width,
+        height - 20
+    ));
+    svg.push('\n');
+
+    // Right section: the day's worst hour, today and the days after
+    let right_x = left_width as f64 + 20.0;
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="35" font-family="Arial" font-size="24" font-weight="bold" fill="black">AQI Forecast</text>"#,
+        right_x
+    ));
+    svg.push('\n');
+    let row_height = (height as f64 - 80.0) / 5.0;
+    for (idx, (date, day_aqi)) in daily_max(&air.hourly, tz_offset)
+        .into_iter()
+        .take(5)
+        .enumerate()
+    {
+        let y = 70.0 + idx as f64 * row_height;
+        let day_category = AqiCategory::from_aqi(day_aqi);
+        svg.push_str(&format!(
+            r#"  <text x="{}" y="{}" font-family="Arial" font-size="22" font-weight="bold" fill="black">{}</text>"#,
+            right_x,
+            y + 5.0,
+            locale.weekday(date.weekday())
+        ));
+        svg.push('\n');
+        svg.push_str(&gradient_bar_svg(
+            (right_x, y + 22.0),
+            (170.0, 16.0),
+            aqi_fill_percent(day_aqi),
+            "aqiGradient",
+            &format!("forecastClip{}", idx),
+        ));
+        let (fill, text_fill) = day_category.colors();
+        svg.push_str(&format!(
+            r#"  <rect x="{}" y="{}" width="64" height="40" fill="{}" stroke="black" stroke-width="2" rx="4"/>"#,
+            right_x + 190.0,
+            y - 12.0,
+            fill
+        ));
+        svg.push('\n');
+        svg.push_str(&format!(
+            r#"  <text x="{}" y="{}" text-anchor="middle" font-family="Arial" font-size="24" font-weight="bold" fill="{}">{}</text>"#,
+            right_x + 222.0,
+            y + 17.0,
+            text_fill,
+            day_aqi
+        ));
+        svg.push('\n');
+        svg.push_str(&format!(
+            r#"  <text x="{}" y="{}" font-family="Arial" font-size="14" fill="black">{}</text>"#,
+            right_x,
+            y + 55.0,
+            day_category.name()
+        ));
+        svg.push('\n');
+    }
+
+    // Footer with battery and last updated
+    let footer_y = height - 10;
+    let pct = battery_pct.unwrap_or(50);
+    svg.push_str(&svg_common::battery_label_svg(
+        10.0,
+        footer_y as f64,
+        "start",
+        12,
+    ));
+    svg.push_str(&svg_common::battery_bar_svg(
+        75.0,
+        footer_y as f64 - 10.0,
+        pct,
+        2.0,
+        "batteryClip",
+    ));
+    svg.push('\n');
+    let now = Local::now();
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">Last updated: {:02}:{:02}:{:02}</text>"#,
+        width - 10,
+        footer_y,
+        now.hour(),
+        now.minute(),
+        now.second()
+    ));
+    svg.push('\n');
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        air_pollution, aqi, daily_max, AirPollution, AirQualityData, AqiCategory,
+        OpenMeteoAirQuality, PollenKind, Pollutant, Pollutants,
+    };
+    use chrono::{FixedOffset, NaiveDate};
+
+    fn pollutants(pm2_5: f32, pm10: f32, o3: f32, no2: f32) -> Pollutants {
+        Pollutants {
+            dt: 0,
+            pm2_5,
+            pm10,
+            o3,
+            no2,
+        }
+    }
+
+    #[test]
+    fn aqi_follows_the_epa_breakpoints() {
+        // Breakpoint ends map to the category ends
+        assert_eq!(aqi(&pollutants(9.0, 0.0, 0.0, 0.0)), (50, Pollutant::Pm2_5));
+        assert_eq!(aqi(&pollutants(35.4, 0.0, 0.0, 0.0)).0, 100);
+        // Truncated to 12.3, then interpolated between 9.1 and 35.4
+        assert_eq!(
+            aqi(&pollutants(12.34, 20.5, 48.28, 14.91)),
+            (57, Pollutant::Pm2_5)
+        );
+        // 140 µg/m³ of ozone is 71 ppb, the bottom of the third range
+        assert_eq!(aqi(&pollutants(5.0, 8.1, 140.0, 5.2)), (101, Pollutant::O3));
+        // Past the top of the 8-hour ozone table
+        assert_eq!(aqi(&pollutants(0.0, 0.0, 1000.0, 0.0)).0, 300);
+        assert_eq!(aqi(&pollutants(0.0, 700.0, 0.0, 0.0)).0, 500);
+
+        assert_eq!(AqiCategory::from_aqi(50), AqiCategory::Good);
+        assert_eq!(
+            AqiCategory::from_aqi(101),
+            AqiCategory::UnhealthyForSensitiveGroups
+        );
+        assert_eq!(AqiCategory::from_aqi(301), AqiCategory::Hazardous);
+    }
+
+    #[test]
+    fn parses_recorded_openweather_responses() {
+        let current: AirPollution = serde_json::from_str(include_str!(
+            "../tests/fixtures/air_quality/openweather_current.json"
+        ))
+        .unwrap();
+        let forecast: AirPollution = serde_json::from_str(include_str!(
+            "../tests/fixtures/air_quality/openweather_forecast.json"
+        ))
+        .unwrap();
+        let air = air_pollution(current, forecast, -28800).unwrap();
+        assert_eq!(air.lat, 37.7749);
+        assert_eq!(air.current.pm2_5, 12.34);
+        assert!(air.pollen.is_none());
+        assert_eq!(air.hourly.len(), 4);
+
+        // The afternoon's particulates make today's worst hour; ozone tomorrow's
+        let tz = FixedOffset::east_opt(-28800).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+        assert_eq!(
+            daily_max(&air.hourly, tz),
+            [(day(5), 112), (day(6), 101), (day(7), 17)]
+        );
+    }
+
+    #[test]
+    fn parses_recorded_open_meteo_response() {
+        let forecast: OpenMeteoAirQuality = serde_json::from_str(include_str!(
+            "../tests/fixtures/air_quality/open_meteo.json"
+        ))
+        .unwrap();
+        let air = AirQualityData::from(forecast);
+        assert_eq!(air.timezone_offset, 3600);
+        assert_eq!(air.current.o3, 48.28);
+        // The last hour has no values
+        assert_eq!(air.hourly.len(), 2);
+        assert_eq!(air.hourly[1].pm2_5, 14.0);
+
+        // Alder and birch, with no olive pollen reported
+        let pollen = air.pollen.unwrap();
+        assert_eq!(pollen.tree, 42.5);
+        assert_eq!(PollenKind::Tree.level(&pollen), 2);
+        assert_eq!(PollenKind::Grass.level(&pollen), 1);
+        assert_eq!(PollenKind::Weed.level(&pollen), 0);
+    }
+}
@@ -0,0 +1,687 @@
+//! Sun, moon and tides for one place, computed locally: rise and set times, the three
+//! twilights, day length and its trend, the moon's phase and upcoming phases, today's
+//! tide curve when the screen has harmonics, and the day length across the year.
+
+use crate::astronomy::{
+    self, Body, Crossing, MoonPhase, ASTRONOMICAL_TWILIGHT, CIVIL_TWILIGHT, NAUTICAL_TWILIGHT,
+};
+use crate::locale::Locale;
+use crate::svg_common::{self, Canvas, CardLine, CompactCard};
+use crate::tides::Harmonics;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::prelude::*;
+use chrono::Duration;
+use reverse_geocoder::ReverseGeocoder;
+use std::fs;
+
+/// Mean length of a lunation, in days
+const SYNODIC_MONTH: f64 = 29.530589;
+
+const CHART_TOP: f64 = 300.0;
+
+/// The UTC instant of local midnight starting `date`, or the first hour after it that
+/// exists where daylight saving skips midnight
+fn local_midnight<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> DateTime<Utc> {
+    (0..3)
+        .find_map(|hour| {
+            tz.from_local_datetime(&date.and_hms_opt(hour, 0, 0)?)
+                .earliest()
+        })
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)))
+}
+
+/// The UTC span of a local day
+fn local_day<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let next = date.succ_opt().unwrap_or(date);
+    (local_midnight(tz, date), local_midnight(tz, next))
+}
+
+/// The first rise and first set in a list of crossings
+fn rise_and_set(crossings: &[Crossing]) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let first = |rising: bool| {
+        crossings
+            .iter()
+            .find(|crossing| crossing.rising == rising)
+            .map(|crossing| crossing.time)
+    };
+    (first(true), first(false))
+}
+
+/// Day length, e.g. "10h 54m"
+fn format_length(length: Duration) -> String {
+    format!("{}h {:02}m", length.num_hours(), length.num_minutes() % 60)
+}
+
+/// Change from yesterday, e.g. "2m 31s longer than yesterday"
+fn format_trend(change: Duration) -> String {
+    let seconds = change.num_seconds();
+    if seconds == 0 {
+        return "Same as yesterday".to_string();
+    }
+    let amount = if seconds.abs() >= 60 {
+        format!("{}m {:02}s", seconds.abs() / 60, seconds.abs() % 60)
+    } else {
+        format!("{}s", seconds.abs())
+    };
+    format!(
+        "{} {} than yesterday",
+        amount,
+        if seconds > 0 { "longer" } else { "shorter" }
+    )
+}
+
+fn moon_icon_file(phase: MoonPhase) -> &'static str {
+    match phase {
+        MoonPhase::New => "moon-new.svg",
+        MoonPhase::WaxingCrescent => "moon-waxing-crescent.svg",
+        MoonPhase::FirstQuarter => "moon-first-quarter.svg",
+        MoonPhase::WaxingGibbous => "moon-waxing-gibbous.svg",
+        MoonPhase::Full => "moon-full.svg",
+        MoonPhase::WaningGibbous => "moon-waning-gibbous.svg",
+        MoonPhase::LastQuarter => "moon-last-quarter.svg",
+        MoonPhase::WaningCrescent => "moon-waning-crescent.svg",
+    }
+}
+
+/// Loads an icon from the static set as a base64-encoded data URI
+fn load_icon_as_data_uri(icon_filename: &str) -> Result<String, std::io::Error> {
+    let icon_path = format!("assets/static/fill-svg-static/{}", icon_filename);
+    let svg_content = fs::read(&icon_path)?;
+    let encoded = general_purpose::STANDARD.encode(&svg_content);
+    Ok(format!("data:image/svg+xml;base64,{}", encoded))
+}
+
+fn icon_svg(file: &str, x: f64, y: f64, size: f64) -> String {
+    match load_icon_as_data_uri(file) {
+        Ok(data_uri) => {
+            format!(
+                r#"  <image x="{}" y="{}" width="{}" height="{}" href="{}"/>"#,
+                x, y, size, size, data_uri
+            ) + "\n"
+        }
+        Err(_) => String::new(),
+    }
+}
+
+/// An icon with a small label and a large time beside it
+fn event_svg(icon: &str, label: &str, time: Option<String>, x: f64, y: f64) -> String {
+    let mut svg = icon_svg(icon, x - 6.0, y - 6.0, 56.0);
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="14" fill="black">{}</text>"#,
+        x + 52.0,
+        y + 16.0,
+        label
+    ));
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" font-family="Arial" font-size="22" font-weight="bold" fill="black">{}</text>"#,
+        x + 52.0,
+        y + 40.0,
+        time.unwrap_or_else(|| "—".to_string())
+    ));
+    svg.push('\n');
+    svg
+}
+
+/// Hours of daylight on each day of `year`
+fn yearly_daylight<Tz: TimeZone>(tz: &Tz, year: i32, lat: f64, lon: f64) -> Vec<f64> {
+    let Some(first) = NaiveDate::from_ymd_opt(year, 1, 1) else {
+        return Vec::new();
+    };
+    first
+        .iter_days()
+        .take_while(|date| date.year() == year)
+        .map(|date| {
+            let (start, end) = local_day(tz, date);
+            astronomy::daylight(lat, lon, start, end).num_seconds() as f64 / 3600.0
+        })
+        .collect()
+}
+
+/// The year's day length with today marked, in the box `(x, y, width, height)`
+fn daylight_chart_svg(
+    hours: &[f64],
+    today: usize,
+    locale: Locale,
+    (x, y, width, height): (f64, f64, f64, f64),
+) -> String {
+    let mut svg = format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="16" font-weight="bold" fill="black">Daylight this year</text>"#,
+        x,
+        y - 8.0
+    );
+    svg.push('\n');
+    if hours.is_empty() {
+        return svg;
+    }
+    let chart_x = x + 30.0;
+    let chart_width = width - 30.0;
+    let day_x = |day: usize| chart_x + day as f64 / hours.len() as f64 * chart_width;
+    let hour_y = |h: f64| y + height - h / 24.0 * height;
+
+    let mut points = format!("{},{} ", chart_x, y + height);
+    for (day, &h) in hours.iter().enumerate() {
+        points.push_str(&format!("{:.1},{:.1} ", day_x(day), hour_y(h)));
+    }
+    points.push_str(&format!("{},{}", chart_x + chart_width, y + height));
+    svg.push_str(&format!(
+        r#"  <polygon points="{}" fill="gold" stroke="black" stroke-width="1.5"/>"#,
+        points
+    ));
+    svg.push_str(&format!(
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="1"/>"#,
+        chart_x, y, chart_width, height
+    ));
+    svg.push('\n');
+    svg.push_str(&svg_common::axis_minmax_labels(
+        chart_x - 4.0,
+        y + 8.0,
+        y + height,
+        "24h",
+        "0h",
+    ));
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" text-anchor="end" font-size="10" fill="black">12h</text>"#,
+        chart_x - 4.0,
+        hour_y(12.0) + 4.0
+    ));
+    svg.push('\n');
+
+    // Month initials along the bottom
+    let year_start = NaiveDate::from_ymd_opt(2001, 1, 1).expect("valid date");
+    for month in 1..=12 {
+        let Some(first) = NaiveDate::from_ymd_opt(2001, month, 1) else {
+            continue;
+        };
+        let day = (first - year_start).num_days() as usize;
+        let initial: String = locale
+            .month(&first)
+            .chars()
+            .take(1)
+            .flat_map(char::to_uppercase)
+            .collect();
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{}" text-anchor="middle" font-size="10" fill="black">{}</text>"#,
+            day_x(day) + chart_width / 24.0,
+            y + height + 12.0,
+            initial
+        ));
+    }
+    svg.push('\n');
+
+    // Today
+    if let Some(&h) = hours.get(today) {
+        svg.push_str(&format!(
+            r#"  <line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="red" stroke-width="2"/><circle cx="{x:.1}" cy="{:.1}" r="4" fill="red" stroke="black" stroke-width="1"/>"#,
+            y,
+            y + height,
+            hour_y(h),
+            x = day_x(today)
+        ));
+        svg.push('\n');
+    }
+    svg
+}
+
+/// The tide over the local day `[start, end)` with its highs and lows and the current
+/// time marked, in the box `(x, y, width, height)`
+#[allow(clippy::too_many_arguments)]
+fn tide_chart_svg<Tz: TimeZone>(
+    harmonics: &Harmonics,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    now: DateTime<Utc>,
+    tz: &Tz,
+    locale: Locale,
+    (x, y, width, height): (f64, f64, f64, f64),
+) -> String {
+    let mut svg = icon_svg("tide-high.svg", x - 8.0, y - 34.0, 36.0);
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="16" font-weight="bold" fill="black">Tides today</text>"#,
+        x + 28.0,
+        y - 8.0
+    ));
+    svg.push('\n');
+
+    let step = Duration::minutes(15);
+    let mut samples = Vec::new();
+    let mut time = start;
+    while time <= end {
+        samples.push((time, harmonics.height(time)));
+        time += step;
+    }
+    let extremes = harmonics.extremes(start, end);
+    let (low, high) = samples.iter().fold((f64::MAX, f64::MIN), |(low, high), s| {
+        (low.min(s.1), high.max(s.1))
+    });
+    let range = (high - low).max(0.1);
+    // Room above and below the curve for the labels
+    let (low, high) = (low - range * 0.4, high + range * 0.4);
+
+    let chart_x = x + 30.0;
+    let chart_width = width - 30.0;
+    let span = (end - start).num_seconds().max(1) as f64;
+    let time_x = |t: DateTime<Utc>| chart_x + (t - start).num_seconds() as f64 / span * chart_width;
+    let height_y = |h: f64| y + height - (h - low) / (high - low) * height;
+
+    svg.push_str(&format!(
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="1"/>"#,
+        chart_x, y, chart_width, height
+    ));
+    let points: Vec<String> = samples
+        .iter()
+        .map(|&(t, h)| format!("{:.1},{:.1}", time_x(t), height_y(h)))
+        .collect();
+    svg.push_str(&format!(
+        r#"<polyline points="{}" fill="none" stroke="blue" stroke-width="2.5"/>"#,
+        points.join(" ")
+    ));
+    svg.push('\n');
+    svg.push_str(&svg_common::axis_minmax_labels(
+        chart_x - 4.0,
+        y + 8.0,
+        y + height,
+        &format!("{:.0} {}", high, harmonics.units),
+        &format!("{:.0} {}", low, harmonics.units),
+    ));
+    svg.push('\n');
+
+    for extreme in &extremes {
+        let (px, py) = (time_x(extreme.time), height_y(extreme.height));
+        let label_y = if extreme.high { py - 16.0 } else { py + 14.0 };
+        svg.push_str(&format!(
+            r#"  <circle cx="{:.1}" cy="{:.1}" r="3" fill="black"/><text x="{:.1}" y="{:.1}" text-anchor="middle" font-size="10" fill="black">{}</text><text x="{:.1}" y="{:.1}" text-anchor="middle" font-size="10" font-weight="bold" fill="black">{}</text>"#,
+            px,
+            py,
+            px,
+            label_y,
+            locale.time(&extreme.time.with_timezone(tz)),
+            px,
+            label_y + 11.0,
+            harmonics.format_height(extreme.height)
+        ));
+        svg.push('\n');
+    }
+
+    // Hours along the bottom
+    for hour in [6, 12, 18] {
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{}" text-anchor="middle" font-size="10" fill="black">{}</text>"#,
+            chart_x + hour as f64 / 24.0 * chart_width,
+            y + height + 12.0,
+            locale.hour(hour)
+        ));
+    }
+    if start <= now && now < end {
+        svg.push_str(&format!(
+            r#"<line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="red" stroke-width="2"/>"#,
+            y,
+            y + height,
+            x = time_x(now)
+        ));
+    }
+    svg.push('\n');
+    svg
+}
+
+/// Sunrise and sunset as the headline, then day length, the moon and the rest of
+/// today's tides, without the charts
+#[allow(clippy::too_many_arguments)]
+fn compact_almanac_svg<Tz: TimeZone>(
+    lat: f64,
+    lon: f64,
+    tides: Option<&Harmonics>,
+    now: DateTime<Tz>,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let tz = now.timezone();
+    let now_utc = now.with_timezone(&Utc);
+    let today = now.date_naive();
+    let (day_start, day_end) = local_day(&tz, today);
+    let local_time = |time: Option<DateTime<Utc>>| {
+        time.map(|time| locale.time(&time.with_timezone(&tz)))
+            .unwrap_or_else(|| "—".to_string())
+    };
+
+    let mut card = CompactCard::new(
+        format!(
+            "{} · {}",
+            locale.long_date(&today),
+            geocoder.search((lat, lon)).record.name
+        ),
+        battery_pct,
+    );
+    let (sunrise, sunset) = rise_and_set(&astronomy::crossings(
+        Body::Sun,
+        None,
+        lat,
+        lon,
+        day_start,
+        day_end,
+    ));
+    card.headline = Some(CardLine::new(format!(
+        "{} – {}",
+        local_time(sunrise),
+        local_time(sunset)
+    )));
+    card.icon = load_icon_as_data_uri("sunrise.svg").ok();
+
+    let length = astronomy::daylight(lat, lon, day_start, day_end);
+    let (yesterday_start, yesterday_end) = local_day(&tz, today.pred_opt().unwrap_or(today));
+    let yesterday = astronomy::daylight(lat, lon, yesterday_start, yesterday_end);
+    card.lines
+        .push(CardLine::new("Day length").with_value(format_length(length)));
+    card.lines
+        .push(CardLine::new(format_trend(length - yesterday)));
+
+    let elongation = astronomy::moon_elongation(now_utc);
+    card.lines.push(
+        CardLine::new(MoonPhase::from_elongation(elongation).name()).with_value(format!(
+            "{:.0}%",
+            astronomy::moon_illumination(elongation) * 100.0
+        )),
+    );
+    let (moonrise, moonset) = rise_and_set(&astronomy::crossings(
+        Body::Moon,
+        None,
+        lat,
+        lon,
+        day_start,
+        day_end,
+    ));
+    card.lines
+        .push(CardLine::new("Moon rise / set").with_value(format!(
+            "{} / {}",
+            local_time(moonrise),
+            local_time(moonset)
+        )));
+    if let Some(harmonics) = tides {
+        for extreme in harmonics
+            .extremes(day_start, day_end)
+            .into_iter()
+            .filter(|extreme| extreme.time > now_utc)
+        {
+            card.lines.push(
+                CardLine::new(if extreme.high {
+                    "High tide"
+                } else {
+                    "Low tide"
+                })
+                .with_value(format!(
+                    "{} · {}",
+                    local_time(Some(extreme.time)),
+                    harmonics.format_height(extreme.height)
+                )),
+            );
+        }
+    }
+    card.svg(canvas)
+}
+
+/// Generates the almanac SVG
+///
+/// # Arguments
+/// * `lat`, `lon` - Where to compute for
+/// * `tides` - Harmonics for the tide chart; the daylight chart spans the width without
+/// * `now` - Current time in the zone to show times in
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `geocoder` - Reverse geocoder for the place name
+/// * `locale` - Date and clock format
+/// * `canvas` - Size to lay out for
+///
+/// # Returns
+/// A String containing the SVG markup
+#[allow(clippy::too_many_arguments)]
+pub fn generate_almanac_svg<Tz: TimeZone>(
+    lat: f64,
+    lon: f64,
+    tides: Option<&Harmonics>,
+    now: DateTime<Tz>,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let (width, height) = (canvas.width, canvas.height);
+    let tz = now.timezone();
+    let now_utc = now.with_timezone(&Utc);
+    let today = now.date_naive();
+    let (day_start, day_end) = local_day(&tz, today);
+    let local_time = |time: DateTime<Utc>| locale.time(&time.with_timezone(&tz));
+    if canvas.is_compact() {
+        return compact_almanac_svg(lat, lon, tides, now, battery_pct, geocoder, locale, canvas);
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    );
+    svg.push('\n');
+    svg.push_str("  <defs>");
+    svg.push_str(svg_common::BATTERY_GRADIENT_DEF);
+    svg.push_str("</defs>\n");
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+    svg.push('\n');
+
+    svg.push_str(
+        r#"  <text x="20" y="35" font-family="Arial" font-size="28" font-weight="bold" fill="black">Almanac</text>"#,
+    );
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <text x="20" y="58" font-family="Arial" font-size="16" fill="black">{} · {}</text>"#,
+        locale.long_date(&today),
+        svg_common::escape_xml_text(&geocoder.search((lat, lon)).record.name)
+    ));
+    svg.push('\n');
+
+    // Sun
+    let sun = astronomy::crossings(Body::Sun, None, lat, lon, day_start, day_end);
+    let (sunrise, sunset) = rise_and_set(&sun);
+    svg.push_str(&event_svg(
+        "sunrise.svg",
+        "Sunrise",
+        sunrise.map(local_time),
+        20.0,
+        75.0,
+    ));
+    svg.push_str(&event_svg(
+        "sunset.svg",
+        "Sunset",
+        sunset.map(local_time),
+        200.0,
+        75.0,
+    ));
+
+    let length = astronomy::daylight(lat, lon, day_start, day_end);
+    let (yesterday_start, yesterday_end) = local_day(&tz, today.pred_opt().unwrap_or(today));
+    let yesterday = astronomy::daylight(lat, lon, yesterday_start, yesterday_end);
+    svg.push_str(&format!(
+        r#"  <text x="20" y="150" font-family="Arial" font-size="18" fill="black">Day length <tspan font-weight="bold">{}</tspan></text>"#,
+        format_length(length)
+    ));
+    svg.push_str(&format!(
+        r#"<text x="20" y="170" font-family="Arial" font-size="14" font-style="italic" fill="black">{}</text>"#,
+        format_trend(length - yesterday)
+    ));
+    svg.push('\n');
+
+    svg.push_str(
+        r#"  <text x="20" y="198" font-family="Arial" font-size="14" font-weight="bold" fill="black">Twilight</text><text x="200" y="198" font-family="Arial" font-size="14" font-weight="bold" fill="black">Dawn</text><text x="290" y="198" font-family="Arial" font-size="14" font-weight="bold" fill="black">Dusk</text>"#,
+    );
+    svg.push('\n');
+    for (i, (name, altitude)) in [
+        ("Civil", CIVIL_TWILIGHT),
+        ("Nautical", NAUTICAL_TWILIGHT),
+        ("Astronomical", ASTRONOMICAL_TWILIGHT),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let y = 220.0 + i as f64 * 22.0;
+        let events = astronomy::crossings(Body::Sun, Some(altitude), lat, lon, day_start, day_end);
+        let (dawn, dusk) = rise_and_set(&events);
+        let format =
+            |time: Option<DateTime<Utc>>| time.map(local_time).unwrap_or_else(|| "—".to_string());
+        svg.push_str(&format!(
+            r#"  <text x="20" y="{y}" font-family="Arial" font-size="15" fill="black">{}</text><text x="200" y="{y}" font-family="Arial" font-size="15" fill="black">{}</text><text x="290" y="{y}" font-family="Arial" font-size="15" fill="black">{}</text>"#,
+            name,
+            format(dawn),
+            format(dusk),
+            y = y
+        ));
+        svg.push('\n');
+    }
+
+    // Moon
+    let moon_x = (width / 2) as f64 + 20.0;
+    let elongation = astronomy::moon_elongation(now_utc);
+    let phase = MoonPhase::from_elongation(elongation);
+    svg.push_str(&icon_svg(moon_icon_file(phase), moon_x - 10.0, 62.0, 96.0));
+    svg.push_str(&format!(
+        r#"  <text x="{x}" y="95" font-family="Arial" font-size="22" font-weight="bold" fill="black">{}</text><text x="{x}" y="118" font-family="Arial" font-size="16" fill="black">{:.0}% illuminated</text><text x="{x}" y="139" font-family="Arial" font-size="14" fill="black">{:.1} days old</text>"#,
+        phase.name(),
+        astronomy::moon_illumination(elongation) * 100.0,
+        elongation / 360.0 * SYNODIC_MONTH,
+        x = moon_x + 90.0
+    ));
+    svg.push('\n');
+
+    let moon = astronomy::crossings(Body::Moon, None, lat, lon, day_start, day_end);
+    let (moonrise, moonset) = rise_and_set(&moon);
+    svg.push_str(&event_svg(
+        "moonrise.svg",
+        "Moonrise",
+        moonrise.map(local_time),
+        moon_x,
+        155.0,
+    ));
+    svg.push_str(&event_svg(
+        "moonset.svg",
+        "Moonset",
+        moonset.map(local_time),
+        moon_x + 180.0,
+        155.0,
+    ));
+
+    // The next four principal phases, soonest first
+    let mut upcoming: Vec<(DateTime<Utc>, MoonPhase)> = [
+        (0.0, MoonPhase::New),
+        (90.0, MoonPhase::FirstQuarter),
+        (180.0, MoonPhase::Full),
+        (270.0, MoonPhase::LastQuarter),
+    ]
+    .into_iter()
+    .map(|(target, phase)| (astronomy::next_elongation(now_utc, target), phase))
+    .collect();
+    upcoming.sort_by_key(|&(time, _)| time);
+    for (i, (time, phase)) in upcoming.into_iter().enumerate() {
+        svg.push_str(&format!(
+            r#"  <text x="{}" y="{}" font-family="Arial" font-size="14" fill="black">{} <tspan font-weight="bold">{}</tspan></text>"#,
+            moon_x + (i % 2) as f64 * 180.0,
+            242.0 + (i / 2) as f64 * 22.0,
+            phase.name(),
+            locale.day_month(&time.with_timezone(&tz))
+        ));
+    }
+    svg.push('\n');
+
+    // Charts along the bottom
+    let chart_height = (height as f64 - CHART_TOP - 40.0).max(40.0);
+    let hours = yearly_daylight(&tz, today.year(), lat, lon);
+    let today_index = today.ordinal0() as usize;
+    match tides {
+        Some(harmonics) => {
+            let half = (width / 2) as f64;
+            svg.push_str(&tide_chart_svg(
+                harmonics,
+                day_start,
+                day_end,
+                now_utc,
+                &tz,
+                locale,
+                (20.0, CHART_TOP, half - 40.0, chart_height),
+            ));
+            svg.push_str(&daylight_chart_svg(
+                &hours,
+                today_index,
+                locale,
+                (half + 20.0, CHART_TOP, half - 40.0, chart_height),
+            ));
+        }
+        None => svg.push_str(&daylight_chart_svg(
+            &hours,
+            today_index,
+            locale,
+            (20.0, CHART_TOP, width as f64 - 40.0, chart_height),
+        )),
+    }
+
+    // Footer with battery and last updated
+    let footer_y = height - 10;
+    let pct = battery_pct.unwrap_or(50);
+    svg.push_str(&svg_common::battery_label_svg(
+        10.0,
+        footer_y as f64,
+        "start",
+        12,
+    ));
+    svg.push_str(&svg_common::battery_bar_svg(
+        75.0,
+        footer_y as f64 - 10.0,
+        pct,
+        2.0,
+        "batteryClip",
+    ));
+    svg.push('\n');
+    let updated = Local::now();
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">Last updated: {:02}:{:02}:{:02}</text>"#,
+        width - 10,
+        footer_y,
+        updated.hour(),
+        updated.minute(),
+        updated.second()
+    ));
+    svg.push('\n');
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_trend, local_day, yearly_daylight};
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+    use chrono_tz::America::New_York;
+
+    #[test]
+    fn days_follow_the_local_calendar() {
+        // The spring-forward day is 23 hours long
+        let (start, end) = local_day(&New_York, NaiveDate::from_ymd_opt(2025, 3, 9).unwrap());
+        assert_eq!(start, Utc.with_ymd_and_hms(2025, 3, 9, 5, 0, 0).unwrap());
+        assert_eq!(end - start, Duration::hours(23));
+
+        // New York's shortest day is about 9h15m and its longest about 15h05m
+        let hours = yearly_daylight(&New_York, 2025, 40.7128, -74.0060);
+        assert_eq!(hours.len(), 365);
+        let shortest = hours.iter().cloned().fold(f64::MAX, f64::min);
+        let longest = hours.iter().cloned().fold(f64::MIN, f64::max);
+        assert!((shortest - 9.25).abs() < 0.05, "{}", shortest);
+        assert!((longest - 15.08).abs() < 0.05, "{}", longest);
+
+        assert_eq!(
+            format_trend(Duration::seconds(151)),
+            "2m 31s longer than yesterday"
+        );
+        assert_eq!(
+            format_trend(Duration::seconds(-42)),
+            "42s shorter than yesterday"
+        );
+    }
+}
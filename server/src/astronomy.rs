@@ -0,0 +1,369 @@
+//! Sun and moon positions for the almanac screen, computed locally from the
+//! low-precision series in Meeus, "Astronomical Algorithms" (chapters 25 and 47).
+//! Rise, set and twilight times come out within a minute or two, and moon phases
+//! within the hour, which is plenty for a wall display.
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Altitude of the sun's center at sunrise and sunset, allowing for refraction and
+/// its radius
+pub const SUNRISE: f64 = -0.833;
+pub const CIVIL_TWILIGHT: f64 = -6.0;
+pub const NAUTICAL_TWILIGHT: f64 = -12.0;
+pub const ASTRONOMICAL_TWILIGHT: f64 = -18.0;
+
+/// Step for scanning a day for rises and sets; short enough that the moon cannot rise
+/// and set again within one
+const SCAN_STEP: Duration = Duration::minutes(10);
+
+fn julian_day(time: DateTime<Utc>) -> f64 {
+    time.timestamp() as f64 / 86400.0 + 2440587.5
+}
+
+/// Julian centuries since J2000.0
+fn centuries(time: DateTime<Utc>) -> f64 {
+    (julian_day(time) - 2451545.0) / 36525.0
+}
+
+fn sin_deg(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos_deg(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
+
+/// Right ascension and declination, in degrees
+#[derive(Debug, Clone, Copy)]
+struct Equatorial {
+    ra: f64,
+    dec: f64,
+}
+
+fn obliquity(t: f64) -> f64 {
+    23.439291 - 0.0130042 * t
+}
+
+fn to_equatorial(longitude: f64, latitude: f64, t: f64) -> Equatorial {
+    let e = obliquity(t);
+    let ra = (sin_deg(longitude) * cos_deg(e) - latitude.to_radians().tan() * sin_deg(e))
+        .atan2(cos_deg(longitude))
+        .to_degrees();
+    let dec = (sin_deg(latitude) * cos_deg(e)
+        + cos_deg(latitude) * sin_deg(e) * sin_deg(longitude))
+    .asin()
+    .to_degrees();
+    Equatorial { ra, dec }
+}
+
+/// Apparent ecliptic longitude of the sun, in degrees
+fn sun_longitude(t: f64) -> f64 {
+    let l0 = 280.46646 + 36000.76983 * t;
+    let m = 357.52911 + 35999.05029 * t;
+    let c = (1.914602 - 0.004817 * t) * sin_deg(m)
+        + 0.019993 * sin_deg(2.0 * m)
+        + 0.000289 * sin_deg(3.0 * m);
+    let omega = 125.04 - 1934.136 * t;
+    (l0 + c - 0.00569 - 0.00478 * sin_deg(omega)).rem_euclid(360.0)
+}
+
+/// Ecliptic longitude and latitude of the moon in degrees, and its distance in km
+fn moon_ecliptic(t: f64) -> (f64, f64, f64) {
+    let l = 218.3164477 + 481267.88123421 * t;
+    let d = 297.8501921 + 445267.1114034 * t;
+    let m = 357.5291092 + 35999.0502909 * t;
+    let mp = 134.9633964 + 477198.8675055 * t;
+    let f = 93.2720950 + 483202.0175233 * t;
+    let longitude = l
+        + 6.288774 * sin_deg(mp)
+        + 1.274027 * sin_deg(2.0 * d - mp)
+        + 0.658314 * sin_deg(2.0 * d)
+        + 0.213618 * sin_deg(2.0 * mp)
+        - 0.185116 * sin_deg(m)
+        - 0.114332 * sin_deg(2.0 * f)
+        + 0.058793 * sin_deg(2.0 * d - 2.0 * mp)
+        + 0.057066 * sin_deg(2.0 * d - m - mp)
+        + 0.053322 * sin_deg(2.0 * d + mp)
+        + 0.045758 * sin_deg(2.0 * d - m)
+        - 0.040923 * sin_deg(m - mp)
+        - 0.034720 * sin_deg(d)
+        - 0.030383 * sin_deg(m + mp);
+    let latitude = 5.128122 * sin_deg(f)
+        + 0.280602 * sin_deg(mp + f)
+        + 0.277693 * sin_deg(mp - f)
+        + 0.173237 * sin_deg(2.0 * d - f)
+        + 0.055413 * sin_deg(2.0 * d - mp + f)
+        + 0.046271 * sin_deg(2.0 * d - mp - f);
+    let distance = 385000.56
+        - 20905.355 * cos_deg(mp)
+        - 3699.111 * cos_deg(2.0 * d - mp)
+        - 2955.968 * cos_deg(2.0 * d)
+        - 569.925 * cos_deg(2.0 * mp);
+    (longitude.rem_euclid(360.0), latitude, distance)
+}
+
+/// Greenwich mean sidereal time, in degrees
+fn sidereal_time(time: DateTime<Utc>) -> f64 {
+    let d = julian_day(time) - 2451545.0;
+    let t = d / 36525.0;
+    (280.46061837 + 360.98564736629 * d + 0.000387933 * t * t).rem_euclid(360.0)
+}
+
+fn altitude(position: Equatorial, time: DateTime<Utc>, lat: f64, lon: f64) -> f64 {
+    let hour_angle = sidereal_time(time) + lon - position.ra;
+    (sin_deg(lat) * sin_deg(position.dec)
+        + cos_deg(lat) * cos_deg(position.dec) * cos_deg(hour_angle))
+    .asin()
+    .to_degrees()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Body {
+    Sun,
+    Moon,
+}
+
+impl Body {
+    /// Geocentric altitude of the center, in degrees
+    pub fn altitude(self, time: DateTime<Utc>, lat: f64, lon: f64) -> f64 {
+        let t = centuries(time);
+        let position = match self {
+            Body::Sun => to_equatorial(sun_longitude(t), 0.0, t),
+            Body::Moon => {
+                let (longitude, latitude, _) = moon_ecliptic(t);
+                to_equatorial(longitude, latitude, t)
+            }
+        };
+        altitude(position, time, lat, lon)
+    }
+
+    /// Altitude of the center when the upper limb touches the horizon. The moon's
+    /// depends on its distance through parallax.
+    fn horizon(self, time: DateTime<Utc>) -> f64 {
+        match self {
+            Body::Sun => SUNRISE,
+            Body::Moon => {
+                let (_, _, distance) = moon_ecliptic(centuries(time));
+                let parallax = (6378.14 / distance).asin().to_degrees();
+                0.7275 * parallax - 0.5667
+            }
+        }
+    }
+}
+
+/// A rise or set, or a twilight beginning or ending
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crossing {
+    pub time: DateTime<Utc>,
+    pub rising: bool,
+}
+
+/// Every time in `[start, end)` that `body` crosses `altitude`, or its rise and set
+/// horizon when `altitude` is `None`
+pub fn crossings(
+    body: Body,
+    altitude: Option<f64>,
+    lat: f64,
+    lon: f64,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<Crossing> {
+    let above = |time: DateTime<Utc>| {
+        body.altitude(time, lat, lon) - altitude.unwrap_or_else(|| body.horizon(time))
+    };
+    let mut found = Vec::new();
+    let mut time = start;
+    let mut value = above(time);
+    while time < end {
+        let next = (time + SCAN_STEP).min(end);
+        let next_value = above(next);
+        if (value < 0.0) != (next_value < 0.0) {
+            // Bisect down to a few seconds
+            let (mut low, mut high) = (time, next);
+            while high - low > Duration::seconds(5) {
+                let mid = low + (high - low) / 2;
+                if (above(mid) < 0.0) == (value < 0.0) {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            found.push(Crossing {
+                time: high,
+                rising: value < 0.0,
+            });
+        }
+        time = next;
+        value = next_value;
+    }
+    found
+}
+
+/// Time the sun is above the horizon in `[start, end)`, usually one day
+pub fn daylight(lat: f64, lon: f64, start: DateTime<Utc>, end: DateTime<Utc>) -> Duration {
+    let events = crossings(Body::Sun, None, lat, lon, start, end);
+    let mut up_since = (Body::Sun.altitude(start, lat, lon) > SUNRISE).then_some(start);
+    let mut total = Duration::zero();
+    for event in events {
+        match (event.rising, up_since) {
+            (true, _) => up_since = Some(event.time),
+            (false, Some(since)) => {
+                total += event.time - since;
+                up_since = None;
+            }
+            (false, None) => {}
+        }
+    }
+    if let Some(since) = up_since {
+        total += end - since;
+    }
+    total
+}
+
+/// Degrees the moon is east of the sun: 0 at new moon, 180 at full
+pub fn moon_elongation(time: DateTime<Utc>) -> f64 {
+    let t = centuries(time);
+    (moon_ecliptic(t).0 - sun_longitude(t)).rem_euclid(360.0)
+}
+
+/// Fraction of the disc that is lit
+pub fn moon_illumination(elongation: f64) -> f64 {
+    (1.0 - cos_deg(elongation)) / 2.0
+}
+
+/// The first time after `from` that the elongation reaches `target` degrees
+pub fn next_elongation(from: DateTime<Utc>, target: f64) -> DateTime<Utc> {
+    let behind = |time: DateTime<Utc>| (moon_elongation(time) - target).rem_euclid(360.0);
+    // Step until the distance behind the target wraps around, then bisect
+    let step = Duration::hours(6);
+    let mut low = from;
+    while behind(low + step) >= behind(low) {
+        low += step;
+    }
+    let mut high = low + step;
+    while high - low > Duration::minutes(1) {
+        let mid = low + (high - low) / 2;
+        if behind(mid) >= behind(low) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    high
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoonPhase {
+    New,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    Full,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+impl MoonPhase {
+    /// New, full and the quarters within a day either side of the exact moment, the
+    /// crescents and gibbous phases between
+    pub fn from_elongation(elongation: f64) -> Self {
+        // Degrees the elongation advances in a day
+        const DAY: f64 = 12.2;
+        let elongation = elongation.rem_euclid(360.0);
+        let near = |target: f64| {
+            let offset = (elongation - target).rem_euclid(360.0);
+            offset.min(360.0 - offset) <= DAY
+        };
+        if near(0.0) {
+            MoonPhase::New
+        } else if near(90.0) {
+            MoonPhase::FirstQuarter
+        } else if near(180.0) {
+            MoonPhase::Full
+        } else if near(270.0) {
+            MoonPhase::LastQuarter
+        } else if elongation < 90.0 {
+            MoonPhase::WaxingCrescent
+        } else if elongation < 180.0 {
+            MoonPhase::WaxingGibbous
+        } else if elongation < 270.0 {
+            MoonPhase::WaningGibbous
+        } else {
+            MoonPhase::WaningCrescent
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MoonPhase::New => "New Moon",
+            MoonPhase::WaxingCrescent => "Waxing Crescent",
+            MoonPhase::FirstQuarter => "First Quarter",
+            MoonPhase::WaxingGibbous => "Waxing Gibbous",
+            MoonPhase::Full => "Full Moon",
+            MoonPhase::WaningGibbous => "Waning Gibbous",
+            MoonPhase::LastQuarter => "Last Quarter",
+            MoonPhase::WaningCrescent => "Waning Crescent",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        crossings, daylight, moon_elongation, moon_illumination, next_elongation, Body, MoonPhase,
+        CIVIL_TWILIGHT,
+    };
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    fn assert_near(actual: DateTime<Utc>, expected: DateTime<Utc>, minutes: i64) {
+        assert!(
+            (actual - expected).num_minutes().abs() <= minutes,
+            "{} is not within {} minutes of {}",
+            actual,
+            minutes,
+            expected
+        );
+    }
+
+    #[test]
+    fn sun_and_moon_events_match_published_times() {
+        // Greenwich on the June solstice: sunrise 03:43 and sunset 20:21 UTC
+        let (lat, lon) = (51.4769, 0.0);
+        let (start, end) = (utc(2025, 6, 21, 0, 0), utc(2025, 6, 22, 0, 0));
+        let sun = crossings(Body::Sun, None, lat, lon, start, end);
+        assert_eq!(sun.len(), 2);
+        assert!(sun[0].rising && !sun[1].rising);
+        assert_near(sun[0].time, utc(2025, 6, 21, 3, 43), 2);
+        assert_near(sun[1].time, utc(2025, 6, 21, 20, 21), 2);
+        let length = daylight(lat, lon, start, end);
+        assert!(
+            (length - Duration::minutes(16 * 60 + 38))
+                .num_minutes()
+                .abs()
+                <= 3
+        );
+        // Civil dusk is 47 minutes after sunset, and astronomical twilight never ends
+        let civil = crossings(Body::Sun, Some(CIVIL_TWILIGHT), lat, lon, start, end);
+        assert_near(civil[1].time, utc(2025, 6, 21, 21, 8), 3);
+        assert!(crossings(Body::Sun, Some(-18.0), lat, lon, start, end).is_empty());
+
+        // The total lunar eclipse of 2025-03-14 and the new moon after it
+        let full = next_elongation(utc(2025, 3, 1, 0, 0), 180.0);
+        assert_near(full, utc(2025, 3, 14, 6, 55), 60);
+        assert!(moon_illumination(moon_elongation(full)) > 0.999);
+        let new = next_elongation(full, 0.0);
+        assert_near(new, utc(2025, 3, 29, 10, 58), 60);
+        assert_eq!(
+            MoonPhase::from_elongation(moon_elongation(utc(2025, 3, 18, 0, 0))),
+            MoonPhase::WaningGibbous
+        );
+        // A third lit is still a crescent, two days before first quarter
+        assert_eq!(MoonPhase::from_elongation(71.0), MoonPhase::WaxingCrescent);
+        assert_eq!(MoonPhase::from_elongation(355.0), MoonPhase::New);
+    }
+}
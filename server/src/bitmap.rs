@@ -1,32 +1,18 @@
 //! E-ink display bitmap generator
 //!
 //! Generates raw bitmaps in the native format for GxEPD2 7-color displays
-//! Format: EPBM header + raw pixel data (1 byte per pixel)
-
-/// Approximate RGB values for E Ink Spectra 6 pigments
-const PALETTE: [[u8; 3]; 6] = [
-    [0, 0, 0],       // Black
-    [255, 255, 255], // White
-    [0, 255, 0],     // Green
-    [0, 0, 255],     // Blue
-    [255, 0, 0],     // Red (Spectra Red is fairly pure)
-    [255, 255, 0],   // Yellow
-];
-
-/// Pre-computed CIELAB values for the palette (for faster color matching)
-/// Computed from the RGB values above using D65 illuminant
-const PALETTE_LAB: [[f32; 3]; 6] = [
-    [0.0, 0.0, 0.0],         // Black: L=0
-    [100.0, 0.0, 0.0],       // White: L=100
-    [87.73, -86.18, 83.18],  // Green
-    [32.30, 79.19, -107.86], // Blue
-    [53.24, 80.09, 67.20],   // Red
-    [97.14, -21.55, 94.48],  // Yellow
-];
-
-/// E-ink display color palette (E Ink Spectra 6)
+//! Format: EPBM header + raw pixel data (1 byte per pixel), or EPBM v2 (magic "EPB2")
+//! with packed 4-bit or run-length encoded pixels for clients that ask for it
+
+use crate::dither::{dither_pixmap, DitherMode};
+use crate::palette::DitherPalette;
+use crate::panel::PaletteColor;
+
+/// E-ink display color palette (E Ink Spectra 6), as positions in the seed-e1002
+/// panel palette. Black and White are at the same positions in every panel palette.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
 pub enum EpdColor {
     Black = 0,
     White = 1,
@@ -54,11 +40,32 @@ impl EpdBitmap {
         }
     }
 
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
     /// Set pixel at (x, y) to given color
     pub fn set_pixel(&mut self, x: u16, y: u16, color: EpdColor) {
+        self.set_index(x, y, color as u8);
+    }
+
+    /// Set pixel at (x, y) to a raw palette index
+    pub fn set_index(&mut self, x: u16, y: u16, index: u8) {
         if x < self.width && y < self.height {
-            let index = y as usize * self.width as usize + x as usize;
-            self.data[index] = color as u8;
+            self.data[y as usize * self.width as usize + x as usize] = index;
+        }
+    }
+
+    /// Palette index at (x, y); White outside the bitmap
+    pub fn get_index(&self, x: u16, y: u16) -> u8 {
+        if x < self.width && y < self.height {
+            self.data[y as usize * self.width as usize + x as usize]
+        } else {
+            EpdColor::White as u8
         }
     }
 
@@ -81,31 +88,136 @@ impl EpdBitmap {
 
         bytes
     }
+
+    /// Write bitmap in the requested encoding, or a smaller one when it doesn't pay off:
+    /// `Rle` falls back to `Packed4` for busy images, and `Packed4` to `Raw` for tiny
+    /// ones. `Raw` produces the v1 format above; the others produce v2: "EPB2" magic +
+    /// width + height + version byte (2) + encoding byte + pixel data. The magic tells
+    /// the versions apart, since a v2 body can be the same length as a v1 one.
+    pub fn to_bytes_encoded(&self, encoding: EpbmEncoding) -> Vec<u8> {
+        match encoding {
+            EpbmEncoding::Raw => self.to_bytes(),
+            EpbmEncoding::Packed4 => {
+                let packed = self.to_v2_bytes(EpbmEncoding::Packed4);
+                if packed.len() < 8 + self.data.len() {
+                    packed
+                } else {
+                    self.to_bytes()
+                }
+            }
+            EpbmEncoding::Rle => {
+                let rle = self.to_v2_bytes(EpbmEncoding::Rle);
+                let fallback = self.to_bytes_encoded(EpbmEncoding::Packed4);
+                if rle.len() < fallback.len() {
+                    rle
+                } else {
+                    fallback
+                }
+            }
+        }
+    }
+
+    fn to_v2_bytes(&self, encoding: EpbmEncoding) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(10 + self.data.len() / 2);
+        bytes.extend_from_slice(b"EPB2");
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.push(2);
+        bytes.push(encoding as u8);
+
+        match encoding {
+            EpbmEncoding::Raw => bytes.extend_from_slice(&self.data),
+            EpbmEncoding::Packed4 => {
+                // Two pixels per byte, first pixel in the high nibble
+                for pair in self.data.chunks(2) {
+                    let hi = pair[0];
+                    let lo = pair.get(1).copied().unwrap_or(EpdColor::White as u8);
+                    bytes.push(hi << 4 | lo);
+                }
+            }
+            EpbmEncoding::Rle => {
+                // One byte per run: (length - 1) in the top 5 bits, color in the low 3 bits.
+                // Runs continue across row boundaries and are at most 32 pixels long.
+                let mut pixels = self.data.iter().peekable();
+                while let Some(&color) = pixels.next() {
+                    let mut run = 1u8;
+                    while run < 32 && pixels.peek() == Some(&&color) {
+                        pixels.next();
+                        run += 1;
+                    }
+                    bytes.push((run - 1) << 3 | color);
+                }
+            }
+        }
+
+        bytes
+    }
+}
+
+impl EpdBitmap {
+    /// Encode as PNG, mapping each palette position back to its approximate pigment color
+    /// (`DitherPalette::preview_rgb`). Shows the dithered output as the panel will display it.
+    pub fn to_png(&self, colors: &[[u8; 3]]) -> Result<Vec<u8>, String> {
+        let mut pixmap = tiny_skia::Pixmap::new(self.width as u32, self.height as u32)
+            .ok_or("Failed to create pixmap")?;
+        let white = colors[EpdColor::White as usize];
+        for (pixel, &index) in pixmap.pixels_mut().iter_mut().zip(&self.data) {
+            let [r, g, b] = colors.get(index as usize).copied().unwrap_or(white);
+            *pixel = tiny_skia::ColorU8::from_rgba(r, g, b, 255).premultiply();
+        }
+        pixmap
+            .encode_png()
+            .map_err(|e| format!("Failed to encode PNG: {}", e))
+    }
 }
 
-/// Generate a test pattern bitmap with color bars
-pub fn generate_test_bitmap(width: u16, height: u16) -> EpdBitmap {
+/// Pixel data encoding for EPBM responses, negotiated per request
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpbmEncoding {
+    /// 1 byte per pixel (v1, the default)
+    Raw = 0,
+    /// 4 bits per pixel
+    Packed4 = 1,
+    /// Run-length encoded, 3-bit color + 5-bit run length per byte
+    Rle = 2,
+}
+
+impl EpbmEncoding {
+    /// Parse the name used in `?format=` and in the `Accept` header's `encoding=` parameter
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "raw" => Some(Self::Raw),
+            "packed4" => Some(Self::Packed4),
+            "rle" => Some(Self::Rle),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Packed4 => "packed4",
+            Self::Rle => "rle",
+        }
+    }
+}
+
+/// Generate a test pattern bitmap with one color bar per palette entry
+pub fn generate_test_bitmap(width: u16, height: u16, palette: &[PaletteColor]) -> EpdBitmap {
     let mut bitmap = EpdBitmap::new(width, height);
 
-    // Color bars - each 1/6th of the width
-    let bar_width = width / 6;
-    let colors = [
-        EpdColor::Black,
-        EpdColor::White,
-        EpdColor::Green,
-        EpdColor::Blue,
-        EpdColor::Red,
-        EpdColor::Yellow,
-    ];
+    // Color bars - each 1/Nth of the width
+    let bar_width = width / palette.len() as u16;
 
     // Draw horizontal color bars in top half
-    for (i, &color) in colors.iter().enumerate() {
+    for i in 0..palette.len() {
         let x1 = i as u16 * bar_width;
         let x2 = ((i + 1) as u16 * bar_width).min(width);
 
         for y in 0..height / 2 {
             for x in x1..x2 {
-                bitmap.set_pixel(x, y, color);
+                bitmap.set_index(x, y, i as u8);
             }
         }
     }
@@ -122,214 +234,23 @@ pub fn generate_test_bitmap(width: u16, height: u16) -> EpdBitmap {
 
 /// Generate weather display bitmap
 #[allow(dead_code)]
-pub fn generate_weather_bitmap(width: u16, height: u16, _weather_data: &str) -> EpdBitmap {
+pub fn generate_weather_bitmap(
+    width: u16,
+    height: u16,
+    palette: &[PaletteColor],
+    _weather_data: &str,
+) -> EpdBitmap {
     // For now, just generate a test pattern
     // TODO: Integrate with weather data and render forecast
-    generate_test_bitmap(width, height)
-}
-
-/// Convert e-ink display color to approximate RGB values
-#[allow(dead_code)]
-fn epd_color_to_rgb(color: EpdColor) -> (u8, u8, u8) {
-    let idx = color as usize;
-    (PALETTE[idx][0], PALETTE[idx][1], PALETTE[idx][2])
-}
-
-/// Convert sRGB (0-255) to linear RGB (0.0-1.0)
-/// Applies inverse gamma correction
-fn srgb_to_linear(c: u8) -> f32 {
-    let c = c as f32 / 255.0;
-    if c <= 0.04045 {
-        c / 12.92
-    } else {
-        ((c + 0.055) / 1.055).powf(2.4)
-    }
+    generate_test_bitmap(width, height, palette)
 }
 
-/// Convert linear RGB to XYZ color space (D65 illuminant)
-fn rgb_to_xyz(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
-    let r_lin = srgb_to_linear(r);
-    let g_lin = srgb_to_linear(g);
-    let b_lin = srgb_to_linear(b);
-
-    // sRGB to XYZ matrix (D65)
-    let x = r_lin * 0.4124564 + g_lin * 0.3575761 + b_lin * 0.1804375;
-    let y = r_lin * 0.2126729 + g_lin * 0.7151522 + b_lin * 0.0721750;
-    let z = r_lin * 0.0193339 + g_lin * 0.119_192 + b_lin * 0.9503041;
-
-    (x * 100.0, y * 100.0, z * 100.0) // Scale to 0-100 range
-}
-
-/// XYZ to CIELAB conversion helper function
-fn xyz_to_lab_component(t: f32) -> f32 {
-    const DELTA: f32 = 6.0 / 29.0;
-    if t > DELTA * DELTA * DELTA {
-        t.cbrt()
-    } else {
-        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
-    }
-}
-
-/// Convert XYZ to CIELAB color space (D65 illuminant)
-/// Returns (L*, a*, b*) where L* is lightness (0-100), a* and b* are color components
-fn xyz_to_lab(x: f32, y: f32, z: f32) -> (f32, f32, f32) {
-    // D65 reference white point
-    const XN: f32 = 95.047;
-    const YN: f32 = 100.000;
-    const ZN: f32 = 108.883;
-
-    let fx = xyz_to_lab_component(x / XN);
-    let fy = xyz_to_lab_component(y / YN);
-    let fz = xyz_to_lab_component(z / ZN);
-
-    let l = 116.0 * fy - 16.0;
-    let a = 500.0 * (fx - fy);
-    let b = 200.0 * (fy - fz);
-
-    (l, a, b)
-}
-
-/// Convert RGB to CIELAB color space
-fn rgb_to_lab(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
-    let (x, y, z) = rgb_to_xyz(r, g, b);
-    xyz_to_lab(x, y, z)
-}
-
-/// Calculate perceptual color difference in CIELAB space (Delta E)
-fn delta_e(l1: f32, a1: f32, b1: f32, l2: f32, a2: f32, b2: f32) -> f32 {
-    let dl = l1 - l2;
-    let da = a1 - a2;
-    let db = b1 - b2;
-    (dl * dl + da * da + db * db).sqrt()
-}
-
-/// Convert RGB to HSL color space
-/// Returns (hue [0-360], saturation [0-1], lightness [0-1])
-fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
-    let r = r as f32 / 255.0;
-    let g = g as f32 / 255.0;
-    let b = b as f32 / 255.0;
-
-    let max = r.max(g).max(b);
-    let min = r.min(g).min(b);
-    let delta = max - min;
-
-    // Lightness
-    let l = (max + min) / 2.0;
-
-    // Saturation
-    let s = if delta == 0.0 {
-        0.0
-    } else {
-        delta / (1.0 - (2.0 * l - 1.0).abs())
-    };
-
-    // Hue
-    let h = if delta == 0.0 {
-        0.0
-    } else if max == r {
-        60.0 * (((g - b) / delta) % 6.0)
-    } else if max == g {
-        60.0 * (((b - r) / delta) + 2.0)
-    } else {
-        60.0 * (((r - g) / delta) + 4.0)
-    };
-
-    let h = if h < 0.0 { h + 360.0 } else { h };
-
-    (h, s, l)
-}
-
-/// Convert HSL to RGB color space
-/// Takes (hue [0-360], saturation [0-1], lightness [0-1])
-/// Returns (r, g, b) in [0-255]
-fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
-    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
-    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
-    let m = l - c / 2.0;
-
-    let (r, g, b) = if h < 60.0 {
-        (c, x, 0.0)
-    } else if h < 120.0 {
-        (x, c, 0.0)
-    } else if h < 180.0 {
-        (0.0, c, x)
-    } else if h < 240.0 {
-        (0.0, x, c)
-    } else if h < 300.0 {
-        (x, 0.0, c)
-    } else {
-        (c, 0.0, x)
-    };
-
-    (
-        ((r + m) * 255.0).round().clamp(0.0, 255.0) as u8,
-        ((g + m) * 255.0).round().clamp(0.0, 255.0) as u8,
-        ((b + m) * 255.0).round().clamp(0.0, 255.0) as u8,
-    )
-}
-
-/// Boost saturation to compensate for e-paper's less vivid pigments
-/// Increases saturation by the given factor (e.g., 1.2 for 20% boost)
-/// This helps the dithering algorithm favor primary colors over black/white
-fn boost_saturation(r: u8, g: u8, b: u8, boost_factor: f32) -> (u8, u8, u8) {
-    let (h, s, l) = rgb_to_hsl(r, g, b);
-
-    // Boost saturation, clamping to [0, 1]
-    let s_boosted = (s * boost_factor).min(1.0);
-
-    hsl_to_rgb(h, s_boosted, l)
-}
-
-/// Atkinson dithering error diffusion pattern
-/// Diffuses only 6/8 (75%) of error to neighbors, absorbing the rest
-/// Pattern (fractions of error distributed):
-///       X   1/8 1/8
-///   1/8 1/8 1/8
-///       1/8
-const ATKINSON_KERNEL: [(i32, i32, f32); 6] = [
-    (1, 0, 1.0 / 8.0),  // Right
-    (2, 0, 1.0 / 8.0),  // Right + 1
-    (-1, 1, 1.0 / 8.0), // Below left
-    (0, 1, 1.0 / 8.0),  // Below
-    (1, 1, 1.0 / 8.0),  // Below right
-    (0, 2, 1.0 / 8.0),  // Below + 1
-];
-
-/// Map RGB color to nearest e-ink display color using perceptual color matching
-/// This function finds the closest color without dithering (used by error diffusion)
-fn rgb_to_epd_color(r: u8, g: u8, b: u8) -> EpdColor {
-    // Pre-process: Boost saturation by 20% to compensate for e-paper's less vivid pigments
-    let (r, g, b) = boost_saturation(r, g, b, 1.2);
-
-    // Convert input RGB to CIELAB
-    let (l, a, b) = rgb_to_lab(r, g, b);
-
-    // Calculate perceptual distances to all palette colors
-    let colors = [
-        EpdColor::Black,
-        EpdColor::White,
-        EpdColor::Green,
-        EpdColor::Blue,
-        EpdColor::Red,
-        EpdColor::Yellow,
-    ];
-
-    colors
-        .iter()
-        .enumerate()
-        .map(|(idx, &color)| {
-            let lab = PALETTE_LAB[idx];
-            let dist = delta_e(l, a, b, lab[0], lab[1], lab[2]);
-            (color, dist)
-        })
-        .min_by(|a, b| a.1.total_cmp(&b.1))
-        .map(|(color, _)| color)
-        .unwrap_or(EpdColor::White)
-}
-
-/// Render SVG bytes to e-ink bitmap using Atkinson error diffusion dithering
-pub fn render_svg_to_bitmap(svg_data: &[u8], width: u16, height: u16) -> Result<EpdBitmap, String> {
+/// Rasterize SVG bytes to a pixmap of the given size on a white background
+pub fn rasterize_svg(
+    svg_data: &[u8],
+    width: u16,
+    height: u16,
+) -> Result<tiny_skia::Pixmap, String> {
     // Parse SVG with font configuration
     let mut opts = usvg::Options::default();
 
@@ -364,77 +285,39 @@ pub fn render_svg_to_bitmap(svg_data: &[u8], width: u16, height: u16) -> Result<
     // Render SVG to pixmap
     resvg::render(&tree, transform, &mut pixmap.as_mut());
 
-    // Save debug PNG to see what was rendered (debug builds only)
-    #[cfg(debug_assertions)]
-    if let Err(e) = pixmap.save_png("debug_render.png") {
-        eprintln!("Warning: Could not save debug PNG: {}", e);
-    }
+    Ok(pixmap)
+}
 
-    // Convert pixmap to e-ink bitmap with Atkinson error diffusion dithering
-    let mut bitmap = EpdBitmap::new(width, height);
+/// Render SVG bytes to e-ink bitmap using the given dithering mode and palette.
+/// Pixels are palette positions; see `Panel::to_native` for the driver's values.
+pub fn render_svg_to_bitmap(
+    svg_data: &[u8],
+    width: u16,
+    height: u16,
+    dither: DitherMode,
+    palette: &DitherPalette,
+) -> Result<EpdBitmap, String> {
+    let pixmap = rasterize_svg(svg_data, width, height)?;
+
+    // Save the rendered PNG to see what was drawn, when DEBUG_RENDER names a file for it
+    if let Some(path) = std::env::var_os("DEBUG_RENDER") {
+        if let Err(e) = pixmap.save_png(&path) {
+            eprintln!("Warning: Could not save debug PNG: {}", e);
+        }
+    }
 
-    // Create error buffer for Atkinson dithering (stores RGB error values)
-    let mut errors = vec![vec![(0.0f32, 0.0f32, 0.0f32); width as usize]; height as usize];
+    let bitmap = dither_pixmap(&pixmap, dither, palette);
 
     // Track color usage for debugging
     let mut color_counts = std::collections::HashMap::new();
-
-    // Process pixels in raster order for error diffusion
-    for y in 0..height {
-        for x in 0..width {
-            let pixel = pixmap
-                .pixel(x as u32, y as u32)
-                .ok_or("Failed to get pixel")?;
-
-            // Get accumulated error from previous pixels
-            let (err_r, err_g, err_b) = errors[y as usize][x as usize];
-
-            // Add error to pixel value
-            let r = (pixel.red() as f32 + err_r).clamp(0.0, 255.0) as u8;
-            let g = (pixel.green() as f32 + err_g).clamp(0.0, 255.0) as u8;
-            let b = (pixel.blue() as f32 + err_b).clamp(0.0, 255.0) as u8;
-
-            // Find closest e-ink color
-            let color = rgb_to_epd_color(r, g, b);
-
-            // Get the actual RGB values of the chosen color
-            let (cr, cg, cb) = epd_color_to_rgb(color);
-
-            // Calculate quantization error
-            let quant_err_r = r as f32 - cr as f32;
-            let quant_err_g = g as f32 - cg as f32;
-            let quant_err_b = b as f32 - cb as f32;
-
-            // Distribute error to neighboring pixels using Atkinson kernel
-            for &(dx, dy, weight) in &ATKINSON_KERNEL {
-                let nx = x as i32 + dx;
-                let ny = y as i32 + dy;
-
-                if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
-                    let err = &mut errors[ny as usize][nx as usize];
-                    err.0 += quant_err_r * weight;
-                    err.1 += quant_err_g * weight;
-                    err.2 += quant_err_b * weight;
-                }
-            }
-
-            *color_counts.entry(color as u8).or_insert(0) += 1;
-            bitmap.set_pixel(x, y, color);
-        }
+    for &color in &bitmap.data {
+        *color_counts.entry(color).or_insert(0) += 1;
     }
 
     // Print color statistics
     println!("Color usage in converted bitmap:");
-    for (color_val, count) in color_counts.iter() {
-        let color_name = match color_val {
-            0 => "Black",
-            1 => "White",
-            2 => "Green",
-            3 => "Blue",
-            4 => "Red",
-            5 => "Yellow",
-            _ => "Unknown",
-        };
+    for (&color_val, count) in color_counts.iter() {
+        let color_name = palette.name(color_val).unwrap_or("Unknown");
         println!(
             "  {}: {} pixels ({:.2}%)",
             color_name,
@@ -445,3 +328,80 @@ pub fn render_svg_to_bitmap(svg_data: &[u8], width: u16, height: u16) -> Result<
 
     Ok(bitmap)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{EpbmEncoding, EpdBitmap, EpdColor};
+    use crate::palette::DitherPalette;
+    use crate::panel::DEFAULT_PANEL;
+
+    #[test]
+    fn raw_encoding_is_unchanged_v1() {
+        let bitmap = EpdBitmap::new(4, 2);
+        assert_eq!(
+            bitmap.to_bytes_encoded(EpbmEncoding::Raw),
+            bitmap.to_bytes()
+        );
+        assert_eq!(bitmap.to_bytes().len(), 8 + 4 * 2);
+    }
+
+    #[test]
+    fn packed4_puts_first_pixel_in_high_nibble() {
+        let mut bitmap = EpdBitmap::new(7, 1);
+        bitmap.set_pixel(0, 0, EpdColor::Red);
+        bitmap.set_pixel(1, 0, EpdColor::Black);
+        bitmap.set_pixel(2, 0, EpdColor::Yellow);
+
+        let bytes = bitmap.to_bytes_encoded(EpbmEncoding::Packed4);
+        assert_eq!(&bytes[..10], b"EPB2\x00\x07\x00\x01\x02\x01");
+        // Odd pixel count is padded with white
+        assert_eq!(&bytes[10..], &[0x40, 0x51, 0x11, 0x11]);
+    }
+
+    #[test]
+    fn rle_splits_long_runs_and_changes_of_color() {
+        let mut bitmap = EpdBitmap::new(40, 1);
+        bitmap.set_pixel(39, 0, EpdColor::Blue);
+
+        let bytes = bitmap.to_bytes_encoded(EpbmEncoding::Rle);
+        assert_eq!(&bytes[8..10], &[2, EpbmEncoding::Rle as u8]);
+        // 39 white = 32 + 7, then 1 blue
+        assert_eq!(&bytes[10..], &[31 << 3 | 1, 6 << 3 | 1, 3]);
+
+        // A blank panel compresses by well over 10x
+        let blank = EpdBitmap::new(800, 480).to_bytes_encoded(EpbmEncoding::Rle);
+        assert_eq!(blank.len(), 10 + 800 * 480 / 32);
+
+        // Alternating colors don't compress as runs, so they go out packed
+        let mut busy = EpdBitmap::new(40, 2);
+        for x in (0..40).step_by(2) {
+            busy.set_pixel(x, 0, EpdColor::Black);
+            busy.set_pixel(x + 1, 1, EpdColor::Red);
+        }
+        let bytes = busy.to_bytes_encoded(EpbmEncoding::Rle);
+        assert_eq!(&bytes[8..10], &[2, EpbmEncoding::Packed4 as u8]);
+        assert_eq!(bytes.len(), 10 + 40);
+        // Packing a single pixel saves nothing, so it stays v1
+        let tiny = EpdBitmap::new(1, 1).to_bytes_encoded(EpbmEncoding::Rle);
+        assert_eq!(tiny, EpdBitmap::new(1, 1).to_bytes());
+    }
+
+    #[test]
+    fn png_preview_uses_palette_colors() {
+        let mut bitmap = EpdBitmap::new(2, 1);
+        bitmap.set_pixel(1, 0, EpdColor::Red);
+
+        let png = tiny_skia::Pixmap::decode_png(
+            &bitmap
+                .to_png(&DitherPalette::from_panel(DEFAULT_PANEL, 1.2).preview_rgb())
+                .unwrap(),
+        )
+        .unwrap();
+        let rgb = |x| {
+            let p = png.pixel(x, 0).unwrap();
+            (p.red(), p.green(), p.blue())
+        };
+        assert_eq!(rgb(0), (255, 255, 255));
+        assert_eq!(rgb(1), (255, 0, 0));
+    }
+}
@@ -0,0 +1,565 @@
+//! In-memory cache for upstream data and the EPBM bitmaps rendered from it.
+//! Several panels wake at the same hour; without this every hit re-fetched from the
+//! upstream API and re-ran the SVG render + dither.
+//!
+//! Entries are keyed by endpoint plus the query parameters that affect the fetched
+//! data (coordinates, FRED date range, weight user). Parameters that only affect
+//! rendering (battery level) select one of the bitmaps stored under that entry.
+//!
+//! The last good data for each key is also written to disk, so that an upstream
+//! outage right after a restart still has something better than a test pattern.
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct Entry<T> {
+    data: Arc<T>,
+    fetched_at: Instant,
+    /// Wall-clock fetch time, shown on the panel when this entry is served stale.
+    fetched_at_utc: DateTime<Utc>,
+    /// Rendered EPBM bytes for this data, keyed by render-only parameters.
+    bitmaps: HashMap<String, Bytes>,
+    /// Set while a background stale-while-revalidate refresh is in flight.
+    refreshing: bool,
+}
+
+/// On-disk form of the last good fetch for one key.
+#[derive(Serialize, Deserialize)]
+struct Persisted<T> {
+    fetched_at: DateTime<Utc>,
+    data: T,
+}
+
+/// Result of a cache lookup.
+pub enum Snapshot<T> {
+    /// Data within its TTL (or being refreshed in the background).
+    Current(Arc<T>),
+    /// The upstream fetch failed; `data` is the last successful fetch.
+    LastGood {
+        data: Arc<T>,
+        fetched_at: DateTime<Utc>,
+        error: String,
+    },
+}
+
+impl<T> Snapshot<T> {
+    /// The data, whether current or last good.
+    pub fn data(&self) -> &T {
+        match self {
+            Snapshot::Current(data) | Snapshot::LastGood { data, .. } => data,
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        matches!(self, Snapshot::Current(_))
+    }
+}
+
+/// Per-data-source cache with a fixed TTL.
+///
+/// Entries younger than `ttl` are served as-is. Entries older than `ttl` but younger
+/// than `2 * ttl` are served immediately while a single background refresh replaces
+/// them (stale-while-revalidate). Anything older is re-fetched inline.
+pub struct SourceCache<T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry<T>>>,
+    /// Serializes inline fetches of each key so panels waking together share one upstream
+    /// call, without a slow fetch holding up the other keys.
+    fetch_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// Directory holding one JSON file per key with the last good data, if persistence is on.
+    persist_dir: Option<PathBuf>,
+}
+
+enum Lookup<T> {
+    Fresh(Arc<T>),
+    Stale(Arc<T>),
+    Miss,
+}
+
+impl<T: Serialize + DeserializeOwned + Send + Sync + 'static> SourceCache<T> {
+    /// Memory-only cache.
+    #[allow(dead_code)]
+    pub fn new(ttl: Duration) -> Arc<Self> {
+        Self::with_persist_dir(ttl, None)
+    }
+
+    /// Cache that also keeps the last good data for each key under `dir`.
+    pub fn persistent(ttl: Duration, dir: impl Into<PathBuf>) -> Arc<Self> {
+        Self::with_persist_dir(ttl, Some(dir.into()))
+    }
+
+    fn with_persist_dir(ttl: Duration, persist_dir: Option<PathBuf>) -> Arc<Self> {
+        Arc::new(Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            fetch_locks: Mutex::new(HashMap::new()),
+            persist_dir,
+        })
+    }
+
+    fn lookup(&self, key: &str, now: Instant) -> Lookup<T> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = entries.get_mut(key) else {
+            return Lookup::Miss;
+        };
+        let age = now.saturating_duration_since(entry.fetched_at);
+        if age < self.ttl {
+            Lookup::Fresh(entry.data.clone())
+        } else if age < self.ttl * 2 && !entry.refreshing {
+            entry.refreshing = true;
+            Lookup::Stale(entry.data.clone())
+        } else if age < self.ttl * 2 {
+            // A refresh is already running; keep serving the stale copy.
+            Lookup::Fresh(entry.data.clone())
+        } else {
+            Lookup::Miss
+        }
+    }
+
+    fn store(&self, key: &str, data: Arc<T>, now: Instant) {
+        let fetched_at_utc = Utc::now();
+        self.persist(key, &data, fetched_at_utc);
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(
+            key.to_string(),
+            Entry {
+                data,
+                fetched_at: now,
+                fetched_at_utc,
+                bitmaps: HashMap::new(),
+                refreshing: false,
+            },
+        );
+    }
+
+    fn clear_refreshing(&self, key: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = entries.get_mut(key) {
+            entry.refreshing = false;
+        }
+    }
+
+    fn fetch_lock(&self, key: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.fetch_locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks.entry(key.to_string()).or_default().clone()
+    }
+
+    /// Forgets the lock for `key` once no other request is waiting on it.
+    fn release_fetch_lock(&self, key: &str, lock: Arc<tokio::sync::Mutex<()>>) {
+        let mut locks = self.fetch_locks.lock().unwrap_or_else(|e| e.into_inner());
+        // One reference in the map, one here
+        if Arc::strong_count(&lock) == 2 {
+            locks.remove(key);
+        }
+    }
+
+    fn persist_path(&self, key: &str) -> Option<PathBuf> {
+        self.persist_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", file_name(key))))
+    }
+
+    fn persist(&self, key: &str, data: &T, fetched_at: DateTime<Utc>) {
+        let Some(path) = self.persist_path(key) else {
+            return;
+        };
+        let result = std::fs::create_dir_all(path.parent().unwrap_or(&path))
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                serde_json::to_vec(&Persisted { fetched_at, data }).map_err(|e| e.to_string())
+            })
+            .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Could not persist {}: {}", path.display(), e);
+        }
+    }
+
+    /// The most recent successful fetch for `key`, from memory or else from disk.
+    fn last_good(&self, key: &str) -> Option<(Arc<T>, DateTime<Utc>)> {
+        {
+            let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(entry) = entries.get(key) {
+                return Some((entry.data.clone(), entry.fetched_at_utc));
+            }
+        }
+        let path = self.persist_path(key)?;
+        let json = std::fs::read(&path).ok()?;
+        match serde_json::from_slice::<Persisted<T>>(&json) {
+            Ok(persisted) => Some((Arc::new(persisted.data), persisted.fetched_at)),
+            Err(e) => {
+                eprintln!("Ignoring unreadable {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Returns cached data for `key`, calling `fetch` when the entry is missing or expired.
+    /// If that fetch fails, falls back to the last good data for `key` when there is any.
+    pub async fn get_or_fetch<F, Fut>(
+        self: &Arc<Self>,
+        key: &str,
+        fetch: F,
+    ) -> Result<Snapshot<T>, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, String>> + Send + 'static,
+    {
+        match self.cached(key, fetch) {
+            Ok(snapshot) => Ok(snapshot),
+            Err(fetch) => self.fetch_locked(key, fetch).await,
+        }
+    }
+
+    /// Like `get_or_fetch`, but waits at most `wait` for an inline fetch. A fetch still
+    /// running then carries on in the background and fills the entry for later requests,
+    /// while this one gets the last good data, or an error when there is none.
+    pub async fn get_or_fetch_within<F, Fut>(
+        self: &Arc<Self>,
+        key: &str,
+        wait: Duration,
+        fetch: F,
+    ) -> Result<Snapshot<T>, String>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, String>> + Send + 'static,
+    {
+        let fetch = match self.cached(key, fetch) {
+            Ok(snapshot) => return Ok(snapshot),
+            Err(fetch) => fetch,
+        };
+        let cache = Arc::clone(self);
+        let owned_key = key.to_string();
+        let mut fill = tokio::spawn(async move { cache.fetch_locked(&owned_key, fetch).await });
+        match tokio::time::timeout(wait, &mut fill).await {
+            Ok(filled) => filled.map_err(|e| e.to_string())?,
+            Err(_) => {
+                let error = format!("still fetching after {}s", wait.as_secs());
+                match self.last_good(key) {
+                    Some((data, fetched_at)) => Ok(Snapshot::LastGood {
+                        data,
+                        fetched_at,
+                        error,
+                    }),
+                    None => Err(error),
+                }
+            }
+        }
+    }
+
+    /// The entry for `key` if it is fresh, or stale with `fetch` refreshing it in the
+    /// background. `fetch` is handed back when it has to be fetched inline.
+    fn cached<F, Fut>(self: &Arc<Self>, key: &str, fetch: F) -> Result<Snapshot<T>, F>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, String>> + Send + 'static,
+    {
+        match self.lookup(key, Instant::now()) {
+            Lookup::Fresh(data) => Ok(Snapshot::Current(data)),
+            Lookup::Stale(data) => {
+                let cache = Arc::clone(self);
+                let key = key.to_string();
+                let refresh = fetch();
+                tokio::spawn(async move {
+                    match refresh.await {
+                        Ok(fresh) => cache.store(&key, Arc::new(fresh), Instant::now()),
+                        Err(e) => {
+                            eprintln!("Background refresh of {} failed: {}", key, e);
+                            cache.clear_refreshing(&key);
+                        }
+                    }
+                });
+                Ok(Snapshot::Current(data))
+            }
+            Lookup::Miss => Err(fetch),
+        }
+    }
+
+    async fn fetch_locked<F, Fut>(&self, key: &str, fetch: F) -> Result<Snapshot<T>, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        let lock = self.fetch_lock(key);
+        let guard = lock.lock().await;
+        let result = self.fetch_inline(key, fetch).await;
+        drop(guard);
+        self.release_fetch_lock(key, lock);
+        result
+    }
+
+    async fn fetch_inline<F, Fut>(&self, key: &str, fetch: F) -> Result<Snapshot<T>, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        // Another request may have filled the entry while we waited for the lock.
+        if let Lookup::Fresh(data) = self.lookup(key, Instant::now()) {
+            return Ok(Snapshot::Current(data));
+        }
+        match fetch().await {
+            Ok(data) => {
+                let data = Arc::new(data);
+                self.store(key, data.clone(), Instant::now());
+                Ok(Snapshot::Current(data))
+            }
+            Err(error) => match self.last_good(key) {
+                Some((data, fetched_at)) => {
+                    eprintln!("Serving last good data for {}: {}", key, error);
+                    Ok(Snapshot::LastGood {
+                        data,
+                        fetched_at,
+                        error,
+                    })
+                }
+                None => Err(error),
+            },
+        }
+    }
+
+    /// Returns the EPBM bytes for `data` under `render_key`, rendering them on first use.
+    /// Bitmaps are only cached while `data` is still the current entry for `key`, and a
+    /// failed render is not cached at all.
+    pub fn bitmap<E>(
+        &self,
+        key: &str,
+        render_key: &str,
+        data: &Arc<T>,
+        render: impl FnOnce(&T) -> Result<Vec<u8>, E>,
+    ) -> Result<Bytes, E> {
+        {
+            let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(bytes) = entries
+                .get(key)
+                .filter(|entry| Arc::ptr_eq(&entry.data, data))
+                .and_then(|entry| entry.bitmaps.get(render_key))
+            {
+                return Ok(bytes.clone());
+            }
+        }
+
+        let bytes = Bytes::from(render(data)?);
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = entries.get_mut(key) {
+            if Arc::ptr_eq(&entry.data, data) {
+                entry.bitmaps.insert(render_key.to_string(), bytes.clone());
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// `key` as a file name: ASCII letters, digits, `-` and `_` are kept and every other byte
+/// is percent-encoded, so distinct keys never share a file.
+fn file_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_name, Lookup, Snapshot, SourceCache};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn fresh_entries_are_not_refetched() {
+        let cache = SourceCache::new(Duration::from_secs(60));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..3 {
+            let calls = calls.clone();
+            let value = cache
+                .get_or_fetch("k", move || async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Ok(42)
+                })
+                .await
+                .unwrap();
+            assert!(matches!(value, Snapshot::Current(v) if *v == 42));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn a_slow_fetch_does_not_hold_up_other_keys() {
+        let cache = SourceCache::new(Duration::from_secs(60));
+        let (started, release) = tokio::sync::oneshot::channel::<()>();
+        let slow = {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_fetch("slow", move || async move {
+                        let _ = started.send(());
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                        Ok(1)
+                    })
+                    .await
+            })
+        };
+        release.await.unwrap();
+
+        let other = tokio::time::timeout(
+            Duration::from_secs(1),
+            cache.get_or_fetch("other", || async { Ok(2) }),
+        )
+        .await
+        .expect("fetch of another key waited for the slow one");
+        assert!(matches!(other, Ok(Snapshot::Current(v)) if *v == 2));
+        slow.abort();
+    }
+
+    #[tokio::test]
+    async fn a_cold_fetch_past_the_wait_fills_the_entry_in_the_background() {
+        let cache = SourceCache::new(Duration::from_secs(60));
+        // Past twice the TTL, so only good as a fallback
+        cache.store(
+            "warm",
+            Arc::new(5),
+            Instant::now() - Duration::from_secs(180),
+        );
+
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let waited = cache
+            .get_or_fetch_within("cold", Duration::from_millis(50), move || async move {
+                let _ = released.await;
+                Ok(3)
+            })
+            .await;
+        assert!(waited.is_err());
+
+        // The fill keeps the fetch lock, so the next request gets its result
+        release.send(()).unwrap();
+        let filled = cache
+            .get_or_fetch("cold", || async { Err("fetched again".to_string()) })
+            .await;
+        assert!(matches!(filled, Ok(Snapshot::Current(v)) if *v == 3));
+
+        // With earlier data to fall back on, that is served while the fill runs
+        let waited = cache
+            .get_or_fetch_within("warm", Duration::from_millis(50), || async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(6)
+            })
+            .await;
+        assert!(matches!(waited, Ok(Snapshot::LastGood { data, .. }) if *data == 5));
+    }
+
+    #[test]
+    fn file_names_are_distinct_per_key() {
+        assert_eq!(file_name("1.5,2"), "1%2E5%2C2");
+        assert_ne!(file_name("1.5,2"), file_name("1,5.2"));
+        assert_eq!(file_name("weight-user_1"), "weight-user_1");
+    }
+
+    #[test]
+    fn lookup_classifies_by_age() {
+        let cache = SourceCache::new(Duration::from_secs(60));
+        let start = Instant::now();
+        cache.store("k", Arc::new(1), start);
+
+        assert!(matches!(
+            cache.lookup("k", start + Duration::from_secs(30)),
+            Lookup::Fresh(_)
+        ));
+        assert!(matches!(
+            cache.lookup("k", start + Duration::from_secs(90)),
+            Lookup::Stale(_)
+        ));
+        // Only the first stale hit triggers a refresh; later ones just get the old data.
+        assert!(matches!(
+            cache.lookup("k", start + Duration::from_secs(91)),
+            Lookup::Fresh(_)
+        ));
+        assert!(matches!(
+            cache.lookup("k", start + Duration::from_secs(121)),
+            Lookup::Miss
+        ));
+        assert!(matches!(cache.lookup("other", start), Lookup::Miss));
+    }
+
+    #[test]
+    fn bitmaps_are_cached_per_render_key_and_dropped_on_refresh() {
+        let cache = SourceCache::new(Duration::from_secs(60));
+        let data = Arc::new(7);
+        cache.store("k", data.clone(), Instant::now());
+
+        let renders = AtomicUsize::new(0);
+        let render = |v: &i32| {
+            renders.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, String>(vec![*v as u8])
+        };
+
+        assert_eq!(&cache.bitmap("k", "a", &data, render).unwrap()[..], &[7]);
+        assert_eq!(&cache.bitmap("k", "a", &data, render).unwrap()[..], &[7]);
+        assert_eq!(renders.load(Ordering::SeqCst), 1);
+        cache.bitmap("k", "b", &data, render).unwrap();
+        assert_eq!(renders.load(Ordering::SeqCst), 2);
+
+        // A failed render is retried on the next request
+        let failed = cache.bitmap("k", "c", &data, |_| Err("encoder failed".to_string()));
+        assert!(failed.is_err());
+        assert_eq!(&cache.bitmap("k", "c", &data, render).unwrap()[..], &[7]);
+        assert_eq!(renders.load(Ordering::SeqCst), 3);
+
+        let refreshed = Arc::new(8);
+        cache.store("k", refreshed.clone(), Instant::now());
+        assert_eq!(
+            &cache.bitmap("k", "a", &refreshed, render).unwrap()[..],
+            &[8]
+        );
+        assert_eq!(renders.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn failed_fetch_falls_back_to_last_good_data_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("iot-image-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let cache = SourceCache::persistent(Duration::ZERO, &dir);
+        cache
+            .get_or_fetch("lat=1&lon=2", || async { Ok(5) })
+            .await
+            .unwrap();
+
+        // Same process: the expired in-memory entry is used.
+        let snapshot = cache
+            .get_or_fetch("lat=1&lon=2", || async { Err("offline".to_string()) })
+            .await
+            .unwrap();
+        assert!(
+            matches!(snapshot, Snapshot::LastGood { data, error, .. } if *data == 5 && error == "offline")
+        );
+
+        // After a restart only the file on disk is left.
+        let restarted = SourceCache::<i32>::persistent(Duration::ZERO, &dir);
+        let snapshot = restarted
+            .get_or_fetch("lat=1&lon=2", || async { Err("offline".to_string()) })
+            .await
+            .unwrap();
+        assert!(matches!(snapshot, Snapshot::LastGood { data, .. } if *data == 5));
+
+        let missing = restarted
+            .get_or_fetch("other", || async { Err("offline".to_string()) })
+            .await;
+        assert!(missing.is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,793 @@
+//! Server configuration file (TOML): data sources with their credentials, and the
+//! named screens to serve. Each screen gets `/{name}/{panel}.bin`, `/{name}/{panel}.png`,
+//! `/{name}/svg` and `/{name}/png` routes, so a second weather location or stock basket
+//! is a config change rather than a code change.
+//!
+//! String values may reference environment variables as `${NAME}`, so secrets can stay
+//! in the systemd `EnvironmentFile`. See `deploy/bundle/config.example.toml`.
+
+use crate::device::DeviceConfig;
+use crate::dither::DitherMode;
+use crate::indicators::Indicator;
+use crate::locale::{Locale, Units};
+use crate::panel::{find_panel, Panel, DEFAULT_PANEL};
+use crate::portfolio::valid_currency;
+use crate::quote_provider::QuoteSource;
+use crate::sensors::valid_sensor_id;
+use crate::stocks::StockLayout;
+use crate::weather_provider::Provider;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Directory for the last good upstream data, used when a fetch fails
+    #[serde(default = "default_state_dir")]
+    pub state_dir: PathBuf,
+    /// JSON file of measured pigment colors per panel (see palette.rs for the format)
+    pub palette_file: Option<PathBuf>,
+    /// Saturation boost applied to rendered colors before dithering. 1.2 suits the
+    /// idealized palettes; measured palettes usually want 1.0
+    #[serde(default = "default_saturation_boost")]
+    pub saturation_boost: f32,
+    #[serde(default)]
+    pub sources: Sources,
+    pub screens: Vec<Screen>,
+    /// Displays served from `/device/{id}/next.bin`, each with a playlist of screens
+    #[serde(default)]
+    pub devices: Vec<DeviceConfig>,
+}
+
+/// Upstream data sources. Only the ones used by a configured screen are required.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sources {
+    pub openweather: Option<ApiSource>,
+    pub twelve_data: Option<ApiSource>,
+    pub alpha_vantage: Option<ApiSource>,
+    pub stock_csv: Option<StockCsvSource>,
+    pub fred: Option<ApiSource>,
+    pub weight: Option<WeightSource>,
+    pub nws: Option<NwsSource>,
+    /// Broker the home sensors publish to; needs a build with `--features mqtt`
+    pub mqtt: Option<MqttSource>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiSource {
+    pub api_key: String,
+    /// Cache TTL; defaults per source
+    pub ttl_secs: Option<u64>,
+    /// Requests allowed a minute; defaults to the free tier's limit where there is one
+    pub requests_per_minute: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeightSource {
+    /// Directory containing weight data CSV files
+    pub data_dir: String,
+    pub ttl_secs: Option<u64>,
+}
+
+/// Daily prices kept as files, one `{SYMBOL}.csv` per symbol
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StockCsvSource {
+    pub data_dir: PathBuf,
+}
+
+/// The National Weather Service needs no key, but asks every client to identify itself
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NwsSource {
+    /// e.g. "iot-image (you@example.com)"
+    pub user_agent: String,
+}
+
+/// Sensor readings are also accepted over HTTP, so a broker is optional
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+#[serde(deny_unknown_fields)]
+pub struct MqttSource {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// Topic filter; the last level of each topic names the sensor
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Location {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Tide predictions for the almanac screen
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TideSource {
+    /// A station's `harcon.json` from NOAA CO-OPS
+    pub harmonics: PathBuf,
+    /// Height of mean sea level above the chart datum (e.g. MLLW), in the file's units;
+    /// heights are relative to mean sea level when omitted
+    #[serde(default)]
+    pub mean_sea_level: f64,
+}
+
+/// IANA zone names, e.g. "America/Los_Angeles"
+fn deserialize_timezone<'de, D>(deserializer: D) -> Result<Option<chrono_tz::Tz>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let name = Option::<String>::deserialize(deserializer)?;
+    name.map(|name| {
+        name.parse()
+            .map_err(|_| serde::de::Error::custom(format!("unknown time zone {}", name)))
+    })
+    .transpose()
+}
+
+/// What a screen shows, with its parameters. Request query parameters (`lat`, `lon`,
+/// `user`, `date`, `duration`) still override these per request.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ScreenKind {
+    Weather {
+        location: Location,
+        /// Forecast source; OpenWeatherMap when omitted
+        #[serde(default)]
+        provider: Provider,
+        /// Show the alert details instead while a severe alert is in effect
+        #[serde(default)]
+        switch_to_alerts: bool,
+    },
+    /// The next 48 hours from the hourly forecast
+    WeatherHourly {
+        location: Location,
+        #[serde(default)]
+        provider: Provider,
+    },
+    /// The next hour's precipitation; needs a provider with a nowcast
+    WeatherNowcast {
+        location: Location,
+        #[serde(default)]
+        provider: Provider,
+    },
+    /// Every current alert in full; needs a provider with alerts
+    WeatherAlerts {
+        location: Location,
+        #[serde(default)]
+        provider: Provider,
+    },
+    /// Today's forecast against the recorded history and climate normals
+    WeatherHistory {
+        location: Location,
+        #[serde(default)]
+        provider: Provider,
+        /// NOAA daily normals CSV (DATE as MM-DD, DLY-TMAX-NORMAL, DLY-TMIN-NORMAL,
+        /// MTD-PRCP-NORMAL), in °F and inches
+        normals: Option<PathBuf>,
+    },
+    WeatherOverview {
+        location: Location,
+    },
+    /// Air quality index, its forecast and pollen; openweather or open-meteo
+    AirQuality {
+        location: Location,
+        #[serde(default)]
+        provider: Provider,
+    },
+    /// Sun, moon and optionally tides, computed locally
+    Almanac {
+        location: Location,
+        /// Zone for the times shown; the server's when omitted
+        #[serde(default, deserialize_with = "deserialize_timezone")]
+        timezone: Option<chrono_tz::Tz>,
+        tides: Option<TideSource>,
+    },
+    Stocks {
+        /// Comma-separated (e.g. "BTC/USD,QQQ,IONQ,TSLA"); those that don't fit the
+        /// layout go on further pages
+        symbols: String,
+        /// Price sources to try in order for each symbol; Twelve Data when omitted
+        #[serde(default = "default_quote_sources")]
+        providers: Vec<QuoteSource>,
+        /// Overlays and panels per symbol, e.g. `{ QQQ = ["sma20", "bollinger", "volume"] }`
+        #[serde(default)]
+        indicators: BTreeMap<String, Vec<Indicator>>,
+        /// Charts, sparklines or a watchlist; chosen by the number of symbols when omitted
+        #[serde(default)]
+        layout: StockLayout,
+    },
+    /// Value and P&L of the positions in a holdings file, priced like the stocks screen
+    Portfolio {
+        /// CSV with `symbol`, `quantity`, `cost_basis` and optionally `currency` columns
+        holdings: PathBuf,
+        #[serde(default = "default_quote_sources")]
+        providers: Vec<QuoteSource>,
+        /// Currency the totals are shown in; "USD" when omitted
+        #[serde(default = "default_currency")]
+        currency: String,
+    },
+    Fred {
+        /// Days of history; the FRED module's default when omitted
+        duration: Option<usize>,
+    },
+    WeightForecast {
+        /// CSV file name without extension; defaults to "weight"
+        user: Option<String>,
+    },
+    WeightVelocity {
+        user: Option<String>,
+    },
+    /// Home sensor readings beside the outdoor conditions
+    Indoor {
+        location: Location,
+        #[serde(default)]
+        provider: Provider,
+        /// Sensor IDs, one to three
+        sensors: Vec<String>,
+        /// CO2 level that brings up the ventilation warning, in ppm; 1000 when omitted
+        co2_warning: Option<f32>,
+    },
+    /// Battery history of the devices that report telemetry
+    Battery {
+        /// Days of history; 30 when omitted
+        duration: Option<usize>,
+    },
+}
+
+/// A named screen from the config file, with its panel and dither names resolved
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "ScreenConfig")]
+pub struct Screen {
+    /// Route prefix, e.g. "weather" or "weight/forecast"
+    pub name: String,
+    pub kind: ScreenKind,
+    /// Panel for the routes without one in the path (`/svg`, `/png`)
+    pub panel: &'static Panel,
+    /// Overrides the screen type's default dithering
+    pub dither: Option<DitherMode>,
+    /// Units for the weather screens; imperial when omitted
+    pub units: Option<Units>,
+    /// Language and clock format for dates and times; en-US when omitted
+    pub locale: Option<Locale>,
+}
+
+#[derive(Deserialize)]
+struct ScreenConfig {
+    name: String,
+    #[serde(flatten)]
+    kind: ScreenKind,
+    panel: Option<String>,
+    dither: Option<String>,
+    units: Option<String>,
+    locale: Option<String>,
+}
+
+impl TryFrom<ScreenConfig> for Screen {
+    type Error = String;
+
+    fn try_from(config: ScreenConfig) -> Result<Self, String> {
+        let name = config.name.trim_matches('/').to_string();
+        let valid_segment = |segment: &str| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        };
+        // `/device/...`, `/telemetry/...` and `/sensors/...` are the device endpoints
+        let reserved = matches!(
+            name.split('/').next(),
+            Some("device" | "telemetry" | "sensors")
+        );
+        if !name.split('/').all(valid_segment) || reserved {
+            return Err(format!("Invalid screen name: {:?}", config.name));
+        }
+        let panel = match config.panel.as_deref() {
+            Some(panel) => find_panel(panel)
+                .ok_or_else(|| format!("Screen {}: unknown panel {}", name, panel))?,
+            None => DEFAULT_PANEL,
+        };
+        let dither = match config.dither.as_deref() {
+            Some(dither) => Some(
+                DitherMode::from_name(dither)
+                    .ok_or_else(|| format!("Screen {}: unknown dither mode {}", name, dither))?,
+            ),
+            None => None,
+        };
+        let units = match config.units.as_deref() {
+            Some(units) => Some(
+                Units::from_name(units)
+                    .ok_or_else(|| format!("Screen {}: unknown units {}", name, units))?,
+            ),
+            None => None,
+        };
+        let locale = match config.locale.as_deref() {
+            Some(locale) => Some(
+                Locale::from_name(locale)
+                    .ok_or_else(|| format!("Screen {}: unknown locale {}", name, locale))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            name,
+            kind: config.kind,
+            panel,
+            dither,
+            units,
+            locale,
+        })
+    }
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+fn default_state_dir() -> PathBuf {
+    PathBuf::from("state")
+}
+
+fn default_saturation_boost() -> f32 {
+    1.2
+}
+
+fn default_quote_sources() -> Vec<QuoteSource> {
+    vec![QuoteSource::TwelveData]
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_topic() -> String {
+    "sensors/+".to_string()
+}
+
+fn default_mqtt_client_id() -> String {
+    "iot-image".to_string()
+}
+
+impl Config {
+    /// Read, interpolate `${NAME}` environment variables, parse and validate
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&text, |name| std::env::var(name).ok())
+    }
+
+    fn parse(text: &str, env: impl Fn(&str) -> Option<String>) -> Result<Self, Box<dyn Error>> {
+        let mut value: toml::Value = toml::from_str(text)?;
+        interpolate_env(&mut value, &env)?;
+        let config = Self::deserialize(value)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.screens.is_empty() {
+            return Err("No screens configured".to_string());
+        }
+        if self.sources.mqtt.is_some() && !cfg!(feature = "mqtt") {
+            return Err("[sources.mqtt] needs a server built with `--features mqtt`".to_string());
+        }
+        for (i, screen) in self.screens.iter().enumerate() {
+            if self.screens[..i].iter().any(|s| s.name == screen.name) {
+                return Err(format!("Duplicate screen name: {}", screen.name));
+            }
+            if let ScreenKind::WeatherNowcast {
+                provider: Provider::Nws,
+                ..
+            } = screen.kind
+            {
+                return Err(format!(
+                    "Screen {}: NWS has no minute-by-minute forecast",
+                    screen.name
+                ));
+            }
+            if let ScreenKind::WeatherAlerts {
+                provider: Provider::OpenMeteo,
+                ..
+            } = screen.kind
+            {
+                return Err(format!(
+                    "Screen {}: Open-Meteo has no weather alerts",
+                    screen.name
+                ));
+            }
+            if let ScreenKind::AirQuality {
+                provider: Provider::Nws,
+                ..
+            } = screen.kind
+            {
+                return Err(format!(
+                    "Screen {}: NWS has no air quality data",
+                    screen.name
+                ));
+            }
+            if let ScreenKind::Indoor { sensors, .. } = &screen.kind {
+                if sensors.is_empty() || sensors.len() > 3 {
+                    return Err(format!("Screen {}: list one to three sensors", screen.name));
+                }
+                if let Some(id) = sensors.iter().find(|id| !valid_sensor_id(id)) {
+                    return Err(format!("Screen {}: invalid sensor ID {}", screen.name, id));
+                }
+            }
+            if let ScreenKind::Portfolio {
+                providers,
+                currency,
+                ..
+            } = &screen.kind
+            {
+                if !valid_currency(currency) {
+                    return Err(format!(
+                        "Screen {}: currency should be a code such as USD",
+                        screen.name
+                    ));
+                }
+                self.check_quote_sources(&screen.name, providers)?;
+            }
+            if let ScreenKind::Stocks {
+                symbols,
+                providers,
+                indicators,
+                ..
+            } = &screen.kind
+            {
+                if let Some(symbol) = indicators
+                    .keys()
+                    .find(|&key| !symbols.split(',').any(|s| s.trim() == key))
+                {
+                    return Err(format!(
+                        "Screen {}: indicators for {}, which is not in symbols",
+                        screen.name, symbol
+                    ));
+                }
+                self.check_quote_sources(&screen.name, providers)?;
+            }
+            let (source, configured) = match &screen.kind {
+                ScreenKind::Weather {
+                    provider: Provider::OpenWeather,
+                    ..
+                }
+                | ScreenKind::WeatherHourly {
+                    provider: Provider::OpenWeather,
+                    ..
+                }
+                | ScreenKind::WeatherNowcast {
+                    provider: Provider::OpenWeather,
+                    ..
+                }
+                | ScreenKind::WeatherAlerts {
+                    provider: Provider::OpenWeather,
+                    ..
+                }
+                | ScreenKind::WeatherHistory {
+                    provider: Provider::OpenWeather,
+                    ..
+                }
+                | ScreenKind::AirQuality {
+                    provider: Provider::OpenWeather,
+                    ..
+                }
+                | ScreenKind::Indoor {
+                    provider: Provider::OpenWeather,
+                    ..
+                }
+                | ScreenKind::WeatherOverview { .. } => {
+                    ("openweather", self.sources.openweather.is_some())
+                }
+                // Keyless; `[sources.nws]` only sets the User-Agent
+                ScreenKind::Weather { .. }
+                | ScreenKind::WeatherHourly { .. }
+                | ScreenKind::WeatherNowcast { .. }
+                | ScreenKind::WeatherAlerts { .. }
+                | ScreenKind::WeatherHistory { .. }
+                | ScreenKind::AirQuality { .. }
+                | ScreenKind::Indoor { .. } => continue,
+                // Checked per provider above
+                ScreenKind::Stocks { .. } | ScreenKind::Portfolio { .. } => continue,
+                ScreenKind::Fred { .. } => ("fred", self.sources.fred.is_some()),
+                ScreenKind::WeightForecast { .. } | ScreenKind::WeightVelocity { .. } => {
+                    ("weight", self.sources.weight.is_some())
+                }
+                // Drawn from the server's own check-in log
+                ScreenKind::Battery { .. } => continue,
+                // Computed locally
+                ScreenKind::Almanac { .. } => continue,
+            };
+            if !configured {
+                return Err(format!(
+                    "Screen {} needs a [sources.{}] section",
+                    screen.name, source
+                ));
+            }
+        }
+        Ok(())
+    }
+    /// Every provider listed needs its source configured, except the keyless Stooq
+    fn check_quote_sources(&self, screen: &str, providers: &[QuoteSource]) -> Result<(), String> {
+        if providers.is_empty() {
+            return Err(format!("Screen {}: no stock providers", screen));
+        }
+        for provider in providers {
+            let (source, configured) = match provider {
+                QuoteSource::TwelveData => ("twelve_data", self.sources.twelve_data.is_some()),
+                QuoteSource::AlphaVantage => {
+                    ("alpha_vantage", self.sources.alpha_vantage.is_some())
+                }
+                QuoteSource::Csv => ("stock_csv", self.sources.stock_csv.is_some()),
+                QuoteSource::Stooq => continue,
+            };
+            if !configured {
+                return Err(format!(
+                    "Screen {}: provider {} needs a [sources.{}] section",
+                    screen,
+                    provider.name(),
+                    source
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replaces `${NAME}` in every string value with the variable's value. An unset
+/// variable is an error rather than an empty string, so a missing secret fails at startup.
+fn interpolate_env(
+    value: &mut toml::Value,
+    env: &impl Fn(&str) -> Option<String>,
+) -> Result<(), String> {
+    match value {
+        toml::Value::String(s) => {
+            let mut out = String::with_capacity(s.len());
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find("${") {
+                out.push_str(&rest[..start]);
+                let end = rest[start..]
+                    .find('}')
+                    .ok_or_else(|| format!("Unterminated ${{ in {:?}", s))?;
+                let name = &rest[start + 2..start + end];
+                out.push_str(
+                    &env(name)
+                        .ok_or_else(|| format!("Environment variable {} is not set", name))?,
+                );
+                rest = &rest[start + end + 1..];
+            }
+            out.push_str(rest);
+            *s = out;
+        }
+        toml::Value::Array(items) => {
+            for item in items {
+                interpolate_env(item, env)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, item) in table.iter_mut() {
+                interpolate_env(item, env)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Location, ScreenKind};
+    use crate::device::Devices;
+    use crate::dither::DitherMode;
+    use crate::indicators::Indicator;
+    use crate::locale::{Locale, Units};
+    use crate::quote_provider::QuoteSource;
+    use crate::stocks::StockLayout;
+    use crate::weather_provider::Provider;
+    use std::collections::BTreeMap;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "OPEN_WEATHER_KEY" => Some("weather-secret".to_string()),
+            "STOCK_SYMBOLS" => Some("QQQ,TSLA".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn example_config_parses() {
+        let text = include_str!("../../deploy/bundle/config.example.toml");
+        let config = Config::parse(text, |_| Some("x".to_string())).unwrap();
+        assert!(config.screens.iter().any(|s| s.name == "weather"));
+        assert!(config.screens.iter().any(|s| s.name == "weight/forecast"));
+        assert!(Devices::new(config.devices, &config.screens).is_ok());
+    }
+
+    #[test]
+    fn env_vars_are_interpolated_and_screens_resolved() {
+        let config = Config::parse(
+            r#"
+            [sources.openweather]
+            api_key = "${OPEN_WEATHER_KEY}"
+
+            [sources.twelve_data]
+            api_key = "literal-key"
+            ttl_secs = 60
+
+            [[screens]]
+            name = "weather-tahoe"
+            type = "weather"
+            location = { lat = 39.1, lon = -120.0 }
+            panel = "mono-4in2"
+            dither = "bayer"
+            units = "uk"
+            locale = "en-GB"
+
+            [[screens]]
+            name = "/stocks/tech/"
+            type = "stocks"
+            symbols = "AAPL,${STOCK_SYMBOLS}"
+            indicators = { QQQ = ["SMA20", "volume"] }
+            "#,
+            env,
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 8080);
+        let weather = config.sources.openweather.as_ref().unwrap();
+        assert_eq!(weather.api_key, "weather-secret");
+        assert_eq!(
+            config.sources.twelve_data.as_ref().unwrap().ttl_secs,
+            Some(60)
+        );
+
+        let tahoe = &config.screens[0];
+        assert_eq!(
+            tahoe.kind,
+            ScreenKind::Weather {
+                location: Location {
+                    lat: 39.1,
+                    lon: -120.0
+                },
+                provider: Provider::OpenWeather,
+                switch_to_alerts: false,
+            }
+        );
+        assert_eq!(tahoe.panel.name, "mono-4in2");
+        assert_eq!(tahoe.dither, Some(DitherMode::Bayer));
+        assert_eq!(tahoe.units, Some(Units::Uk));
+        assert_eq!(tahoe.locale, Some(Locale::EnGb));
+
+        let stocks = &config.screens[1];
+        assert_eq!(stocks.name, "stocks/tech");
+        assert_eq!(stocks.panel.name, "seed-e1002");
+        assert_eq!(
+            stocks.kind,
+            ScreenKind::Stocks {
+                symbols: "AAPL,QQQ,TSLA".to_string(),
+                providers: vec![QuoteSource::TwelveData],
+                indicators: BTreeMap::from([(
+                    "QQQ".to_string(),
+                    vec![Indicator::Sma(20), Indicator::Volume]
+                )]),
+                layout: StockLayout::Auto,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        let screen = |extra: &str| {
+            format!(
+                "[sources.fred]\napi_key = \"k\"\n[[screens]]\nname = \"fred\"\ntype = \"fred\"\n{}",
+                extra
+            )
+        };
+        assert!(Config::parse(&screen(""), env).is_ok());
+        assert!(Config::parse(&screen("panel = \"nope\""), env).is_err());
+        assert!(Config::parse(&screen("dither = \"nope\""), env).is_err());
+        assert!(Config::parse(&screen("units = \"furlongs\""), env).is_err());
+        assert!(Config::parse(&screen("locale = \"xx\""), env).is_err());
+        assert!(Config::parse(
+            &screen("[[screens]]\nname = \"fred\"\ntype = \"fred\""),
+            env
+        )
+        .is_err());
+        assert!(Config::parse(
+            &screen("[[screens]]\nname = \"x\"\ntype = \"stocks\"\nsymbols = \"Q\""),
+            env
+        )
+        .is_err());
+        // Stooq needs no key, but every other provider listed needs its source
+        let stocks = |providers: &str| {
+            screen(&format!(
+                "[[screens]]\nname = \"x\"\ntype = \"stocks\"\nsymbols = \"Q\"\nproviders = {}",
+                providers
+            ))
+        };
+        assert!(Config::parse(&stocks(r#"["stooq"]"#), env).is_ok());
+        assert!(Config::parse(&stocks(r#"["stooq", "alpha-vantage"]"#), env).is_err());
+        assert!(Config::parse(&stocks("[]"), env).is_err());
+        let portfolio = |currency: &str| {
+            screen(&format!(
+                "[[screens]]\nname = \"p\"\ntype = \"portfolio\"\nholdings = \"h.csv\"\nproviders = [\"stooq\"]\ncurrency = \"{}\"",
+                currency
+            ))
+        };
+        assert!(Config::parse(&portfolio("EUR"), env).is_ok());
+        assert!(Config::parse(&portfolio("euro"), env).is_err());
+        assert!(Config::parse(
+            &screen("[[screens]]\nname = \"x\"\ntype = \"stocks\"\nsymbols = \"Q\"\nproviders = [\"stooq\"]\nindicators = { Q = [\"sma\"] }"),
+            env
+        )
+        .is_err());
+        assert!(Config::parse(
+            &screen("[[screens]]\nname = \"a/../b\"\ntype = \"fred\""),
+            env
+        )
+        .is_err());
+        assert!(Config::parse(
+            &screen("[[screens]]\nname = \"device/x\"\ntype = \"fred\""),
+            env
+        )
+        .is_err());
+        assert!(Config::parse(
+            &screen("[[screens]]\nname = \"telemetry\"\ntype = \"battery\""),
+            env
+        )
+        .is_err());
+        assert!(Config::parse(
+            &screen("[[screens]]\nname = \"battery\"\ntype = \"battery\""),
+            env
+        )
+        .is_ok());
+        // Only OpenWeatherMap needs a key
+        let weather = |provider: &str| {
+            screen(&format!(
+                "[[screens]]\nname = \"w\"\ntype = \"weather\"\nlocation = {{ lat = 1.0, lon = 2.0 }}\n{}",
+                provider
+            ))
+        };
+        assert!(Config::parse(&weather(""), env).is_err());
+        let hourly =
+            |provider: &str| weather(provider).replace("\"weather\"", "\"weather-hourly\"");
+        assert!(Config::parse(&hourly(""), env).is_err());
+        assert!(Config::parse(&hourly("provider = \"open-meteo\""), env).is_ok());
+        let nowcast =
+            |provider: &str| weather(provider).replace("\"weather\"", "\"weather-nowcast\"");
+        assert!(Config::parse(&nowcast("provider = \"open-meteo\""), env).is_ok());
+        let err = Config::parse(&nowcast("provider = \"nws\""), env).unwrap_err();
+        assert!(err.to_string().contains("minute-by-minute"), "{}", err);
+        let alerts =
+            |provider: &str| weather(provider).replace("\"weather\"", "\"weather-alerts\"");
+        assert!(Config::parse(&alerts("provider = \"nws\""), env).is_ok());
+        assert!(Config::parse(&alerts("provider = \"open-meteo\""), env).is_err());
+        let air = |provider: &str| weather(provider).replace("\"weather\"", "\"air-quality\"");
+        assert!(Config::parse(&air(""), env).is_err());
+        assert!(Config::parse(&air("provider = \"open-meteo\""), env).is_ok());
+        assert!(Config::parse(&air("provider = \"nws\""), env).is_err());
+        assert!(Config::parse(&weather("provider = \"open-meteo\""), env).is_ok());
+        assert!(Config::parse(&weather("provider = \"nws\""), env).is_ok());
+        assert!(Config::parse(&weather("provider = \"accuweather\""), env).is_err());
+
+        let missing = "[sources.fred]\napi_key = \"${FRED_API_KEY}\"\n[[screens]]\nname = \"fred\"\ntype = \"fred\"";
+        let err = Config::parse(missing, env).unwrap_err().to_string();
+        assert!(err.contains("FRED_API_KEY"), "{}", err);
+    }
+}
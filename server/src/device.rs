@@ -0,0 +1,253 @@
+//! Device registry: per-device playlists of screens with time-of-day rules, served
+//! from `/device/{id}/next.bin` so displays can be reconfigured centrally instead of
+//! in each client's firmware.
+//!
+//! On each request the entries whose window contains the current local time are
+//! active, and the device rotates through them in playlist order. When no entry is
+//! active the first entry is shown.
+
+use crate::config::Screen;
+use crate::panel::{find_panel, Panel, DEFAULT_PANEL};
+use chrono::NaiveTime;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+    pub id: String,
+    /// Panel name; defaults to seed-e1002
+    pub panel: Option<String>,
+    pub playlist: Vec<PlaylistEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlaylistEntry {
+    /// Name of a configured screen
+    pub screen: String,
+    /// Start of the window, "HH:MM" local time; the start of the day when omitted
+    pub from: Option<TimeOfDay>,
+    /// End of the window (exclusive); the end of the day when omitted. A window that
+    /// ends before it starts wraps past midnight.
+    pub until: Option<TimeOfDay>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(NaiveTime);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        NaiveTime::parse_from_str(&value, "%H:%M")
+            .map(TimeOfDay)
+            .map_err(|_| format!("Invalid time of day {:?}, expected HH:MM", value))
+    }
+}
+
+impl PlaylistEntry {
+    fn is_active(&self, now: NaiveTime) -> bool {
+        let from = self.from.map_or(NaiveTime::MIN, |t| t.0);
+        match self.until.map(|t| t.0) {
+            None => now >= from,
+            Some(until) if from <= until => now >= from && now < until,
+            Some(until) => now >= from || now < until,
+        }
+    }
+}
+
+struct Device {
+    panel: &'static Panel,
+    /// Screen index for each playlist entry
+    screens: Vec<usize>,
+    entries: Vec<PlaylistEntry>,
+}
+
+pub struct Devices {
+    devices: HashMap<String, Device>,
+    /// Requests served per device, which picks the next entry among the active ones
+    cursors: Mutex<HashMap<String, usize>>,
+}
+
+impl Devices {
+    pub fn new(configs: Vec<DeviceConfig>, screens: &[Screen]) -> Result<Self, String> {
+        let mut devices = HashMap::new();
+        for config in configs {
+            if config.playlist.is_empty() {
+                return Err(format!("Device {} has an empty playlist", config.id));
+            }
+            let panel = match config.panel.as_deref() {
+                Some(name) => find_panel(name)
+                    .ok_or_else(|| format!("Device {}: unknown panel {}", config.id, name))?,
+                None => DEFAULT_PANEL,
+            };
+            let screens = config
+                .playlist
+                .iter()
+                .map(|entry| {
+                    screens
+                        .iter()
+                        .position(|screen| screen.name == entry.screen)
+                        .ok_or_else(|| {
+                            format!("Device {}: unknown screen {}", config.id, entry.screen)
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let device = Device {
+                panel,
+                screens,
+                entries: config.playlist,
+            };
+            if devices.insert(config.id.clone(), device).is_some() {
+                return Err(format!("Duplicate device id: {}", config.id));
+            }
+        }
+        Ok(Self {
+            devices,
+            cursors: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.devices.keys().map(String::as_str)
+    }
+
+    /// Panel and screen index for the device's current slot, or `None` for an unknown
+    /// device. `advance` moves the rotation on; previews leave it where it is.
+    pub fn next_screen(
+        &self,
+        id: &str,
+        now: NaiveTime,
+        advance: bool,
+    ) -> Option<(&'static Panel, usize)> {
+        let device = self.devices.get(id)?;
+        let active: Vec<usize> = device
+            .entries
+            .iter()
+            .zip(&device.screens)
+            .filter(|(entry, _)| entry.is_active(now))
+            .map(|(_, &screen)| screen)
+            .collect();
+        if active.is_empty() {
+            return Some((device.panel, device.screens[0]));
+        }
+        let mut cursors = self.cursors.lock().unwrap();
+        let cursor = cursors.entry(id.to_string()).or_insert(0);
+        let screen = active[*cursor % active.len()];
+        if advance {
+            *cursor = cursor.wrapping_add(1);
+        }
+        Some((device.panel, screen))
+    }
+
+    /// The next start or end of one of the device's playlist windows after `now`
+    /// (wrapping past midnight), so the device can wake when its slot changes
+    pub fn next_boundary(&self, id: &str, now: NaiveTime) -> Option<NaiveTime> {
+        let device = self.devices.get(id)?;
+        let seconds_until = |t: NaiveTime| match (t - now).num_seconds().rem_euclid(86400) {
+            0 => 86400,
+            seconds => seconds,
+        };
+        device
+            .entries
+            .iter()
+            .flat_map(|entry| [entry.from, entry.until])
+            .flatten()
+            .map(|t| t.0)
+            .min_by_key(|&t| seconds_until(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceConfig, Devices, PlaylistEntry, TimeOfDay};
+    use crate::config::{Screen, ScreenKind};
+    use crate::panel::DEFAULT_PANEL;
+    use chrono::NaiveTime;
+
+    fn screen(name: &str) -> Screen {
+        Screen {
+            name: name.to_string(),
+            kind: ScreenKind::Fred { duration: None },
+            panel: DEFAULT_PANEL,
+            dither: None,
+            units: None,
+            locale: None,
+        }
+    }
+
+    fn entry(screen: &str, from: Option<&str>, until: Option<&str>) -> PlaylistEntry {
+        let time = |t: &str| TimeOfDay::try_from(t.to_string()).unwrap();
+        PlaylistEntry {
+            screen: screen.to_string(),
+            from: from.map(time),
+            until: until.map(time),
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn playlist_follows_time_windows_and_rotates() {
+        let screens = vec![screen("weather"), screen("stocks"), screen("fred")];
+        let devices = Devices::new(
+            vec![DeviceConfig {
+                id: "kitchen".to_string(),
+                panel: Some("mono-4in2".to_string()),
+                playlist: vec![
+                    entry("weather", Some("06:00"), Some("12:00")),
+                    entry("stocks", Some("09:30"), Some("16:00")),
+                    entry("fred", Some("16:00"), Some("02:00")),
+                ],
+            }],
+            &screens,
+        )
+        .unwrap();
+
+        let next = |now, advance| devices.next_screen("kitchen", now, advance).unwrap().1;
+        assert_eq!(next(at(7, 0), true), 0);
+        // Weather and stocks overlap in the late morning: rotate between them
+        assert_eq!(next(at(10, 0), false), 1);
+        assert_eq!(next(at(10, 0), true), 1);
+        assert_eq!(next(at(10, 0), true), 0);
+        // Evening window wraps past midnight
+        assert_eq!(next(at(23, 0), true), 2);
+        assert_eq!(next(at(1, 59), true), 2);
+        // Nothing scheduled: first entry
+        assert_eq!(next(at(3, 0), true), 0);
+
+        assert_eq!(devices.next_boundary("kitchen", at(7, 0)), Some(at(9, 30)));
+        assert_eq!(devices.next_boundary("kitchen", at(16, 0)), Some(at(2, 0)));
+        assert_eq!(devices.next_boundary("kitchen", at(3, 0)), Some(at(6, 0)));
+
+        let (panel, _) = devices.next_screen("kitchen", at(7, 0), false).unwrap();
+        assert_eq!(panel.name, "mono-4in2");
+        assert!(devices.next_screen("hallway", at(7, 0), true).is_none());
+    }
+
+    #[test]
+    fn invalid_devices_are_rejected() {
+        let screens = vec![screen("weather")];
+        let device = |id: &str, screen: &str| DeviceConfig {
+            id: id.to_string(),
+            panel: None,
+            playlist: vec![entry(screen, None, None)],
+        };
+        assert!(Devices::new(vec![device("a", "nope")], &screens).is_err());
+        assert!(Devices::new(
+            vec![device("a", "weather"), device("a", "weather")],
+            &screens
+        )
+        .is_err());
+        assert!(TimeOfDay::try_from("25:00".to_string()).is_err());
+    }
+}
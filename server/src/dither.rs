@@ -0,0 +1,400 @@
+//! Dithering from the rendered RGB pixmap down to a panel's palette.
+//!
+//! Error diffusion (Atkinson, Floyd–Steinberg, JJN, Stucki) works in CIELAB on
+//! gamut-mapped colors and suits gradients; ordered
+//! Bayer and blue-noise thresholds keep flat fills stable between refreshes; `None`
+//! keeps thin chart lines and small text crisp.
+
+use crate::bitmap::EpdBitmap;
+use crate::palette::DitherPalette;
+use std::sync::OnceLock;
+
+/// How `dither_pixmap` maps RGB pixels to palette colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DitherMode {
+    /// Error diffusion that drops 25% of the error; high contrast, washes out gradients
+    Atkinson,
+    FloydSteinberg,
+    JarvisJudiceNinke,
+    Stucki,
+    /// 8x8 ordered threshold matrix
+    Bayer,
+    /// Threshold matrix generated with void-and-cluster
+    BlueNoise,
+    /// Nearest palette color only
+    None,
+}
+
+impl DitherMode {
+    /// Parse the name used in `?dither=`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "atkinson" => Some(Self::Atkinson),
+            "floyd-steinberg" | "fs" => Some(Self::FloydSteinberg),
+            "jarvis-judice-ninke" | "jjn" => Some(Self::JarvisJudiceNinke),
+            "stucki" => Some(Self::Stucki),
+            "bayer" => Some(Self::Bayer),
+            "blue-noise" => Some(Self::BlueNoise),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Atkinson => "atkinson",
+            Self::FloydSteinberg => "floyd-steinberg",
+            Self::JarvisJudiceNinke => "jarvis-judice-ninke",
+            Self::Stucki => "stucki",
+            Self::Bayer => "bayer",
+            Self::BlueNoise => "blue-noise",
+            Self::None => "none",
+        }
+    }
+}
+
+/// Atkinson dithering error diffusion pattern
+/// Diffuses only 6/8 (75%) of error to neighbors, absorbing the rest
+/// Pattern (fractions of error distributed):
+///       X   1/8 1/8
+///   1/8 1/8 1/8
+///       1/8
+const ATKINSON_KERNEL: [(i32, i32, f32); 6] = [
+    (1, 0, 1.0 / 8.0),  // Right
+    (2, 0, 1.0 / 8.0),  // Right + 1
+    (-1, 1, 1.0 / 8.0), // Below left
+    (0, 1, 1.0 / 8.0),  // Below
+    (1, 1, 1.0 / 8.0),  // Below right
+    (0, 2, 1.0 / 8.0),  // Below + 1
+];
+
+/// Floyd–Steinberg: diffuses all of the error to four neighbors
+///         X   7/16
+///   3/16 5/16 1/16
+const FLOYD_STEINBERG_KERNEL: [(i32, i32, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+
+/// Jarvis–Judice–Ninke: 12 neighbors over two rows, weights in 48ths
+const JARVIS_JUDICE_NINKE_KERNEL: [(i32, i32, f32); 12] = [
+    (1, 0, 7.0 / 48.0),
+    (2, 0, 5.0 / 48.0),
+    (-2, 1, 3.0 / 48.0),
+    (-1, 1, 5.0 / 48.0),
+    (0, 1, 7.0 / 48.0),
+    (1, 1, 5.0 / 48.0),
+    (2, 1, 3.0 / 48.0),
+    (-2, 2, 1.0 / 48.0),
+    (-1, 2, 3.0 / 48.0),
+    (0, 2, 5.0 / 48.0),
+    (1, 2, 3.0 / 48.0),
+    (2, 2, 1.0 / 48.0),
+];
+
+/// Stucki: same footprint as JJN with sharper weights, in 42nds
+const STUCKI_KERNEL: [(i32, i32, f32); 12] = [
+    (1, 0, 8.0 / 42.0),
+    (2, 0, 4.0 / 42.0),
+    (-2, 1, 2.0 / 42.0),
+    (-1, 1, 4.0 / 42.0),
+    (0, 1, 8.0 / 42.0),
+    (1, 1, 4.0 / 42.0),
+    (2, 1, 2.0 / 42.0),
+    (-2, 2, 1.0 / 42.0),
+    (-1, 2, 2.0 / 42.0),
+    (0, 2, 4.0 / 42.0),
+    (1, 2, 2.0 / 42.0),
+    (2, 2, 1.0 / 42.0),
+];
+
+/// Classic 8x8 Bayer index matrix (values 0-63)
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// RGB offset range for threshold dithering. Large enough that a gray ramp reaches
+/// both black and white, small enough that flat palette colors stay solid.
+const THRESHOLD_SPREAD: f32 = 192.0;
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Convert a rendered pixmap to an e-ink bitmap of palette positions using the
+/// given dithering mode
+pub fn dither_pixmap(
+    pixmap: &tiny_skia::Pixmap,
+    mode: DitherMode,
+    palette: &DitherPalette,
+) -> EpdBitmap {
+    match mode {
+        DitherMode::Atkinson => diffuse_error(pixmap, palette, &ATKINSON_KERNEL),
+        DitherMode::FloydSteinberg => diffuse_error(pixmap, palette, &FLOYD_STEINBERG_KERNEL),
+        DitherMode::JarvisJudiceNinke => {
+            diffuse_error(pixmap, palette, &JARVIS_JUDICE_NINKE_KERNEL)
+        }
+        DitherMode::Stucki => diffuse_error(pixmap, palette, &STUCKI_KERNEL),
+        DitherMode::Bayer => threshold(pixmap, palette, |x, y| {
+            (BAYER_8X8[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5
+        }),
+        DitherMode::BlueNoise => {
+            let ranks = blue_noise_ranks();
+            let n = (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as f32;
+            threshold(pixmap, palette, |x, y| {
+                let i = (y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE;
+                (ranks[i] as f32 + 0.5) / n - 0.5
+            })
+        }
+        DitherMode::None => threshold(pixmap, palette, |_, _| 0.0),
+    }
+}
+
+/// Error diffusion in raster order with the given kernel of (dx, dy, weight)
+fn diffuse_error(
+    pixmap: &tiny_skia::Pixmap,
+    palette: &DitherPalette,
+    kernel: &[(i32, i32, f32)],
+) -> EpdBitmap {
+    let (width, height) = (pixmap.width() as usize, pixmap.height() as usize);
+    let mut bitmap = EpdBitmap::new(width as u16, height as u16);
+
+    // Error buffer (stores Lab error values)
+    let mut errors = vec![vec![[0.0f32; 3]; width]; height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = pixmap.pixels()[y * width + x].demultiply();
+
+            // Add accumulated error from previous pixels, then pull the result back
+            // into what the pigments can reproduce so out-of-gamut error can't build up
+            let lab = palette.input_lab(pixel.red(), pixel.green(), pixel.blue());
+            let err = errors[y][x];
+            let lab = palette.gamut_map([lab[0] + err[0], lab[1] + err[1], lab[2] + err[2]]);
+
+            // Find closest e-ink color and its quantization error
+            let color = palette.nearest(lab);
+            let chosen = palette.lab(color);
+            let quant_err = [lab[0] - chosen[0], lab[1] - chosen[1], lab[2] - chosen[2]];
+
+            // Distribute error to neighboring pixels
+            for &(dx, dy, weight) in kernel {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+
+                if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
+                    let err = &mut errors[ny as usize][nx as usize];
+                    for c in 0..3 {
+                        err[c] += quant_err[c] * weight;
+                    }
+                }
+            }
+
+            bitmap.set_index(x as u16, y as u16, color);
+        }
+    }
+
+    bitmap
+}
+
+/// Threshold dithering: offsets each pixel by `offset(x, y)` (in -0.5..0.5) times
+/// `THRESHOLD_SPREAD` before picking the nearest palette color. No gamut mapping here:
+/// without error to carry, an out-of-gamut color is best served by its nearest pigment.
+fn threshold(
+    pixmap: &tiny_skia::Pixmap,
+    palette: &DitherPalette,
+    offset: impl Fn(usize, usize) -> f32,
+) -> EpdBitmap {
+    let (width, height) = (pixmap.width() as usize, pixmap.height() as usize);
+    let mut bitmap = EpdBitmap::new(width as u16, height as u16);
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = pixmap.pixels()[y * width + x].demultiply();
+            let delta = offset(x, y) * THRESHOLD_SPREAD;
+            let shift = |c: u8| (c as f32 + delta).clamp(0.0, 255.0) as u8;
+            let lab = palette.input_lab(
+                shift(pixel.red()),
+                shift(pixel.green()),
+                shift(pixel.blue()),
+            );
+            let color = palette.nearest(lab);
+            bitmap.set_index(x as u16, y as u16, color);
+        }
+    }
+
+    bitmap
+}
+
+/// Blue-noise threshold ranks (0..BLUE_NOISE_SIZE²), generated once on first use
+fn blue_noise_ranks() -> &'static [u16] {
+    static RANKS: OnceLock<Vec<u16>> = OnceLock::new();
+    RANKS.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 1.5))
+}
+
+/// Ulichney's void-and-cluster method on a `size`x`size` torus. Returns the rank
+/// at which each cell turns on; thresholding by rank gives blue-noise patterns.
+fn void_and_cluster(size: usize, sigma: f32) -> Vec<u16> {
+    let n = size * size;
+
+    // Gaussian weight for each toroidal offset
+    let gauss: Vec<f32> = (0..n)
+        .map(|i| {
+            let (dx, dy) = (i % size, i / size);
+            let dx = dx.min(size - dx) as f32;
+            let dy = dy.min(size - dy) as f32;
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+
+    // Energy of each cell is the Gaussian-weighted count of "on" cells around it
+    let toggle = |pattern: &mut [bool], energy: &mut [f32], p: usize| {
+        pattern[p] = !pattern[p];
+        let sign = if pattern[p] { 1.0 } else { -1.0 };
+        let (px, py) = (p % size, p / size);
+        for (q, e) in energy.iter_mut().enumerate() {
+            let dx = (q % size + size - px) % size;
+            let dy = (q / size + size - py) % size;
+            *e += sign * gauss[dy * size + dx];
+        }
+    };
+    // Tightest cluster among on cells, or largest void among off cells
+    let extreme = |pattern: &[bool], energy: &[f32], on: bool| -> usize {
+        let candidates = (0..n).filter(|&i| pattern[i] == on);
+        if on {
+            candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        } else {
+            candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        }
+        .unwrap_or(0)
+    };
+
+    // Initial pattern: 10% of cells on, from a fixed-seed xorshift so output is stable
+    let mut pattern = vec![false; n];
+    let mut energy = vec![0.0f32; n];
+    let mut seed = 0x2545_f491u32;
+    let mut ones = 0;
+    while ones < n / 10 {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let p = seed as usize % n;
+        if !pattern[p] {
+            toggle(&mut pattern, &mut energy, p);
+            ones += 1;
+        }
+    }
+
+    // Move the tightest cluster into the largest void until that changes nothing
+    for _ in 0..n {
+        let cluster = extreme(&pattern, &energy, true);
+        toggle(&mut pattern, &mut energy, cluster);
+        let void = extreme(&pattern, &energy, false);
+        toggle(&mut pattern, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0u16; n];
+
+    // Phase 1: rank the prototype's on cells by removing tightest clusters
+    let (mut p1_pattern, mut p1_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..ones).rev() {
+        let cluster = extreme(&p1_pattern, &p1_energy, true);
+        toggle(&mut p1_pattern, &mut p1_energy, cluster);
+        ranks[cluster] = rank as u16;
+    }
+
+    // Phases 2 and 3: fill the remaining cells, largest void first
+    for rank in ones..n {
+        let void = extreme(&pattern, &energy, false);
+        toggle(&mut pattern, &mut energy, void);
+        ranks[void] = rank as u16;
+    }
+
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dither_pixmap, void_and_cluster, DitherMode};
+    use crate::bitmap::rasterize_svg;
+    use crate::palette::DitherPalette;
+    use crate::panel::DEFAULT_PANEL;
+    use std::path::PathBuf;
+
+    /// Gray ramp, hue ramp, and a thin diagonal line: the cases the modes disagree on
+    const GOLDEN_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="96" height="64">
+        <defs>
+            <linearGradient id="gray"><stop offset="0" stop-color="black"/><stop offset="1" stop-color="white"/></linearGradient>
+            <linearGradient id="hue"><stop offset="0" stop-color="#ff0000"/><stop offset="0.5" stop-color="#00ff00"/><stop offset="1" stop-color="#0000ff"/></linearGradient>
+        </defs>
+        <rect width="96" height="24" fill="url(#gray)"/>
+        <rect y="24" width="96" height="24" fill="url(#hue)"/>
+        <line x1="0" y1="52" x2="96" y2="62" stroke="#1a5fb4" stroke-width="1"/>
+    </svg>"##;
+
+    /// Compares each mode against `testdata/dither/<mode>.png`.
+    /// Run with `UPDATE_GOLDEN=1` to regenerate after an intentional change.
+    #[test]
+    fn dither_modes_match_golden_images() {
+        let pixmap = rasterize_svg(GOLDEN_SVG.as_bytes(), 96, 64).unwrap();
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/dither");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let palette = DitherPalette::from_panel(DEFAULT_PANEL, 1.2);
+
+        for mode in [
+            DitherMode::Atkinson,
+            DitherMode::FloydSteinberg,
+            DitherMode::JarvisJudiceNinke,
+            DitherMode::Stucki,
+            DitherMode::Bayer,
+            DitherMode::BlueNoise,
+            DitherMode::None,
+        ] {
+            let png = dither_pixmap(&pixmap, mode, &palette)
+                .to_png(&palette.preview_rgb())
+                .unwrap();
+            let path = dir.join(format!("{}.png", mode.name()));
+            if update {
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(&path, &png).unwrap();
+                continue;
+            }
+            let golden = std::fs::read(&path).unwrap_or_else(|e| {
+                panic!(
+                    "missing {} ({}); run with UPDATE_GOLDEN=1",
+                    path.display(),
+                    e
+                )
+            });
+            let decode = |bytes: &[u8]| {
+                tiny_skia::Pixmap::decode_png(bytes)
+                    .unwrap()
+                    .data()
+                    .to_vec()
+            };
+            assert!(
+                decode(&png) == decode(&golden),
+                "{} differs from {}; run with UPDATE_GOLDEN=1 if intended",
+                mode.name(),
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn void_and_cluster_ranks_are_a_permutation() {
+        let mut ranks = void_and_cluster(16, 1.5);
+        ranks.sort_unstable();
+        assert!(ranks.iter().enumerate().all(|(i, &r)| r as usize == i));
+    }
+}
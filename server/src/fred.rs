@@ -1,6 +1,7 @@
-use crate::svg_common;
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use crate::upstream::{FetchError, RateLimit, Upstream};
 use chrono::{Local, NaiveDate, Timelike};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 // FRED API response structures
 #[derive(Debug, Deserialize)]
@@ -14,7 +15,7 @@ pub struct FredObservation {
     pub value: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SeriesData {
     #[allow(dead_code)]
     pub symbol: String,
@@ -22,14 +23,14 @@ pub struct SeriesData {
     pub points: Vec<DataPoint>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataPoint {
     #[allow(dead_code)]
     pub date: String,
     pub value: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FredData {
     pub vix: SeriesData,
     pub sp500: SeriesData,
@@ -51,7 +52,7 @@ const YIELD_CURVE_VEL_THRESHOLD: f64 = 3.0;
 
 /// Classifies the type of yield curve steepening based on which leg is driving it.
 /// This is critical for distinguishing crisis signals from benign expansion.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum SteepeningType {
     /// 3M rate falling → short-end front-running Fed cuts; systemic risk signal
     BullSteepening,
@@ -65,6 +66,30 @@ pub enum SteepeningType {
     Stable,
 }
 
+impl SteepeningType {
+    /// Label and color for the signal
+    fn signal(&self) -> (&'static str, &'static str) {
+        match self {
+            SteepeningType::BullSteepening => ("BULL STEEP \u{26a0} Crisis Signal", "red"),
+            SteepeningType::BearSteepening => ("Bear Steep \u{2014} Expansion", "#cc8800"),
+            SteepeningType::Flattening => ("Flattening \u{2014} Caution", "#cc8800"),
+            SteepeningType::Inverting => ("Inverting \u{26a0} Warning", "red"),
+            SteepeningType::Stable => ("Stable", "#666666"),
+        }
+    }
+}
+
+/// VIX levels where calm turns to elevated and elevated to fear
+const VIX_CALM: f64 = 20.0;
+const VIX_FEAR: f64 = 40.0;
+/// S&P 500 drawdowns from the window's high marking a correction and a bear market
+const SP500_CORRECTION: f64 = -0.07;
+const SP500_BEAR: f64 = -0.20;
+/// High-yield spread levels, in percentage points, where normal turns to elevated and
+/// elevated to stress
+const CREDIT_SPREAD_NORMAL: f64 = 3.0;
+const CREDIT_SPREAD_STRESS: f64 = 4.0;
+
 use crate::kalman::KalmanFilter;
 
 // Position process noise ~0.07 pp/day; velocity process noise tightened to reduce
@@ -160,11 +185,13 @@ fn classify_at_point(
 }
 
 async fn fetch_series(
+    upstream: &Upstream,
+    limit: &RateLimit,
     api_key: &str,
     series_id: &str,
     end_date: Option<&str>,
     duration: usize,
-) -> Result<Vec<DataPoint>, Box<dyn std::error::Error>> {
+) -> Result<Vec<DataPoint>, FetchError> {
     let url = if let Some(end) = end_date {
         // Parse end date from YYYYMMDD format
         let end_date = NaiveDate::parse_from_str(end, "%Y%m%d")
@@ -185,9 +212,7 @@ async fn fetch_series(
         )
     };
 
-    let client = reqwest::Client::new();
-    let response = client.get(&url).send().await?;
-    let fred_response: FredResponse = response.json().await?;
+    let fred_response: FredResponse = upstream.get_json(&url, limit).await?;
 
     let mut points: Vec<DataPoint> = fred_response
         .observations
@@ -213,6 +238,8 @@ async fn fetch_series(
 /// Fetches economic data from FRED API
 ///
 /// # Arguments
+/// * `upstream` - Shared HTTP client
+/// * `limit` - FRED's rate limit
 /// * `api_key` - FRED API key (get one free at https://fred.stlouisfed.org/docs/api/api_key.html)
 /// * `end_date` - Optional end date in YYYYMMDD format (defaults to today)
 /// * `duration` - Optional duration in days (defaults to 365)
@@ -220,10 +247,12 @@ async fn fetch_series(
 /// # Returns
 /// Result containing FredData on success, or error message on failure
 pub async fn fetch_fred(
+    upstream: &Upstream,
+    limit: &RateLimit,
     api_key: &str,
     end_date: Option<&str>,
     duration: Option<usize>,
-) -> Result<FredData, Box<dyn std::error::Error>> {
+) -> Result<FredData, FetchError> {
     let duration = duration.unwrap_or(365);
 
     // Determine the actual end date to display
@@ -237,31 +266,19 @@ pub async fn fetch_fred(
         .unwrap_or_else(|_| Local::now().date_naive());
     let chart_start_date = chart_end_date - chrono::Duration::days(duration as i64);
 
-    let vix = fetch_series(api_key, "VIXCLS", end_date, duration).await?;
-    let sp500 = fetch_series(api_key, "SP500", end_date, duration).await?;
-    let credit_spread = fetch_series(api_key, "BAMLH0A0HYM2", end_date, duration).await?;
-    let yield_curve = fetch_series(
-        api_key,
-        "T10Y3M",
-        end_date,
-        duration + YIELD_CURVE_WARMUP_DAYS,
-    )
-    .await?;
-    // Fetch individual legs to distinguish bull vs. bear steepening.
-    let dgs10 = fetch_series(
-        api_key,
-        "DGS10",
-        end_date,
-        duration + YIELD_CURVE_WARMUP_DAYS,
-    )
-    .await?;
-    let dtb3 = fetch_series(
-        api_key,
-        "DTB3",
-        end_date,
-        duration + YIELD_CURVE_WARMUP_DAYS,
-    )
-    .await?;
+    // The yield curve legs distinguish bull from bear steepening. All six are fetched at
+    // once, within FRED's rate limit.
+    let series =
+        |series_id, days| fetch_series(upstream, limit, api_key, series_id, end_date, days);
+    let warmed_up = duration + YIELD_CURVE_WARMUP_DAYS;
+    let (vix, sp500, credit_spread, yield_curve, dgs10, dtb3) = tokio::try_join!(
+        series("VIXCLS", duration),
+        series("SP500", duration),
+        series("BAMLH0A0HYM2", duration),
+        series("T10Y3M", warmed_up),
+        series("DGS10", warmed_up),
+        series("DTB3", warmed_up),
+    )?;
 
     let yield_curve_velocity = compute_velocity_series(&yield_curve);
     let dgs10_velocity = compute_velocity_series(&dgs10);
@@ -393,17 +410,87 @@ pub async fn fetch_fred(
     })
 }
 
+/// The latest value of each series and the zone it is in, red where the charts turn red
+fn compact_fred_svg(fred: &FredData, battery_pct: Option<u8>, canvas: Canvas) -> String {
+    let latest = |series: &SeriesData| series.points.last().map(|p| p.value);
+    let mut card = CompactCard::new("Market Crash Monitor", battery_pct);
+    if let Some(vix) = latest(&fred.vix) {
+        let (zone, color) = if vix >= VIX_FEAR {
+            ("fear", Ink::Red)
+        } else if vix >= VIX_CALM {
+            ("elevated", Ink::Black)
+        } else {
+            ("calm", Ink::Black)
+        };
+        card.lines.push(
+            CardLine::new("VIX")
+                .with_value(format!("{:.1} · {}", vix, zone))
+                .colored(color),
+        );
+    }
+    if let Some(sp500) = latest(&fred.sp500) {
+        let high = fred
+            .sp500
+            .points
+            .iter()
+            .map(|p| p.value)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let drawdown = sp500 / high - 1.0;
+        let line = CardLine::new("S&P 500").with_value(format!(
+            "{:.0} · {:+.1}% from high",
+            sp500,
+            drawdown * 100.0
+        ));
+        card.lines.push(if drawdown <= SP500_BEAR {
+            line.colored(Ink::Red)
+        } else {
+            line
+        });
+    }
+    if let Some(spread) = latest(&fred.credit_spread) {
+        let (zone, color) = if spread >= CREDIT_SPREAD_STRESS {
+            ("stress", Ink::Red)
+        } else if spread >= CREDIT_SPREAD_NORMAL {
+            ("elevated", Ink::Black)
+        } else {
+            ("normal", Ink::Black)
+        };
+        card.lines.push(
+            CardLine::new("Credit spread")
+                .with_value(format!("{:.2} · {}", spread, zone))
+                .colored(color),
+        );
+    }
+    let (signal, color) = fred.yield_curve_steepening.signal();
+    card.lines
+        .push(CardLine::new("Yield curve").with_value(format!("{:+.2}", fred.yield_curve_level)));
+    card.lines
+        .push(CardLine::new(signal).colored(if color == "red" { Ink::Red } else { Ink::Black }));
+    if let Ok(end_date) = NaiveDate::parse_from_str(&fred.end_date, "%Y%m%d") {
+        card.lines.push(CardLine::new(format!(
+            "{} days to {}",
+            fred.duration,
+            end_date.format("%b %d, %Y")
+        )));
+    }
+    card.svg(canvas)
+}
+
 /// Generates an SVG display of economic data
 ///
 /// # Arguments
 /// * `fred` - The FRED economic data to display
 /// * `battery_pct` - Optional battery percentage to display
+/// * `canvas` - Size to lay out for
 ///
 /// # Returns
 /// A String containing the SVG markup
-pub fn generate_fred_svg(fred: &FredData, battery_pct: Option<u8>) -> String {
-    let width = 800;
-    let height = 480;
+pub fn generate_fred_svg(fred: &FredData, battery_pct: Option<u8>, canvas: Canvas) -> String {
+    let width = canvas.width;
+    let height = canvas.height;
+    if canvas.is_compact() {
+        return compact_fred_svg(fred, battery_pct, canvas);
+    }
     let mut svg = String::new();
 
     svg.push_str(&format!(
@@ -445,13 +532,15 @@ pub fn generate_fred_svg(fred: &FredData, battery_pct: Option<u8>) -> String {
     ));
 
     // Create 2x2 grid of charts (leaving room for header and footer)
-    let chart_width = 380;
-    let chart_height = 200;
+    let chart_width = (width - 40) / 2;
+    let chart_height = (height - 80) / 2;
+    let right_x = 10 + chart_width + 20;
+    let bottom_y = 35 + chart_height + 10;
     let positions = [
-        (10, 35),   // Top-left (VIX)
-        (410, 35),  // Top-right (S&P 500)
-        (10, 245),  // Bottom-left (Credit Spreads)
-        (410, 245), // Bottom-right (Yield Curve)
+        (10, 35),            // Top-left (VIX)
+        (right_x, 35),       // Top-right (S&P 500)
+        (10, bottom_y),      // Bottom-left (Credit Spreads)
+        (right_x, bottom_y), // Bottom-right (Yield Curve)
     ];
 
     // Generate charts
@@ -557,8 +646,8 @@ fn generate_vix_chart(series: &SeriesData, x: i32, y: i32, width: i32, height: i
     }
 
     // VIX regime thresholds
-    let calm_threshold = 20.0;
-    let fear_threshold = 40.0;
+    let calm_threshold = VIX_CALM;
+    let fear_threshold = VIX_FEAR;
 
     // Calculate data range, ensuring thresholds are always visible
     let data_min = series
@@ -715,8 +804,8 @@ fn generate_sp500_chart(series: &SeriesData, x: i32, y: i32, width: i32, height:
         .unwrap_or(100.0);
 
     // Calculate drawdown thresholds based on highest value in chart
-    let threshold_7 = data_max * 0.93; // -7%
-    let threshold_20 = data_max * 0.80; // -20%
+    let threshold_7 = data_max * (1.0 + SP500_CORRECTION); // -7%
+    let threshold_20 = data_max * (1.0 + SP500_BEAR); // -20%
 
     // Ensure both thresholds are always visible
     let min_val = data_min.min(threshold_20);
@@ -870,8 +959,8 @@ fn generate_credit_spread_chart(
     }
 
     // High yield spread regime thresholds
-    let normal_threshold = 3.0;
-    let stress_threshold = 4.0;
+    let normal_threshold = CREDIT_SPREAD_NORMAL;
+    let stress_threshold = CREDIT_SPREAD_STRESS;
 
     // Calculate data range, ensuring thresholds are always visible
     let data_min = series
@@ -997,13 +1086,7 @@ fn generate_yield_curve_chart(
     ));
 
     // Title left, signal right — both on the same row, no sub-header needed
-    let (signal_text, signal_color) = match steepening {
-        SteepeningType::BullSteepening => ("BULL STEEP \u{26a0} Crisis Signal", "red"),
-        SteepeningType::BearSteepening => ("Bear Steep \u{2014} Expansion", "#cc8800"),
-        SteepeningType::Flattening => ("Flattening \u{2014} Caution", "#cc8800"),
-        SteepeningType::Inverting => ("Inverting \u{26a0} Warning", "red"),
-        SteepeningType::Stable => ("Stable", "#666666"),
-    };
+    let (signal_text, signal_color) = steepening.signal();
     svg.push_str(&format!(
         r#"<text x="{}" y="{}" text-anchor="start" font-size="16" font-weight="bold" fill="black">{}</text>"#,
         x + 5,
@@ -0,0 +1,264 @@
+//! Technical indicators for the stocks screen, computed from daily closes. Each returns
+//! one value per input point, `None` until there is enough history for it, so the
+//! results line up with the candles they are drawn over.
+
+use serde::Deserialize;
+
+/// An overlay or sub-panel on a stock chart, as named in the config: `sma20`, `ema50`,
+/// `bollinger`, `rsi`, `macd` or `volume`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Indicator {
+    /// Simple moving average over the period
+    Sma(usize),
+    /// Exponential moving average over the period
+    Ema(usize),
+    /// 20-day bands two standard deviations either side of the 20-day SMA
+    Bollinger,
+    /// 14-day relative strength index
+    Rsi,
+    /// 12/26-day MACD with a 9-day signal line
+    Macd,
+    Volume,
+}
+
+impl Indicator {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let period = |prefix: &str| {
+            name.strip_prefix(prefix)?
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| (2..=200).contains(&n))
+        };
+        match name.as_str() {
+            "bollinger" => Some(Indicator::Bollinger),
+            "rsi" => Some(Indicator::Rsi),
+            "macd" => Some(Indicator::Macd),
+            "volume" => Some(Indicator::Volume),
+            _ => period("sma")
+                .map(Indicator::Sma)
+                .or_else(|| period("ema").map(Indicator::Ema)),
+        }
+    }
+
+    /// Drawn in its own panel under the candles rather than over them
+    pub fn is_panel(self) -> bool {
+        matches!(self, Indicator::Rsi | Indicator::Macd | Indicator::Volume)
+    }
+}
+
+impl TryFrom<String> for Indicator {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, String> {
+        Self::from_name(&name).ok_or_else(|| format!("unknown indicator {}", name))
+    }
+}
+
+pub const BOLLINGER_PERIOD: usize = 20;
+pub const BOLLINGER_WIDTH: f64 = 2.0;
+pub const RSI_PERIOD: usize = 14;
+pub const MACD_FAST: usize = 12;
+pub const MACD_SLOW: usize = 26;
+pub const MACD_SIGNAL: usize = 9;
+
+pub fn sma(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| {
+            let start = (i + 1).checked_sub(period)?;
+            Some(values[start..=i].iter().sum::<f64>() / period as f64)
+        })
+        .collect()
+}
+
+/// Seeded with the SMA of the first `period` values, then smoothed by `2 / (period + 1)`
+pub fn ema(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut result = vec![None; values.len()];
+    let mut previous: Option<f64> = None;
+    for (i, &value) in values.iter().enumerate() {
+        previous = match previous {
+            Some(previous) => Some(previous + alpha * (value - previous)),
+            None if i + 1 == period => Some(values[..period].iter().sum::<f64>() / period as f64),
+            None => None,
+        };
+        result[i] = previous;
+    }
+    result
+}
+
+/// `None`s in `values` are skipped, and the output is `None` there too
+fn ema_of(values: &[Option<f64>], period: usize) -> Vec<Option<f64>> {
+    let first = values
+        .iter()
+        .position(Option::is_some)
+        .unwrap_or(values.len());
+    let present: Vec<f64> = values[first..].iter().map_while(|v| *v).collect();
+    let mut result = vec![None; first];
+    result.extend(ema(&present, period));
+    result.resize(values.len(), None);
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub lower: f64,
+    pub middle: f64,
+    pub upper: f64,
+}
+
+/// The SMA with bands `width` population standard deviations either side
+pub fn bollinger(values: &[f64], period: usize, width: f64) -> Vec<Option<Band>> {
+    sma(values, period)
+        .into_iter()
+        .enumerate()
+        .map(|(i, middle)| {
+            let middle = middle?;
+            let window = &values[i + 1 - period..=i];
+            let variance = window.iter().map(|v| (v - middle).powi(2)).sum::<f64>() / period as f64;
+            let spread = width * variance.sqrt();
+            Some(Band {
+                lower: middle - spread,
+                middle,
+                upper: middle + spread,
+            })
+        })
+        .collect()
+}
+
+/// Wilder's RSI: average gains and losses seeded with the simple average of the first
+/// `period` changes, then smoothed by `1 / period`
+pub fn rsi(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut result = vec![None; values.len()];
+    if values.len() <= period {
+        return result;
+    }
+    let change = |i: usize| values[i] - values[i - 1];
+    let (mut gain, mut loss) = (1..=period).fold((0.0, 0.0), |(gain, loss), i| {
+        let change = change(i);
+        (gain + change.max(0.0), loss + (-change).max(0.0))
+    });
+    gain /= period as f64;
+    loss /= period as f64;
+    let index = |gain: f64, loss: f64| {
+        if loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    };
+    result[period] = Some(index(gain, loss));
+    for (i, value) in result.iter_mut().enumerate().skip(period + 1) {
+        let change = change(i);
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+        *value = Some(index(gain, loss));
+    }
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacdPoint {
+    pub macd: f64,
+    /// `None` until the signal line's EMA has enough MACD values
+    pub signal: Option<f64>,
+}
+
+impl MacdPoint {
+    pub fn histogram(&self) -> Option<f64> {
+        Some(self.macd - self.signal?)
+    }
+}
+
+/// Fast EMA minus slow EMA, with an EMA of that as the signal line
+pub fn macd(values: &[f64], fast: usize, slow: usize, signal: usize) -> Vec<Option<MacdPoint>> {
+    let macd: Vec<Option<f64>> = ema(values, fast)
+        .into_iter()
+        .zip(ema(values, slow))
+        .map(|(fast, slow)| Some(fast? - slow?))
+        .collect();
+    let signal = ema_of(&macd, signal);
+    macd.into_iter()
+        .zip(signal)
+        .map(|(macd, signal)| {
+            Some(MacdPoint {
+                macd: macd?,
+                signal,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bollinger, ema, macd, rsi, sma, Indicator};
+
+    fn assert_close(actual: Option<f64>, expected: f64, tolerance: f64) {
+        let actual = actual.expect("no value");
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn moving_averages_and_bands() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(
+            sma(&values, 3),
+            vec![None, None, Some(2.0), Some(3.0), Some(4.0)]
+        );
+        // Seeded with the SMA, then halfway to each new value
+        assert_eq!(
+            ema(&values, 3),
+            vec![None, None, Some(2.0), Some(3.0), Some(4.0)]
+        );
+        assert_eq!(ema(&[2.0, 4.0, 9.0, 1.0], 3)[3], Some(3.0));
+
+        // 1..=20: mean 10.5, population standard deviation sqrt(399 / 12)
+        let ramp: Vec<f64> = (1..=20).map(f64::from).collect();
+        let band = bollinger(&ramp, 20, 2.0)[19].unwrap();
+        assert_close(Some(band.middle), 10.5, 1e-9);
+        assert_close(
+            Some(band.upper),
+            10.5 + 2.0 * (399.0f64 / 12.0).sqrt(),
+            1e-9,
+        );
+        assert!(bollinger(&ramp, 20, 2.0)[18].is_none());
+
+        assert_eq!(Indicator::from_name("SMA50"), Some(Indicator::Sma(50)));
+        assert_eq!(Indicator::from_name("ema1"), None);
+        assert_eq!(Indicator::from_name("vwap"), None);
+    }
+
+    #[test]
+    fn rsi_matches_wilders_worked_example() {
+        // The closes from StockCharts' RSI spreadsheet. It shows 70.53 and 66.32 because it
+        // rounds the average gain and loss to cents; unrounded they give 70.46 and 66.25
+        let closes = [
+            44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03,
+            45.61, 46.28, 46.28, 46.00,
+        ];
+        let rsi = rsi(&closes, 14);
+        assert!(rsi[13].is_none());
+        assert_close(rsi[14], 70.46, 0.01);
+        assert_close(rsi[15], 66.25, 0.01);
+    }
+
+    #[test]
+    fn macd_of_a_steady_trend_is_the_lag_between_its_averages() {
+        // An EMA of a straight line lags it by slope * (period - 1) / 2, so the 12/26 MACD
+        // of a line rising by 2 a day is 2 * (25 - 11) / 2 = 14, and the signal matches
+        let line: Vec<f64> = (0..60).map(|i| 100.0 + 2.0 * i as f64).collect();
+        let macd = macd(&line, 12, 26, 9);
+        assert!(macd[24].is_none());
+        assert_close(macd[25].map(|m| m.macd), 14.0, 1e-9);
+        assert!(macd[32].unwrap().signal.is_none());
+        let last = macd[59].unwrap();
+        assert_close(last.signal, 14.0, 1e-9);
+        assert_close(last.histogram(), 0.0, 1e-9);
+    }
+}
@@ -0,0 +1,537 @@
+//! Indoor conditions screen: the outdoor temperature and sky from the forecast beside
+//! the latest reading of up to three home sensors, each with sparklines of its last
+//! 24 hours. A red banner asks for ventilation while any sensor's CO2 is at or above
+//! the screen's warning level.
+
+use crate::locale::{Locale, Units};
+use crate::sensors::SensorReading;
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use crate::weather::{load_weather_icon_as_data_uri, WeatherData};
+use chrono::prelude::*;
+use chrono::Duration;
+use reverse_geocoder::ReverseGeocoder;
+
+/// Span of the sparklines
+pub const HISTORY: Duration = Duration::hours(24);
+/// Readings older than this are marked with the time they were taken
+const STALE_AFTER: Duration = Duration::minutes(30);
+/// CO2 level for the ventilation warning when the screen sets none, in ppm
+pub const DEFAULT_CO2_WARNING: f32 = 1000.0;
+
+/// One measurement's values over time
+type Series = Vec<(DateTime<Utc>, f32)>;
+
+/// One configured sensor and its readings over `HISTORY`, oldest first
+pub struct SensorHistory {
+    pub sensor: String,
+    pub readings: Vec<SensorReading>,
+}
+
+impl SensorHistory {
+    fn latest(&self) -> Option<&SensorReading> {
+        self.readings.last()
+    }
+
+    /// The most recent value of one measurement, which may predate the last reading
+    /// when sensors report measurements separately
+    fn latest_value(&self, value: fn(&SensorReading) -> Option<f32>) -> Option<f32> {
+        self.readings.iter().rev().find_map(value)
+    }
+}
+
+/// "living_room" as "Living room"
+fn display_name(sensor: &str) -> String {
+    let name = sensor.replace(['_', '-'], " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+fn celsius_to_units(temp: f32, units: Units) -> f32 {
+    units.fahrenheit_to_units(temp * 9.0 / 5.0 + 32.0)
+}
+
+/// Sensors whose latest CO2 is at or above `warning`, with that level
+fn co2_alerts(sensors: &[SensorHistory], warning: f32) -> Vec<(&str, f32)> {
+    sensors
+        .iter()
+        .filter_map(|s| Some((s.sensor.as_str(), s.latest_value(|r| r.co2)?)))
+        .filter(|&(_, co2)| co2 >= warning)
+        .collect()
+}
+
+/// A measurement over `HISTORY` as a line in the box `(x, y, width, height)`, with its
+/// range to the left
+fn sparkline_svg(
+    points: &[(DateTime<Utc>, f32)],
+    now: DateTime<Utc>,
+    color: &str,
+    suffix: &str,
+    (x, y, width, height): (f64, f64, f64, f64),
+) -> String {
+    let (low, high) = points
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(low, high), &(_, v)| {
+            (low.min(v), high.max(v))
+        });
+    // Keep a steady reading from filling the box with noise: centre it instead
+    let span = (high - low).max(1.0);
+    let base = (high + low - span) / 2.0;
+    let start = now - HISTORY;
+    let x_at = |time: DateTime<Utc>| {
+        x + ((time - start).num_seconds() as f64 / HISTORY.num_seconds() as f64) * width
+    };
+    let y_at = |v: f32| y + height - ((v - base) / span) as f64 * height;
+    let line: Vec<String> = points
+        .iter()
+        .map(|&(time, v)| format!("{:.1},{:.1}", x_at(time), y_at(v)))
+        .collect();
+
+    let mut svg = format!(
+        r##"<line x1="{x}" y1="{bottom}" x2="{}" y2="{bottom}" stroke="#b0b0b0" stroke-width="1"/>"##,
+        x + width,
+        x = x,
+        bottom = y + height
+    );
+    svg.push_str(&format!(
+        r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+        line.join(" "),
+        color
+    ));
+    let (high_label, low_label) = (
+        format!("{:.0}{}", high, suffix),
+        format!("{:.0}{}", low, suffix),
+    );
+    svg.push_str(&svg_common::axis_minmax_labels(
+        x - 4.0,
+        y_at(high) + 8.0,
+        y_at(low),
+        &high_label,
+        // A steady reading needs one label
+        if low_label == high_label {
+            ""
+        } else {
+            &low_label
+        },
+    ));
+    svg.push('\n');
+    svg
+}
+
+/// The latest values and sparklines of one sensor in the box `(x, y, width, height)`
+fn sensor_svg(
+    sensor: &SensorHistory,
+    co2_warning: f32,
+    now: DateTime<Utc>,
+    units: Units,
+    locale: Locale,
+    (x, y, width, height): (f64, f64, f64, f64),
+) -> String {
+    let mut name = svg_common::escape_xml_text(&display_name(&sensor.sensor));
+    let Some(latest) = sensor.latest() else {
+        return format!(
+            r#"  <text x="{x}" y="{}" font-family="Arial" font-size="20" font-weight="bold" fill="black">{}</text><text x="{x}" y="{}" font-family="Arial" font-size="15" font-style="italic" fill="black">No readings in the last day</text>
+"#,
+            y + 20.0,
+            name,
+            y + 44.0,
+            x = x
+        );
+    };
+    if now - latest.time > STALE_AFTER {
+        let seen = latest.time.with_timezone(&Local);
+        name.push_str(&format!(
+            r#" <tspan font-size="14" font-weight="normal" font-style="italic">· seen {}</tspan>"#,
+            locale.time(&seen)
+        ));
+    }
+    let mut svg = format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="20" font-weight="bold" fill="black">{}</text>"#,
+        x,
+        y + 20.0,
+        name
+    );
+    svg.push('\n');
+
+    let temperature = sensor.latest_value(|r| r.temperature);
+    let humidity = sensor.latest_value(|r| r.humidity);
+    let co2 = sensor.latest_value(|r| r.co2);
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="40" font-weight="bold" fill="black">{}</text>"#,
+        x,
+        y + 64.0,
+        temperature.map_or("—".to_string(), |t| format!(
+            "{:.1}°",
+            celsius_to_units(t, units)
+        ))
+    ));
+    let mut details = Vec::new();
+    if let Some(humidity) = humidity {
+        details.push(format!("{:.0}% RH", humidity));
+    }
+    if let Some(co2) = co2 {
+        details.push(if co2 >= co2_warning {
+            format!(
+                r#"<tspan fill="red" font-weight="bold">CO₂ {:.0} ppm</tspan>"#,
+                co2
+            )
+        } else {
+            format!("CO₂ {:.0} ppm", co2)
+        });
+    }
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" font-family="Arial" font-size="16" fill="black">{}</text>"#,
+        x,
+        y + 88.0,
+        details.join(" · ")
+    ));
+    svg.push('\n');
+
+    // A strip per measurement the sensor reports, beside the values
+    let series = |value: fn(&SensorReading) -> Option<f32>| -> Series {
+        sensor
+            .readings
+            .iter()
+            .filter_map(|r| Some((r.time, value(r)?)))
+            .collect()
+    };
+    let temperatures = series(|r| r.temperature)
+        .into_iter()
+        .map(|(time, t)| (time, celsius_to_units(t, units)))
+        .collect();
+    let strips: Vec<(Series, &str, &str)> = vec![
+        (temperatures, "black", "°"),
+        (series(|r| r.humidity), "blue", "%"),
+        (series(|r| r.co2), "red", ""),
+    ]
+    .into_iter()
+    .filter(|(points, _, _)| points.len() > 1)
+    .collect();
+    let chart_x = x + width * 0.45 + 30.0;
+    let chart_width = x + width - chart_x;
+    let strip_height = (height - 12.0) / strips.len().max(1) as f64;
+    for (i, (points, color, suffix)) in strips.iter().enumerate() {
+        svg.push_str(&sparkline_svg(
+            points,
+            now,
+            color,
+            suffix,
+            (
+                chart_x,
+                y + 6.0 + i as f64 * strip_height,
+                chart_width,
+                strip_height - 6.0,
+            ),
+        ));
+    }
+    svg
+}
+
+/// The outdoor temperature as the headline, then the ventilation warning and a line per
+/// sensor with its latest values, without the sparklines
+#[allow(clippy::too_many_arguments)]
+fn compact_indoor_svg(
+    weather: &WeatherData,
+    sensors: &[SensorHistory],
+    co2_warning: f32,
+    now: DateTime<Utc>,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let coords = (weather.lat as f64, weather.lon as f64);
+    let mut card = CompactCard::new(
+        format!("Indoor · {}", geocoder.search(coords).record.name),
+        battery_pct,
+    );
+    if let Some(hour) = weather.hourly.first() {
+        let night = weather
+            .daily
+            .first()
+            .and_then(|day| Some(hour.dt < day.sunrise? || hour.dt >= day.sunset?))
+            .unwrap_or(false);
+        card.icon = load_weather_icon_as_data_uri(hour.condition, night).ok();
+        card.headline = Some(CardLine::new(format!("Out {:.0}°", hour.temp)));
+    }
+    if !co2_alerts(sensors, co2_warning).is_empty() {
+        card.lines
+            .push(CardLine::new("Open a window").colored(Ink::Red));
+    }
+    for sensor in sensors {
+        let mut name = display_name(&sensor.sensor);
+        let Some(latest) = sensor.latest() else {
+            card.lines
+                .push(CardLine::new(name).with_value("No readings"));
+            continue;
+        };
+        if now - latest.time > STALE_AFTER {
+            name.push_str(&format!(
+                " · seen {}",
+                locale.time(&latest.time.with_timezone(&Local))
+            ));
+        }
+        let co2 = sensor.latest_value(|r| r.co2);
+        let values: Vec<String> = [
+            sensor
+                .latest_value(|r| r.temperature)
+                .map(|t| format!("{:.1}°", celsius_to_units(t, units))),
+            sensor
+                .latest_value(|r| r.humidity)
+                .map(|h| format!("{:.0}%", h)),
+            co2.map(|co2| format!("{:.0} ppm", co2)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let line = CardLine::new(name).with_value(values.join(" · "));
+        card.lines
+            .push(if co2.is_some_and(|co2| co2 >= co2_warning) {
+                line.colored(Ink::Red)
+            } else {
+                line
+            });
+    }
+    card.svg(canvas)
+}
+
+/// Generates an SVG of the indoor sensors beside the outdoor conditions
+///
+/// # Arguments
+/// * `weather` - Forecast for the outdoor side
+/// * `sensors` - The screen's sensors, in order, with their last day of readings
+/// * `co2_warning` - CO2 level that brings up the ventilation banner, in ppm
+/// * `now` - Right edge of the sparklines
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `geocoder` - Reverse geocoder for the city name
+/// * `units` - Units the forecast was fetched in, and to show
+/// * `locale` - Date and clock format
+/// * `canvas` - Size to lay out for
+///
+/// # Returns
+/// A String containing the SVG markup
+#[allow(clippy::too_many_arguments)]
+pub fn generate_indoor_svg(
+    weather: &WeatherData,
+    sensors: &[SensorHistory],
+    co2_warning: f32,
+    now: DateTime<Utc>,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let (width, height) = (canvas.width, canvas.height);
+    if canvas.is_compact() {
+        return compact_indoor_svg(
+            weather,
+            sensors,
+            co2_warning,
+            now,
+            battery_pct,
+            geocoder,
+            units,
+            locale,
+            canvas,
+        );
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    );
+    svg.push('\n');
+    svg.push_str("  <defs>");
+    svg.push_str(svg_common::BATTERY_GRADIENT_DEF);
+    svg.push_str("</defs>\n");
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+    svg.push('\n');
+
+    svg.push_str(
+        r#"  <text x="20" y="35" font-family="Arial" font-size="28" font-weight="bold" fill="black">Indoor</text>"#,
+    );
+    svg.push('\n');
+    let coords = (weather.lat as f64, weather.lon as f64);
+    svg.push_str(&format!(
+        r#"  <text x="20" y="58" font-family="Arial" font-size="16" fill="black">{} · {}</text>"#,
+        locale.long_date(&now.with_timezone(&Local)),
+        svg_common::escape_xml_text(&geocoder.search(coords).record.name)
+    ));
+    svg.push('\n');
+
+    // Outdoors: this hour's sky and temperature, today's humidity
+    let outdoor_width = (width as f64 * 0.28).round();
+    svg.push_str(
+        r#"  <text x="20" y="92" font-family="Arial" font-size="20" font-weight="bold" fill="black">Outside</text>"#,
+    );
+    svg.push('\n');
+    if let Some(hour) = weather.hourly.first() {
+        let today = weather.daily.first();
+        let night = today
+            .and_then(|day| Some(hour.dt < day.sunrise? || hour.dt >= day.sunset?))
+            .unwrap_or(false);
+        if let Ok(data_uri) = load_weather_icon_as_data_uri(hour.condition, night) {
+            svg.push_str(&format!(
+                r#"  <image x="10" y="96" width="120" height="120" href="{}"/>"#,
+                data_uri
+            ));
+            svg.push('\n');
+        }
+        svg.push_str(&format!(
+            r#"  <text x="20" y="262" font-family="Arial" font-size="48" font-weight="bold" fill="black">{:.0}°</text>"#,
+            hour.temp
+        ));
+        svg.push_str(&format!(
+            r#"<text x="20" y="288" font-family="Arial" font-size="16" fill="black">Feels like {:.0}°</text>"#,
+            hour.feels_like
+        ));
+        if let Some(day) = today {
+            svg.push_str(&format!(
+                r#"<text x="20" y="312" font-family="Arial" font-size="16" fill="black">{}% RH</text><text x="20" y="336" font-family="Arial" font-size="16" fill="black">High {:.0}° · Low {:.0}°</text>"#,
+                day.humidity, day.temp_max, day.temp_min
+            ));
+        }
+        svg.push('\n');
+    } else {
+        svg.push_str(
+            r#"  <text x="20" y="130" font-family="Arial" font-size="15" font-style="italic" fill="black">No forecast</text>"#,
+        );
+        svg.push('\n');
+    }
+
+    // Ventilation warning across the bottom
+    let alerts = co2_alerts(sensors, co2_warning);
+    let footer_top = height as f64 - 30.0;
+    let bottom = if alerts.is_empty() {
+        footer_top
+    } else {
+        let rooms: Vec<String> = alerts
+            .iter()
+            .map(|(sensor, co2)| format!("{} CO₂ {:.0} ppm", display_name(sensor), co2))
+            .collect();
+        let banner_y = footer_top - 40.0;
+        svg.push_str(&format!(
+            r#"  <rect x="10" y="{}" width="{}" height="34" fill="red" rx="4"/><text x="{}" y="{}" text-anchor="middle" font-family="Arial" font-size="18" font-weight="bold" fill="white">Open a window · {}</text>"#,
+            banner_y,
+            width - 20,
+            width / 2,
+            banner_y + 23.0,
+            svg_common::escape_xml_text(&rooms.join(", "))
+        ));
+        svg.push('\n');
+        banner_y - 6.0
+    };
+
+    // Sensors, one row each
+    svg.push_str(&format!(
+        r#"  <line x1="{x}" y1="76" x2="{x}" y2="{}" stroke="black" stroke-width="1"/>"#,
+        bottom - 6.0,
+        x = outdoor_width
+    ));
+    svg.push('\n');
+    let rows_x = outdoor_width + 20.0;
+    let rows_top = 72.0;
+    let row_height = (bottom - rows_top) / sensors.len().max(1) as f64;
+    for (i, sensor) in sensors.iter().enumerate() {
+        let row_y = rows_top + i as f64 * row_height;
+        if i > 0 {
+            svg.push_str(&format!(
+                r##"  <line x1="{}" y1="{row_y}" x2="{}" y2="{row_y}" stroke="#b0b0b0" stroke-width="1"/>"##,
+                rows_x,
+                width - 20,
+                row_y = row_y
+            ));
+            svg.push('\n');
+        }
+        svg.push_str(&sensor_svg(
+            sensor,
+            co2_warning,
+            now,
+            units,
+            locale,
+            (
+                rows_x,
+                row_y + 4.0,
+                width as f64 - rows_x - 20.0,
+                row_height - 8.0,
+            ),
+        ));
+    }
+
+    // Footer with battery and last updated
+    let footer_y = height - 10;
+    let pct = battery_pct.unwrap_or(50);
+    svg.push_str(&svg_common::battery_label_svg(
+        10.0,
+        footer_y as f64,
+        "start",
+        12,
+    ));
+    svg.push_str(&svg_common::battery_bar_svg(
+        75.0,
+        footer_y as f64 - 10.0,
+        pct,
+        2.0,
+        "batteryClip",
+    ));
+    svg.push('\n');
+    let updated = Local::now();
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">Last updated: {:02}:{:02}:{:02}</text>"#,
+        width - 10,
+        footer_y,
+        updated.hour(),
+        updated.minute(),
+        updated.second()
+    ));
+    svg.push('\n');
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{co2_alerts, display_name, SensorHistory};
+    use crate::sensors::SensorReading;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn co2_alerts_use_each_sensors_latest_level() {
+        let now = Utc::now();
+        let reading = |sensor: &str, minutes_ago: i64, co2: Option<f32>| SensorReading {
+            time: now - Duration::minutes(minutes_ago),
+            sensor: sensor.to_string(),
+            temperature: Some(21.0),
+            humidity: None,
+            co2,
+        };
+        let sensors = [
+            // Aired out since the peak
+            SensorHistory {
+                sensor: "office".to_string(),
+                readings: vec![
+                    reading("office", 20, Some(1400.0)),
+                    reading("office", 5, Some(700.0)),
+                ],
+            },
+            // The last reading has no CO2, so the one before counts
+            SensorHistory {
+                sensor: "living_room".to_string(),
+                readings: vec![
+                    reading("living_room", 10, Some(1200.0)),
+                    reading("living_room", 1, None),
+                ],
+            },
+        ];
+        assert_eq!(co2_alerts(&sensors, 1000.0), vec![("living_room", 1200.0)]);
+        assert_eq!(display_name("living_room"), "Living room");
+    }
+}
@@ -0,0 +1,139 @@
+//! JSON-lines logs under the state directory, one record per line and all of them held
+//! in memory. Device check-ins, observed weather and sensor readings are kept this way.
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// A record in a `JsonLinesStore`
+pub trait LogRecord: Serialize + DeserializeOwned {
+    /// Retention hook: whether the record is still kept at `now`. Checked on load and
+    /// whenever a record is added.
+    fn keep(&self, now: DateTime<Utc>) -> bool;
+}
+
+pub struct JsonLinesStore<T> {
+    /// Log file, if persistence is on
+    path: Option<PathBuf>,
+    records: Mutex<Vec<T>>,
+    /// Lines in the log for records since dropped from memory
+    expired: AtomicUsize,
+}
+
+impl<T> Default for JsonLinesStore<T> {
+    fn default() -> Self {
+        Self {
+            path: None,
+            records: Mutex::new(Vec::new()),
+            expired: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T: LogRecord> JsonLinesStore<T> {
+    /// Store backed by the log at `path`. Unreadable lines and records no longer kept are
+    /// dropped, and the log rewritten without them.
+    pub fn persistent(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mut records = Vec::new();
+        let mut dropped = 0;
+        let now = Utc::now();
+        if let Ok(text) = std::fs::read_to_string(&path) {
+            for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                match serde_json::from_str::<T>(line) {
+                    Ok(record) if record.keep(now) => records.push(record),
+                    Ok(_) => dropped += 1,
+                    Err(e) => {
+                        eprintln!("Ignoring unreadable line in {}: {}", path.display(), e);
+                        dropped += 1;
+                    }
+                }
+            }
+        }
+        let store = Self {
+            path: Some(path),
+            ..Self::default()
+        };
+        if dropped > 0 {
+            store.write_all(&records);
+        }
+        *store.records() = records;
+        store
+    }
+
+    /// Records in the order they were added, unless `record`'s `insert` placed them
+    /// otherwise. Changes made through this aren't logged until `rewrite`.
+    pub fn records(&self) -> MutexGuard<'_, Vec<T>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds a record with `insert`, which returns false if it changed nothing, and
+    /// appends it to the log. Records no longer kept are dropped from memory, and from
+    /// the log once it holds more of them than current records.
+    pub fn record(&self, record: T, insert: impl FnOnce(&mut Vec<T>, T) -> bool) {
+        // Held while writing, so a rewrite can't drop a line appended alongside it
+        let mut records = self.records();
+        let line = serde_json::to_string(&record).map_err(|e| e.to_string());
+        if !insert(&mut records, record) {
+            return;
+        }
+        if let (Some(path), Err(e)) = (&self.path, line.and_then(|line| self.append(&line))) {
+            eprintln!("Could not append to {}: {}", path.display(), e);
+        }
+
+        let now = Utc::now();
+        let before = records.len();
+        records.retain(|record| record.keep(now));
+        let expired = before - records.len();
+        if expired > 0
+            && self.expired.fetch_add(expired, Ordering::Relaxed) + expired > records.len()
+        {
+            self.write_all(&records);
+        }
+    }
+
+    /// Rewrites the log from the records in memory
+    pub fn rewrite(&self) {
+        self.write_all(&self.records());
+    }
+
+    fn write_all(&self, records: &[T]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut text = String::new();
+        for record in records {
+            if let Ok(line) = serde_json::to_string(record) {
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(path, text));
+        match written {
+            Ok(()) => self.expired.store(0, Ordering::Relaxed),
+            Err(e) => eprintln!("Could not rewrite {}: {}", path.display(), e),
+        }
+    }
+
+    fn append(&self, line: &str) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,337 @@
+//! Display units and date formatting for the weather screens.
+//!
+//! Forecasts are fetched in imperial or metric units; UK mixed units (°C with wind in
+//! mph) are fetched as metric and the wind converted when drawn. The
+//! bars keep the same physical range whatever the units.
+
+use chrono::{Datelike, Timelike, Weekday};
+
+/// Units for temperatures, wind speeds and rain amounts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Units {
+    /// °F, mph, inches
+    #[default]
+    Imperial,
+    /// °C, m/s, millimetres
+    Metric,
+    /// °C, mph, millimetres
+    Uk,
+}
+
+impl Units {
+    /// Looks up units by their query-parameter name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "imperial" | "us" => Some(Units::Imperial),
+            "metric" | "si" => Some(Units::Metric),
+            "uk" => Some(Units::Uk),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Units::Imperial => "imperial",
+            Units::Metric => "metric",
+            Units::Uk => "uk",
+        }
+    }
+
+    /// The units forecasts are fetched in, named as OpenWeatherMap's `units` parameter
+    pub fn api_units(self) -> &'static str {
+        match self {
+            Units::Imperial => "imperial",
+            Units::Metric | Units::Uk => "metric",
+        }
+    }
+
+    /// A temperature in the fetched units, in °F
+    pub fn to_fahrenheit(self, temp: f32) -> f32 {
+        match self {
+            Units::Imperial => temp,
+            Units::Metric | Units::Uk => temp * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// A temperature in °F, in these units
+    pub fn fahrenheit_to_units(self, temp: f32) -> f32 {
+        match self {
+            Units::Imperial => temp,
+            Units::Metric | Units::Uk => (temp - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    pub fn temperature_symbol(self) -> &'static str {
+        match self {
+            Units::Imperial => "°F",
+            Units::Metric | Units::Uk => "°C",
+        }
+    }
+
+    /// A wind speed in the fetched units (mph or m/s), in mph
+    pub fn to_mph(self, speed: f32) -> f32 {
+        match self {
+            Units::Imperial => speed,
+            Units::Metric | Units::Uk => speed * 2.236_936,
+        }
+    }
+
+    /// A wind speed in the fetched units, for display
+    pub fn format_wind(self, speed: f32) -> String {
+        match self {
+            Units::Metric => format!("{:.0} m/s", speed),
+            Units::Imperial | Units::Uk => format!("{:.0} mph", self.to_mph(speed)),
+        }
+    }
+
+    /// A rain amount (always millimetres in the forecast), for display
+    pub fn format_rain(self, mm: f32) -> String {
+        match self {
+            Units::Imperial => format!("{:.2} in", mm / 25.4),
+            Units::Metric | Units::Uk => format!("{:.1} mm", mm),
+        }
+    }
+}
+
+/// Language and conventions for dates and times
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    EnUs,
+    EnGb,
+    De,
+    Fr,
+    Es,
+    It,
+    Nl,
+}
+
+impl Locale {
+    /// Looks up a locale by its language tag, e.g. "en-GB" or "de".
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "en" | "en-us" => Some(Locale::EnUs),
+            "en-gb" => Some(Locale::EnGb),
+            "de" | "de-de" | "de-at" | "de-ch" => Some(Locale::De),
+            "fr" | "fr-fr" | "fr-be" | "fr-ch" => Some(Locale::Fr),
+            "es" | "es-es" => Some(Locale::Es),
+            "it" | "it-it" => Some(Locale::It),
+            "nl" | "nl-nl" | "nl-be" => Some(Locale::Nl),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Locale::EnUs => "en-US",
+            Locale::EnGb => "en-GB",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+            Locale::Es => "es",
+            Locale::It => "it",
+            Locale::Nl => "nl",
+        }
+    }
+
+    /// Full weekday names, Monday first
+    fn weekdays(self) -> [&'static str; 7] {
+        match self {
+            Locale::EnUs | Locale::EnGb => [
+                "Monday",
+                "Tuesday",
+                "Wednesday",
+                "Thursday",
+                "Friday",
+                "Saturday",
+                "Sunday",
+            ],
+            Locale::De => [
+                "Montag",
+                "Dienstag",
+                "Mittwoch",
+                "Donnerstag",
+                "Freitag",
+                "Samstag",
+                "Sonntag",
+            ],
+            Locale::Fr => [
+                "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+            ],
+            Locale::Es => [
+                "lunes",
+                "martes",
+                "miércoles",
+                "jueves",
+                "viernes",
+                "sábado",
+                "domingo",
+            ],
+            Locale::It => [
+                "lunedì",
+                "martedì",
+                "mercoledì",
+                "giovedì",
+                "venerdì",
+                "sabato",
+                "domenica",
+            ],
+            Locale::Nl => [
+                "maandag",
+                "dinsdag",
+                "woensdag",
+                "donderdag",
+                "vrijdag",
+                "zaterdag",
+                "zondag",
+            ],
+        }
+    }
+
+    /// Abbreviated month names, January first
+    fn months(self) -> [&'static str; 12] {
+        match self {
+            Locale::EnUs | Locale::EnGb => [
+                "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+            ],
+            Locale::De => [
+                "Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez",
+            ],
+            Locale::Fr => [
+                "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.",
+                "nov.", "déc.",
+            ],
+            Locale::Es => [
+                "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sept", "oct", "nov", "dic",
+            ],
+            Locale::It => [
+                "gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic",
+            ],
+            Locale::Nl => [
+                "jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec",
+            ],
+        }
+    }
+
+    /// Everyone but the US uses the 24-hour clock
+    fn clock_24h(self) -> bool {
+        self != Locale::EnUs
+    }
+
+    pub fn weekday(self, weekday: Weekday) -> &'static str {
+        self.weekdays()[weekday.num_days_from_monday() as usize]
+    }
+
+    /// Weekday, day and month, e.g. "Wednesday, Mar 5" or "Mittwoch, 5. Mär"
+    pub fn long_date(self, date: &impl Datelike) -> String {
+        let weekday = self.weekday(date.weekday());
+        let month = self.months()[date.month0() as usize];
+        let day = date.day();
+        match self {
+            Locale::EnUs => format!("{}, {} {}", weekday, month, day),
+            Locale::De => format!("{}, {}. {}", weekday, day, month),
+            Locale::Es => format!("{}, {} {}", weekday, day, month),
+            Locale::EnGb | Locale::Fr | Locale::It | Locale::Nl => {
+                format!("{} {} {}", weekday, day, month)
+            }
+        }
+    }
+
+    /// Abbreviated month name, e.g. "Mar" or "mars"
+    pub fn month(self, date: &impl Datelike) -> &'static str {
+        self.months()[date.month0() as usize]
+    }
+
+    /// Day and month, e.g. "Mar 5" or "5. Mär"
+    pub fn day_month(self, date: &impl Datelike) -> String {
+        let month = self.months()[date.month0() as usize];
+        let day = date.day();
+        match self {
+            Locale::EnUs => format!("{} {}", month, day),
+            Locale::De => format!("{}. {}", day, month),
+            Locale::EnGb | Locale::Fr | Locale::Es | Locale::It | Locale::Nl => {
+                format!("{} {}", day, month)
+            }
+        }
+    }
+
+    /// Numeric day and month, e.g. "3/5" or "5.3."
+    pub fn short_date(self, date: &impl Datelike) -> String {
+        let (day, month) = (date.day(), date.month());
+        match self {
+            Locale::EnUs => format!("{}/{}", month, day),
+            Locale::De => format!("{}.{}.", day, month),
+            Locale::Nl => format!("{}-{}", day, month),
+            Locale::EnGb | Locale::Fr | Locale::Es | Locale::It => format!("{}/{}", day, month),
+        }
+    }
+
+    /// Time of day, e.g. "6:45 am" or "06:45"
+    pub fn time(self, time: &impl Timelike) -> String {
+        if self.clock_24h() {
+            format!("{:02}:{:02}", time.hour(), time.minute())
+        } else {
+            let (pm, hour) = time.hour12();
+            format!(
+                "{}:{:02} {}",
+                hour,
+                time.minute(),
+                if pm { "pm" } else { "am" }
+            )
+        }
+    }
+
+    /// Whole hour, e.g. "3pm" or "15:00"
+    pub fn hour(self, hour: u32) -> String {
+        if self.clock_24h() {
+            format!("{:02}:00", hour)
+        } else {
+            let hour12 = match hour % 12 {
+                0 => 12,
+                h => h,
+            };
+            format!("{}{}", hour12, if hour < 12 { "am" } else { "pm" })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Locale, Units};
+    use chrono::{NaiveDate, NaiveTime};
+
+    #[test]
+    fn units_convert_to_the_bar_scales() {
+        assert_eq!(Units::from_name("UK"), Some(Units::Uk));
+        assert_eq!(Units::Uk.api_units(), "metric");
+        assert_eq!(Units::Metric.to_fahrenheit(100.0), 212.0);
+        assert_eq!(Units::Imperial.to_fahrenheit(50.0), 50.0);
+        assert_eq!(Units::Uk.fahrenheit_to_units(212.0), 100.0);
+        assert!((Units::Uk.to_mph(10.0) - 22.37).abs() < 0.01);
+        assert_eq!(Units::Metric.format_wind(10.0), "10 m/s");
+        assert_eq!(Units::Uk.format_wind(10.0), "22 mph");
+        assert_eq!(Units::Imperial.format_rain(12.7), "0.50 in");
+        assert_eq!(Units::Uk.format_rain(12.7), "12.7 mm");
+    }
+
+    #[test]
+    fn dates_and_times_follow_the_locale() {
+        // A Wednesday
+        let date = NaiveDate::from_ymd_opt(2025, 3, 5).unwrap();
+        let time = NaiveTime::from_hms_opt(18, 7, 0).unwrap();
+        assert_eq!(Locale::EnUs.long_date(&date), "Wednesday, Mar 5");
+        assert_eq!(Locale::EnGb.long_date(&date), "Wednesday 5 Mar");
+        assert_eq!(Locale::De.long_date(&date), "Mittwoch, 5. Mär");
+        assert_eq!(Locale::Fr.long_date(&date), "mercredi 5 mars");
+        assert_eq!(Locale::EnUs.day_month(&date), "Mar 5");
+        assert_eq!(Locale::De.day_month(&date), "5. Mär");
+        assert_eq!(Locale::EnUs.short_date(&date), "3/5");
+        assert_eq!(Locale::De.short_date(&date), "5.3.");
+        assert_eq!(Locale::EnUs.time(&time), "6:07 pm");
+        assert_eq!(Locale::Nl.time(&time), "18:07");
+        assert_eq!(Locale::EnUs.hour(0), "12am");
+        assert_eq!(Locale::EnUs.hour(15), "3pm");
+        assert_eq!(Locale::Fr.hour(15), "15:00");
+        assert_eq!(Locale::from_name("de_DE"), Some(Locale::De));
+        assert_eq!(Locale::from_name("xx"), None);
+    }
+}
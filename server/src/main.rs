@@ -1,369 +1,1842 @@
+mod air_quality;
+mod almanac;
+mod astronomy;
 mod bitmap;
+mod cache;
+mod config;
+mod device;
+mod dither;
 mod fred;
+mod indicators;
+mod indoor;
+mod json_lines;
 mod kalman;
+mod locale;
+mod palette;
+mod panel;
+mod portfolio;
+mod quote_provider;
+mod schedule;
+mod sensors;
 mod stocks;
+mod stocks_compact;
 mod svg_common;
+mod telemetry;
+mod tides;
+mod upstream;
 mod weather;
+mod weather_alerts;
+mod weather_history;
+mod weather_hourly;
+mod weather_nowcast;
+mod weather_provider;
 mod weight;
 
+use air_quality::{generate_air_quality_svg, AirQualityData, AirQualitySource};
+use almanac::generate_almanac_svg;
 use axum::{
-    extract::{Query, State},
-    response::IntoResponse,
+    body::Bytes,
+    extract::{Path as UrlPath, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
+use bitmap::{EpbmEncoding, EpdBitmap};
+use cache::{Snapshot, SourceCache};
+use chrono::{Local, NaiveTime, SecondsFormat, Utc};
 use clap::Parser;
-use fred::{fetch_fred, generate_fred_svg};
+use config::{ApiSource, Config, Location, Screen, ScreenKind, Sources};
+use device::Devices;
+use dither::DitherMode;
+use fred::{fetch_fred, generate_fred_svg, FredData};
+use indoor::{generate_indoor_svg, SensorHistory};
+use locale::{Locale, Units};
+use palette::DitherPalette;
+use panel::{find_panel, Panel, PANELS};
+use portfolio::{generate_portfolio_svg, load_holdings, quote_symbols, value_portfolio, Holding};
+use quote_provider::{AlphaVantage, CsvDir, QuoteProvider, QuoteSource, Stooq, TwelveData};
 use reverse_geocoder::ReverseGeocoder;
-use serde::Deserialize;
+use schedule::Refresh;
+use sensors::{ReadingPayload, SensorStore};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use stocks::{fetch_stocks, generate_stocks_svg};
+use std::time::Duration;
+use stocks::{fetch_stocks, generate_stocks_svg, symbol_indicators, StockLayout, StocksData};
+use svg_common::{Canvas, CardLine, CompactCard};
+use telemetry::{generate_battery_svg, CheckIn, TelemetryStore};
+use tides::Harmonics;
+use upstream::{Limits, Upstream};
 use weather::{
-    fetch_weather, fetch_weather_overview, generate_weather_overview_svg, generate_weather_svg,
+    fetch_weather_overview, generate_weather_overview_svg, generate_weather_svg, wrap_text_lines,
+    WeatherData, WeatherOverviewData,
 };
-use weight::{fetch_weight_data, generate_forecast_svg, generate_velocity_svg};
+use weather_alerts::generate_weather_alerts_svg;
+use weather_history::{
+    forecast_observation, generate_weather_history_svg, location_key, read_history_csv,
+    DailyObservation, Normals, ObservationStore,
+};
+use weather_hourly::generate_weather_hourly_svg;
+use weather_nowcast::generate_weather_nowcast_svg;
+use weather_provider::{Nws, OpenMeteo, OpenWeather, Provider, WeatherProvider};
+use weight::{fetch_weight_data, generate_forecast_svg, generate_velocity_svg, WeightData};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Generate weather images for IoT devices")]
 struct Args {
-    /// Latitude (e.g. 37.7749)
-    #[arg(long)]
-    lat: String,
-    /// Longitude (e.g. -122.4194)
-    #[arg(long)]
-    lon: String,
-    /// OpenWeather API key
-    #[arg(long)]
-    open_weather_key: String,
-    /// Twelve Data API key
-    #[arg(long)]
-    stocks_api_key: String,
-    /// Stock symbols (comma-separated, e.g. "BTC/USD,QQQ,IONQ,TSLA")
-    #[arg(long)]
-    stock_symbols: String,
-    /// FRED API key
+    /// Config file with data sources and screens (see deploy/bundle/config.example.toml)
+    #[arg(long, default_value = "config.toml")]
+    config: PathBuf,
+    /// HTTP server port, overriding the config file
     #[arg(long)]
-    fred_api_key: String,
-    /// Directory containing weight data CSV files
-    #[arg(long)]
-    weight_data_dir: String,
-    /// HTTP server port
-    #[arg(long, default_value = "8080")]
-    port: u16,
+    port: Option<u16>,
+    /// Import daily history for the weather-history screen from a CSV, then exit. Takes
+    /// NOAA daily summaries (DATE, TMAX, TMIN, PRCP) or date,high,low,precip columns.
+    #[arg(long, value_name = "CSV", requires_all = ["lat", "lon"])]
+    import_history: Option<PathBuf>,
+    /// Location of the imported history, as in the screen's config
+    #[arg(long, allow_negative_numbers = true)]
+    lat: Option<f64>,
+    #[arg(long, allow_negative_numbers = true)]
+    lon: Option<f64>,
+    /// Units of the imported history: imperial (°F and inches, as NOAA exports) or metric
+    #[arg(long, default_value = "imperial")]
+    import_units: String,
 }
 
 struct AppState {
-    lat: String,
-    lon: String,
-    api_key: String,
-    stocks_api_key: String,
-    stock_symbols: String,
-    fred_api_key: String,
-    weight_data_dir: String,
+    sources: Sources,
+    /// Configured screens; routes refer to them by index
+    screens: Vec<Screen>,
+    devices: Devices,
     /// Built once at startup: constructing it loads and indexes the city dataset,
     /// which is too expensive to redo on every weather request.
     geocoder: ReverseGeocoder,
+    weather_cache: Arc<SourceCache<WeatherData>>,
+    weather_overview_cache: Arc<SourceCache<WeatherOverviewData>>,
+    air_quality_cache: Arc<SourceCache<AirQualityData>>,
+    stocks_cache: Arc<SourceCache<StocksData>>,
+    fred_cache: Arc<SourceCache<FredData>>,
+    /// Shared by the forecast and velocity screens, which render the same CSV.
+    weight_cache: Arc<SourceCache<WeightData>>,
+    /// Dithering palette for each panel, keyed by panel name
+    palettes: HashMap<&'static str, DitherPalette>,
+    /// Check-ins from identified devices, for the battery screen and `/telemetry`
+    telemetry: TelemetryStore,
+    /// Each location's daily highs, lows and rain, for the weather-history screen
+    observations: ObservationStore,
+    /// Home sensor readings, for the indoor screen and `/sensors`; shared with the MQTT
+    /// subscriber when there is one
+    sensors: Arc<SensorStore>,
+    /// HTTP client for the weather, air quality, stock and FRED fetches, reusing its
+    /// connections
+    upstream: Upstream,
+    /// Requests allowed to each of those APIs, across every screen that uses it
+    limits: Limits,
 }
 
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 struct QueryArgs {
     battery_pct: Option<u8>,
-    date: Option<String>,    // Optional end date in YYYYMMDD format
-    duration: Option<usize>, // Optional duration in days
-    user: Option<String>,    // User for weight data (defaults to "weight")
+    battery_mv: Option<u32>, // Raw battery voltage
+    rssi: Option<i32>,       // Wi-Fi signal strength in dBm
+    wake_reason: Option<String>,
+    firmware: Option<String>,
+    download_ms: Option<u32>, // Time taken by the device's previous download
+    date: Option<String>,     // Optional end date in YYYYMMDD format
+    duration: Option<usize>,  // Optional duration in days
+    user: Option<String>,     // User for weight data, overriding the screen's
     lat: Option<String>,
     lon: Option<String>,
+    format: Option<String>, // EPBM encoding: raw (default), packed4 or rle
+    dither: Option<String>, // Dither mode, overriding the screen's default
+    panel: Option<String>,  // Panel profile for the SVG and legacy PNG routes
+    units: Option<String>,  // imperial, metric or uk, overriding the screen's
+    locale: Option<String>, // Language tag for dates and times, e.g. en-GB or de
+    device: Option<String>, // Device ID for `/device/next.bin`, if not in `X-Device-Id`
+    page: Option<usize>,    // 1-based page for screens with several, e.g. weather alerts
 }
 
-const DISPLAY_WIDTH: u16 = 800;
-const DISPLAY_HEIGHT: u16 = 480;
+/// Output of the raster routes: EPBM for the panel, or a PNG of the same dithered
+/// pixels for previewing in a browser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageFormat {
+    Epbm(EpbmEncoding),
+    Png,
+}
 
-fn render_svg_bytes(svg_content: String) -> Vec<u8> {
-    let bitmap =
-        match bitmap::render_svg_to_bitmap(svg_content.as_bytes(), DISPLAY_WIDTH, DISPLAY_HEIGHT) {
-            Ok(bmp) => bmp,
-            Err(e) => {
-                eprintln!("Error rendering SVG: {}", e);
-                bitmap::generate_test_bitmap(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+impl ImageFormat {
+    fn name(self) -> &'static str {
+        match self {
+            ImageFormat::Epbm(encoding) => encoding.name(),
+            ImageFormat::Png => "png",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Epbm(_) => "application/octet-stream",
+            ImageFormat::Png => "image/png",
+        }
+    }
+}
+
+/// Everything about a raster response other than the data being drawn
+#[derive(Clone, Copy)]
+struct RenderTarget<'a> {
+    panel: &'static Panel,
+    palette: &'a DitherPalette,
+    format: ImageFormat,
+    dither: DitherMode,
+}
+
+impl<'a> RenderTarget<'a> {
+    fn new(
+        screen: &Screen,
+        state: &'a AppState,
+        query: &QueryArgs,
+        panel: &'static Panel,
+        format: ImageFormat,
+    ) -> Self {
+        Self {
+            panel,
+            palette: &state.palettes[panel.name],
+            format,
+            dither: requested_dither(screen, query),
+        }
+    }
+
+    /// EPBM is rotated and mapped to the panel's native color values; the PNG preview
+    /// stays in viewing orientation.
+    fn encode(self, bitmap: &EpdBitmap) -> Result<Vec<u8>, String> {
+        match self.format {
+            ImageFormat::Epbm(encoding) => {
+                Ok(self.panel.to_native(bitmap).to_bytes_encoded(encoding))
             }
-        };
+            ImageFormat::Png => bitmap
+                .to_png(&self.palette.preview_rgb())
+                .map_err(|e| format!("Error encoding PNG: {}", e)),
+        }
+    }
+}
 
-    bitmap.to_bytes()
+fn test_pattern(panel: &Panel) -> EpdBitmap {
+    let (width, height) = panel.layout_size();
+    bitmap::generate_test_bitmap(width, height, panel.palette)
 }
 
-fn fallback_bitmap_bytes(error_context: &str, e: impl Display) -> Vec<u8> {
+fn render_svg_bytes(svg_content: String, target: RenderTarget) -> Result<Vec<u8>, String> {
+    let (width, height) = target.panel.layout_size();
+    let bitmap = match bitmap::render_svg_to_bitmap(
+        svg_content.as_bytes(),
+        width,
+        height,
+        target.dither,
+        target.palette,
+    ) {
+        Ok(bmp) => bmp,
+        Err(e) => {
+            eprintln!("Error rendering SVG: {}", e);
+            test_pattern(target.panel)
+        }
+    };
+
+    target.encode(&bitmap)
+}
+
+fn fallback_bitmap_bytes(
+    error_context: &str,
+    e: impl Display,
+    target: RenderTarget,
+) -> Result<Bytes, String> {
     eprintln!("Error {}: {}", error_context, e);
-    bitmap::generate_test_bitmap(DISPLAY_WIDTH, DISPLAY_HEIGHT).to_bytes()
+    target.encode(&test_pattern(target.panel)).map(Bytes::from)
+}
+
+/// Raster response with the recommended next wake for the device: `X-Sleep-Seconds`
+/// and `X-Next-Wake` (RFC 3339, server local time). `wake_by` brings the wake forward
+/// to a local time of day, such as a device's next playlist slot.
+fn image_response(
+    format: ImageFormat,
+    image: Result<(Bytes, Refresh), String>,
+    query: &QueryArgs,
+    wake_by: Option<NaiveTime>,
+) -> Response {
+    let (bytes, refresh) = match image {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("{}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+        }
+    };
+    let now = Local::now();
+    let mut wake = schedule::next_wake(refresh, query.battery_pct, &now);
+    if let Some(time) = wake_by {
+        wake = wake.min(schedule::next_occurrence(&now, time));
+    }
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                HeaderName::from_static("x-sleep-seconds"),
+                schedule::sleep_seconds(&now, &wake).to_string(),
+            ),
+            (
+                HeaderName::from_static("x-next-wake"),
+                wake.to_rfc3339_opts(SecondsFormat::Secs, false),
+            ),
+        ],
+        bytes,
+    )
+        .into_response()
+}
+
+/// Panel and output format for a `/{screen}/{panel}.bin` or `/{screen}/{panel}.png`
+/// request, or `None` for an unknown panel or extension.
+fn panel_file(
+    file: &str,
+    query: &QueryArgs,
+    headers: &HeaderMap,
+) -> Option<(&'static Panel, ImageFormat)> {
+    let (name, extension) = file.rsplit_once('.')?;
+    let format = match extension {
+        "bin" => ImageFormat::Epbm(requested_encoding(query, headers)),
+        "png" => ImageFormat::Png,
+        _ => return None,
+    };
+    Some((find_panel(name)?, format))
 }
 
-fn error_svg(e: impl Display) -> String {
+fn unknown_panel(file: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        format!("Unknown panel file: {}", file),
+    )
+        .into_response()
+}
+
+/// Panel for the routes without one in the path: `?panel=`, else the screen's panel
+fn requested_panel(screen: &Screen, query: &QueryArgs) -> &'static Panel {
+    query
+        .panel
+        .as_deref()
+        .and_then(find_panel)
+        .unwrap_or(screen.panel)
+}
+
+/// EPBM encoding for a bitmap request: `?format=` wins, then an `encoding=` parameter
+/// on the `Accept` header (e.g. `application/x-epbm; encoding=rle`), then v1 raw.
+fn requested_encoding(query: &QueryArgs, headers: &HeaderMap) -> EpbmEncoding {
+    if let Some(encoding) = query.format.as_deref().and_then(EpbmEncoding::from_name) {
+        return encoding;
+    }
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split([',', ';']))
+        .filter_map(|param| param.trim().strip_prefix("encoding="))
+        .find_map(EpbmEncoding::from_name)
+        .unwrap_or(EpbmEncoding::Raw)
+}
+
+/// Per-screen dithering when the request has no `?dither=`. Gradient-heavy screens
+/// get Floyd–Steinberg; the line-only stocks chart is left undithered so thin lines
+/// and small labels stay crisp.
+fn default_dither(kind: &ScreenKind) -> DitherMode {
+    match kind {
+        ScreenKind::Weather { .. }
+        | ScreenKind::WeatherHourly { .. }
+        | ScreenKind::WeatherOverview { .. }
+        | ScreenKind::AirQuality { .. }
+        | ScreenKind::Almanac { .. }
+        | ScreenKind::Indoor { .. }
+        | ScreenKind::Fred { .. } => DitherMode::FloydSteinberg,
+        ScreenKind::Stocks { .. }
+        | ScreenKind::Portfolio { .. }
+        | ScreenKind::WeatherNowcast { .. }
+        | ScreenKind::WeatherAlerts { .. }
+        | ScreenKind::WeatherHistory { .. }
+        | ScreenKind::Battery { .. } => DitherMode::None,
+        ScreenKind::WeightForecast { .. } | ScreenKind::WeightVelocity { .. } => {
+            DitherMode::Atkinson
+        }
+    }
+}
+
+/// `?dither=`, then the screen's configured mode, then the screen type's default
+fn requested_dither(screen: &Screen, query: &QueryArgs) -> DitherMode {
+    query
+        .dither
+        .as_deref()
+        .and_then(DitherMode::from_name)
+        .or(screen.dither)
+        .unwrap_or_else(|| default_dither(&screen.kind))
+}
+
+/// `?units=`, then the screen's units, then imperial
+fn requested_units(screen: &Screen, query: &QueryArgs) -> Units {
+    query
+        .units
+        .as_deref()
+        .and_then(Units::from_name)
+        .or(screen.units)
+        .unwrap_or_default()
+}
+
+/// `?locale=`, then the screen's locale, then en-US
+fn requested_locale(screen: &Screen, query: &QueryArgs) -> Locale {
+    query
+        .locale
+        .as_deref()
+        .and_then(Locale::from_name)
+        .or(screen.locale)
+        .unwrap_or_default()
+}
+
+fn error_svg(e: impl Display, canvas: Canvas) -> String {
+    if canvas.is_compact() {
+        let mut card = CompactCard::new("Error", None);
+        card.lines = wrap_text_lines(&e.to_string(), CompactCard::line_chars(canvas))
+            .into_iter()
+            .map(CardLine::new)
+            .collect();
+        return card.svg(canvas);
+    }
     format!(
-        r#"<svg xmlns="http://www.w3.org/2000/svg" width="800" height="480">
-            <text x="400" y="240" text-anchor="middle" font-size="20">Error: {}</text>
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">
+            <text x="{}" y="{}" text-anchor="middle" font-size="20">Error: {}</text>
         </svg>"#,
+        canvas.width,
+        canvas.height,
+        canvas.width / 2,
+        canvas.height / 2,
         e
     )
 }
 
-fn weight_csv_path(state: &AppState, user: Option<&str>) -> Result<String, String> {
-    let user = user.unwrap_or("weight");
+/// Key for the render-only parameters of a bitmap, within one cached data entry.
+fn render_key(screen: &Screen, query: &QueryArgs, target: RenderTarget) -> String {
+    format!(
+        "{}?battery_pct={:?}&panel={}&format={}&dither={}&units={}&locale={}",
+        screen.name,
+        query.battery_pct,
+        target.panel.name,
+        target.format.name(),
+        target.dither.name(),
+        requested_units(screen, query).name(),
+        requested_locale(screen, query).name()
+    )
+}
+
+/// Renders the SVG for a cache snapshot, adding the stale banner when the upstream
+/// fetch failed and the last good data is being shown instead.
+fn snapshot_svg<T>(
+    snapshot: &Snapshot<T>,
+    canvas: Canvas,
+    generate: impl FnOnce(&T) -> String,
+) -> String {
+    match snapshot {
+        Snapshot::Current(data) => generate(data),
+        Snapshot::LastGood {
+            data,
+            fetched_at,
+            error,
+        } => svg_common::with_stale_banner(
+            &generate(data),
+            canvas,
+            fetched_at.with_timezone(&Local),
+            error,
+        ),
+    }
+}
+
+/// EPBM or PNG bytes for a cache snapshot. Current data goes through the bitmap cache;
+/// stale renders are not cached so the banner disappears as soon as a fetch succeeds,
+/// and failed encodes are not cached so the next request tries again.
+fn snapshot_bitmap<T>(
+    cache: &SourceCache<T>,
+    key: &str,
+    render_key: &str,
+    snapshot: Snapshot<T>,
+    target: RenderTarget,
+    generate: impl FnOnce(&T) -> String,
+) -> Result<Bytes, String>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    match &snapshot {
+        Snapshot::Current(data) => cache.bitmap(key, render_key, data, |data| {
+            render_svg_bytes(generate(data), target)
+        }),
+        Snapshot::LastGood { .. } => render_svg_bytes(
+            snapshot_svg(&snapshot, target.panel.canvas(), generate),
+            target,
+        )
+        .map(Bytes::from),
+    }
+}
+
+fn weight_csv_path(
+    state: &AppState,
+    query: &QueryArgs,
+    user: &Option<String>,
+) -> Result<String, String> {
+    let weight = state
+        .sources
+        .weight
+        .as_ref()
+        .ok_or("Weight data source is not configured")?;
+    let user = query
+        .user
+        .as_deref()
+        .or(user.as_deref())
+        .unwrap_or("weight");
     // Reject any path separators or traversal sequences to prevent directory traversal.
     if user.contains('/') || user.contains('\\') || user.contains("..") {
         return Err(format!("Invalid user parameter: {}", user));
     }
-    Ok(format!("{}/{}.csv", state.weight_data_dir, user))
+    Ok(format!("{}/{}.csv", weight.data_dir, user))
 }
 
-fn weather_coordinates<'a>(state: &'a AppState, query: &'a QueryArgs) -> (&'a str, &'a str) {
+fn api_key(source: &Option<ApiSource>, name: &str) -> Result<String, String> {
+    source
+        .as_ref()
+        .map(|source| source.api_key.clone())
+        .ok_or_else(|| format!("{} is not configured", name))
+}
+
+fn weather_coordinates(location: &Location, query: &QueryArgs) -> (String, String) {
     (
-        query.lat.as_deref().unwrap_or(&state.lat),
-        query.lon.as_deref().unwrap_or(&state.lon),
+        query
+            .lat
+            .clone()
+            .unwrap_or_else(|| location.lat.to_string()),
+        query
+            .lon
+            .clone()
+            .unwrap_or_else(|| location.lon.to_string()),
     )
 }
 
-async fn get_weather_bitmap(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let (lat, lon) = weather_coordinates(&state, &query);
-    let bitmap = match fetch_weather(lat, lon, &state.api_key).await {
-        Ok(weather) => render_svg_bytes(generate_weather_svg(
-            &weather,
-            query.battery_pct,
-            &state.geocoder,
-        )),
-        Err(e) => fallback_bitmap_bytes("fetching weather", e),
-    };
+/// The screen's coordinates or the query's, as numbers
+fn numeric_coordinates(location: &Location, query: &QueryArgs) -> Result<(f64, f64), String> {
+    let (lat, lon) = weather_coordinates(location, query);
+    match (lat.parse::<f64>(), lon.parse::<f64>()) {
+        (Ok(lat), Ok(lon)) => Ok((lat, lon)),
+        _ => Err(format!("Invalid coordinates {},{}", lat, lon)),
+    }
+}
 
-    ([("Content-Type", "application/octet-stream")], bitmap)
+fn weather_cache_key(
+    location: &Location,
+    query: &QueryArgs,
+    units: Units,
+    provider: Provider,
+) -> String {
+    let (lat, lon) = weather_coordinates(location, query);
+    format!(
+        "provider={}&lat={}&lon={}&units={}",
+        provider.name(),
+        lat,
+        lon,
+        units.api_units()
+    )
 }
 
-async fn get_stocks_bitmap(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let bitmap = match fetch_stocks(&state.stocks_api_key, &state.stock_symbols).await {
-        Ok(stocks) => render_svg_bytes(generate_stocks_svg(&stocks, query.battery_pct)),
-        Err(e) => fallback_bitmap_bytes("fetching stocks", e),
-    };
+fn air_quality_cache_key(location: &Location, query: &QueryArgs, provider: Provider) -> String {
+    let (lat, lon) = weather_coordinates(location, query);
+    format!("provider={}&lat={}&lon={}", provider.name(), lat, lon)
+}
 
-    ([("Content-Type", "application/octet-stream")], bitmap)
+/// Sent to the National Weather Service when `[sources.nws]` does not set one
+const DEFAULT_NWS_USER_AGENT: &str = "iot-image-server";
+
+fn weather_provider(
+    state: &AppState,
+    provider: Provider,
+) -> Result<Box<dyn WeatherProvider>, String> {
+    let (sources, upstream, limits) = (&state.sources, &state.upstream, &state.limits);
+    Ok(match provider {
+        Provider::OpenWeather => Box::new(OpenWeather {
+            api_key: api_key(&sources.openweather, "OpenWeather")?,
+            upstream: upstream.clone(),
+            limit: limits.openweather.clone(),
+        }),
+        Provider::OpenMeteo => Box::new(OpenMeteo {
+            upstream: upstream.clone(),
+            limit: limits.open_meteo.clone(),
+        }),
+        Provider::Nws => Box::new(Nws {
+            user_agent: sources.nws.as_ref().map_or_else(
+                || DEFAULT_NWS_USER_AGENT.to_string(),
+                |nws| nws.user_agent.clone(),
+            ),
+            upstream: upstream.clone(),
+            limit: limits.nws.clone(),
+        }),
+    })
 }
 
-async fn get_weather_svg(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let (lat, lon) = weather_coordinates(&state, &query);
-    match fetch_weather(lat, lon, &state.api_key).await {
-        Ok(weather) => {
-            let svg_content = generate_weather_svg(&weather, query.battery_pct, &state.geocoder);
-            ([("Content-Type", "image/svg+xml")], svg_content)
+fn fred_duration(query: &QueryArgs, duration: Option<usize>) -> Option<usize> {
+    query.duration.or(duration)
+}
+
+fn fred_cache_key(query: &QueryArgs, duration: Option<usize>) -> String {
+    format!(
+        "date={:?}&duration={:?}",
+        query.date,
+        fred_duration(query, duration)
+    )
+}
+
+async fn cached_weather(
+    state: &AppState,
+    key: &str,
+    location: &Location,
+    query: &QueryArgs,
+    units: Units,
+    provider: Provider,
+) -> Result<Snapshot<WeatherData>, String> {
+    let (lat, lon) = weather_coordinates(location, query);
+    let history_key = numeric_coordinates(location, query)
+        .ok()
+        .map(|(lat, lon)| location_key(lat, lon));
+    let provider = weather_provider(state, provider)?;
+    let weather = state
+        .weather_cache
+        .get_or_fetch(key, move || async move {
+            provider
+                .fetch(&lat, &lon, units)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+    // Every fresh forecast updates today's record; last good data would overwrite it
+    // with a stale one
+    if let (Snapshot::Current(data), Some(history_key)) = (&weather, history_key) {
+        if let Some(today) = forecast_observation(data, &history_key, units) {
+            state.observations.record(today);
         }
-        Err(e) => ([("Content-Type", "image/svg+xml")], error_svg(e)),
     }
+    Ok(weather)
 }
 
-async fn get_weather_overview_bitmap(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let (lat, lon) = weather_coordinates(&state, &query);
-    let bitmap = match fetch_weather_overview(lat, lon, &state.api_key).await {
-        Ok(weather) => render_svg_bytes(generate_weather_overview_svg(
-            &weather,
+/// The recorded days for a weather-history screen's location, with its key, and the
+/// screen's climate normals
+fn weather_history(
+    state: &AppState,
+    location: &Location,
+    query: &QueryArgs,
+    normals: &Option<PathBuf>,
+) -> Result<(String, Vec<DailyObservation>, Option<Normals>), String> {
+    let (lat, lon) = numeric_coordinates(location, query)?;
+    let key = location_key(lat, lon);
+    let normals = normals
+        .as_deref()
+        .map(Normals::load)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let history = state.observations.history(&key);
+    Ok((key, history, normals))
+}
+
+/// The indoor screen for `weather`, with the last day of each of its sensors
+#[allow(clippy::too_many_arguments)]
+fn indoor_svg(
+    state: &AppState,
+    weather: &WeatherData,
+    sensors: &[String],
+    co2_warning: Option<f32>,
+    battery_pct: Option<u8>,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let now = Utc::now();
+    let sensors: Vec<SensorHistory> = sensors
+        .iter()
+        .map(|sensor| SensorHistory {
+            sensor: sensor.clone(),
+            readings: state.sensors.history(sensor, now - indoor::HISTORY),
+        })
+        .collect();
+    generate_indoor_svg(
+        weather,
+        &sensors,
+        co2_warning.unwrap_or(indoor::DEFAULT_CO2_WARNING),
+        now,
+        battery_pct,
+        &state.geocoder,
+        units,
+        locale,
+        canvas,
+    )
+}
+
+async fn cached_weather_overview(
+    state: &AppState,
+    key: &str,
+    location: &Location,
+    query: &QueryArgs,
+    units: Units,
+) -> Result<Snapshot<WeatherOverviewData>, String> {
+    let (lat, lon) = weather_coordinates(location, query);
+    let api_key = api_key(&state.sources.openweather, "OpenWeather")?;
+    let (upstream, limit) = (state.upstream.clone(), state.limits.openweather.clone());
+    state
+        .weather_overview_cache
+        .get_or_fetch(key, move || async move {
+            fetch_weather_overview(&upstream, &limit, &lat, &lon, &api_key, units)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+}
+
+async fn cached_air_quality(
+    state: &AppState,
+    key: &str,
+    location: &Location,
+    query: &QueryArgs,
+    provider: Provider,
+) -> Result<Snapshot<AirQualityData>, String> {
+    let (lat, lon) = weather_coordinates(location, query);
+    let (source, limit) = match provider {
+        Provider::OpenWeather => (
+            AirQualitySource::OpenWeather {
+                api_key: api_key(&state.sources.openweather, "OpenWeather")?,
+            },
+            state.limits.openweather.clone(),
+        ),
+        Provider::OpenMeteo => (AirQualitySource::OpenMeteo, state.limits.open_meteo.clone()),
+        Provider::Nws => return Err("NWS has no air quality data".to_string()),
+    };
+    let upstream = state.upstream.clone();
+    state
+        .air_quality_cache
+        .get_or_fetch(key, move || async move {
+            source
+                .fetch(&upstream, &limit, &lat, &lon)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+}
+
+fn quote_provider(state: &AppState, source: QuoteSource) -> Result<Box<dyn QuoteProvider>, String> {
+    let (sources, upstream, limits) = (&state.sources, &state.upstream, &state.limits);
+    Ok(match source {
+        QuoteSource::TwelveData => Box::new(TwelveData {
+            api_key: api_key(&sources.twelve_data, "Twelve Data")?,
+            upstream: upstream.clone(),
+            limit: limits.twelve_data.clone(),
+        }),
+        QuoteSource::AlphaVantage => Box::new(AlphaVantage {
+            api_key: api_key(&sources.alpha_vantage, "Alpha Vantage")?,
+            upstream: upstream.clone(),
+            limit: limits.alpha_vantage.clone(),
+        }),
+        QuoteSource::Stooq => Box::new(Stooq {
+            upstream: upstream.clone(),
+            limit: limits.stooq.clone(),
+        }),
+        QuoteSource::Csv => Box::new(CsvDir {
+            data_dir: sources
+                .stock_csv
+                .as_ref()
+                .ok_or("Stock CSV directory is not configured")?
+                .data_dir
+                .clone(),
+        }),
+    })
+}
+
+/// The symbols, and the providers unless they are the default, so that a screen
+/// that only uses Twelve Data keeps its last good data under the same key
+fn stocks_cache_key(symbols: &str, providers: &[QuoteSource]) -> String {
+    if providers == [QuoteSource::TwelveData] {
+        return symbols.to_string();
+    }
+    let names: Vec<&str> = providers.iter().map(|p| p.name()).collect();
+    format!("{}&providers={}", symbols, names.join(","))
+}
+
+/// Longest a request waits on a stocks fetch, inside the device's 30 s HTTP timeout. A
+/// cold fetch of more symbols than the rate limit allows a minute runs on past this.
+const STOCKS_FETCH_WAIT: Duration = Duration::from_secs(20);
+
+/// The stocks cache's data for `symbols`. A fetch still running after
+/// `STOCKS_FETCH_WAIT` fills the cache in the background while this request gets the
+/// last good data, or an error that sends the device back for a retry.
+async fn cached_stocks(
+    state: &AppState,
+    key: &str,
+    symbols: &str,
+    providers: &[QuoteSource],
+) -> Result<Snapshot<StocksData>, String> {
+    let providers = providers
+        .iter()
+        .map(|&source| quote_provider(state, source))
+        .collect::<Result<Vec<_>, _>>()?;
+    let symbols_owned = symbols.to_string();
+    state
+        .stocks_cache
+        .get_or_fetch_within(key, STOCKS_FETCH_WAIT, move || async move {
+            fetch_stocks(&providers, &symbols_owned)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+}
+
+/// Pages a stocks screen's symbols take in `layout`
+fn stocks_pages(stocks: &StocksData, layout: StockLayout, canvas: Canvas) -> usize {
+    let layout = layout.resolve(stocks.stocks.len());
+    stocks
+        .stocks
+        .len()
+        .div_ceil(layout.per_page(canvas).max(1))
+        .max(1)
+}
+
+/// Reads the holdings, which may have changed since the last request, and prices them
+/// from the stocks cache
+async fn cached_portfolio(
+    state: &AppState,
+    holdings: &Path,
+    providers: &[QuoteSource],
+    currency: &str,
+) -> Result<(Vec<Holding>, Snapshot<StocksData>), String> {
+    let holdings = load_holdings(holdings, currency).map_err(|e| e.to_string())?;
+    let symbols = quote_symbols(&holdings, currency).join(",");
+    let key = stocks_cache_key(&symbols, providers);
+    let stocks = cached_stocks(state, &key, &symbols, providers).await?;
+    Ok((holdings, stocks))
+}
+
+/// The portfolio screen, or the reason the prices don't cover the holdings
+fn portfolio_svg(
+    holdings: &[Holding],
+    stocks: &StocksData,
+    currency: &str,
+    battery_pct: Option<u8>,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    match value_portfolio(holdings, currency, stocks) {
+        Ok(valuation) => generate_portfolio_svg(&valuation, currency, battery_pct, locale, canvas),
+        Err(e) => error_svg(e, canvas),
+    }
+}
+
+async fn cached_fred(
+    state: &AppState,
+    key: &str,
+    query: &QueryArgs,
+    duration: Option<usize>,
+) -> Result<Snapshot<FredData>, String> {
+    let api_key = api_key(&state.sources.fred, "FRED")?;
+    let (date, duration) = (query.date.clone(), fred_duration(query, duration));
+    let (upstream, limit) = (state.upstream.clone(), state.limits.fred.clone());
+    state
+        .fred_cache
+        .get_or_fetch(key, move || async move {
+            fetch_fred(&upstream, &limit, &api_key, date.as_deref(), duration)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+}
+
+async fn cached_weight(state: &AppState, csv_path: &str) -> Result<Snapshot<WeightData>, String> {
+    let path = csv_path.to_string();
+    state
+        .weight_cache
+        .get_or_fetch(csv_path, move || async move {
+            fetch_weight_data(Path::new(&path))
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+}
+
+/// Days of telemetry to show: `?duration=`, else the screen's, else 30. No more than
+/// the log keeps, which also keeps a huge `?duration=` from overflowing the date math.
+fn telemetry_days(query: &QueryArgs, duration: Option<usize>) -> usize {
+    query
+        .duration
+        .or(duration)
+        .unwrap_or(telemetry::DEFAULT_DAYS)
+        .min(telemetry::MAX_DAYS)
+}
+
+fn battery_svg(state: &AppState, screen: &Screen, query: &QueryArgs, canvas: Canvas) -> String {
+    let duration = match screen.kind {
+        ScreenKind::Battery { duration } => duration,
+        _ => None,
+    };
+    let days = telemetry_days(query, duration);
+    generate_battery_svg(&state.telemetry.health(days), days, canvas)
+}
+
+/// The almanac needs no upstream data; only the tide file can fail to load
+fn almanac_svg(
+    screen: &Screen,
+    query: &QueryArgs,
+    canvas: Canvas,
+    geocoder: &ReverseGeocoder,
+) -> Result<String, String> {
+    let ScreenKind::Almanac {
+        location,
+        timezone,
+        tides,
+    } = &screen.kind
+    else {
+        return Err(format!("Screen {} is not an almanac", screen.name));
+    };
+    let (lat, lon) = numeric_coordinates(location, query)?;
+    let harmonics = tides
+        .as_ref()
+        .map(|tides| Harmonics::load(&tides.harmonics, tides.mean_sea_level))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let locale = requested_locale(screen, query);
+    Ok(match timezone {
+        Some(tz) => generate_almanac_svg(
+            lat,
+            lon,
+            harmonics.as_ref(),
+            Utc::now().with_timezone(tz),
+            query.battery_pct,
+            geocoder,
+            locale,
+            canvas,
+        ),
+        None => generate_almanac_svg(
+            lat,
+            lon,
+            harmonics.as_ref(),
+            Local::now(),
             query.battery_pct,
-            &state.geocoder,
-        )),
-        Err(e) => fallback_bitmap_bytes("fetching weather overview", e),
+            geocoder,
+            locale,
+            canvas,
+        ),
+    })
+}
+
+/// Refresh hint for a cache snapshot: retry soon when only the last good data is available
+fn snapshot_refresh<T>(snapshot: &Snapshot<T>, refresh: Refresh) -> Refresh {
+    if snapshot.is_current() {
+        refresh
+    } else {
+        Refresh::Retry
+    }
+}
+
+/// Which drawing of a forecast a weather screen shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WeatherView {
+    Daily,
+    Hourly,
+    Nowcast,
+    /// Zero-based page of the alert list
+    Alerts {
+        page: usize,
+    },
+}
+
+/// Zero-based page from the 1-based `?page=`, or one that moves on every hour so that a
+/// device without it sees each page in turn
+fn requested_page(query: &QueryArgs, now: i64) -> usize {
+    query
+        .page
+        .map_or((now / 3600) as usize, |page| page.saturating_sub(1))
+}
+
+/// The screen type's view, or the alerts on a `switch_to_alerts` screen while a severe
+/// one is in effect
+fn weather_view(
+    kind: &ScreenKind,
+    weather: &WeatherData,
+    query: &QueryArgs,
+    canvas: Canvas,
+    now: i64,
+) -> WeatherView {
+    // Past the last page wraps to the first, so the render key only changes with the page
+    let pages = weather_alerts::alert_pages(&weather.alerts, canvas, now);
+    let alerts = WeatherView::Alerts {
+        page: requested_page(query, now) % pages,
     };
+    match kind {
+        ScreenKind::Weather {
+            switch_to_alerts: true,
+            ..
+        } if weather_alerts::severe_alert_active(&weather.alerts, now) => alerts,
+        ScreenKind::WeatherHourly { .. } => WeatherView::Hourly,
+        ScreenKind::WeatherNowcast { .. } => WeatherView::Nowcast,
+        ScreenKind::WeatherAlerts { .. } => alerts,
+        _ => WeatherView::Daily,
+    }
+}
 
-    ([("Content-Type", "application/octet-stream")], bitmap)
+fn weather_svg(
+    state: &AppState,
+    view: WeatherView,
+    weather: &WeatherData,
+    battery_pct: Option<u8>,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let geocoder = &state.geocoder;
+    match view {
+        WeatherView::Daily => {
+            generate_weather_svg(weather, battery_pct, geocoder, units, locale, canvas)
+        }
+        WeatherView::Hourly => {
+            generate_weather_hourly_svg(weather, battery_pct, geocoder, units, locale, canvas)
+        }
+        WeatherView::Nowcast => {
+            generate_weather_nowcast_svg(weather, battery_pct, geocoder, units, locale, canvas)
+        }
+        WeatherView::Alerts { page } => {
+            generate_weather_alerts_svg(weather, battery_pct, geocoder, locale, canvas, page)
+        }
+    }
 }
 
-async fn get_weather_overview_svg(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let (lat, lon) = weather_coordinates(&state, &query);
-    match fetch_weather_overview(lat, lon, &state.api_key).await {
-        Ok(weather) => {
-            let svg_content =
-                generate_weather_overview_svg(&weather, query.battery_pct, &state.geocoder);
-            ([("Content-Type", "image/svg+xml")], svg_content)
+/// EPBM or PNG bytes for one configured screen on one panel, and when the data is next
+/// worth fetching
+async fn screen_image(
+    state: &AppState,
+    screen: &Screen,
+    query: &QueryArgs,
+    panel: &'static Panel,
+    format: ImageFormat,
+) -> Result<(Bytes, Refresh), String> {
+    let target = RenderTarget::new(screen, state, query, panel, format);
+    let canvas = panel.canvas();
+    let render_key = render_key(screen, query, target);
+    let battery_pct = query.battery_pct;
+    let (units, locale) = (
+        requested_units(screen, query),
+        requested_locale(screen, query),
+    );
+    Ok(match &screen.kind {
+        ScreenKind::Weather {
+            location, provider, ..
+        }
+        | ScreenKind::WeatherHourly { location, provider }
+        | ScreenKind::WeatherNowcast { location, provider }
+        | ScreenKind::WeatherAlerts { location, provider } => {
+            let key = weather_cache_key(location, query, units, *provider);
+            match cached_weather(state, &key, location, query, units, *provider).await {
+                Ok(weather) => {
+                    let now = Utc::now().timestamp();
+                    let view = weather_view(&screen.kind, weather.data(), query, canvas, now);
+                    let alerts = weather.data().alerts.iter().map(|a| (a.start, a.end));
+                    let nowcast = view == WeatherView::Nowcast
+                        && weather_nowcast::outlook(&weather.data().minutely, now)
+                            .expects_precipitation();
+                    let refresh = if nowcast {
+                        Refresh::Precipitation
+                    } else if schedule::alert_active(alerts, now) {
+                        Refresh::Alert
+                    } else {
+                        Refresh::Scheduled
+                    };
+                    let refresh = snapshot_refresh(&weather, refresh);
+                    // The view can change while the data stays the same
+                    let render_key = format!("{}&view={:?}", render_key, view);
+                    let bytes = snapshot_bitmap(
+                        &state.weather_cache,
+                        &key,
+                        &render_key,
+                        weather,
+                        target,
+                        |weather| {
+                            weather_svg(state, view, weather, battery_pct, units, locale, canvas)
+                        },
+                    )?;
+                    (bytes, refresh)
+                }
+                Err(e) => (
+                    fallback_bitmap_bytes("fetching weather", e, target)?,
+                    Refresh::Retry,
+                ),
+            }
+        }
+        ScreenKind::WeatherHistory {
+            location,
+            provider,
+            normals,
+        } => {
+            let key = weather_cache_key(location, query, units, *provider);
+            let weather = match cached_weather(state, &key, location, query, units, *provider).await
+            {
+                Ok(weather) => weather,
+                Err(e) => {
+                    return Ok((
+                        fallback_bitmap_bytes("fetching weather", e, target)?,
+                        Refresh::Retry,
+                    ))
+                }
+            };
+            match weather_history(state, location, query, normals) {
+                Ok((history_key, history, normals)) => {
+                    let refresh = snapshot_refresh(&weather, Refresh::Scheduled);
+                    let bytes = snapshot_bitmap(
+                        &state.weather_cache,
+                        &key,
+                        &render_key,
+                        weather,
+                        target,
+                        |weather| {
+                            generate_weather_history_svg(
+                                weather,
+                                &history_key,
+                                &history,
+                                normals.as_ref(),
+                                battery_pct,
+                                &state.geocoder,
+                                units,
+                                locale,
+                                canvas,
+                            )
+                        },
+                    )?;
+                    (bytes, refresh)
+                }
+                Err(e) => (
+                    fallback_bitmap_bytes("reading weather history", e, target)?,
+                    Refresh::Retry,
+                ),
+            }
         }
-        Err(e) => ([("Content-Type", "image/svg+xml")], error_svg(e)),
+        ScreenKind::Indoor {
+            location,
+            provider,
+            sensors,
+            co2_warning,
+        } => {
+            let key = weather_cache_key(location, query, units, *provider);
+            match cached_weather(state, &key, location, query, units, *provider).await {
+                Ok(weather) => {
+                    let refresh = snapshot_refresh(&weather, Refresh::Scheduled);
+                    // Readings arrive between forecasts, so the bitmap isn't cached with them
+                    let svg = snapshot_svg(&weather, canvas, |weather| {
+                        indoor_svg(
+                            state,
+                            weather,
+                            sensors,
+                            *co2_warning,
+                            battery_pct,
+                            units,
+                            locale,
+                            canvas,
+                        )
+                    });
+                    (Bytes::from(render_svg_bytes(svg, target)?), refresh)
+                }
+                Err(e) => (
+                    fallback_bitmap_bytes("fetching weather", e, target)?,
+                    Refresh::Retry,
+                ),
+            }
+        }
+        ScreenKind::WeatherOverview { location } => {
+            let key = weather_cache_key(location, query, units, Provider::OpenWeather);
+            match cached_weather_overview(state, &key, location, query, units).await {
+                Ok(weather) => {
+                    let refresh = snapshot_refresh(&weather, Refresh::Scheduled);
+                    let bytes = snapshot_bitmap(
+                        &state.weather_overview_cache,
+                        &key,
+                        &render_key,
+                        weather,
+                        target,
+                        |weather| {
+                            generate_weather_overview_svg(
+                                weather,
+                                battery_pct,
+                                &state.geocoder,
+                                locale,
+                                canvas,
+                            )
+                        },
+                    )?;
+                    (bytes, refresh)
+                }
+                Err(e) => (
+                    fallback_bitmap_bytes("fetching weather overview", e, target)?,
+                    Refresh::Retry,
+                ),
+            }
+        }
+        ScreenKind::AirQuality { location, provider } => {
+            let key = air_quality_cache_key(location, query, *provider);
+            match cached_air_quality(state, &key, location, query, *provider).await {
+                Ok(air) => {
+                    let refresh = snapshot_refresh(&air, Refresh::Scheduled);
+                    let bytes = snapshot_bitmap(
+                        &state.air_quality_cache,
+                        &key,
+                        &render_key,
+                        air,
+                        target,
+                        |air| {
+                            generate_air_quality_svg(
+                                air,
+                                battery_pct,
+                                &state.geocoder,
+                                locale,
+                                canvas,
+                            )
+                        },
+                    )?;
+                    (bytes, refresh)
+                }
+                Err(e) => (
+                    fallback_bitmap_bytes("fetching air quality", e, target)?,
+                    Refresh::Retry,
+                ),
+            }
+        }
+        ScreenKind::Stocks {
+            symbols,
+            providers,
+            indicators,
+            layout,
+        } => {
+            let key = stocks_cache_key(symbols, providers);
+            let page = requested_page(query, Utc::now().timestamp());
+            match cached_stocks(state, &key, symbols, providers).await {
+                Ok(stocks) => {
+                    let refresh = snapshot_refresh(&stocks, Refresh::Market);
+                    // Pages share the data; the page past the last wraps to the first
+                    let pages = stocks_pages(stocks.data(), *layout, canvas);
+                    let render_key = format!("{}&page={}", render_key, page % pages);
+                    let bytes = snapshot_bitmap(
+                        &state.stocks_cache,
+                        &key,
+                        &render_key,
+                        stocks,
+                        target,
+                        |stocks| {
+                            let indicators = symbol_indicators(symbols, indicators);
+                            generate_stocks_svg(
+                                stocks,
+                                &indicators,
+                                *layout,
+                                page,
+                                battery_pct,
+                                canvas,
+                            )
+                        },
+                    )?;
+                    (bytes, refresh)
+                }
+                Err(e) => (
+                    fallback_bitmap_bytes("fetching stocks", e, target)?,
+                    Refresh::Retry,
+                ),
+            }
+        }
+        ScreenKind::Portfolio {
+            holdings,
+            providers,
+            currency,
+        } => match cached_portfolio(state, holdings, providers, currency).await {
+            Ok((holdings, stocks)) => {
+                let refresh = snapshot_refresh(&stocks, Refresh::Market);
+                // The holdings file can change without the prices, so the bitmap isn't cached
+                let svg = snapshot_svg(&stocks, canvas, |stocks| {
+                    portfolio_svg(&holdings, stocks, currency, battery_pct, locale, canvas)
+                });
+                (Bytes::from(render_svg_bytes(svg, target)?), refresh)
+            }
+            Err(e) => (
+                fallback_bitmap_bytes("pricing the portfolio", e, target)?,
+                Refresh::Retry,
+            ),
+        },
+        ScreenKind::Fred { duration } => {
+            let key = fred_cache_key(query, *duration);
+            match cached_fred(state, &key, query, *duration).await {
+                Ok(fred) => {
+                    let refresh = snapshot_refresh(&fred, Refresh::EconomicRelease);
+                    let bytes = snapshot_bitmap(
+                        &state.fred_cache,
+                        &key,
+                        &render_key,
+                        fred,
+                        target,
+                        |fred| generate_fred_svg(fred, battery_pct, canvas),
+                    )?;
+                    (bytes, refresh)
+                }
+                Err(e) => (
+                    fallback_bitmap_bytes("fetching FRED data", e, target)?,
+                    Refresh::Retry,
+                ),
+            }
+        }
+        ScreenKind::WeightForecast { user } | ScreenKind::WeightVelocity { user } => {
+            let generate = match screen.kind {
+                ScreenKind::WeightForecast { .. } => generate_forecast_svg,
+                _ => generate_velocity_svg,
+            };
+            let csv_path = match weight_csv_path(state, query, user) {
+                Ok(p) => p,
+                Err(e) => {
+                    // A bad user won't fix itself; keep the normal schedule
+                    return Ok((
+                        fallback_bitmap_bytes("invalid user parameter", e, target)?,
+                        Refresh::Scheduled,
+                    ));
+                }
+            };
+            match cached_weight(state, &csv_path).await {
+                Ok(data) => {
+                    let refresh = snapshot_refresh(&data, Refresh::Scheduled);
+                    let bytes = snapshot_bitmap(
+                        &state.weight_cache,
+                        &csv_path,
+                        &render_key,
+                        data,
+                        target,
+                        |data| generate(data, battery_pct, canvas),
+                    )?;
+                    (bytes, refresh)
+                }
+                Err(e) => (
+                    fallback_bitmap_bytes("fetching weight data", e, target)?,
+                    Refresh::Retry,
+                ),
+            }
+        }
+        ScreenKind::Battery { .. } => (
+            Bytes::from(render_svg_bytes(
+                battery_svg(state, screen, query, canvas),
+                target,
+            )?),
+            Refresh::Scheduled,
+        ),
+        ScreenKind::Almanac { .. } => match almanac_svg(screen, query, canvas, &state.geocoder) {
+            Ok(svg) => (
+                Bytes::from(render_svg_bytes(svg, target)?),
+                Refresh::Scheduled,
+            ),
+            Err(e) => (
+                fallback_bitmap_bytes("drawing the almanac", e, target)?,
+                Refresh::Retry,
+            ),
+        },
+    })
+}
+
+/// SVG for one configured screen, with the stale banner when serving last good data
+async fn screen_svg(
+    state: &AppState,
+    screen: &Screen,
+    query: &QueryArgs,
+    canvas: Canvas,
+) -> Result<String, String> {
+    let battery_pct = query.battery_pct;
+    let (units, locale) = (
+        requested_units(screen, query),
+        requested_locale(screen, query),
+    );
+    match &screen.kind {
+        ScreenKind::Weather {
+            location, provider, ..
+        }
+        | ScreenKind::WeatherHourly { location, provider }
+        | ScreenKind::WeatherNowcast { location, provider }
+        | ScreenKind::WeatherAlerts { location, provider } => {
+            let key = weather_cache_key(location, query, units, *provider);
+            let weather = cached_weather(state, &key, location, query, units, *provider).await?;
+            let view = weather_view(
+                &screen.kind,
+                weather.data(),
+                query,
+                canvas,
+                Utc::now().timestamp(),
+            );
+            Ok(snapshot_svg(&weather, canvas, |weather| {
+                weather_svg(state, view, weather, battery_pct, units, locale, canvas)
+            }))
+        }
+        ScreenKind::WeatherHistory {
+            location,
+            provider,
+            normals,
+        } => {
+            let key = weather_cache_key(location, query, units, *provider);
+            let weather = cached_weather(state, &key, location, query, units, *provider).await?;
+            let (history_key, history, normals) = weather_history(state, location, query, normals)?;
+            Ok(snapshot_svg(&weather, canvas, |weather| {
+                generate_weather_history_svg(
+                    weather,
+                    &history_key,
+                    &history,
+                    normals.as_ref(),
+                    battery_pct,
+                    &state.geocoder,
+                    units,
+                    locale,
+                    canvas,
+                )
+            }))
+        }
+        ScreenKind::Indoor {
+            location,
+            provider,
+            sensors,
+            co2_warning,
+        } => {
+            let key = weather_cache_key(location, query, units, *provider);
+            let weather = cached_weather(state, &key, location, query, units, *provider).await?;
+            Ok(snapshot_svg(&weather, canvas, |weather| {
+                indoor_svg(
+                    state,
+                    weather,
+                    sensors,
+                    *co2_warning,
+                    battery_pct,
+                    units,
+                    locale,
+                    canvas,
+                )
+            }))
+        }
+        ScreenKind::WeatherOverview { location } => {
+            let key = weather_cache_key(location, query, units, Provider::OpenWeather);
+            let weather = cached_weather_overview(state, &key, location, query, units).await?;
+            Ok(snapshot_svg(&weather, canvas, |weather| {
+                generate_weather_overview_svg(weather, battery_pct, &state.geocoder, locale, canvas)
+            }))
+        }
+        ScreenKind::AirQuality { location, provider } => {
+            let key = air_quality_cache_key(location, query, *provider);
+            let air = cached_air_quality(state, &key, location, query, *provider).await?;
+            Ok(snapshot_svg(&air, canvas, |air| {
+                generate_air_quality_svg(air, battery_pct, &state.geocoder, locale, canvas)
+            }))
+        }
+        ScreenKind::Stocks {
+            symbols,
+            providers,
+            indicators,
+            layout,
+        } => {
+            let key = stocks_cache_key(symbols, providers);
+            let page = requested_page(query, Utc::now().timestamp());
+            let stocks = cached_stocks(state, &key, symbols, providers).await?;
+            Ok(snapshot_svg(&stocks, canvas, |stocks| {
+                let indicators = symbol_indicators(symbols, indicators);
+                generate_stocks_svg(stocks, &indicators, *layout, page, battery_pct, canvas)
+            }))
+        }
+        ScreenKind::Portfolio {
+            holdings,
+            providers,
+            currency,
+        } => {
+            let (holdings, stocks) = cached_portfolio(state, holdings, providers, currency).await?;
+            Ok(snapshot_svg(&stocks, canvas, |stocks| {
+                portfolio_svg(&holdings, stocks, currency, battery_pct, locale, canvas)
+            }))
+        }
+        ScreenKind::Fred { duration } => {
+            let key = fred_cache_key(query, *duration);
+            let fred = cached_fred(state, &key, query, *duration).await?;
+            Ok(snapshot_svg(&fred, canvas, |fred| {
+                generate_fred_svg(fred, battery_pct, canvas)
+            }))
+        }
+        ScreenKind::WeightForecast { user } => {
+            let data = cached_weight(state, &weight_csv_path(state, query, user)?).await?;
+            Ok(snapshot_svg(&data, canvas, |data| {
+                generate_forecast_svg(data, battery_pct, canvas)
+            }))
+        }
+        ScreenKind::WeightVelocity { user } => {
+            let data = cached_weight(state, &weight_csv_path(state, query, user)?).await?;
+            Ok(snapshot_svg(&data, canvas, |data| {
+                generate_velocity_svg(data, battery_pct, canvas)
+            }))
+        }
+        ScreenKind::Battery { .. } => Ok(battery_svg(state, screen, query, canvas)),
+        ScreenKind::Almanac { .. } => almanac_svg(screen, query, canvas, &state.geocoder),
     }
 }
 
-async fn get_stocks_svg(
+async fn get_screen_file(
     State(state): State<Arc<AppState>>,
+    UrlPath(file): UrlPath<String>,
     Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    match fetch_stocks(&state.stocks_api_key, &state.stock_symbols).await {
-        Ok(stocks) => {
-            let svg_content = generate_stocks_svg(&stocks, query.battery_pct);
-            ([("Content-Type", "image/svg+xml")], svg_content)
+    headers: HeaderMap,
+    index: usize,
+) -> Response {
+    let screen = &state.screens[index];
+    match panel_file(&file, &query, &headers) {
+        Some((panel, format)) => {
+            if let (ImageFormat::Epbm(_), Some(id)) = (format, requested_device(&query, &headers)) {
+                record_check_in(&state, id, screen, &query);
+            }
+            image_response(
+                format,
+                screen_image(&state, screen, &query, panel, format).await,
+                &query,
+                None,
+            )
         }
-        Err(e) => ([("Content-Type", "image/svg+xml")], error_svg(e)),
+        None => unknown_panel(&file),
     }
 }
 
-async fn get_fred_bitmap(
+async fn get_screen_png(
     State(state): State<Arc<AppState>>,
     Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let bitmap = match fetch_fred(&state.fred_api_key, query.date.as_deref(), query.duration).await
-    {
-        Ok(fred) => render_svg_bytes(generate_fred_svg(&fred, query.battery_pct)),
-        Err(e) => fallback_bitmap_bytes("fetching FRED data", e),
-    };
-
-    ([("Content-Type", "application/octet-stream")], bitmap)
+    index: usize,
+) -> Response {
+    let screen = &state.screens[index];
+    let format = ImageFormat::Png;
+    let panel = requested_panel(screen, &query);
+    image_response(
+        format,
+        screen_image(&state, screen, &query, panel, format).await,
+        &query,
+        None,
+    )
 }
 
-async fn get_fred_svg(
+async fn get_screen_svg(
     State(state): State<Arc<AppState>>,
     Query(query): Query<QueryArgs>,
+    index: usize,
 ) -> impl IntoResponse {
-    match fetch_fred(&state.fred_api_key, query.date.as_deref(), query.duration).await {
-        Ok(fred) => {
-            let svg_content = generate_fred_svg(&fred, query.battery_pct);
-            ([("Content-Type", "image/svg+xml")], svg_content)
-        }
-        Err(e) => ([("Content-Type", "image/svg+xml")], error_svg(e)),
-    }
+    let screen = &state.screens[index];
+    let canvas = requested_panel(screen, &query).canvas();
+    let svg_content = screen_svg(&state, screen, &query, canvas)
+        .await
+        .unwrap_or_else(|e| error_svg(e, canvas));
+    ([("Content-Type", "image/svg+xml")], svg_content)
 }
 
-async fn get_weight_forecast_bitmap(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let csv_path = match weight_csv_path(&state, query.user.as_deref()) {
-        Ok(p) => p,
-        Err(e) => {
+/// Device ID for `/device/next.bin` and check-in logging: the `X-Device-Id` header,
+/// else `?device=`
+fn requested_device<'a>(query: &'a QueryArgs, headers: &'a HeaderMap) -> Option<&'a str> {
+    headers
+        .get("x-device-id")
+        .and_then(|value| value.to_str().ok())
+        .or(query.device.as_deref())
+        .filter(|id| !id.is_empty())
+}
+
+/// Logs a bitmap request from an identified device
+fn record_check_in(state: &AppState, device: &str, screen: &Screen, query: &QueryArgs) {
+    state.telemetry.record(CheckIn {
+        time: Utc::now(),
+        device: device.to_string(),
+        screen: screen.name.clone(),
+        battery_pct: query.battery_pct,
+        battery_mv: query.battery_mv,
+        rssi: query.rssi,
+        wake_reason: query.wake_reason.clone(),
+        firmware: query.firmware.clone(),
+        download_ms: query.download_ms,
+    });
+}
+
+/// The device's current playlist entry on its panel, as `next.bin` (EPBM) or
+/// `next.png` (preview, which does not advance the rotation). The chosen screen is
+/// named in the `X-Screen` header.
+async fn device_response(
+    state: &AppState,
+    id: &str,
+    file: &str,
+    query: &QueryArgs,
+    headers: &HeaderMap,
+) -> Response {
+    let format = match file {
+        "next.bin" => ImageFormat::Epbm(requested_encoding(query, headers)),
+        "next.png" => ImageFormat::Png,
+        _ => {
             return (
-                [("Content-Type", "application/octet-stream")],
-                fallback_bitmap_bytes("invalid user parameter", e),
+                StatusCode::NOT_FOUND,
+                format!("Unknown device file: {}", file),
             )
+                .into_response()
         }
     };
-    let bitmap = match fetch_weight_data(Path::new(&csv_path)).await {
-        Ok(data) => render_svg_bytes(generate_forecast_svg(&data, query.battery_pct)),
-        Err(e) => fallback_bitmap_bytes("fetching weight data", e),
+    let now = Local::now().time();
+    let Some((panel, index)) = state
+        .devices
+        .next_screen(id, now, format != ImageFormat::Png)
+    else {
+        return (StatusCode::NOT_FOUND, format!("Unknown device: {}", id)).into_response();
     };
+    let screen = &state.screens[index];
+    if format != ImageFormat::Png {
+        record_check_in(state, id, screen, query);
+    }
+    let mut response = image_response(
+        format,
+        screen_image(state, screen, query, panel, format).await,
+        query,
+        state.devices.next_boundary(id, now),
+    );
+    if let Ok(name) = HeaderValue::from_str(&screen.name) {
+        response.headers_mut().insert("x-screen", name);
+    }
+    response
+}
 
-    ([("Content-Type", "application/octet-stream")], bitmap)
+async fn get_device_file(
+    State(state): State<Arc<AppState>>,
+    UrlPath((id, file)): UrlPath<(String, String)>,
+    Query(query): Query<QueryArgs>,
+    headers: HeaderMap,
+) -> Response {
+    device_response(&state, &id, &file, &query, &headers).await
 }
 
-async fn get_weight_forecast_svg(
+async fn get_device_next(
     State(state): State<Arc<AppState>>,
+    UrlPath(file): UrlPath<String>,
     Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let csv_path = match weight_csv_path(&state, query.user.as_deref()) {
-        Ok(p) => p,
-        Err(e) => return ([("Content-Type", "image/svg+xml")], error_svg(e)),
-    };
-    match fetch_weight_data(Path::new(&csv_path)).await {
-        Ok(data) => {
-            let svg_content = generate_forecast_svg(&data, query.battery_pct);
-            ([("Content-Type", "image/svg+xml")], svg_content)
-        }
-        Err(e) => ([("Content-Type", "image/svg+xml")], error_svg(e)),
+    headers: HeaderMap,
+) -> Response {
+    match requested_device(&query, &headers) {
+        Some(id) => device_response(&state, id, &file, &query, &headers).await,
+        None => (
+            StatusCode::BAD_REQUEST,
+            "Missing device ID: send an X-Device-Id header or ?device=",
+        )
+            .into_response(),
     }
 }
 
-async fn get_weight_velocity_bitmap(
+/// Check-ins and battery estimate for every device heard from in the last `?duration=`
+/// days (30 by default)
+async fn get_telemetry(
     State(state): State<Arc<AppState>>,
     Query(query): Query<QueryArgs>,
 ) -> impl IntoResponse {
-    let csv_path = match weight_csv_path(&state, query.user.as_deref()) {
-        Ok(p) => p,
-        Err(e) => {
-            return (
-                [("Content-Type", "application/octet-stream")],
-                fallback_bitmap_bytes("invalid user parameter", e),
-            )
-        }
-    };
-    let bitmap = match fetch_weight_data(Path::new(&csv_path)).await {
-        Ok(data) => render_svg_bytes(generate_velocity_svg(&data, query.battery_pct)),
-        Err(e) => fallback_bitmap_bytes("fetching weight data", e),
-    };
-
-    ([("Content-Type", "application/octet-stream")], bitmap)
+    Json(state.telemetry.health(telemetry_days(&query, None)))
 }
 
-async fn get_weight_velocity_svg(
+async fn get_device_telemetry(
     State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<String>,
     Query(query): Query<QueryArgs>,
-) -> impl IntoResponse {
-    let csv_path = match weight_csv_path(&state, query.user.as_deref()) {
-        Ok(p) => p,
-        Err(e) => return ([("Content-Type", "image/svg+xml")], error_svg(e)),
-    };
-    match fetch_weight_data(Path::new(&csv_path)).await {
-        Ok(data) => {
-            let svg_content = generate_velocity_svg(&data, query.battery_pct);
-            ([("Content-Type", "image/svg+xml")], svg_content)
+) -> Response {
+    match state
+        .telemetry
+        .device_health(&id, telemetry_days(&query, None))
+    {
+        Some(health) => Json(health).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("No check-ins from {}", id)).into_response(),
+    }
+}
+
+/// Records a home sensor's reading; see the `sensors` module for the JSON
+async fn post_sensor_reading(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<String>,
+    Json(payload): Json<ReadingPayload>,
+) -> Response {
+    match payload.into_reading(&id, Utc::now()) {
+        Ok(reading) => {
+            state.sensors.record(reading);
+            StatusCode::NO_CONTENT.into_response()
         }
-        Err(e) => ([("Content-Type", "image/svg+xml")], error_svg(e)),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// Latest reading from every sensor
+async fn get_sensors(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.sensors.latest())
+}
+
+/// One sensor's readings over the last day
+async fn get_sensor(State(state): State<Arc<AppState>>, UrlPath(id): UrlPath<String>) -> Response {
+    let readings = state.sensors.history(&id, Utc::now() - indoor::HISTORY);
+    if readings.is_empty() {
+        return (StatusCode::NOT_FOUND, format!("No readings from {}", id)).into_response();
     }
+    Json(readings).into_response()
+}
+
+/// `/{screen}/{panel}.bin`, `/{screen}/{panel}.png`, `/{screen}/svg` and `/{screen}/png`
+/// for every configured screen, plus the device playlist, telemetry and sensor routes.
+/// The static `svg` and `png` routes take precedence over the `:file` one.
+fn app_routes(screens: &[Screen]) -> Router<Arc<AppState>> {
+    let router = Router::new()
+        .route("/device/:id/:file", get(get_device_file))
+        .route("/device/:file", get(get_device_next))
+        .route("/telemetry", get(get_telemetry))
+        .route("/telemetry/:id", get(get_device_telemetry))
+        .route("/sensors", get(get_sensors))
+        .route("/sensors/:id", get(get_sensor).post(post_sensor_reading));
+    screens
+        .iter()
+        .enumerate()
+        .fold(router, |router, (index, screen)| {
+            router
+                .route(
+                    &format!("/{}/:file", screen.name),
+                    get(move |state, file, query, headers| {
+                        get_screen_file(state, file, query, headers, index)
+                    }),
+                )
+                .route(
+                    &format!("/{}/svg", screen.name),
+                    get(move |state, query| get_screen_svg(state, query, index)),
+                )
+                .route(
+                    &format!("/{}/png", screen.name),
+                    get(move |state, query| get_screen_png(state, query, index)),
+                )
+        })
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
 
+    let config = match Config::load(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load config {}: {}", args.config.display(), e);
+            return;
+        }
+    };
+    let port = args.port.unwrap_or(config.port);
+
+    if let (Some(csv), Some(lat), Some(lon)) = (&args.import_history, args.lat, args.lon) {
+        let Some(units) = Units::from_name(&args.import_units) else {
+            eprintln!("Unknown units {}", args.import_units);
+            return;
+        };
+        let store = ObservationStore::persistent(config.state_dir.join("observations.jsonl"));
+        match read_history_csv(csv, &location_key(lat, lon), units) {
+            Ok(days) => println!(
+                "Imported {} days for {}",
+                store.import(days),
+                location_key(lat, lon)
+            ),
+            Err(e) => eprintln!("Failed to import {}: {}", csv.display(), e),
+        }
+        return;
+    }
+
     // HTTP server mode
     println!("\n=== iot-image Server Starting ===");
-    println!("Serving e-ink bitmaps on port {}", args.port);
-    println!(
-        "Endpoints:\n  Binary (EPBM):\n    - http://localhost:{}/weather/seed-e1002.bin\n    - http://localhost:{}/weather-overview/seed-e1002.bin\n    - http://localhost:{}/stocks/seed-e1002.bin\n    - http://localhost:{}/fred/seed-e1002.bin\n    - http://localhost:{}/weight/forecast/seed-e1002.bin\n    - http://localhost:{}/weight/velocity/seed-e1002.bin\n  SVG Preview:\n    - http://localhost:{}/weather/svg\n    - http://localhost:{}/weather-overview/svg\n    - http://localhost:{}/stocks/svg\n    - http://localhost:{}/fred/svg\n    - http://localhost:{}/weight/forecast/svg\n    - http://localhost:{}/weight/velocity/svg",
-        args.port, args.port, args.port, args.port, args.port, args.port, args.port, args.port, args.port, args.port, args.port, args.port
-    );
+    println!("Serving e-ink bitmaps on port {}", port);
+    println!("Config: {}", args.config.display());
+    println!("Screens ({{panel}}.bin for EPBM, {{panel}}.png, svg and png for previews):");
+    for screen in &config.screens {
+        println!(
+            "  - http://localhost:{}/{}/{}.bin",
+            port, screen.name, screen.panel.name
+        );
+    }
     println!("Format: Raw e-ink bitmap (EPBM)");
-    println!("Display: 800x480, 7 colors");
-    println!("Weather location: ({}, {})\n", args.lat, args.lon);
+    println!("Panels:");
+    for panel in &PANELS {
+        println!(
+            "  - {}: {}x{}, {} colors ({})",
+            panel.name,
+            panel.width,
+            panel.height,
+            panel.palette.len(),
+            panel.description
+        );
+    }
+    println!("Last good data: {}\n", config.state_dir.display());
+
+    let devices = match Devices::new(config.devices, &config.screens) {
+        Ok(devices) => devices,
+        Err(e) => {
+            eprintln!("Failed to load devices: {}", e);
+            return;
+        }
+    };
+    if !devices.is_empty() {
+        println!("Devices:");
+        for id in devices.ids() {
+            println!("  - http://localhost:{}/device/{}/next.bin", port, id);
+        }
+        println!();
+    }
+    println!("Telemetry: http://localhost:{}/telemetry", port);
+    println!("Sensors: http://localhost:{}/sensors\n", port);
+
+    let palettes =
+        match palette::build_palettes(config.palette_file.as_deref(), config.saturation_boost) {
+            Ok(palettes) => palettes,
+            Err(e) => {
+                eprintln!("Failed to load palette file: {}", e);
+                return;
+            }
+        };
 
+    let sources = &config.sources;
+    let ttl = |ttl_secs: Option<u64>, default_secs: u64| {
+        Duration::from_secs(ttl_secs.unwrap_or(default_secs))
+    };
+    let weather_ttl = ttl(sources.openweather.as_ref().and_then(|s| s.ttl_secs), 600);
+    let stocks_ttl = ttl(sources.twelve_data.as_ref().and_then(|s| s.ttl_secs), 900);
+    let fred_ttl = ttl(sources.fred.as_ref().and_then(|s| s.ttl_secs), 86400);
+    let weight_ttl = ttl(sources.weight.as_ref().and_then(|s| s.ttl_secs), 300);
+    let state_dir = &config.state_dir;
+
+    let sensors = Arc::new(SensorStore::persistent(state_dir.join("sensors.jsonl")));
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = sources.mqtt.clone() {
+        println!(
+            "Subscribing to {} on {}:{}",
+            mqtt.topic, mqtt.host, mqtt.port
+        );
+        tokio::spawn(sensors::mqtt::subscribe(mqtt, sensors.clone()));
+    }
+
+    let app = app_routes(&config.screens);
     let state = Arc::new(AppState {
-        lat: args.lat.clone(),
-        lon: args.lon.clone(),
-        api_key: args.open_weather_key.clone(),
-        stocks_api_key: args.stocks_api_key.clone(),
-        stock_symbols: args.stock_symbols.clone(),
-        fred_api_key: args.fred_api_key.clone(),
-        weight_data_dir: args.weight_data_dir.clone(),
         geocoder: ReverseGeocoder::new(),
+        weather_cache: SourceCache::persistent(weather_ttl, state_dir.join("weather")),
+        weather_overview_cache: SourceCache::persistent(
+            weather_ttl,
+            state_dir.join("weather-overview"),
+        ),
+        air_quality_cache: SourceCache::persistent(weather_ttl, state_dir.join("air-quality")),
+        stocks_cache: SourceCache::persistent(stocks_ttl, state_dir.join("stocks")),
+        fred_cache: SourceCache::persistent(fred_ttl, state_dir.join("fred")),
+        weight_cache: SourceCache::persistent(weight_ttl, state_dir.join("weight")),
+        palettes,
+        telemetry: TelemetryStore::persistent(state_dir.join("telemetry.jsonl")),
+        observations: ObservationStore::persistent(state_dir.join("observations.jsonl")),
+        sensors: sensors.clone(),
+        upstream: Upstream::new(upstream::REQUEST_TIMEOUT),
+        limits: Limits::new(&config.sources),
+        sources: config.sources,
+        screens: config.screens,
+        devices,
     });
+    let app = app.with_state(state);
 
-    let app = Router::new()
-        .route("/weather/seed-e1002.bin", get(get_weather_bitmap))
-        .route(
-            "/weather-overview/seed-e1002.bin",
-            get(get_weather_overview_bitmap),
-        )
-        .route("/stocks/seed-e1002.bin", get(get_stocks_bitmap))
-        .route("/fred/seed-e1002.bin", get(get_fred_bitmap))
-        .route(
-            "/weight/forecast/seed-e1002.bin",
-            get(get_weight_forecast_bitmap),
-        )
-        .route(
-            "/weight/velocity/seed-e1002.bin",
-            get(get_weight_velocity_bitmap),
-        )
-        .route("/weather/svg", get(get_weather_svg))
-        .route("/weather-overview/svg", get(get_weather_overview_svg))
-        .route("/stocks/svg", get(get_stocks_svg))
-        .route("/fred/svg", get(get_fred_svg))
-        .route("/weight/forecast/svg", get(get_weight_forecast_svg))
-        .route("/weight/velocity/svg", get(get_weight_velocity_svg))
-        .with_state(state);
-    let addr = SocketAddr::from(([0, 0, 0, 0], args.port));
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
     let listener = match tokio::net::TcpListener::bind(addr).await {
         Ok(listener) => listener,
         Err(e) => {
@@ -381,52 +1854,237 @@ async fn main() {
 
 #[cfg(test)]
 mod tests {
-    use super::{weather_coordinates, AppState, QueryArgs};
+    use super::{
+        app_routes, panel_file, requested_dither, requested_encoding, weather_coordinates,
+        AppState, Devices, EpbmEncoding, ImageFormat, Limits, Location, Provider, QueryArgs,
+        QuoteSource, Screen, ScreenKind, SourceCache, Sources, StockLayout, TelemetryStore,
+        Upstream,
+    };
+    use crate::device::{DeviceConfig, PlaylistEntry};
+    use crate::dither::DitherMode;
+    use crate::palette;
+    use crate::panel::DEFAULT_PANEL;
+    use crate::weather_history::ObservationStore;
+    use axum::body::Body;
+    use axum::http::{header, HeaderMap, HeaderValue, Request, StatusCode};
     use reverse_geocoder::ReverseGeocoder;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    const SAN_FRANCISCO: Location = Location {
+        lat: 37.7749,
+        lon: -122.4194,
+    };
 
-    fn test_state() -> AppState {
+    fn test_state(screens: Vec<Screen>, devices: Vec<DeviceConfig>) -> AppState {
         AppState {
-            lat: "37.7749".to_string(),
-            lon: "-122.4194".to_string(),
-            api_key: "weather-key".to_string(),
-            stocks_api_key: "stocks-key".to_string(),
-            stock_symbols: "QQQ".to_string(),
-            fred_api_key: "fred-key".to_string(),
-            weight_data_dir: "/tmp".to_string(),
+            sources: Sources::default(),
+            devices: Devices::new(devices, &screens).unwrap(),
+            screens,
             geocoder: ReverseGeocoder::new(),
+            weather_cache: SourceCache::new(Duration::from_secs(60)),
+            weather_overview_cache: SourceCache::new(Duration::from_secs(60)),
+            air_quality_cache: SourceCache::new(Duration::from_secs(60)),
+            stocks_cache: SourceCache::new(Duration::from_secs(60)),
+            fred_cache: SourceCache::new(Duration::from_secs(60)),
+            weight_cache: SourceCache::new(Duration::from_secs(60)),
+            palettes: palette::build_palettes(None, 1.2).unwrap(),
+            telemetry: TelemetryStore::default(),
+            observations: ObservationStore::default(),
+            sensors: Arc::default(),
+            upstream: Upstream::new(Duration::from_secs(5)),
+            limits: Limits::default(),
         }
     }
 
     #[test]
-    fn weather_coordinates_default_to_state() {
-        let state = test_state();
-        let query = QueryArgs {
-            battery_pct: None,
-            date: None,
-            duration: None,
-            user: None,
-            lat: None,
-            lon: None,
-        };
+    fn weather_coordinates_default_to_screen_location() {
+        let query = QueryArgs::default();
 
         assert_eq!(
-            weather_coordinates(&state, &query),
-            ("37.7749", "-122.4194")
+            weather_coordinates(&SAN_FRANCISCO, &query),
+            ("37.7749".to_string(), "-122.4194".to_string())
         );
     }
 
     #[test]
     fn weather_coordinates_use_query_overrides() {
-        let state = test_state();
         let query = QueryArgs {
-            battery_pct: None,
-            date: None,
-            duration: None,
-            user: None,
             lat: Some("40.7128".to_string()),
             lon: Some("-74.0060".to_string()),
+            ..QueryArgs::default()
+        };
+
+        assert_eq!(
+            weather_coordinates(&SAN_FRANCISCO, &query),
+            ("40.7128".to_string(), "-74.0060".to_string())
+        );
+    }
+
+    #[test]
+    fn encoding_comes_from_query_then_accept_header() {
+        let mut query = QueryArgs::default();
+        let mut headers = HeaderMap::new();
+        assert_eq!(requested_encoding(&query, &headers), EpbmEncoding::Raw);
+
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/x-epbm; encoding=rle, */*"),
+        );
+        assert_eq!(requested_encoding(&query, &headers), EpbmEncoding::Rle);
+
+        query.format = Some("packed4".to_string());
+        assert_eq!(requested_encoding(&query, &headers), EpbmEncoding::Packed4);
+    }
+
+    #[test]
+    fn panel_file_parses_panel_and_extension() {
+        let query = QueryArgs {
+            format: Some("rle".to_string()),
+            ..QueryArgs::default()
         };
+        let headers = HeaderMap::new();
+
+        let (panel, format) = panel_file("seed-e1002.bin", &query, &headers).unwrap();
+        assert_eq!(panel.name, "seed-e1002");
+        assert_eq!(format, ImageFormat::Epbm(EpbmEncoding::Rle));
+
+        let (panel, format) = panel_file("mono-2in9.png", &query, &headers).unwrap();
+        assert_eq!(panel.name, "mono-2in9");
+        assert_eq!(format, ImageFormat::Png);
+
+        assert!(panel_file("unknown.bin", &query, &headers).is_none());
+        assert!(panel_file("seed-e1002.gif", &query, &headers).is_none());
+        assert!(panel_file("seed-e1002", &query, &headers).is_none());
+    }
 
-        assert_eq!(weather_coordinates(&state, &query), ("40.7128", "-74.0060"));
+    #[test]
+    fn dither_comes_from_query_then_screen_then_type() {
+        let mut screen = Screen {
+            name: "stocks".to_string(),
+            kind: ScreenKind::Stocks {
+                symbols: "QQQ".to_string(),
+                providers: vec![QuoteSource::TwelveData],
+                indicators: Default::default(),
+                layout: StockLayout::Auto,
+            },
+            panel: DEFAULT_PANEL,
+            dither: None,
+            units: None,
+            locale: None,
+        };
+        let mut query = QueryArgs::default();
+        assert_eq!(requested_dither(&screen, &query), DitherMode::None);
+
+        screen.dither = Some(DitherMode::Bayer);
+        assert_eq!(requested_dither(&screen, &query), DitherMode::Bayer);
+
+        query.dither = Some("stucki".to_string());
+        assert_eq!(requested_dither(&screen, &query), DitherMode::Stucki);
+    }
+
+    #[tokio::test]
+    async fn routes_are_generated_per_screen_and_device() {
+        // No sources are configured, so a fetch fails fast and the test pattern is served
+        let screens = vec![Screen {
+            name: "weather/tahoe".to_string(),
+            kind: ScreenKind::Weather {
+                location: SAN_FRANCISCO,
+                provider: Provider::OpenWeather,
+                switch_to_alerts: false,
+            },
+            panel: DEFAULT_PANEL,
+            dither: None,
+            units: None,
+            locale: None,
+        }];
+        let devices = vec![DeviceConfig {
+            id: "kitchen".to_string(),
+            panel: Some("mono-2in9".to_string()),
+            playlist: vec![PlaylistEntry {
+                screen: "weather/tahoe".to_string(),
+                from: None,
+                until: None,
+            }],
+        }];
+        let app = app_routes(&screens).with_state(Arc::new(test_state(screens, devices)));
+
+        let get = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let request = Request::get(uri).body(Body::empty()).unwrap();
+                app.oneshot(request).await.unwrap()
+            }
+        };
+        let status = |uri: &'static str| async move { get(uri).await.status() };
+        assert_eq!(status("/weather/tahoe/mono-4in2.bin").await, StatusCode::OK);
+        assert_eq!(status("/weather/tahoe/png").await, StatusCode::OK);
+        assert_eq!(status("/weather/tahoe/svg").await, StatusCode::OK);
+        assert_eq!(
+            status("/weather/tahoe/unknown.bin").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status("/weather/seed-e1002.bin").await,
+            StatusCode::NOT_FOUND
+        );
+
+        let response = get("/device/kitchen/next.bin?battery_pct=80&rssi=-61").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-screen"], "weather/tahoe");
+        // No source configured, so the fetch failed: retry in an hour
+        assert_eq!(response.headers()["x-sleep-seconds"], "3600");
+        assert_eq!(
+            status("/device/next.png?device=kitchen").await,
+            StatusCode::OK
+        );
+        assert_eq!(status("/device/next.bin").await, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            status("/device/hallway/next.bin").await,
+            StatusCode::NOT_FOUND
+        );
+
+        // Only the EPBM requests from an identified device were logged
+        let response = get("/telemetry/kitchen").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let check_ins = health["check_ins"].as_array().unwrap();
+        assert_eq!(check_ins.len(), 1);
+        assert_eq!(check_ins[0]["battery_pct"], 80);
+        assert_eq!(check_ins[0]["rssi"], -61);
+        assert_eq!(status("/telemetry/hallway").await, StatusCode::NOT_FOUND);
+        assert_eq!(
+            status("/telemetry?duration=18446744073709551615").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("/telemetry/kitchen?duration=18446744073709551615").await,
+            StatusCode::OK
+        );
+
+        let post = |uri: &'static str, json: &'static str| {
+            let app = app.clone();
+            async move {
+                let request = Request::post(uri)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(json))
+                    .unwrap();
+                app.oneshot(request).await.unwrap().status()
+            }
+        };
+        assert_eq!(
+            post("/sensors/bedroom", r#"{"temperature": 19.5, "co2": 900}"#).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            post("/sensors/bedroom", "{}").await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(status("/sensors/bedroom").await, StatusCode::OK);
+        assert_eq!(status("/sensors/office").await, StatusCode::NOT_FOUND);
     }
 }
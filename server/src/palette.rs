@@ -0,0 +1,570 @@
+//! Dithering palettes: the colors a panel can show, in CIELAB, with gamut mapping.
+//!
+//! By default each panel uses the idealized sRGB values from its profile. A palette
+//! file (`palette_file` in the config) replaces them with colorimetric measurements of the real
+//! pigments, which are much duller than the sRGB primaries. Inputs are then compressed
+//! into the convex hull of the measured colors before matching, so flat areas of an
+//! out-of-gamut color settle on a stable pattern instead of accumulating error.
+//!
+//! Palette file format (JSON), keyed by panel name and then palette color name. Each
+//! color needs `lab` (measured L*a*b*, D65) and/or `rgb` (used for PNG previews;
+//! derived from `lab` when omitted). Colors not listed keep their profile values.
+//!
+//! ```json
+//! {
+//!   "seed-e1002": {
+//!     "saturation_boost": 1.0,
+//!     "colors": {
+//!       "White": { "lab": [72.5, -1.2, 3.4] },
+//!       "Green": { "lab": [45.1, -27.8, 17.2], "rgb": [61, 118, 82] }
+//!     }
+//!   }
+//! }
+//! ```
+
+use crate::panel::{Panel, PANELS};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+/// One panel's entry in the palette file
+#[derive(Debug, Deserialize)]
+pub struct PanelCalibration {
+    /// Overrides the config's `saturation_boost` for this panel
+    saturation_boost: Option<f32>,
+    #[serde(default)]
+    colors: HashMap<String, MeasuredColor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MeasuredColor {
+    lab: Option<[f32; 3]>,
+    rgb: Option<[u8; 3]>,
+}
+
+#[derive(Debug, Clone)]
+struct Swatch {
+    name: &'static str,
+    /// Preview color for PNG output
+    rgb: [u8; 3],
+    lab: [f32; 3],
+}
+
+/// A half-space `normal · x <= offset` bounding the palette's gamut in Lab
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    normal: [f32; 3],
+    offset: f32,
+}
+
+/// Palette used for dithering one panel, indexed by palette position
+#[derive(Debug, Clone)]
+pub struct DitherPalette {
+    swatches: Vec<Swatch>,
+    /// Pre-boost applied to input colors in HSL; 1.0 disables it
+    saturation_boost: f32,
+    /// Faces of the convex hull of the swatches; empty when they are coplanar
+    /// (e.g. black/white or black/white/red)
+    hull: Vec<Plane>,
+}
+
+impl DitherPalette {
+    /// The panel's profile colors, as sRGB
+    pub fn from_panel(panel: &Panel, saturation_boost: f32) -> Self {
+        let swatches = panel
+            .palette
+            .iter()
+            .map(|color| Swatch {
+                name: color.name,
+                rgb: color.rgb,
+                lab: rgb_to_lab(color.rgb[0], color.rgb[1], color.rgb[2]),
+            })
+            .collect();
+        Self::new(swatches, saturation_boost)
+    }
+
+    /// The panel's profile colors with measured values from a palette file entry
+    pub fn calibrated(
+        panel: &Panel,
+        calibration: &PanelCalibration,
+        default_boost: f32,
+    ) -> Result<Self, String> {
+        let mut palette = Self::from_panel(panel, default_boost);
+        for (name, measured) in &calibration.colors {
+            let swatch = palette
+                .swatches
+                .iter_mut()
+                .find(|swatch| swatch.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("{} has no color named {}", panel.name, name))?;
+            match (measured.lab, measured.rgb) {
+                (Some(lab), rgb) => {
+                    swatch.lab = lab;
+                    swatch.rgb = rgb.unwrap_or_else(|| lab_to_rgb(lab));
+                }
+                (None, Some(rgb)) => {
+                    swatch.rgb = rgb;
+                    swatch.lab = rgb_to_lab(rgb[0], rgb[1], rgb[2]);
+                }
+                (None, None) => {
+                    return Err(format!("{} {}: needs lab or rgb", panel.name, name));
+                }
+            }
+        }
+        Ok(Self::new(
+            palette.swatches,
+            calibration.saturation_boost.unwrap_or(default_boost),
+        ))
+    }
+
+    fn new(swatches: Vec<Swatch>, saturation_boost: f32) -> Self {
+        let lab: Vec<[f32; 3]> = swatches.iter().map(|swatch| swatch.lab).collect();
+        Self {
+            hull: convex_hull(&lab),
+            swatches,
+            saturation_boost,
+        }
+    }
+
+    pub fn name(&self, index: u8) -> Option<&'static str> {
+        self.swatches.get(index as usize).map(|swatch| swatch.name)
+    }
+
+    pub fn lab(&self, index: u8) -> [f32; 3] {
+        self.swatches[index as usize].lab
+    }
+
+    /// Preview colors for each palette position
+    pub fn preview_rgb(&self) -> Vec<[u8; 3]> {
+        self.swatches.iter().map(|swatch| swatch.rgb).collect()
+    }
+
+    /// Input pixel to Lab, after the saturation boost, with neutrals mapped onto the
+    /// panel's black-white axis so sRGB black and white land exactly on its black and
+    /// paper white
+    pub fn input_lab(&self, r: u8, g: u8, b: u8) -> [f32; 3] {
+        let (r, g, b) = if self.saturation_boost == 1.0 {
+            (r, g, b)
+        } else {
+            boost_saturation(r, g, b, self.saturation_boost)
+        };
+        let [l, a, b] = rgb_to_lab(r, g, b);
+        let neutral = self.neutral((l / 100.0).clamp(0.0, 1.0));
+        [neutral[0], a + neutral[1], b + neutral[2]]
+    }
+
+    /// Point `t` of the way from the black swatch to the white swatch
+    fn neutral(&self, t: f32) -> [f32; 3] {
+        let black = self.swatches[0].lab;
+        let white = self.swatches[1].lab;
+        [
+            black[0] + t * (white[0] - black[0]),
+            black[1] + t * (white[1] - black[1]),
+            black[2] + t * (white[2] - black[2]),
+        ]
+    }
+
+    /// Pull a Lab color into the palette's gamut, toward the black-white axis. Colors
+    /// already in gamut are unchanged. The anchor sits halfway between the input's
+    /// lightness and mid-gray: clipping at constant lightness would turn light, saturated
+    /// colors (sRGB green, yellow) into near-white on a dull palette.
+    pub fn gamut_map(&self, lab: [f32; 3]) -> [f32; 3] {
+        let black = self.swatches[0].lab;
+        let white = self.swatches[1].lab;
+
+        if self.hull.is_empty() {
+            // Flat palette: clamp lightness to black..white and limit chroma to the most
+            // saturated swatch
+            let lightness = lab[0].clamp(black[0].min(white[0]), black[0].max(white[0]));
+            let max_chroma = self
+                .swatches
+                .iter()
+                .map(|swatch| swatch.lab[1].hypot(swatch.lab[2]))
+                .fold(0.0, f32::max);
+            let chroma = lab[1].hypot(lab[2]);
+            let scale = if chroma > max_chroma {
+                max_chroma / chroma
+            } else {
+                1.0
+            };
+            return [lightness, lab[1] * scale, lab[2] * scale];
+        }
+
+        let t = ((lab[0] - black[0]) / (white[0] - black[0])).clamp(0.0, 1.0);
+        let anchor = self.neutral((t + 0.5) / 2.0);
+        // Largest step from the anchor toward `lab` that stays inside every face
+        let direction = sub(lab, anchor);
+        let mut step = 1.0f32;
+        for plane in &self.hull {
+            let towards = dot(plane.normal, direction);
+            if towards > 0.0 {
+                step = step.min(((plane.offset - dot(plane.normal, anchor)) / towards).max(0.0));
+            }
+        }
+        [
+            anchor[0] + step * direction[0],
+            anchor[1] + step * direction[1],
+            anchor[2] + step * direction[2],
+        ]
+    }
+
+    /// Position of the perceptually nearest palette color (Delta E 1976)
+    pub fn nearest(&self, lab: [f32; 3]) -> u8 {
+        self.swatches
+            .iter()
+            .enumerate()
+            .map(|(idx, swatch)| (idx, delta_e(lab, swatch.lab)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(1, |(idx, _)| idx as u8)
+    }
+}
+
+/// Dithering palettes for every panel, with a palette file's measurements applied
+pub fn build_palettes(
+    palette_file: Option<&Path>,
+    saturation_boost: f32,
+) -> Result<HashMap<&'static str, DitherPalette>, Box<dyn Error>> {
+    let mut calibrations: HashMap<String, PanelCalibration> = match palette_file {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => HashMap::new(),
+    };
+
+    let mut palettes = HashMap::new();
+    for panel in &PANELS {
+        let palette = match calibrations.remove(panel.name) {
+            Some(calibration) => DitherPalette::calibrated(panel, &calibration, saturation_boost)?,
+            None => DitherPalette::from_panel(panel, saturation_boost),
+        };
+        palettes.insert(panel.name, palette);
+    }
+    if let Some(name) = calibrations.keys().next() {
+        return Err(format!("Palette file names unknown panel {}", name).into());
+    }
+    Ok(palettes)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Faces of the convex hull of a handful of points, by checking every triple.
+/// Returns no faces when the points are coplanar.
+fn convex_hull(points: &[[f32; 3]]) -> Vec<Plane> {
+    const EPSILON: f32 = 1e-3;
+    let mut planes = Vec::new();
+    for i in 0..points.len() {
+        for j in i + 1..points.len() {
+            for k in j + 1..points.len() {
+                let normal = cross(sub(points[j], points[i]), sub(points[k], points[i]));
+                let length = dot(normal, normal).sqrt();
+                if length < EPSILON {
+                    continue;
+                }
+                let normal = normal.map(|c| c / length);
+                let offset = dot(normal, points[i]);
+                let sides = points.iter().map(|&p| dot(normal, p) - offset);
+                let above = sides.clone().filter(|&s| s > EPSILON).count();
+                let below = sides.filter(|&s| s < -EPSILON).count();
+                if above == 0 && below > 0 {
+                    planes.push(Plane { normal, offset });
+                } else if below == 0 && above > 0 {
+                    planes.push(Plane {
+                        normal: normal.map(|c| -c),
+                        offset: -offset,
+                    });
+                }
+            }
+        }
+    }
+    planes
+}
+
+/// Perceptual color difference in CIELAB space (Delta E 1976)
+fn delta_e(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let d = sub(a, b);
+    dot(d, d).sqrt()
+}
+
+/// Convert sRGB (0-255) to linear RGB (0.0-1.0)
+/// Applies inverse gamma correction
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert linear RGB to XYZ color space (D65 illuminant)
+fn rgb_to_xyz(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let r_lin = srgb_to_linear(r);
+    let g_lin = srgb_to_linear(g);
+    let b_lin = srgb_to_linear(b);
+
+    // sRGB to XYZ matrix (D65)
+    let x = r_lin * 0.4124564 + g_lin * 0.3575761 + b_lin * 0.1804375;
+    let y = r_lin * 0.2126729 + g_lin * 0.7151522 + b_lin * 0.0721750;
+    let z = r_lin * 0.0193339 + g_lin * 0.119_192 + b_lin * 0.9503041;
+
+    (x * 100.0, y * 100.0, z * 100.0) // Scale to 0-100 range
+}
+
+/// XYZ to CIELAB conversion helper function
+fn xyz_to_lab_component(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+/// Convert XYZ to CIELAB color space (D65 illuminant)
+/// Returns (L*, a*, b*) where L* is lightness (0-100), a* and b* are color components
+fn xyz_to_lab(x: f32, y: f32, z: f32) -> (f32, f32, f32) {
+    // D65 reference white point
+    const XN: f32 = 95.047;
+    const YN: f32 = 100.000;
+    const ZN: f32 = 108.883;
+
+    let fx = xyz_to_lab_component(x / XN);
+    let fy = xyz_to_lab_component(y / YN);
+    let fz = xyz_to_lab_component(z / ZN);
+
+    let l = 116.0 * fy - 16.0;
+    let a = 500.0 * (fx - fy);
+    let b = 200.0 * (fy - fz);
+
+    (l, a, b)
+}
+
+/// Convert RGB to CIELAB color space
+fn rgb_to_lab(r: u8, g: u8, b: u8) -> [f32; 3] {
+    let (x, y, z) = rgb_to_xyz(r, g, b);
+    let (l, a, b) = xyz_to_lab(x, y, z);
+    [l, a, b]
+}
+
+/// Convert CIELAB back to sRGB, clipping colors outside the sRGB gamut
+fn lab_to_rgb(lab: [f32; 3]) -> [u8; 3] {
+    const DELTA: f32 = 6.0 / 29.0;
+    let f_inv = |t: f32| {
+        if t > DELTA {
+            t * t * t
+        } else {
+            3.0 * DELTA * DELTA * (t - 4.0 / 29.0)
+        }
+    };
+    let fy = (lab[0] + 16.0) / 116.0;
+    let x = 0.95047 * f_inv(fy + lab[1] / 500.0);
+    let y = f_inv(fy);
+    let z = 1.08883 * f_inv(fy - lab[2] / 200.0);
+
+    // XYZ to linear sRGB (D65)
+    let r = x * 3.2404542 - y * 1.5371385 - z * 0.4985314;
+    let g = -x * 0.969266 + y * 1.8760108 + z * 0.041556;
+    let b = x * 0.0556434 - y * 0.2040259 + z * 1.0572252;
+
+    [r, g, b].map(linear_to_srgb)
+}
+
+/// Convert linear RGB (0.0-1.0) to sRGB (0-255)
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+/// Convert RGB to HSL color space
+/// Returns (hue [0-360], saturation [0-1], lightness [0-1])
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let r = r as f32 / 255.0;
+    let g = g as f32 / 255.0;
+    let b = b as f32 / 255.0;
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    // Lightness
+    let l = (max + min) / 2.0;
+
+    // Saturation
+    let s = if delta == 0.0 {
+        0.0
+    } else {
+        delta / (1.0 - (2.0 * l - 1.0).abs())
+    };
+
+    // Hue
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * (((g - b) / delta) % 6.0)
+    } else if max == g {
+        60.0 * (((b - r) / delta) + 2.0)
+    } else {
+        60.0 * (((r - g) / delta) + 4.0)
+    };
+
+    let h = if h < 0.0 { h + 360.0 } else { h };
+
+    (h, s, l)
+}
+
+/// Convert HSL to RGB color space
+/// Takes (hue [0-360], saturation [0-1], lightness [0-1])
+/// Returns (r, g, b) in [0-255]
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+
+    let (r, g, b) = if h < 60.0 {
+        (c, x, 0.0)
+    } else if h < 120.0 {
+        (x, c, 0.0)
+    } else if h < 180.0 {
+        (0.0, c, x)
+    } else if h < 240.0 {
+        (0.0, x, c)
+    } else if h < 300.0 {
+        (x, 0.0, c)
+    } else {
+        (c, 0.0, x)
+    };
+
+    (
+        ((r + m) * 255.0).round().clamp(0.0, 255.0) as u8,
+        ((g + m) * 255.0).round().clamp(0.0, 255.0) as u8,
+        ((b + m) * 255.0).round().clamp(0.0, 255.0) as u8,
+    )
+}
+
+/// Boost saturation to compensate for e-paper's less vivid pigments
+/// Increases saturation by the given factor (e.g., 1.2 for 20% boost)
+/// This helps the dithering algorithm favor primary colors over black/white
+fn boost_saturation(r: u8, g: u8, b: u8, boost_factor: f32) -> (u8, u8, u8) {
+    let (h, s, l) = rgb_to_hsl(r, g, b);
+
+    // Boost saturation, clamping to [0, 1]
+    let s_boosted = (s * boost_factor).min(1.0);
+
+    hsl_to_rgb(h, s_boosted, l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_palettes, lab_to_rgb, rgb_to_lab, DitherPalette, PanelCalibration};
+    use crate::dither::{dither_pixmap, DitherMode};
+    use crate::panel::{find_panel, DEFAULT_PANEL};
+
+    #[test]
+    fn lab_round_trips_through_rgb() {
+        for rgb in [[0, 0, 0], [255, 255, 255], [12, 200, 90], [250, 40, 130]] {
+            assert_eq!(lab_to_rgb(rgb_to_lab(rgb[0], rgb[1], rgb[2])), rgb);
+        }
+    }
+
+    #[test]
+    fn gamut_map_pulls_colors_onto_the_measured_hull() {
+        let calibration: PanelCalibration = serde_json::from_str(
+            r#"{ "saturation_boost": 1.0, "colors": {
+                "Black": { "lab": [18.0, 2.0, -3.0] },
+                "White": { "lab": [73.0, -1.0, 3.0] },
+                "Green": { "lab": [48.0, -28.0, 18.0] },
+                "Blue": { "lab": [33.0, 8.0, -38.0] },
+                "Red": { "lab": [40.0, 46.0, 30.0] },
+                "Yellow": { "lab": [74.0, -4.0, 62.0] }
+            } }"#,
+        )
+        .unwrap();
+        let palette = DitherPalette::calibrated(DEFAULT_PANEL, &calibration, 1.2).unwrap();
+        assert_eq!(palette.saturation_boost, 1.0);
+
+        // sRGB white and black are the panel's paper white and black
+        let white = palette.gamut_map(palette.input_lab(255, 255, 255));
+        assert!(
+            super::delta_e(white, [73.0, -1.0, 3.0]) < 0.01,
+            "{:?}",
+            white
+        );
+        let black = palette.gamut_map(palette.input_lab(0, 0, 0));
+        assert!(
+            super::delta_e(black, [18.0, 2.0, -3.0]) < 0.01,
+            "{:?}",
+            black
+        );
+
+        // Saturated sRGB green lands on the hull, nearer to the measured green
+        let green = palette.gamut_map(palette.input_lab(0, 255, 0));
+        assert!(palette
+            .hull
+            .iter()
+            .all(|p| super::dot(p.normal, green) <= p.offset + 0.01));
+        assert_eq!(palette.nearest(green), 2);
+
+        // In-gamut colors are left alone
+        let mid = [45.0, 5.0, 5.0];
+        assert_eq!(palette.gamut_map(mid), mid);
+    }
+
+    #[test]
+    fn flat_palettes_keep_lightness() {
+        for name in ["mono-4in2", "bwr-4in2"] {
+            let palette = DitherPalette::from_panel(find_panel(name).unwrap(), 1.2);
+            let white = palette.gamut_map(palette.input_lab(255, 255, 255));
+            let black = palette.gamut_map(palette.input_lab(0, 0, 0));
+            assert_eq!(palette.nearest(white), 1, "{}: {:?}", name, white);
+            assert_eq!(palette.nearest(black), 0, "{}: {:?}", name, black);
+            assert!(super::delta_e(white, palette.lab(1)) < 0.01, "{:?}", white);
+            assert!(super::delta_e(black, palette.lab(0)) < 0.01, "{:?}", black);
+            let gray = [45.0, 0.0, 0.0];
+            assert_eq!(palette.gamut_map(gray), gray);
+
+            // Error diffusion leaves a white area white
+            let mut pixmap = tiny_skia::Pixmap::new(100, 100).unwrap();
+            pixmap.fill(tiny_skia::Color::WHITE);
+            let bitmap = dither_pixmap(&pixmap, DitherMode::FloydSteinberg, &palette);
+            let stray = (0..100)
+                .flat_map(|y| (0..100).map(move |x| (x, y)))
+                .filter(|&(x, y)| bitmap.get_index(x, y) != 1)
+                .count();
+            assert_eq!(stray, 0, "{}", name);
+        }
+    }
+
+    #[test]
+    fn palette_file_rejects_unknown_names() {
+        let bwr = find_panel("bwr-4in2").unwrap();
+        let calibration: PanelCalibration =
+            serde_json::from_str(r#"{ "colors": { "Green": { "rgb": [0, 128, 0] } } }"#).unwrap();
+        assert!(DitherPalette::calibrated(bwr, &calibration, 1.2).is_err());
+
+        let dir = std::env::temp_dir().join(format!("palette-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("palette.json");
+        std::fs::write(&path, r#"{ "no-such-panel": { "colors": {} } }"#).unwrap();
+        assert!(build_palettes(Some(&path), 1.2).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(build_palettes(None, 1.2).unwrap().len(), 5);
+    }
+}
@@ -0,0 +1,269 @@
+//! E-paper panel profiles: resolution, palette, native color indices, and rotation.
+//!
+//! Screens are laid out and dithered in the panel's viewing orientation, using
+//! palette positions (0 = black, 1 = white for every palette). `to_native` then
+//! rotates the bitmap to the controller's scan order and maps palette positions to
+//! the values the panel's driver expects.
+
+use crate::bitmap::EpdBitmap;
+use crate::svg_common::Canvas;
+
+/// One pigment: its approximate appearance and the value the panel driver uses for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteColor {
+    pub name: &'static str,
+    pub rgb: [u8; 3],
+    pub index: u8,
+}
+
+/// Clockwise rotation from the viewing orientation to the controller's scan order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum Rotation {
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+#[derive(Debug)]
+pub struct Panel {
+    /// Route name, e.g. `/weather/seed-e1002.bin`
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Native (controller) resolution
+    pub width: u16,
+    pub height: u16,
+    pub rotation: Rotation,
+    /// Black first and white second; the rest in any order
+    pub palette: &'static [PaletteColor],
+}
+
+/// Approximate RGB values for E Ink Spectra 6 pigments, indexed as GxEPD2 expects
+const SPECTRA6: [PaletteColor; 6] = [
+    PaletteColor {
+        name: "Black",
+        rgb: [0, 0, 0],
+        index: 0,
+    },
+    PaletteColor {
+        name: "White",
+        rgb: [255, 255, 255],
+        index: 1,
+    },
+    PaletteColor {
+        name: "Green",
+        rgb: [0, 255, 0],
+        index: 2,
+    },
+    PaletteColor {
+        name: "Blue",
+        rgb: [0, 0, 255],
+        index: 3,
+    },
+    // Spectra Red is fairly pure
+    PaletteColor {
+        name: "Red",
+        rgb: [255, 0, 0],
+        index: 4,
+    },
+    PaletteColor {
+        name: "Yellow",
+        rgb: [255, 255, 0],
+        index: 5,
+    },
+];
+
+/// Spectra 6 on the 13.3" controller, which skips value 4 and orders the colors differently
+const SPECTRA6_13IN3: [PaletteColor; 6] = [
+    PaletteColor {
+        name: "Black",
+        rgb: [0, 0, 0],
+        index: 0,
+    },
+    PaletteColor {
+        name: "White",
+        rgb: [255, 255, 255],
+        index: 1,
+    },
+    PaletteColor {
+        name: "Yellow",
+        rgb: [255, 255, 0],
+        index: 2,
+    },
+    PaletteColor {
+        name: "Red",
+        rgb: [255, 0, 0],
+        index: 3,
+    },
+    PaletteColor {
+        name: "Blue",
+        rgb: [0, 0, 255],
+        index: 5,
+    },
+    PaletteColor {
+        name: "Green",
+        rgb: [0, 255, 0],
+        index: 6,
+    },
+];
+
+const BLACK_WHITE: [PaletteColor; 2] = [
+    PaletteColor {
+        name: "Black",
+        rgb: [0, 0, 0],
+        index: 0,
+    },
+    PaletteColor {
+        name: "White",
+        rgb: [255, 255, 255],
+        index: 1,
+    },
+];
+
+const BLACK_WHITE_RED: [PaletteColor; 3] = [
+    PaletteColor {
+        name: "Black",
+        rgb: [0, 0, 0],
+        index: 0,
+    },
+    PaletteColor {
+        name: "White",
+        rgb: [255, 255, 255],
+        index: 1,
+    },
+    PaletteColor {
+        name: "Red",
+        rgb: [200, 0, 0],
+        index: 2,
+    },
+];
+
+pub const PANELS: [Panel; 5] = [
+    Panel {
+        name: "seed-e1002",
+        description: "Seeed reTerminal E1002, 7.3\" Spectra 6",
+        width: 800,
+        height: 480,
+        rotation: Rotation::None,
+        palette: &SPECTRA6,
+    },
+    Panel {
+        name: "spectra6-13in3",
+        description: "13.3\" Spectra 6, portrait controller mounted landscape",
+        width: 1200,
+        height: 1600,
+        rotation: Rotation::Cw90,
+        palette: &SPECTRA6_13IN3,
+    },
+    Panel {
+        name: "mono-4in2",
+        description: "4.2\" black/white",
+        width: 400,
+        height: 300,
+        rotation: Rotation::None,
+        palette: &BLACK_WHITE,
+    },
+    Panel {
+        name: "mono-2in9",
+        description: "2.9\" black/white, portrait controller mounted landscape",
+        width: 128,
+        height: 296,
+        rotation: Rotation::Cw90,
+        palette: &BLACK_WHITE,
+    },
+    Panel {
+        name: "bwr-4in2",
+        description: "4.2\" black/white/red",
+        width: 400,
+        height: 300,
+        rotation: Rotation::None,
+        palette: &BLACK_WHITE_RED,
+    },
+];
+
+/// The panel the client firmware in this repo drives
+pub const DEFAULT_PANEL: &Panel = &PANELS[0];
+
+pub fn find_panel(name: &str) -> Option<&'static Panel> {
+    PANELS.iter().find(|panel| panel.name == name)
+}
+
+impl Panel {
+    /// Size in the viewing orientation, which is what screens are laid out and dithered at
+    pub fn layout_size(&self) -> (u16, u16) {
+        match self.rotation {
+            Rotation::None | Rotation::Cw180 => (self.width, self.height),
+            Rotation::Cw90 | Rotation::Cw270 => (self.height, self.width),
+        }
+    }
+
+    /// SVG canvas for this panel, one unit per pixel. Generators pick their layout and
+    /// font sizes from it (see `Canvas::is_compact`).
+    pub fn canvas(&self) -> Canvas {
+        let (width, height) = self.layout_size();
+        Canvas {
+            width: width as i32,
+            height: height as i32,
+        }
+    }
+
+    /// Rotate a layout-orientation bitmap to scan order and map palette positions to
+    /// the driver's color values
+    pub fn to_native(&self, bitmap: &EpdBitmap) -> EpdBitmap {
+        let (lw, lh) = (bitmap.width(), bitmap.height());
+        let mut native = EpdBitmap::new(self.width, self.height);
+        for y in 0..lh {
+            for x in 0..lw {
+                let (nx, ny) = match self.rotation {
+                    Rotation::None => (x, y),
+                    Rotation::Cw90 => (lh - 1 - y, x),
+                    Rotation::Cw180 => (lw - 1 - x, lh - 1 - y),
+                    Rotation::Cw270 => (y, lw - 1 - x),
+                };
+                let position = bitmap.get_index(x, y) as usize;
+                let color = self.palette.get(position).unwrap_or(&self.palette[1]);
+                native.set_index(nx, ny, color.index);
+            }
+        }
+        native
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_panel, PANELS};
+    use crate::bitmap::EpdBitmap;
+
+    #[test]
+    fn panels_are_consistent() {
+        for panel in &PANELS {
+            assert_eq!(find_panel(panel.name).unwrap().name, panel.name);
+            assert_eq!(panel.palette[0].name, "Black", "{}", panel.name);
+            assert_eq!(panel.palette[1].name, "White", "{}", panel.name);
+            // RLE stores colors in 3 bits
+            assert!(panel.palette.iter().all(|c| c.index < 8), "{}", panel.name);
+            let canvas = panel.canvas();
+            let (width, height) = panel.layout_size();
+            assert_eq!((canvas.width, canvas.height), (width as i32, height as i32));
+        }
+        assert!(find_panel("nope").is_none());
+    }
+
+    #[test]
+    fn to_native_rotates_and_maps_indices() {
+        let panel = find_panel("spectra6-13in3").unwrap();
+        assert_eq!(panel.layout_size(), (1600, 1200));
+
+        let mut layout = EpdBitmap::new(1600, 1200);
+        layout.set_index(0, 0, 2); // Yellow in the top-left corner as viewed
+        layout.set_index(1599, 1199, 5); // Green in the bottom-right
+
+        let native = panel.to_native(&layout);
+        assert_eq!((native.width(), native.height()), (1200, 1600));
+        // Rotated clockwise: viewed top-left lands at the native top-right
+        assert_eq!(native.get_index(1199, 0), 2);
+        assert_eq!(native.get_index(0, 1599), 6);
+        assert_eq!(native.get_index(600, 800), 1);
+    }
+}
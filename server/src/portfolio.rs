@@ -0,0 +1,652 @@
+//! The portfolio screen: real positions valued from the same daily series the stocks
+//! screen downloads. Holdings come from a local CSV with `symbol`, `quantity`,
+//! `cost_basis` (the total paid for the position) and an optional `currency`.
+//! Positions priced in another currency than the screen's are converted with the
+//! `{CURRENCY}/{BASE}` pair, fetched alongside the symbols.
+
+use crate::locale::Locale;
+use crate::stocks::{StockPoint, StocksData, CHART_DAYS};
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+/// Colors of the allocation segments, in holdings order
+const SEGMENT_COLORS: [&str; 5] = ["blue", "green", "red", "yellow", "black"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holding {
+    pub symbol: String,
+    pub quantity: f64,
+    /// Total paid for the position, in `currency`
+    pub cost_basis: f64,
+    /// Currency the symbol is priced in, e.g. "USD"
+    pub currency: String,
+}
+
+#[derive(Deserialize)]
+struct HoldingRecord {
+    symbol: String,
+    quantity: f64,
+    cost_basis: f64,
+    #[serde(default)]
+    currency: Option<String>,
+}
+
+/// Reads a holdings CSV. A row without a currency is priced in `base`.
+pub fn load_holdings(path: &Path, base: &str) -> Result<Vec<Holding>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file);
+    let mut holdings: Vec<Holding> = Vec::new();
+    for result in rdr.deserialize() {
+        let record: HoldingRecord = result?;
+        if record.symbol.is_empty() || record.symbol.contains(',') {
+            return Err(format!("Invalid symbol {:?} in {}", record.symbol, path.display()).into());
+        }
+        let valid = |amount: f64| amount.is_finite() && amount >= 0.0;
+        if !valid(record.quantity) || record.quantity == 0.0 || !valid(record.cost_basis) {
+            return Err(format!(
+                "{}: quantity must be positive and cost basis not negative",
+                record.symbol
+            )
+            .into());
+        }
+        if holdings.iter().any(|h| h.symbol == record.symbol) {
+            return Err(format!("{} is listed twice in {}", record.symbol, path.display()).into());
+        }
+        let currency = match record.currency.filter(|c| !c.is_empty()) {
+            Some(currency) => currency.to_ascii_uppercase(),
+            None => base.to_string(),
+        };
+        if !valid_currency(&currency) {
+            return Err(format!("Invalid currency {}", currency).into());
+        }
+        holdings.push(Holding {
+            symbol: record.symbol,
+            quantity: record.quantity,
+            cost_basis: record.cost_basis,
+            currency,
+        });
+    }
+    if holdings.is_empty() {
+        return Err(format!("No holdings in {}", path.display()).into());
+    }
+    Ok(holdings)
+}
+
+/// A three-letter code such as "USD"
+pub fn valid_currency(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// The symbols to fetch: each holding's, then a pair for each other currency
+pub fn quote_symbols(holdings: &[Holding], base: &str) -> Vec<String> {
+    let mut symbols: Vec<String> = holdings.iter().map(|h| h.symbol.clone()).collect();
+    for holding in holdings {
+        let pair = fx_pair(&holding.currency, base);
+        if holding.currency != base && !symbols.contains(&pair) {
+            symbols.push(pair);
+        }
+    }
+    symbols
+}
+
+fn fx_pair(currency: &str, base: &str) -> String {
+    format!("{}/{}", currency, base)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub symbol: String,
+    /// Market value in the base currency
+    pub value: f64,
+    /// Cost basis in the base currency, at today's exchange rate
+    pub cost: f64,
+    /// Change in value since the symbol's previous close, each close priced at its own
+    /// day's exchange rate as on the chart
+    pub day_change: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Valuation {
+    pub positions: Vec<Position>,
+    /// Portfolio value on each day of the chart window, oldest first
+    pub history: Vec<(String, f64)>,
+}
+
+impl Valuation {
+    pub fn value(&self) -> f64 {
+        self.positions.iter().map(|p| p.value).sum()
+    }
+
+    pub fn cost(&self) -> f64 {
+        self.positions.iter().map(|p| p.cost).sum()
+    }
+
+    pub fn day_change(&self) -> f64 {
+        self.positions.iter().map(|p| p.day_change).sum()
+    }
+}
+
+/// The last close on or before `date`, for series sorted oldest first
+fn close_on(points: &[StockPoint], date: &str) -> Option<f64> {
+    let end = points.partition_point(|p| p.date.as_str() <= date);
+    Some(points[..end].last()?.close)
+}
+
+/// Values the holdings from `stocks`, which was fetched for `quote_symbols(holdings,
+/// base)` and is in the same order. Days on which a market was shut carry its last
+/// close forward, and the history starts once every position has a price.
+pub fn value_portfolio(
+    holdings: &[Holding],
+    base: &str,
+    stocks: &StocksData,
+) -> Result<Valuation, String> {
+    let symbols = quote_symbols(holdings, base);
+    if stocks.stocks.len() != symbols.len() {
+        return Err("Prices do not match the holdings".to_string());
+    }
+    let series = |symbol: &str| -> &[StockPoint] {
+        symbols
+            .iter()
+            .position(|s| s == symbol)
+            .map_or(&[][..], |i| &stocks.stocks[i].points)
+    };
+    // Priced in `base` at the close on or before `date`; `None` for the latest
+    let rate = |holding: &Holding, date: Option<&str>| -> Option<f64> {
+        if holding.currency == base {
+            return Some(1.0);
+        }
+        let points = series(&fx_pair(&holding.currency, base));
+        match date {
+            Some(date) => close_on(points, date),
+            None => points.last().map(|p| p.close),
+        }
+    };
+
+    let mut positions = Vec::new();
+    for holding in holdings {
+        let points = series(&holding.symbol);
+        let (Some(last), Some(today_rate)) = (points.last(), rate(holding, None)) else {
+            return Err(format!("No prices for {}", holding.symbol));
+        };
+        let value = holding.quantity * last.close * today_rate;
+        let previous_value = points.len().checked_sub(2).map_or(value, |i| {
+            let previous = &points[i];
+            holding.quantity
+                * previous.close
+                * rate(holding, Some(&previous.date)).unwrap_or(today_rate)
+        });
+        positions.push(Position {
+            symbol: holding.symbol.clone(),
+            value,
+            cost: holding.cost_basis * today_rate,
+            day_change: value - previous_value,
+        });
+    }
+
+    let mut dates: Vec<&str> = holdings
+        .iter()
+        .flat_map(|h| series(&h.symbol).iter().map(|p| p.date.as_str()))
+        .collect();
+    dates.sort_unstable();
+    dates.dedup();
+    let history: Vec<(String, f64)> = dates
+        .into_iter()
+        .filter_map(|date| {
+            let value = holdings
+                .iter()
+                .map(|h| {
+                    Some(h.quantity * close_on(series(&h.symbol), date)? * rate(h, Some(date))?)
+                })
+                .sum::<Option<f64>>()?;
+            Some((date.to_string(), value))
+        })
+        .collect();
+    let start = history.len().saturating_sub(CHART_DAYS);
+
+    Ok(Valuation {
+        positions,
+        history: history[start..].to_vec(),
+    })
+}
+
+/// "$1,234.56", or "CHF 1,234.56" for a currency without a symbol
+fn format_money(amount: f64, currency: &str, decimals: usize) -> String {
+    let sign = if amount < 0.0 { "-" } else { "" };
+    let text = format!("{:.*}", decimals, amount.abs());
+    let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if !fraction.is_empty() {
+        grouped = format!("{}.{}", grouped, fraction);
+    }
+    match currency {
+        "USD" | "CAD" | "AUD" | "NZD" => format!("{}${}", sign, grouped),
+        "EUR" => format!("{}€{}", sign, grouped),
+        "GBP" => format!("{}£{}", sign, grouped),
+        "JPY" => format!("{}¥{}", sign, grouped),
+        _ => format!("{}{} {}", sign, currency, grouped),
+    }
+}
+
+/// "+$1,234 (+1.2%)"
+fn format_change(change: f64, of: f64, currency: &str) -> String {
+    let plus = if change >= 0.0 { "+" } else { "" };
+    let pct = if of.abs() > 0.0 {
+        format!(" ({}{:.1}%)", plus, change / of * 100.0)
+    } else {
+        String::new()
+    };
+    format!("{}{}{}", plus, format_money(change, currency, 0), pct)
+}
+
+fn change_color(change: f64) -> &'static str {
+    if change >= 0.0 {
+        "green"
+    } else {
+        "red"
+    }
+}
+
+/// The value as the headline, then today's change, the P&L and each position's weight
+fn compact_portfolio_svg(
+    valuation: &Valuation,
+    currency: &str,
+    battery_pct: Option<u8>,
+    canvas: Canvas,
+) -> String {
+    let value = valuation.value();
+    let day_change = valuation.day_change();
+    let pnl = value - valuation.cost();
+    let mut card = CompactCard::new("Portfolio", battery_pct);
+    card.headline = Some(CardLine::new(format_money(value, currency, 0)));
+    card.lines.push(
+        CardLine::new("Today")
+            .with_value(format_change(day_change, value - day_change, currency))
+            .colored(Ink::for_change(day_change)),
+    );
+    card.lines.push(
+        CardLine::new("Total P&L")
+            .with_value(format_change(pnl, valuation.cost(), currency))
+            .colored(Ink::for_change(pnl)),
+    );
+    for position in &valuation.positions {
+        let weight = if value > 0.0 {
+            position.value / value * 100.0
+        } else {
+            0.0
+        };
+        card.lines.push(
+            CardLine::new(format!("{} {:.1}%", position.symbol, weight)).with_value(format_money(
+                position.value,
+                currency,
+                0,
+            )),
+        );
+    }
+    card.svg(canvas)
+}
+
+/// Generates an SVG with the portfolio's totals, its value over the chart window and
+/// each position's share of it
+///
+/// # Arguments
+/// * `valuation` - Positions and history from `value_portfolio`
+/// * `currency` - Base currency the values are in
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `locale` - Date format for the chart
+/// * `canvas` - Size to lay out for
+pub fn generate_portfolio_svg(
+    valuation: &Valuation,
+    currency: &str,
+    battery_pct: Option<u8>,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let width = canvas.width;
+    let height = canvas.height;
+    if canvas.is_compact() {
+        return compact_portfolio_svg(valuation, currency, battery_pct, canvas);
+    }
+    let mut svg = String::new();
+
+    svg.push_str(&format!(
+        r#"<svg viewBox="0 0 {} {}" xmlns="http://www.w3.org/2000/svg">"#,
+        width, height
+    ));
+    svg.push_str(r#"<defs>"#);
+    svg.push_str(svg_common::BATTERY_GRADIENT_DEF);
+    svg.push_str(r#"</defs>"#);
+    svg.push_str(&format!(
+        r#"<rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+
+    // Totals across the top
+    let value = valuation.value();
+    let day_change = valuation.day_change();
+    let pnl = value - valuation.cost();
+    svg.push_str(&format!(
+        r#"<text x="10" y="20" font-size="14" fill="black">Portfolio value</text><text x="10" y="56" font-size="34" font-weight="bold" fill="black">{}</text>"#,
+        format_money(value, currency, 2)
+    ));
+    let totals = [
+        ("Today", day_change, value - day_change),
+        ("Total P&amp;L", pnl, valuation.cost()),
+    ];
+    // Today's change over the chart, the P&L over the allocation
+    for (x, (label, change, of)) in [width * 3 / 5 - 10, width - 10].into_iter().zip(totals) {
+        svg.push_str(&format!(
+            r#"<text x="{x}" y="20" text-anchor="end" font-size="14" fill="black">{}</text><text x="{x}" y="50" text-anchor="end" font-size="20" font-weight="bold" fill="{}">{}</text>"#,
+            label,
+            change_color(change),
+            format_change(change, of, currency),
+            x = x
+        ));
+    }
+
+    // Value over the window on the left
+    let chart = (10, 75, width * 3 / 5 - 20, height - 110);
+    svg.push_str(&history_chart_svg(
+        &valuation.history,
+        currency,
+        locale,
+        chart,
+    ));
+
+    // Allocation bar and positions on the right
+    let list_x = chart.0 + chart.2 + 20;
+    let list_w = width - list_x - 10;
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" font-size="14" font-weight="bold" fill="black">Allocation</text>"#,
+        list_x,
+        chart.1 + 12
+    ));
+    let bar_y = chart.1 + 20;
+    let mut segment_x = list_x as f64;
+    for (position, color) in valuation
+        .positions
+        .iter()
+        .zip(SEGMENT_COLORS.iter().cycle())
+    {
+        let segment_w = if value > 0.0 {
+            position.value / value * list_w as f64
+        } else {
+            0.0
+        };
+        svg.push_str(&format!(
+            r#"<rect x="{:.1}" y="{}" width="{:.1}" height="22" fill="{}"/>"#,
+            segment_x, bar_y, segment_w, color
+        ));
+        segment_x += segment_w;
+    }
+    svg.push_str(&format!(
+        r#"<rect x="{}" y="{}" width="{}" height="22" fill="none" stroke="black" stroke-width="1"/>"#,
+        list_x, bar_y, list_w
+    ));
+
+    let row_h =
+        ((chart.1 + chart.3 - bar_y - 40) / valuation.positions.len().max(1) as i32).clamp(14, 34);
+    let font = (row_h - 4).min(16);
+    for (i, (position, color)) in valuation
+        .positions
+        .iter()
+        .zip(SEGMENT_COLORS.iter().cycle())
+        .enumerate()
+    {
+        let y = bar_y + 40 + i as i32 * row_h;
+        if y + row_h > chart.1 + chart.3 + 10 {
+            break;
+        }
+        let weight = if value > 0.0 {
+            position.value / value * 100.0
+        } else {
+            0.0
+        };
+        let pnl = position.value - position.cost;
+        svg.push_str(&format!(
+            r#"<rect x="{}" y="{}" width="{s}" height="{s}" fill="{}" stroke="black" stroke-width="1"/>"#,
+            list_x,
+            y - font + 2,
+            color,
+            s = font - 2
+        ));
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" font-size="{}" font-weight="bold" fill="black">{}</text>"#,
+            list_x + font + 2,
+            y,
+            font,
+            svg_common::escape_xml_text(&position.symbol)
+        ));
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" text-anchor="end" font-size="{}" fill="black">{:.1}%</text>"#,
+            list_x + list_w * 11 / 20,
+            y,
+            font,
+            weight
+        ));
+        let pnl_pct = if position.cost > 0.0 {
+            format!("{:+.1}%", pnl / position.cost * 100.0)
+        } else {
+            "—".to_string()
+        };
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" text-anchor="end" font-size="{}" fill="{}">{}</text>"#,
+            list_x + list_w,
+            y,
+            font,
+            change_color(pnl),
+            pnl_pct
+        ));
+    }
+
+    // Footer with last updated and battery bar, as on the stocks screen
+    let footer_y = height - 10;
+    use chrono::{Local, Timelike};
+    let now = Local::now();
+    svg.push_str(&format!(
+        r#"<text x="10" y="{}" font-size="12" fill="black">Last updated: {:02}:{:02}:{:02}</text>"#,
+        footer_y,
+        now.hour(),
+        now.minute(),
+        now.second()
+    ));
+    let battery_x = (width - 110) as f64;
+    svg.push_str(&svg_common::battery_label_svg(
+        battery_x - 5.0,
+        footer_y as f64,
+        "end",
+        12,
+    ));
+    svg.push_str(&svg_common::battery_bar_svg(
+        battery_x,
+        (footer_y - 10) as f64,
+        battery_pct.unwrap_or(50),
+        2.0,
+        "batteryClip",
+    ));
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// The portfolio's value as a line in the box `(x, y, width, height)`
+fn history_chart_svg(
+    history: &[(String, f64)],
+    currency: &str,
+    locale: Locale,
+    (x, y, width, height): (i32, i32, i32, i32),
+) -> String {
+    let mut svg = format!(
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white" stroke="black" stroke-width="2"/>"#,
+        x, y, width, height
+    );
+    if history.len() < 2 {
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" text-anchor="middle" font-size="14" fill="black">Not enough history</text>"#,
+            x + width / 2,
+            y + height / 2
+        ));
+        return svg;
+    }
+    let min = history
+        .iter()
+        .map(|(_, v)| *v)
+        .fold(f64::INFINITY, f64::min);
+    let max = history
+        .iter()
+        .map(|(_, v)| *v)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+
+    let chart_x = x + 60;
+    let chart_y = y + 10;
+    let chart_w = width - 70;
+    let chart_h = height - 30;
+    let last = history.len() - 1;
+    let point = |i: usize, value: f64| {
+        (
+            chart_x + (chart_w * i as i32) / last as i32,
+            chart_y + chart_h - ((value - min) / range * chart_h as f64) as i32,
+        )
+    };
+
+    // In the color of the change over the window
+    let color = change_color(history[last].1 - history[0].1);
+    let line: Vec<String> = history
+        .iter()
+        .enumerate()
+        .map(|(i, (_, value))| {
+            let (px, py) = point(i, *value);
+            format!("{},{}", px, py)
+        })
+        .collect();
+    svg.push_str(&format!(
+        r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+        line.join(" "),
+        color
+    ));
+
+    svg.push_str(&svg_common::axis_minmax_labels(
+        (chart_x - 5) as f64,
+        (chart_y + 5) as f64,
+        (chart_y + chart_h) as f64,
+        &format_money(max, currency, 0),
+        &format_money(min, currency, 0),
+    ));
+    let date_label = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|date| locale.short_date(&date))
+            .unwrap_or_else(|_| date.to_string())
+    };
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{ty}" font-size="12" fill="black">{}</text><text x="{}" y="{ty}" text-anchor="end" font-size="12" fill="black">{}</text>"#,
+        chart_x,
+        date_label(&history[0].0),
+        chart_x + chart_w,
+        date_label(&history[last].0),
+        ty = y + height - 5
+    ));
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_money, quote_symbols, value_portfolio, Holding};
+    use crate::stocks::{StockData, StockPoint, StocksData};
+
+    fn series(symbol: &str, closes: &[(&str, f64)]) -> StockData {
+        StockData {
+            symbol: symbol.to_string(),
+            points: closes
+                .iter()
+                .map(|&(date, close)| StockPoint {
+                    date: date.to_string(),
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn positions_are_valued_in_the_base_currency() {
+        let holdings = [
+            Holding {
+                symbol: "QQQ".to_string(),
+                quantity: 10.0,
+                cost_basis: 4000.0,
+                currency: "USD".to_string(),
+            },
+            Holding {
+                symbol: "SAP.DE".to_string(),
+                quantity: 4.0,
+                cost_basis: 600.0,
+                currency: "EUR".to_string(),
+            },
+        ];
+        assert_eq!(
+            quote_symbols(&holdings, "USD"),
+            ["QQQ", "SAP.DE", "EUR/USD"]
+        );
+
+        // QQQ is shut on Friday the 6th, and SAP hasn't listed on the 4th
+        let stocks = StocksData {
+            stocks: vec![
+                series(
+                    "QQQ",
+                    &[
+                        ("2025-03-04", 400.0),
+                        ("2025-03-05", 410.0),
+                        ("2025-03-07", 420.0),
+                    ],
+                ),
+                series(
+                    "SAP.DE",
+                    &[
+                        ("2025-03-05", 200.0),
+                        ("2025-03-06", 210.0),
+                        ("2025-03-07", 250.0),
+                    ],
+                ),
+                series("EUR/USD", &[("2025-03-05", 1.0), ("2025-03-07", 1.5)]),
+            ],
+        };
+        let valuation = value_portfolio(&holdings, "USD", &stocks).unwrap();
+
+        assert_eq!(valuation.positions[0].value, 4200.0);
+        assert_eq!(valuation.positions[0].day_change, 100.0);
+        // 4 × 250 € at 1.5, bought for 600 € at today's rate
+        assert_eq!(valuation.positions[1].value, 1500.0);
+        assert_eq!(valuation.positions[1].cost, 900.0);
+        // 1500 against 4 × 210 € at Thursday's 1.0
+        assert_eq!(valuation.positions[1].day_change, 660.0);
+        assert_eq!(valuation.value(), 5700.0);
+        assert_eq!(
+            valuation.history,
+            vec![
+                ("2025-03-05".to_string(), 4900.0),
+                ("2025-03-06".to_string(), 4940.0),
+                ("2025-03-07".to_string(), 5700.0),
+            ]
+        );
+        // The day's change is the chart's last step
+        assert_eq!(valuation.day_change(), 5700.0 - 4940.0);
+
+        assert_eq!(format_money(-1234567.891, "USD", 2), "-$1,234,567.89");
+        assert_eq!(format_money(999.0, "CHF", 0), "CHF 999");
+    }
+}
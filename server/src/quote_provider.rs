@@ -0,0 +1,140 @@
+//! Daily price sources for the stocks screen. Each provider fetches one symbol's daily
+//! bars from its own API and returns them as `StockPoint`s, oldest first; a screen lists
+//! the providers to try in order, so a symbol falls over to the next one when a
+//! provider is out of credits or down.
+//!
+//! Parsing is kept separate from fetching so each provider can be tested against
+//! recorded responses in `tests/fixtures/stocks/`.
+
+mod alpha_vantage;
+mod csv_dir;
+mod stooq;
+mod twelve_data;
+
+use crate::stocks::StockPoint;
+use crate::upstream::FetchError;
+use serde::Deserialize;
+use std::future::Future;
+use std::pin::Pin;
+
+pub use alpha_vantage::AlphaVantage;
+pub use csv_dir::CsvDir;
+pub use stooq::Stooq;
+pub use twelve_data::TwelveData;
+
+pub type QuoteFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<StockPoint>, FetchError>> + Send + 'a>>;
+
+/// A source of daily open, high, low and close prices
+pub trait QuoteProvider: Send + Sync {
+    /// Name for logs
+    fn name(&self) -> &'static str;
+    /// Up to `days` of the latest daily bars for `symbol` (e.g. "QQQ" or "BTC/USD"),
+    /// oldest first
+    fn daily<'a>(&'a self, symbol: &'a str, days: usize) -> QuoteFuture<'a>;
+}
+
+/// Which providers a stocks screen tries, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuoteSource {
+    /// Needs `[sources.twelve_data]`
+    TwelveData,
+    /// Needs `[sources.alpha_vantage]`
+    AlphaVantage,
+    /// Stooq's CSV downloads, which need no key
+    Stooq,
+    /// Files in `[sources.stock_csv]`'s directory
+    Csv,
+}
+
+impl QuoteSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            QuoteSource::TwelveData => "twelve-data",
+            QuoteSource::AlphaVantage => "alpha-vantage",
+            QuoteSource::Stooq => "stooq",
+            QuoteSource::Csv => "csv",
+        }
+    }
+}
+
+/// Parses daily bars from a CSV with `Date`, `Open`, `High`, `Low` and `Close` columns
+/// and optionally `Volume` (any case, any order, others ignored), as Stooq and most
+/// spreadsheet exports write them. Rows without a price are skipped; the result is sorted
+/// oldest first.
+pub fn parse_daily_csv(text: &str) -> Result<Vec<StockPoint>, FetchError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|h| h.to_ascii_lowercase())
+        .collect();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| format!("CSV has no {} column", name))
+    };
+    let (date, open, high, low, close) = (
+        column("date")?,
+        column("open")?,
+        column("high")?,
+        column("low")?,
+        column("close")?,
+    );
+    let volume = column("volume").ok();
+    let mut points = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let price = |i: usize| record.get(i)?.parse::<f64>().ok();
+        let (Some(open), Some(high), Some(low), Some(close)) =
+            (price(open), price(high), price(low), price(close))
+        else {
+            continue;
+        };
+        points.push(StockPoint {
+            date: record.get(date).unwrap_or_default().to_string(),
+            open,
+            high,
+            low,
+            close,
+            volume: volume.and_then(price),
+        });
+    }
+    if points.is_empty() {
+        return Err(format!(
+            "No prices in CSV: {}",
+            text.chars().take(100).collect::<String>()
+        )
+        .into());
+    }
+    points.sort_by(|a, b| a.date.cmp(&b.date));
+    Ok(points)
+}
+
+/// The last `days` points of `points`
+fn latest(mut points: Vec<StockPoint>, days: usize) -> Vec<StockPoint> {
+    points.drain(..points.len().saturating_sub(days));
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{latest, parse_daily_csv};
+
+    #[test]
+    fn parses_recorded_stooq_csv() {
+        let points = parse_daily_csv(include_str!("../tests/fixtures/stocks/QQQ.csv")).unwrap();
+        assert_eq!(points.len(), 5);
+        assert_eq!(points[0].date, "2025-03-03");
+        assert_eq!(points[4].close, 481.66);
+        assert_eq!(points[4].volume, Some(68776100.0));
+        assert_eq!(latest(points, 2)[0].date, "2025-03-06");
+
+        assert!(parse_daily_csv("No data").is_err());
+        assert!(parse_daily_csv("Date,Close\n2025-03-03,1.0\n").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Alpha Vantage daily series. The free tier allows 25 requests a day, so it suits a
+//! fallback better than a primary source. Currency pairs such as "BTC/USD" use the
+//! digital currency series.
+
+use super::{latest, QuoteFuture, QuoteProvider};
+use crate::stocks::StockPoint;
+use crate::upstream::{RateLimit, Upstream};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub struct AlphaVantage {
+    pub api_key: String,
+    pub upstream: Upstream,
+    pub limit: Arc<RateLimit>,
+}
+
+impl QuoteProvider for AlphaVantage {
+    fn name(&self) -> &'static str {
+        "Alpha Vantage"
+    }
+
+    fn daily<'a>(&'a self, symbol: &'a str, days: usize) -> QuoteFuture<'a> {
+        Box::pin(async move {
+            let url = match symbol.split_once('/') {
+                Some((base, market)) => format!(
+                    "https://www.alphavantage.co/query?function=DIGITAL_CURRENCY_DAILY&symbol={}&market={}&apikey={}",
+                    base, market, self.api_key
+                ),
+                // The compact series is the last 100 days
+                None => format!(
+                    "https://www.alphavantage.co/query?function=TIME_SERIES_DAILY&symbol={}&outputsize=compact&apikey={}",
+                    symbol, self.api_key
+                ),
+            };
+            let response: Response = self.upstream.get_json(&url, &self.limit).await?;
+            Ok(latest(parse(response)?, days))
+        })
+    }
+}
+
+/// Errors and rate limiting come back with a 200 status and a message instead of the
+/// series
+#[derive(Debug, Deserialize)]
+struct Response {
+    #[serde(
+        rename = "Time Series (Daily)",
+        alias = "Time Series (Digital Currency Daily)"
+    )]
+    series: Option<BTreeMap<String, Bar>>,
+    #[serde(rename = "Error Message")]
+    error: Option<String>,
+    #[serde(rename = "Note")]
+    note: Option<String>,
+    #[serde(rename = "Information")]
+    information: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Bar {
+    #[serde(rename = "1. open")]
+    open: String,
+    #[serde(rename = "2. high")]
+    high: String,
+    #[serde(rename = "3. low")]
+    low: String,
+    #[serde(rename = "4. close")]
+    close: String,
+    #[serde(rename = "5. volume")]
+    volume: Option<String>,
+}
+
+fn parse(response: Response) -> Result<Vec<StockPoint>, String> {
+    let Some(series) = response.series else {
+        return Err(response
+            .error
+            .or(response.note)
+            .or(response.information)
+            .unwrap_or_else(|| "No time series in response".to_string()));
+    };
+    // Keyed by date, so already oldest first
+    Ok(series
+        .into_iter()
+        .filter_map(|(date, bar)| {
+            Some(StockPoint {
+                date,
+                open: bar.open.parse().ok()?,
+                high: bar.high.parse().ok()?,
+                low: bar.low.parse().ok()?,
+                close: bar.close.parse().ok()?,
+                volume: bar.volume.as_deref().and_then(|v| v.parse().ok()),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Response};
+
+    #[test]
+    fn parses_recorded_daily_series() {
+        let response: Response = serde_json::from_str(include_str!(
+            "../../tests/fixtures/stocks/alpha_vantage.json"
+        ))
+        .unwrap();
+        let points = parse(response).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].date, "2025-03-05");
+        assert_eq!(points[2].high, 488.0);
+
+        let limited: Response = serde_json::from_str(
+            r#"{"Information": "We have detected your API key and our standard API rate limit is 25 requests per day."}"#,
+        )
+        .unwrap();
+        assert!(parse(limited).unwrap_err().contains("25 requests per day"));
+    }
+}
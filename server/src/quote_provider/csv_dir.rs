@@ -0,0 +1,54 @@
+//! Daily bars from CSV files in a local directory, one per symbol, for offline use or
+//! as the last fallback. "QQQ" is read from `QQQ.csv` and "BTC/USD" from `BTC-USD.csv`,
+//! in the same format as a Stooq download.
+
+use super::{latest, parse_daily_csv, QuoteFuture, QuoteProvider};
+use std::path::PathBuf;
+
+pub struct CsvDir {
+    pub data_dir: PathBuf,
+}
+
+impl QuoteProvider for CsvDir {
+    fn name(&self) -> &'static str {
+        "CSV"
+    }
+
+    fn daily<'a>(&'a self, symbol: &'a str, days: usize) -> QuoteFuture<'a> {
+        Box::pin(async move {
+            let file_name = symbol.replace('/', "-");
+            // Reject anything that could leave the directory; without separators a dot
+            // is only part of the name, as in `VOD.UK.csv`
+            if file_name.is_empty()
+                || !file_name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '^' | '=' | '.'))
+            {
+                return Err(format!("Invalid symbol for a file name: {}", symbol).into());
+            }
+            let path = self.data_dir.join(format!("{}.csv", file_name));
+            let text = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            Ok(latest(parse_daily_csv(&text)?, days))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsvDir;
+    use crate::quote_provider::QuoteProvider;
+
+    #[tokio::test]
+    async fn reads_symbol_files() {
+        let provider = CsvDir {
+            data_dir: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/stocks").into(),
+        };
+        let points = provider.daily("QQQ", 3).await.unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[2].date, "2025-03-07");
+        assert!(provider.daily("../QQQ", 3).await.is_err());
+        assert!(provider.daily("SPY", 3).await.is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Stooq's daily CSV downloads, which need no key. US listings take a `.us` suffix and
+//! currency pairs are written without the slash, so "QQQ" is fetched as `qqq.us` and
+//! "BTC/USD" as `btcusd`.
+
+use super::{latest, parse_daily_csv, QuoteFuture, QuoteProvider};
+use crate::upstream::{RateLimit, Upstream};
+use std::sync::Arc;
+
+pub struct Stooq {
+    pub upstream: Upstream,
+    pub limit: Arc<RateLimit>,
+}
+
+impl QuoteProvider for Stooq {
+    fn name(&self) -> &'static str {
+        "Stooq"
+    }
+
+    fn daily<'a>(&'a self, symbol: &'a str, days: usize) -> QuoteFuture<'a> {
+        Box::pin(async move {
+            let url = format!("https://stooq.com/q/d/l/?s={}&i=d", stooq_symbol(symbol));
+            let text = self.upstream.get_text(&url, &self.limit).await?;
+            // The whole history comes back; keep the end of it
+            Ok(latest(parse_daily_csv(&text)?, days))
+        })
+    }
+}
+
+fn stooq_symbol(symbol: &str) -> String {
+    let symbol = symbol.to_ascii_lowercase();
+    if symbol.contains('/') {
+        symbol.replace('/', "")
+    } else if symbol.contains('.') || symbol.starts_with('^') {
+        // Already has a market suffix, or is an index such as ^spx
+        symbol
+    } else {
+        format!("{}.us", symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stooq_symbol;
+
+    #[test]
+    fn symbols_are_mapped_to_stooq_tickers() {
+        assert_eq!(stooq_symbol("QQQ"), "qqq.us");
+        assert_eq!(stooq_symbol("BTC/USD"), "btcusd");
+        assert_eq!(stooq_symbol("VOD.UK"), "vod.uk");
+        assert_eq!(stooq_symbol("^SPX"), "^spx");
+    }
+}
@@ -0,0 +1,102 @@
+//! Twelve Data time series API. The free tier allows 800 credits a day, one per symbol
+//! fetched.
+
+use super::{QuoteFuture, QuoteProvider};
+use crate::stocks::StockPoint;
+use crate::upstream::{RateLimit, Upstream};
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub struct TwelveData {
+    pub api_key: String,
+    pub upstream: Upstream,
+    pub limit: Arc<RateLimit>,
+}
+
+impl QuoteProvider for TwelveData {
+    fn name(&self) -> &'static str {
+        "Twelve Data"
+    }
+
+    fn daily<'a>(&'a self, symbol: &'a str, days: usize) -> QuoteFuture<'a> {
+        Box::pin(async move {
+            let url = format!(
+                "https://api.twelvedata.com/time_series?symbol={}&interval=1day&outputsize={}&apikey={}",
+                symbol, days, self.api_key
+            );
+            let response: Response = self.upstream.get_json(&url, &self.limit).await?;
+            Ok(parse(response)?)
+        })
+    }
+}
+
+/// Errors come back with a 200 status, `"status": "error"` and no values
+#[derive(Debug, Deserialize)]
+struct Response {
+    status: String,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    values: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Value {
+    datetime: String,
+    open: String,
+    high: String,
+    low: String,
+    close: String,
+    /// Absent for currency pairs
+    #[serde(default)]
+    volume: Option<String>,
+}
+
+fn parse(response: Response) -> Result<Vec<StockPoint>, String> {
+    if response.status != "ok" {
+        return Err(response
+            .message
+            .unwrap_or_else(|| format!("status {}", response.status)));
+    }
+    let mut points: Vec<StockPoint> = response
+        .values
+        .iter()
+        .filter_map(|value| {
+            Some(StockPoint {
+                date: value.datetime.clone(),
+                open: value.open.parse().ok()?,
+                high: value.high.parse().ok()?,
+                low: value.low.parse().ok()?,
+                close: value.close.parse().ok()?,
+                volume: value.volume.as_deref().and_then(|v| v.parse().ok()),
+            })
+        })
+        .collect();
+
+    // Twelve Data returns newest first, so reverse for ascending order (oldest to newest)
+    points.reverse();
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Response};
+
+    #[test]
+    fn parses_recorded_time_series() {
+        let response: Response =
+            serde_json::from_str(include_str!("../../tests/fixtures/stocks/twelve_data.json"))
+                .unwrap();
+        let points = parse(response).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].date, "2025-03-05");
+        assert_eq!(points[2].close, 486.22);
+        assert_eq!(points[2].volume, Some(68776100.0));
+
+        let error: Response = serde_json::from_str(
+            r#"{"code": 429, "message": "You have run out of API credits for the day.", "status": "error"}"#,
+        )
+        .unwrap();
+        assert!(parse(error).unwrap_err().contains("run out of API credits"));
+    }
+}
@@ -0,0 +1,242 @@
+//! Recommended time for a device's next refresh, sent with every bitmap as
+//! `X-Sleep-Seconds` and `X-Next-Wake` so the client does not need its own schedule.
+//!
+//! The wake time follows when the data is next expected to change: every half hour
+//! while the US market is open, just after FRED's morning releases, hourly while a
+//! weather alert is in effect, every quarter hour while the nowcast has rain within the
+//! hour, and otherwise at fixed local hours. A low battery stretches the interval.
+
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Weekday};
+use chrono_tz::America::New_York;
+
+/// Local hours for screens without a better signal; matches the client's old schedule
+const WAKE_HOURS: [u32; 3] = [6, 12, 18];
+/// Refresh interval for stocks during regular trading hours
+const MARKET_INTERVAL: Duration = Duration::minutes(30);
+/// Refresh interval while a weather alert is in effect or about to start
+const ALERT_INTERVAL: Duration = Duration::hours(1);
+/// Refresh interval while precipitation is falling or due within the hour
+const PRECIPITATION_INTERVAL: Duration = Duration::minutes(15);
+/// An alert this close to starting already counts
+const ALERT_LEAD: Duration = Duration::hours(6);
+/// Retry interval after a failed upstream fetch
+const RETRY_INTERVAL: Duration = Duration::hours(1);
+/// Wakes closer than this are pushed to the next slot, to avoid refreshing twice when
+/// the device's clock runs slightly early
+const MIN_SLEEP: Duration = Duration::minutes(5);
+/// Long enough for a stocks screen to sleep from Friday's close to Monday's open
+const MAX_SLEEP: Duration = Duration::hours(72);
+
+/// What decides when a screen's data is next worth fetching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
+    /// Intraday prices: NYSE regular hours, 09:30 to 16:00 ET on weekdays
+    Market,
+    /// FRED series, which post by the 08:30 ET release time on weekdays
+    EconomicRelease,
+    /// A weather alert is in effect or starts within a few hours
+    Alert,
+    /// The nowcast has precipitation falling or starting within the hour
+    Precipitation,
+    /// The fixed local wake hours
+    Scheduled,
+    /// The upstream fetch failed, so the device shows stale data or a test pattern
+    Retry,
+}
+
+/// Whether any alert is in effect at `now` or starts within `ALERT_LEAD`
+pub fn alert_active(alerts: impl IntoIterator<Item = (i64, i64)>, now: i64) -> bool {
+    alerts
+        .into_iter()
+        .any(|(start, end)| start <= now + ALERT_LEAD.num_seconds() && end > now)
+}
+
+/// Next wake time, in `now`'s time zone. Below 20% battery the interval is doubled,
+/// below 10% quadrupled, up to three days.
+pub fn next_wake<Tz: TimeZone>(
+    refresh: Refresh,
+    battery_pct: Option<u8>,
+    now: &DateTime<Tz>,
+) -> DateTime<Tz> {
+    let wake = match refresh {
+        Refresh::Market => next_market_wake(now),
+        Refresh::EconomicRelease => next_release_wake(now),
+        Refresh::Alert => now.clone() + ALERT_INTERVAL,
+        Refresh::Precipitation => now.clone() + PRECIPITATION_INTERVAL,
+        Refresh::Scheduled => next_scheduled_wake(now),
+        Refresh::Retry => now.clone() + RETRY_INTERVAL,
+    };
+    let factor = match battery_pct {
+        Some(pct) if pct < 10 => 4,
+        Some(pct) if pct < 20 => 2,
+        _ => 1,
+    };
+    let interval = ((wake - now.clone()) * factor).clamp(MIN_SLEEP, MAX_SLEEP);
+    now.clone() + interval
+}
+
+/// The next `time` of day in `now`'s time zone, at least `MIN_SLEEP` away
+pub fn next_occurrence<Tz: TimeZone>(now: &DateTime<Tz>, time: NaiveTime) -> DateTime<Tz> {
+    next_time_in(now, &now.timezone(), &[time], |_| true)
+}
+
+/// Seconds from `now` until `wake`
+pub fn sleep_seconds<Tz: TimeZone>(now: &DateTime<Tz>, wake: &DateTime<Tz>) -> i64 {
+    (wake.clone() - now.clone()).num_seconds()
+}
+
+fn is_weekday(date: chrono::NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// First moment at or after `MIN_SLEEP` from now that is `time` on a day accepted by
+/// `day_ok`, in zone `tz`
+fn next_time_in<Tz: TimeZone, Z: TimeZone>(
+    now: &DateTime<Tz>,
+    tz: &Z,
+    times: &[NaiveTime],
+    day_ok: impl Fn(chrono::NaiveDate) -> bool,
+) -> DateTime<Tz> {
+    let earliest = now.clone() + MIN_SLEEP;
+    let local = now.with_timezone(tz);
+    // A week is enough to find a weekday
+    for day in local.date_naive().iter_days().take(8) {
+        if !day_ok(day) {
+            continue;
+        }
+        for &time in times {
+            if let Some(candidate) = tz.from_local_datetime(&day.and_time(time)).earliest() {
+                let candidate = candidate.with_timezone(&now.timezone());
+                if candidate >= earliest {
+                    return candidate;
+                }
+            }
+        }
+    }
+    now.clone() + MAX_SLEEP
+}
+
+fn next_scheduled_wake<Tz: TimeZone>(now: &DateTime<Tz>) -> DateTime<Tz> {
+    let times = WAKE_HOURS.map(|hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap());
+    next_time_in(now, &now.timezone(), &times, |_| true)
+}
+
+fn next_market_wake<Tz: TimeZone>(now: &DateTime<Tz>) -> DateTime<Tz> {
+    let open = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+    let close = NaiveTime::from_hms_opt(16, 0, 0).unwrap();
+    let eastern = now.with_timezone(&New_York);
+    let time = eastern.time();
+    if is_weekday(eastern.date_naive()) && time >= open && time < close {
+        // Every half hour, plus one last refresh for the closing price
+        let close_refresh = New_York
+            .from_local_datetime(&eastern.date_naive().and_time(close + MIN_SLEEP))
+            .earliest()
+            .map(|t| t.with_timezone(&now.timezone()));
+        let wake = now.clone() + MARKET_INTERVAL;
+        return match close_refresh {
+            Some(close_refresh) if close_refresh < wake => close_refresh,
+            _ => wake,
+        };
+    }
+    // Shortly after the next open, once the first bars are in
+    next_time_in(now, &New_York, &[open + MIN_SLEEP], is_weekday)
+}
+
+fn next_release_wake<Tz: TimeZone>(now: &DateTime<Tz>) -> DateTime<Tz> {
+    let release = NaiveTime::from_hms_opt(8, 45, 0).unwrap();
+    next_time_in(now, &New_York, &[release], is_weekday)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alert_active, next_wake, Refresh};
+    use chrono::{DateTime, TimeZone};
+    use chrono_tz::America::{Los_Angeles, New_York};
+    use chrono_tz::Tz;
+
+    fn pacific(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Tz> {
+        Los_Angeles.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn eastern(wake: DateTime<Tz>) -> String {
+        wake.with_timezone(&New_York).format("%a %H:%M").to_string()
+    }
+
+    #[test]
+    fn scheduled_wakes_use_local_hours() {
+        // 2025-03-05 is a Wednesday
+        let now = pacific(2025, 3, 5, 7, 0);
+        assert_eq!(
+            next_wake(Refresh::Scheduled, None, &now),
+            pacific(2025, 3, 5, 12, 0)
+        );
+        // Within five minutes of a slot: skip to the next one
+        let now = pacific(2025, 3, 5, 11, 57);
+        assert_eq!(
+            next_wake(Refresh::Scheduled, None, &now),
+            pacific(2025, 3, 5, 18, 0)
+        );
+        let now = pacific(2025, 3, 5, 20, 0);
+        assert_eq!(
+            next_wake(Refresh::Scheduled, None, &now),
+            pacific(2025, 3, 6, 6, 0)
+        );
+    }
+
+    #[test]
+    fn market_wakes_follow_trading_hours() {
+        // 07:00 PT = 10:00 ET, market open
+        let now = pacific(2025, 3, 5, 7, 0);
+        assert_eq!(eastern(next_wake(Refresh::Market, None, &now)), "Wed 10:30");
+        // 12:50 PT = 15:50 ET: the closing refresh comes first
+        let now = pacific(2025, 3, 5, 12, 50);
+        assert_eq!(eastern(next_wake(Refresh::Market, None, &now)), "Wed 16:05");
+        // Friday evening: Monday's open
+        let now = pacific(2025, 3, 7, 18, 0);
+        assert_eq!(eastern(next_wake(Refresh::Market, None, &now)), "Mon 09:35");
+        // Low battery doubles the interval
+        let now = pacific(2025, 3, 5, 7, 0);
+        assert_eq!(
+            eastern(next_wake(Refresh::Market, Some(15), &now)),
+            "Wed 11:00"
+        );
+    }
+
+    #[test]
+    fn releases_alerts_and_battery() {
+        let now = pacific(2025, 3, 5, 7, 0);
+        assert_eq!(
+            eastern(next_wake(Refresh::EconomicRelease, None, &now)),
+            "Thu 08:45"
+        );
+        assert_eq!(
+            next_wake(Refresh::Alert, None, &now),
+            pacific(2025, 3, 5, 8, 0)
+        );
+        assert_eq!(
+            next_wake(Refresh::Precipitation, None, &now),
+            pacific(2025, 3, 5, 7, 15)
+        );
+        // Very low battery: four times the interval, capped at three days
+        assert_eq!(
+            next_wake(Refresh::Alert, Some(5), &now),
+            pacific(2025, 3, 5, 11, 0)
+        );
+        assert_eq!(
+            next_wake(Refresh::Scheduled, Some(5), &now),
+            pacific(2025, 3, 6, 3, 0)
+        );
+        // (US daylight saving time starts that Sunday)
+        let now = pacific(2025, 3, 7, 18, 0);
+        assert_eq!(
+            next_wake(Refresh::Market, Some(5), &now),
+            pacific(2025, 3, 10, 19, 0)
+        );
+
+        let t = pacific(2025, 3, 5, 7, 0).timestamp();
+        assert!(alert_active([(t - 3600, t + 3600)], t));
+        assert!(alert_active([(t + 3600, t + 7200)], t));
+        assert!(!alert_active([(t + 86400, t + 90000)], t));
+        assert!(!alert_active([(t - 7200, t - 3600)], t));
+    }
+}
@@ -0,0 +1,240 @@
+//! Indoor readings from home sensors. Each sensor POSTs a JSON reading to `/sensors/{id}`,
+//! or, in builds with the `mqtt` feature, publishes it to a broker topic ending in its ID:
+//!
+//! ```json
+//! {"temperature": 21.4, "humidity": 45, "co2": 820}
+//! ```
+//!
+//! Temperatures are in °C; any field may be left out, and `time` (RFC 3339) defaults to
+//! when the reading arrived. Readings are appended to a JSON-lines file under the state
+//! directory and kept for a week, for the `indoor` screen's sparklines.
+
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
+use crate::json_lines::{JsonLinesStore, LogRecord};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Readings older than this are dropped
+const RETENTION: Duration = Duration::days(7);
+/// How far ahead of the server's clock a reading's `time` may be
+const MAX_CLOCK_SKEW: Duration = Duration::minutes(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SensorReading {
+    pub time: DateTime<Utc>,
+    pub sensor: String,
+    /// °C
+    pub temperature: Option<f32>,
+    /// Relative humidity, %
+    pub humidity: Option<f32>,
+    /// ppm
+    pub co2: Option<f32>,
+}
+
+/// Body of a reading, as POSTed or published over MQTT
+#[derive(Debug, Deserialize)]
+pub struct ReadingPayload {
+    pub temperature: Option<f32>,
+    pub humidity: Option<f32>,
+    pub co2: Option<f32>,
+    pub time: Option<DateTime<Utc>>,
+}
+
+impl ReadingPayload {
+    pub fn into_reading(self, sensor: &str, now: DateTime<Utc>) -> Result<SensorReading, String> {
+        if !valid_sensor_id(sensor) {
+            return Err(format!(
+                "Invalid sensor ID '{}': use letters, digits, '-' and '_'",
+                sensor
+            ));
+        }
+        if self.temperature.is_none() && self.humidity.is_none() && self.co2.is_none() {
+            return Err("Reading has no temperature, humidity or co2".to_string());
+        }
+        if let Some(t) = self.temperature {
+            if !(-50.0..=80.0).contains(&t) {
+                return Err(format!("Temperature {} °C out of range", t));
+            }
+        }
+        if let Some(h) = self.humidity {
+            if !(0.0..=100.0).contains(&h) {
+                return Err(format!("Humidity {}% out of range", h));
+            }
+        }
+        if let Some(co2) = self.co2 {
+            if !(0.0..=50000.0).contains(&co2) {
+                return Err(format!("CO2 {} ppm out of range", co2));
+            }
+        }
+        let time = self.time.unwrap_or(now);
+        if time > now + MAX_CLOCK_SKEW {
+            return Err(format!(
+                "Reading time {} is in the future",
+                time.to_rfc3339()
+            ));
+        }
+        if time < now - RETENTION {
+            return Err(format!(
+                "Reading time {} is older than the {} days kept",
+                time.to_rfc3339(),
+                RETENTION.num_days()
+            ));
+        }
+        Ok(SensorReading {
+            time,
+            sensor: sensor.to_string(),
+            temperature: self.temperature,
+            humidity: self.humidity,
+            co2: self.co2,
+        })
+    }
+}
+
+/// Sensor IDs end up in URLs, MQTT topics and the log, so keep them plain
+pub fn valid_sensor_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl LogRecord for SensorReading {
+    fn keep(&self, now: DateTime<Utc>) -> bool {
+        self.time >= now - RETENTION
+    }
+}
+
+#[derive(Default)]
+pub struct SensorStore {
+    log: JsonLinesStore<SensorReading>,
+}
+
+impl SensorStore {
+    /// Store backed by a JSON-lines file
+    pub fn persistent(path: impl Into<PathBuf>) -> Self {
+        let log = JsonLinesStore::persistent(path);
+        log.records().sort_by_key(|r: &SensorReading| r.time);
+        Self { log }
+    }
+
+    /// Adds a reading and drops those past `RETENTION`
+    pub fn record(&self, reading: SensorReading) {
+        self.log.record(reading, |readings, reading| {
+            // Readings with an explicit time may arrive out of order
+            let at = readings.partition_point(|r| r.time <= reading.time);
+            readings.insert(at, reading);
+            true
+        });
+    }
+
+    /// Latest reading from every sensor, by ID
+    pub fn latest(&self) -> Vec<SensorReading> {
+        let readings = self.log.records();
+        let mut latest: Vec<SensorReading> = Vec::new();
+        for reading in readings.iter().rev() {
+            if !latest.iter().any(|r| r.sensor == reading.sensor) {
+                latest.push(reading.clone());
+            }
+        }
+        latest.sort_by(|a, b| a.sensor.cmp(&b.sensor));
+        latest
+    }
+
+    /// One sensor's readings since `since`, oldest first
+    pub fn history(&self, sensor: &str, since: DateTime<Utc>) -> Vec<SensorReading> {
+        self.log
+            .records()
+            .iter()
+            .filter(|r| r.sensor == sensor && r.time >= since)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadingPayload, SensorReading, SensorStore};
+    use chrono::{Duration, Utc};
+
+    fn payload(json: &str) -> ReadingPayload {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn payload_validation() {
+        let now = Utc::now();
+        let reading = payload(r#"{"temperature": 21.5, "co2": 800}"#)
+            .into_reading("living_room", now)
+            .unwrap();
+        assert_eq!(reading.time, now);
+        assert_eq!(reading.humidity, None);
+
+        assert!(payload("{}").into_reading("kitchen", now).is_err());
+        assert!(payload(r#"{"humidity": 120}"#)
+            .into_reading("kitchen", now)
+            .is_err());
+        assert!(payload(r#"{"co2": 600}"#)
+            .into_reading("../kitchen", now)
+            .is_err());
+
+        // Clock skew of a few minutes is fine; a reading from tomorrow or last month is not
+        let at = |time: chrono::DateTime<Utc>| {
+            payload(&format!(
+                r#"{{"co2": 600, "time": "{}"}}"#,
+                time.to_rfc3339()
+            ))
+            .into_reading("kitchen", now)
+        };
+        assert!(at(now + Duration::minutes(2)).is_ok());
+        assert!(at(now + Duration::days(1)).is_err());
+        assert!(at(now - Duration::days(30)).is_err());
+    }
+
+    #[test]
+    fn store_persists_and_keeps_latest_per_sensor() {
+        let path =
+            std::env::temp_dir().join(format!("iot-image-sensors-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let now = Utc::now();
+        let store = SensorStore::persistent(&path);
+        for (sensor, hours_ago, temperature) in [
+            ("bedroom", 24 * 10, 15.0),
+            ("bedroom", 1, 19.0),
+            ("bedroom", 3, 18.0),
+            ("office", 2, 22.0),
+        ] {
+            store.record(SensorReading {
+                time: now - Duration::hours(hours_ago),
+                sensor: sensor.to_string(),
+                temperature: Some(temperature),
+                humidity: None,
+                co2: None,
+            });
+        }
+        let latest = store.latest();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].temperature, Some(19.0));
+
+        // The reading past the retention period is dropped as it's recorded, and from the
+        // log as soon as that holds more expired readings than current ones
+        let temperatures = |store: &SensorStore| -> Vec<f32> {
+            store
+                .history("bedroom", now - Duration::days(30))
+                .iter()
+                .map(|r| r.temperature.unwrap())
+                .collect()
+        };
+        assert_eq!(temperatures(&store), vec![18.0, 19.0]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+        assert_eq!(
+            temperatures(&SensorStore::persistent(&path)),
+            vec![18.0, 19.0]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,159 @@
+//! MQTT subscriber for sensor readings, built with `--features mqtt`. Each message on the
+//! configured topic filter carries the same JSON as `POST /sensors/{id}`; the sensor ID is
+//! the last level of the topic, so `home/sensors/bedroom` is `bedroom`.
+
+use super::{ReadingPayload, SensorReading, SensorStore};
+use crate::config::MqttSource;
+use chrono::{DateTime, Utc};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Pause before reconnecting after the broker drops or refuses the connection
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+pub fn parse_message(
+    topic: &str,
+    payload: &[u8],
+    now: DateTime<Utc>,
+) -> Result<SensorReading, String> {
+    let sensor = topic.rsplit('/').next().unwrap_or(topic);
+    let payload: ReadingPayload = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+    payload.into_reading(sensor, now)
+}
+
+/// Record readings from the broker until the server exits, reconnecting as needed
+pub async fn subscribe(source: MqttSource, store: Arc<SensorStore>) {
+    let mut options = MqttOptions::new(&source.client_id, &source.host, source.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &source.username {
+        options.set_credentials(username, source.password.as_deref().unwrap_or(""));
+    }
+    let (client, mut event_loop) = AsyncClient::new(options, 10);
+    loop {
+        match event_loop.poll().await {
+            // Subscribe on every connect: the session is clean, so the broker forgets
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                if let Err(e) = client.try_subscribe(&source.topic, QoS::AtMostOnce) {
+                    eprintln!("Could not subscribe to {}: {}", source.topic, e);
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                match parse_message(&publish.topic, &publish.payload, Utc::now()) {
+                    Ok(reading) => store.record(reading),
+                    Err(e) => eprintln!("Ignoring MQTT message on {}: {}", publish.topic, e),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("MQTT broker {}:{}: {}", source.host, source.port, e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_message, subscribe};
+    use crate::config::MqttSource;
+    use crate::sensors::SensorStore;
+    use chrono::Utc;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Packet type and body of the next MQTT packet from the client
+    async fn read_packet(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let kind = stream.read_u8().await.unwrap() >> 4;
+        let (mut len, mut shift) = (0, 0);
+        loop {
+            let byte = stream.read_u8().await.unwrap();
+            len |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body).await.unwrap();
+        (kind, body)
+    }
+
+    /// Just enough of an MQTT 3.1.1 broker for one client: accepts its connection and
+    /// subscription, publishes `messages` to it and returns the topic filter it asked for
+    async fn broker(listener: TcpListener, messages: &[(&str, &[u8])]) -> (TcpStream, String) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert_eq!(read_packet(&mut stream).await.0, 1, "expected CONNECT");
+        stream.write_all(&[0x20, 2, 0, 0]).await.unwrap();
+
+        let (kind, body) = read_packet(&mut stream).await;
+        assert_eq!(kind, 8, "expected SUBSCRIBE");
+        let filter_len = u16::from_be_bytes([body[2], body[3]]) as usize;
+        let filter = String::from_utf8(body[4..4 + filter_len].to_vec()).unwrap();
+        stream
+            .write_all(&[0x90, 3, body[0], body[1], 0])
+            .await
+            .unwrap();
+
+        for (topic, payload) in messages {
+            let mut packet = vec![0x30, (2 + topic.len() + payload.len()) as u8];
+            packet.extend_from_slice(&(topic.len() as u16).to_be_bytes());
+            packet.extend_from_slice(topic.as_bytes());
+            packet.extend_from_slice(payload);
+            stream.write_all(&packet).await.unwrap();
+        }
+        (stream, filter)
+    }
+
+    #[test]
+    fn sensor_id_is_last_topic_level() {
+        let reading =
+            parse_message("home/sensors/bedroom", br#"{"humidity": 41}"#, Utc::now()).unwrap();
+        assert_eq!(reading.sensor, "bedroom");
+        assert!(parse_message("home/sensors/", br#"{"humidity": 41}"#, Utc::now()).is_err());
+        assert!(parse_message("home/sensors/bedroom", b"41", Utc::now()).is_err());
+    }
+
+    #[tokio::test]
+    async fn published_readings_reach_the_store() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let source = MqttSource {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            topic: "home/sensors/+".to_string(),
+            username: None,
+            password: None,
+            client_id: "iot-image-test".to_string(),
+        };
+        let store = Arc::new(SensorStore::default());
+        let subscriber = tokio::spawn(subscribe(source, store.clone()));
+
+        let messages: [(&str, &[u8]); 3] = [
+            ("home/sensors/bedroom", br#"{"temperature": 21.5}"#),
+            ("home/sensors/attic", b"not json"),
+            ("home/sensors/kitchen", br#"{"co2": 820}"#),
+        ];
+        let (_connection, filter) = broker(listener, &messages).await;
+        assert_eq!(filter, "home/sensors/+");
+
+        let latest = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let latest = store.latest();
+                if latest.len() == 2 {
+                    return latest;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("readings never reached the store");
+        subscriber.abort();
+
+        assert_eq!(latest[0].sensor, "bedroom");
+        assert_eq!(latest[0].temperature, Some(21.5));
+        assert_eq!(latest[1].sensor, "kitchen");
+        assert_eq!(latest[1].co2, Some(820.0));
+    }
+}
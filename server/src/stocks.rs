@@ -1,118 +1,136 @@
-use crate::svg_common;
-use serde::Deserialize;
+use crate::indicators::{self, Indicator};
+use crate::quote_provider::QuoteProvider;
+use crate::stocks_compact;
+use crate::svg_common::{self, Canvas, CompactCard};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-// Twelve Data API response structures
 #[allow(dead_code)]
-#[derive(Debug, Deserialize)]
-pub struct TwelveDataResponse {
-    pub meta: TwelveDataMeta,
-    pub values: Vec<TwelveDataValue>,
-    pub status: String,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
-pub struct TwelveDataMeta {
-    pub symbol: String,
-    pub interval: String,
-    #[serde(default)]
-    pub currency: Option<String>,
-    #[serde(default)]
-    pub currency_base: Option<String>,
-    #[serde(default)]
-    pub currency_quote: Option<String>,
-    #[serde(default)]
-    pub exchange_timezone: Option<String>,
-    pub exchange: String,
-    #[serde(rename = "type")]
-    pub instrument_type: String,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct TwelveDataValue {
-    pub datetime: String,
-    pub open: String,
-    pub high: String,
-    pub low: String,
-    pub close: String,
-}
-
-#[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StockPoint {
     pub date: String,
     pub open: f64,
     pub high: f64,
     pub low: f64,
     pub close: f64,
+    /// Shares traded; not reported for currency pairs
+    #[serde(default)]
+    pub volume: Option<f64>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StockData {
     pub symbol: String,
     pub points: Vec<StockPoint>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StocksData {
     pub stocks: Vec<StockData>,
 }
 
-fn parse_twelve_data(data: TwelveDataResponse) -> Vec<StockPoint> {
-    let mut points: Vec<StockPoint> = data
-        .values
-        .iter()
-        .filter_map(|value| {
-            let open = value.open.parse::<f64>().ok()?;
-            let high = value.high.parse::<f64>().ok()?;
-            let low = value.low.parse::<f64>().ok()?;
-            let close = value.close.parse::<f64>().ok()?;
-            Some(StockPoint {
-                date: value.datetime.clone(),
-                open,
-                high,
-                low,
-                close,
-            })
-        })
-        .collect();
+/// Days of history fetched for each symbol: a year, for the watchlist's 52-week range
+/// and to warm up the indicators before the part that is drawn
+const DAYS: usize = 365;
+/// Days drawn on each chart
+pub const CHART_DAYS: usize = 60;
+/// Colors of the line overlays, in the order configured
+const OVERLAY_COLORS: [&str; 3] = ["blue", "black", "yellow"];
+
+/// How a stocks screen arranges its symbols. Those that don't fit go on further pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StockLayout {
+    /// Chosen by the number of symbols
+    #[default]
+    Auto,
+    /// One large candlestick chart
+    Single,
+    /// Four candlestick charts
+    #[serde(rename = "grid-2x2")]
+    Grid2x2,
+    /// Six sparklines
+    #[serde(rename = "grid-3x2")]
+    Grid3x2,
+    /// Twelve sparklines
+    #[serde(rename = "grid-4x3")]
+    Grid4x3,
+    /// A row per symbol with its price, change, 52-week range and sparkline
+    Watchlist,
+}
+
+impl StockLayout {
+    /// The layout `Auto` picks for `count` symbols
+    pub fn resolve(self, count: usize) -> Self {
+        match (self, count) {
+            (StockLayout::Auto, 0 | 1) => StockLayout::Single,
+            (StockLayout::Auto, 2..=4) => StockLayout::Grid2x2,
+            (StockLayout::Auto, 5 | 6) => StockLayout::Grid3x2,
+            (StockLayout::Auto, 7..=12) => StockLayout::Grid4x3,
+            (StockLayout::Auto, _) => StockLayout::Watchlist,
+            (layout, _) => layout,
+        }
+    }
+
+    /// Columns and rows of a grid layout
+    fn grid(self) -> Option<(i32, i32)> {
+        match self {
+            StockLayout::Single => Some((1, 1)),
+            StockLayout::Grid2x2 => Some((2, 2)),
+            StockLayout::Grid3x2 => Some((3, 2)),
+            StockLayout::Grid4x3 => Some((4, 3)),
+            StockLayout::Auto | StockLayout::Watchlist => None,
+        }
+    }
 
-    // Twelve Data returns newest first, so reverse for ascending order (oldest to newest)
-    points.reverse();
-    points
+    /// Symbols that fit on one page of `canvas`. Small panels show a line per symbol
+    /// (see `stocks_compact::card_svg`) in every layout but `Single`.
+    pub fn per_page(self, canvas: Canvas) -> usize {
+        match self.grid() {
+            Some((1, 1)) => 1,
+            _ if canvas.is_compact() => CompactCard::rows(canvas, false),
+            Some((columns, rows)) => (columns * rows) as usize,
+            None => stocks_compact::watchlist_rows(canvas.height - FOOTER_HEIGHT - 10),
+        }
+    }
+}
+
+/// Space under the charts for the last updated time and battery
+const FOOTER_HEIGHT: i32 = 30;
+
+/// Daily bars for `symbol` from the first of `providers` that has them
+async fn fetch_symbol(
+    providers: &[Box<dyn QuoteProvider>],
+    symbol: &str,
+) -> Result<Vec<StockPoint>, String> {
+    let mut errors = Vec::new();
+    for provider in providers {
+        match provider.daily(symbol, DAYS).await {
+            Ok(points) if !points.is_empty() => return Ok(points),
+            Ok(_) => errors.push(format!("{}: no prices", provider.name())),
+            Err(e) => {
+                eprintln!("{} failed for {}: {}", provider.name(), symbol, e);
+                errors.push(format!("{}: {}", provider.name(), e));
+            }
+        }
+    }
+    Err(format!("No prices for {} ({})", symbol, errors.join("; ")))
 }
 
+/// Fetches every symbol at once, each falling over to the next provider when one fails.
+/// The providers' rate limits decide how many requests actually go out together.
 pub async fn fetch_stocks(
-    api_key: &str,
+    providers: &[Box<dyn QuoteProvider>],
     symbols_str: &str,
 ) -> Result<StocksData, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    let mut stocks = Vec::new();
-
     let symbols: Vec<&str> = symbols_str.split(',').map(|s| s.trim()).collect();
+    let fetches = symbols.iter().map(|symbol| fetch_symbol(providers, symbol));
+    let results = join_all(fetches).await;
 
-    for symbol in symbols {
-        let url = format!(
-            "https://api.twelvedata.com/time_series?symbol={}&interval=1day&outputsize=60&apikey={}",
-            symbol, api_key
-        );
-        let text = client.get(&url).send().await?.text().await?;
-        eprintln!(
-            "{} API Response (first 500 chars): {}",
-            symbol,
-            &text.chars().take(500).collect::<String>()
-        );
-
-        let response: TwelveDataResponse = serde_json::from_str(&text).map_err(|e| {
-            format!(
-                "Failed to parse {} response: {}. Response: {}",
-                symbol,
-                e,
-                &text.chars().take(200).collect::<String>()
-            )
-        })?;
-
-        let points = parse_twelve_data(response);
+    let mut stocks = Vec::new();
+    for (symbol, points) in symbols.into_iter().zip(results) {
+        let points = points?;
         // Use "BTC" for display instead of "BTC/USD"
         let display_symbol = if symbol == "BTC/USD" {
             "BTC".to_string()
@@ -129,9 +147,48 @@ pub async fn fetch_stocks(
     Ok(StocksData { stocks })
 }
 
-pub fn generate_stocks_svg(stocks: &StocksData, battery_pct: Option<u8>) -> String {
-    let width = 800;
-    let height = 480;
+/// Each symbol's configured indicators, in the order of `symbols`
+pub fn symbol_indicators<'a>(
+    symbols: &str,
+    indicators: &'a BTreeMap<String, Vec<Indicator>>,
+) -> Vec<&'a [Indicator]> {
+    symbols
+        .split(',')
+        .map(|symbol| indicators.get(symbol.trim()).map_or(&[][..], Vec::as_slice))
+        .collect()
+}
+
+/// Generates an SVG of one page of the symbols, laid out as charts, sparklines or a
+/// watchlist
+///
+/// # Arguments
+/// * `stocks` - Daily prices for each symbol
+/// * `indicators` - Overlays and panels for each symbol, in the same order
+/// * `layout` - Arrangement; `Auto` picks one by the number of symbols
+/// * `page` - Zero-based page, wrapping around past the last
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `canvas` - Size to lay out for
+pub fn generate_stocks_svg(
+    stocks: &StocksData,
+    indicators: &[&[Indicator]],
+    layout: StockLayout,
+    page: usize,
+    battery_pct: Option<u8>,
+    canvas: Canvas,
+) -> String {
+    let width = canvas.width;
+    let height = canvas.height;
+    let layout = layout.resolve(stocks.stocks.len());
+    let per_page = layout.per_page(canvas).max(1);
+    let pages = stocks.stocks.len().div_ceil(per_page).max(1);
+    let page = page % pages;
+    let start = page * per_page;
+    let end = (start + per_page).min(stocks.stocks.len());
+    let shown = &stocks.stocks[start..end];
+    if canvas.is_compact() {
+        return stocks_compact::card_svg(shown, page, pages, battery_pct, canvas);
+    }
+
     let mut svg = String::new();
 
     svg.push_str(&format!(
@@ -150,22 +207,53 @@ pub fn generate_stocks_svg(stocks: &StocksData, battery_pct: Option<u8>) -> Stri
         width, height
     ));
 
-    // Create 2x2 grid of charts (leaving room for footer)
-    let chart_width = 380;
-    let chart_height = 215;
-    let positions = [
-        (10, 10),   // Top-left
-        (410, 10),  // Top-right
-        (10, 235),  // Bottom-left
-        (410, 235), // Bottom-right
-    ];
-
-    for (i, stock) in stocks.stocks.iter().enumerate() {
-        if i >= positions.len() {
-            break;
+    match layout.grid() {
+        Some((columns, rows)) => {
+            // Cells 20 apart across and 10 down, leaving room for the footer
+            let cell_width = (width - 20 - 20 * (columns - 1)) / columns;
+            let cell_height = (height - FOOTER_HEIGHT - 10 - 10 * (rows - 1)) / rows;
+            for (i, stock) in shown.iter().enumerate() {
+                let (column, row) = (i as i32 % columns, i as i32 / columns);
+                let x = 10 + column * (cell_width + 20);
+                let y = 10 + row * (cell_height + 10);
+                if matches!(layout, StockLayout::Single | StockLayout::Grid2x2) {
+                    let indicators = indicators.get(start + i).copied().unwrap_or_default();
+                    svg.push_str(&generate_chart_svg(
+                        stock,
+                        indicators,
+                        x,
+                        y,
+                        cell_width,
+                        cell_height,
+                    ));
+                } else {
+                    svg.push_str(&stocks_compact::sparkline_cell_svg(
+                        stock,
+                        x,
+                        y,
+                        cell_width,
+                        cell_height,
+                    ));
+                }
+            }
         }
-        let (x, y) = positions[i];
-        svg.push_str(&generate_chart_svg(stock, x, y, chart_width, chart_height));
+        None => svg.push_str(&stocks_compact::watchlist_svg(
+            shown,
+            10,
+            10,
+            width - 20,
+            height - FOOTER_HEIGHT - 10,
+        )),
+    }
+
+    if pages > 1 {
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" text-anchor="middle" font-size="12" fill="black">Page {} of {}</text>"#,
+            width / 2,
+            height - 10,
+            page + 1,
+            pages
+        ));
     }
 
     // Footer with last updated and battery bar
@@ -208,7 +296,180 @@ pub fn generate_stocks_svg(stocks: &StocksData, battery_pct: Option<u8>) -> Stri
     svg
 }
 
-fn generate_chart_svg(stock: &StockData, x: i32, y: i32, width: i32, height: i32) -> String {
+/// A line through the values that are present, or nothing
+fn polyline_svg(
+    points: impl Iterator<Item = (i32, Option<f64>)>,
+    y_at: impl Fn(f64) -> i32,
+    color: &str,
+    dash: &str,
+) -> String {
+    let points: Vec<String> = points
+        .filter_map(|(x, value)| Some(format!("{},{}", x, y_at(value?))))
+        .collect();
+    if points.len() < 2 {
+        return String::new();
+    }
+    format!(
+        r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="1.5"{}/>"#,
+        points.join(" "),
+        color,
+        if dash.is_empty() {
+            String::new()
+        } else {
+            format!(r#" stroke-dasharray="{}""#, dash)
+        }
+    )
+}
+
+/// Compact volume label, e.g. "68M"
+fn format_volume(volume: f64) -> String {
+    if volume >= 1e9 {
+        format!("{:.1}B", volume / 1e9)
+    } else if volume >= 1e6 {
+        format!("{:.0}M", volume / 1e6)
+    } else if volume >= 1e3 {
+        format!("{:.0}K", volume / 1e3)
+    } else {
+        format!("{:.0}", volume)
+    }
+}
+
+/// One indicator's panel under the candles, in the box `(x, y, width, height)`; `x_at`
+/// places the `i`th visible point
+fn indicator_panel_svg(
+    indicator: Indicator,
+    points: &[StockPoint],
+    closes: &[f64],
+    start: usize,
+    x_at: &impl Fn(usize) -> i32,
+    bar_width: i32,
+    (x, y, width, height): (i32, i32, i32, i32),
+) -> String {
+    let mut svg = format!(
+        r#"<line x1="{x}" y1="{y}" x2="{}" y2="{y}" stroke="black" stroke-width="1"/>"#,
+        x + width,
+        x = x,
+        y = y
+    );
+    let label = |text: String| {
+        format!(
+            r#"<text x="{}" y="{}" font-size="9" fill="black">{}</text>"#,
+            x + 2,
+            y + 9,
+            text
+        )
+    };
+    let bar = |i: usize, top: i32, bottom: i32, color: &str| {
+        format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            x_at(i) - bar_width / 2,
+            top.min(bottom),
+            bar_width,
+            (bottom - top).abs().max(1),
+            color
+        )
+    };
+    let bottom = y + height;
+    match indicator {
+        Indicator::Volume => {
+            let visible = &points[start..];
+            let max = visible.iter().filter_map(|p| p.volume).fold(0.0, f64::max);
+            if max <= 0.0 {
+                svg.push_str(&label("Volume n/a".to_string()));
+                return svg;
+            }
+            for (i, point) in visible.iter().enumerate() {
+                if let Some(volume) = point.volume {
+                    let top = bottom - (volume / max * (height - 2) as f64) as i32;
+                    let color = if point.close >= point.open {
+                        "green"
+                    } else {
+                        "red"
+                    };
+                    svg.push_str(&bar(i, top, bottom, color));
+                }
+            }
+            let last = visible.last().and_then(|p| p.volume).unwrap_or(0.0);
+            svg.push_str(&label(format!("Vol {}", format_volume(last))));
+        }
+        Indicator::Rsi => {
+            let rsi = &indicators::rsi(closes, indicators::RSI_PERIOD)[start..];
+            let y_at = |value: f64| bottom - (value / 100.0 * height as f64) as i32;
+            for level in [30.0, 70.0] {
+                svg.push_str(&format!(
+                    r##"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="#888888" stroke-width="1" stroke-dasharray="2,2"/>"##,
+                    x,
+                    x + width,
+                    y = y_at(level)
+                ));
+            }
+            svg.push_str(&polyline_svg(
+                rsi.iter().enumerate().map(|(i, v)| (x_at(i), *v)),
+                y_at,
+                "blue",
+                "",
+            ));
+            if let Some(last) = rsi.last().copied().flatten() {
+                svg.push_str(&label(format!("RSI {:.0}", last)));
+            }
+        }
+        Indicator::Macd => {
+            let macd = &indicators::macd(
+                closes,
+                indicators::MACD_FAST,
+                indicators::MACD_SLOW,
+                indicators::MACD_SIGNAL,
+            )[start..];
+            let extent = macd
+                .iter()
+                .flatten()
+                .flat_map(|m| [m.macd.abs(), m.signal.unwrap_or(0.0).abs()])
+                .fold(0.0, f64::max);
+            if extent <= 0.0 {
+                svg.push_str(&label("MACD n/a".to_string()));
+                return svg;
+            }
+            let middle = y + height / 2;
+            let y_at = |value: f64| middle - (value / extent * (height / 2 - 1) as f64) as i32;
+            for (i, point) in macd.iter().enumerate() {
+                if let Some(histogram) = point.and_then(|m| m.histogram()) {
+                    let color = if histogram >= 0.0 { "green" } else { "red" };
+                    svg.push_str(&bar(i, y_at(histogram), middle, color));
+                }
+            }
+            svg.push_str(&polyline_svg(
+                macd.iter()
+                    .enumerate()
+                    .map(|(i, m)| (x_at(i), m.map(|m| m.macd))),
+                y_at,
+                "blue",
+                "",
+            ));
+            svg.push_str(&polyline_svg(
+                macd.iter()
+                    .enumerate()
+                    .map(|(i, m)| (x_at(i), m.and_then(|m| m.signal))),
+                y_at,
+                "black",
+                "",
+            ));
+            if let Some(last) = macd.last().copied().flatten() {
+                svg.push_str(&label(format!("MACD {:.2}", last.macd)));
+            }
+        }
+        Indicator::Sma(_) | Indicator::Ema(_) | Indicator::Bollinger => {}
+    }
+    svg
+}
+
+fn generate_chart_svg(
+    stock: &StockData,
+    indicators: &[Indicator],
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+) -> String {
     let mut svg = String::new();
 
     // Chart border
@@ -217,8 +478,12 @@ fn generate_chart_svg(stock: &StockData, x: i32, y: i32, width: i32, height: i32
         x, y, width, height
     ));
 
+    // The earlier days only warm up the indicators
+    let start = stock.points.len().saturating_sub(CHART_DAYS);
+    let points = &stock.points[start..];
+
     // Display current price and change on same line as symbol
-    if let (Some(first), Some(last)) = (stock.points.first(), stock.points.last()) {
+    if let (Some(first), Some(last)) = (points.first(), points.last()) {
         let change = last.close - first.close;
         let change_pct = (change / first.close) * 100.0;
         let change_sign = if change >= 0.0 { "+" } else { "" };
@@ -259,21 +524,79 @@ fn generate_chart_svg(stock: &StockData, x: i32, y: i32, width: i32, height: i32
         ));
     }
 
-    if stock.points.is_empty() {
+    if points.is_empty() {
         return svg;
     }
 
-    // Find min and max prices for scaling (use high/low from candlesticks)
-    let min_price = stock
-        .points
+    // Line overlays over the visible days, with their colors and dashes
+    let closes: Vec<f64> = stock.points.iter().map(|p| p.close).collect();
+    let mut overlays: Vec<(String, Vec<Option<f64>>, &str, &str)> = Vec::new();
+    for (indicator, color) in indicators
+        .iter()
+        .filter(|i| !i.is_panel())
+        .zip(OVERLAY_COLORS.iter().cycle())
+    {
+        match *indicator {
+            Indicator::Sma(period) => overlays.push((
+                format!("SMA{}", period),
+                indicators::sma(&closes, period)[start..].to_vec(),
+                color,
+                "",
+            )),
+            Indicator::Ema(period) => overlays.push((
+                format!("EMA{}", period),
+                indicators::ema(&closes, period)[start..].to_vec(),
+                color,
+                "",
+            )),
+            Indicator::Bollinger => {
+                let bands = &indicators::bollinger(
+                    &closes,
+                    indicators::BOLLINGER_PERIOD,
+                    indicators::BOLLINGER_WIDTH,
+                )[start..];
+                overlays.push((
+                    format!(
+                        "BB({},{})",
+                        indicators::BOLLINGER_PERIOD,
+                        indicators::BOLLINGER_WIDTH
+                    ),
+                    bands.iter().map(|b| b.map(|b| b.upper)).collect(),
+                    color,
+                    "4,2",
+                ));
+                overlays.push((
+                    String::new(),
+                    bands.iter().map(|b| b.map(|b| b.lower)).collect(),
+                    color,
+                    "4,2",
+                ));
+            }
+            Indicator::Rsi | Indicator::Macd | Indicator::Volume => {}
+        }
+    }
+    let panels: Vec<Indicator> = indicators
+        .iter()
+        .copied()
+        .filter(|i| i.is_panel())
+        .collect();
+
+    // Find min and max prices for scaling (use high/low from candlesticks and the overlays)
+    let overlay_values = || {
+        overlays
+            .iter()
+            .flat_map(|(_, values, _, _)| values.iter().flatten())
+    };
+    let min_price = points
         .iter()
         .map(|p| p.low)
+        .chain(overlay_values().copied())
         .min_by(|a, b| a.total_cmp(b))
         .unwrap_or(0.0);
-    let max_price = stock
-        .points
+    let max_price = points
         .iter()
         .map(|p| p.high)
+        .chain(overlay_values().copied())
         .max_by(|a, b| a.total_cmp(b))
         .unwrap_or(100.0);
 
@@ -283,54 +606,95 @@ fn generate_chart_svg(stock: &StockData, x: i32, y: i32, width: i32, height: i32
         1.0
     };
 
-    // Chart area (leave space for title and labels)
+    // Chart area (leave space for title, legend and labels), with the indicator panels
+    // taking slices off the bottom
+    let legend_height = if overlays.is_empty() { 0 } else { 10 };
     let chart_x = x + 40;
-    let chart_y = y + 35;
+    let chart_y = y + 35 + legend_height;
     let chart_w = width - 50;
-    let chart_h = height - 55;
+    let chart_h = height - 55 - legend_height;
+    let panel_h = chart_h / (3 + panels.len() as i32);
+    let price_h = chart_h - panel_h * panels.len() as i32;
+
+    let num_points = points.len();
+    let candle_width = if num_points > 1 {
+        (chart_w as f64 / num_points as f64 * 0.7).max(1.0) as i32
+    } else {
+        10
+    };
+    let x_at =
+        |i: usize| chart_x + (chart_w * i as i32) / num_points.max(1) as i32 + candle_width / 2;
+    let y_at = |price: f64| {
+        chart_y + price_h - ((price - min_price) / price_range * price_h as f64) as i32
+    };
 
     // Draw candlesticks
-    let num_points = stock.points.len();
-    if num_points > 0 {
-        let candle_width = if num_points > 1 {
-            (chart_w as f64 / num_points as f64 * 0.7).max(1.0) as i32
-        } else {
-            10
-        };
+    for (i, point) in points.iter().enumerate() {
+        let px = x_at(i);
 
-        for (i, point) in stock.points.iter().enumerate() {
-            let px = chart_x + (chart_w * i as i32) / num_points.max(1) as i32 + candle_width / 2;
+        // Calculate y positions
+        let high_y = y_at(point.high);
+        let low_y = y_at(point.low);
+        let open_y = y_at(point.open);
+        let close_y = y_at(point.close);
 
-            // Calculate y positions
-            let high_y = chart_y + chart_h
-                - ((point.high - min_price) / price_range * chart_h as f64) as i32;
-            let low_y =
-                chart_y + chart_h - ((point.low - min_price) / price_range * chart_h as f64) as i32;
-            let open_y = chart_y + chart_h
-                - ((point.open - min_price) / price_range * chart_h as f64) as i32;
-            let close_y = chart_y + chart_h
-                - ((point.close - min_price) / price_range * chart_h as f64) as i32;
+        // Determine candle color (green if close >= open, red otherwise)
+        let is_bullish = point.close >= point.open;
+        let color = if is_bullish { "green" } else { "red" };
 
-            // Determine candle color (green if close >= open, red otherwise)
-            let is_bullish = point.close >= point.open;
-            let color = if is_bullish { "green" } else { "red" };
+        // Draw high-low line (wick)
+        svg.push_str(&format!(
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="1"/>"#,
+            px, high_y, px, low_y, color
+        ));
 
-            // Draw high-low line (wick)
-            svg.push_str(&format!(
-                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="1"/>"#,
-                px, high_y, px, low_y, color
-            ));
+        // Draw open-close rectangle (body)
+        let body_top = open_y.min(close_y);
+        let body_height = (open_y - close_y).abs().max(1);
+        svg.push_str(&format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="{}" stroke-width="1"/>"#,
+            px - candle_width / 2, body_top, candle_width, body_height, color, color
+        ));
+    }
 
-            // Draw open-close rectangle (body)
-            let body_top = open_y.min(close_y);
-            let body_height = (open_y - close_y).abs().max(1);
+    // Overlays over the candles, and their legend under the title
+    let mut legend_x = x + 5;
+    for (name, values, color, dash) in &overlays {
+        svg.push_str(&polyline_svg(
+            values.iter().enumerate().map(|(i, v)| (x_at(i), *v)),
+            y_at,
+            color,
+            dash,
+        ));
+        if !name.is_empty() {
             svg.push_str(&format!(
-                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="{}" stroke-width="1"/>"#,
-                px - candle_width / 2, body_top, candle_width, body_height, color, color
+                r#"<text x="{}" y="{}" font-size="10" font-weight="bold" fill="{}">{}</text>"#,
+                legend_x,
+                y + 34,
+                color,
+                name
             ));
+            legend_x += name.len() as i32 * 7 + 8;
         }
     }
 
+    for (i, &indicator) in panels.iter().enumerate() {
+        svg.push_str(&indicator_panel_svg(
+            indicator,
+            &stock.points,
+            &closes,
+            start,
+            &x_at,
+            candle_width,
+            (
+                chart_x,
+                chart_y + price_h + i as i32 * panel_h,
+                chart_w,
+                panel_h,
+            ),
+        ));
+    }
+
     // Y-axis labels (min and max)
     let format_price = |price: f64| -> String {
         if price > 5000.0 {
@@ -343,10 +707,139 @@ fn generate_chart_svg(stock: &StockData, x: i32, y: i32, width: i32, height: i32
     svg.push_str(&svg_common::axis_minmax_labels(
         (chart_x - 5) as f64,
         (chart_y + 5) as f64,
-        (chart_y + chart_h) as f64,
+        (chart_y + price_h) as f64,
         &format_price(max_price),
         &format_price(min_price),
     ));
 
     svg
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fetch_stocks, generate_stocks_svg, StockData, StockLayout, StockPoint, StocksData,
+    };
+    use crate::quote_provider::{QuoteFuture, QuoteProvider};
+    use crate::svg_common::Canvas;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// One bar per symbol, taking longer for the earlier symbols
+    #[derive(Default)]
+    struct Slow {
+        in_flight: AtomicUsize,
+        most_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl QuoteProvider for Slow {
+        fn name(&self) -> &'static str {
+            "Slow"
+        }
+
+        fn daily<'a>(&'a self, symbol: &'a str, _days: usize) -> QuoteFuture<'a> {
+            Box::pin(async move {
+                let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.most_in_flight.fetch_max(now, Ordering::SeqCst);
+                let delay = match symbol {
+                    "A" => 60,
+                    "B" => 30,
+                    _ => 0,
+                };
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                if symbol == "FAIL" {
+                    return Err("no such symbol".into());
+                }
+                Ok(vec![StockPoint {
+                    date: "2025-03-07".to_string(),
+                    open: 1.0,
+                    high: 1.0,
+                    low: 1.0,
+                    close: 1.0,
+                    volume: None,
+                }])
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn symbols_are_fetched_together_and_kept_in_order() {
+        let slow = Slow::default();
+        let most_in_flight = slow.most_in_flight.clone();
+        let providers: Vec<Box<dyn QuoteProvider>> = vec![Box::new(slow)];
+        let stocks = fetch_stocks(&providers, "A, B,C").await.unwrap();
+        let symbols: Vec<&str> = stocks.stocks.iter().map(|s| s.symbol.as_str()).collect();
+        // C finishes first and A last, but they come back as listed
+        assert_eq!(symbols, ["A", "B", "C"]);
+        assert_eq!(most_in_flight.load(Ordering::SeqCst), 3);
+        assert!(fetch_stocks(&providers, "A,FAIL").await.is_err());
+    }
+
+    #[test]
+    fn auto_layout_follows_the_symbol_count() {
+        use StockLayout::*;
+        let layouts: Vec<StockLayout> = [1, 2, 4, 5, 6, 7, 12, 13, 40]
+            .into_iter()
+            .map(|count| Auto.resolve(count))
+            .collect();
+        assert_eq!(
+            layouts,
+            [Single, Grid2x2, Grid2x2, Grid3x2, Grid3x2, Grid4x3, Grid4x3, Watchlist, Watchlist]
+        );
+        // A configured layout is kept whatever the count
+        assert_eq!(Grid2x2.resolve(12), Grid2x2);
+    }
+
+    #[test]
+    fn symbols_per_page_follow_the_panel() {
+        let canvas = |width, height| Canvas { width, height };
+        assert_eq!(StockLayout::Grid4x3.per_page(canvas(800, 480)), 12);
+        // Watchlist rows under the headings and above the footer
+        assert_eq!(StockLayout::Watchlist.per_page(canvas(800, 480)), 12);
+        assert_eq!(StockLayout::Watchlist.per_page(canvas(1600, 1200)), 33);
+        // Small panels get a line per symbol, unless there is a single chart
+        assert_eq!(StockLayout::Grid2x2.per_page(canvas(296, 128)), 7);
+        assert_eq!(StockLayout::Watchlist.per_page(canvas(400, 300)), 10);
+        assert_eq!(StockLayout::Single.per_page(canvas(296, 128)), 1);
+    }
+
+    #[test]
+    fn pages_wrap_around() {
+        let point = |close| StockPoint {
+            date: "2025-03-07".to_string(),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: None,
+        };
+        let stocks = StocksData {
+            stocks: (0..5)
+                .map(|i| StockData {
+                    symbol: format!("SYM{}", i),
+                    points: vec![point(10.0), point(11.0)],
+                })
+                .collect(),
+        };
+        let shown = |page| {
+            let svg = generate_stocks_svg(
+                &stocks,
+                &[],
+                StockLayout::Grid2x2,
+                page,
+                None,
+                Canvas::default(),
+            );
+            (0..5)
+                .filter(|i| svg.contains(&format!("SYM{}", i)))
+                .collect::<Vec<_>>()
+        };
+        // Five symbols take two pages of four, so the third page is the first again
+        assert_eq!(shown(0), [0, 1, 2, 3]);
+        assert_eq!(shown(1), [4]);
+        assert_eq!(shown(2), [0, 1, 2, 3]);
+        assert_eq!(shown(7), [4]);
+    }
+}
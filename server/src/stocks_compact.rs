@@ -0,0 +1,296 @@
+//! The stocks screen's compact views, for more symbols than fit as candlestick charts:
+//! sparkline cells for the 3x2 and 4x3 grids, and the watchlist table. Both show the
+//! change since the previous close, and a sparkline of the closes over the chart window.
+//! Small panels get a text card with a line per symbol instead.
+
+use crate::stocks::{StockData, StockPoint, CHART_DAYS};
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use chrono::{Duration, NaiveDate};
+
+/// Height of a watchlist row, and of its column headings
+const ROW_HEIGHT: i32 = 34;
+const HEADING_HEIGHT: i32 = 20;
+
+/// Watchlist rows that fit in `height`
+pub fn watchlist_rows(height: i32) -> usize {
+    ((height - HEADING_HEIGHT) / ROW_HEIGHT).max(1) as usize
+}
+
+/// The last close and its change since the one before
+fn day_change(points: &[StockPoint]) -> Option<(f64, f64)> {
+    let last = points.last()?;
+    let previous = points
+        .len()
+        .checked_sub(2)
+        .map_or(last.open, |i| points[i].close);
+    Some((last.close, last.close - previous))
+}
+
+/// Lowest low and highest high over the 52 weeks to the last bar, or as much of them
+/// as was fetched
+fn year_range(points: &[StockPoint]) -> Option<(f64, f64)> {
+    let last = NaiveDate::parse_from_str(&points.last()?.date, "%Y-%m-%d").ok()?;
+    let since = (last - Duration::weeks(52)).format("%Y-%m-%d").to_string();
+    let year = points.iter().filter(|p| p.date >= since);
+    let low = year.clone().map(|p| p.low).fold(f64::INFINITY, f64::min);
+    let high = year.map(|p| p.high).fold(f64::NEG_INFINITY, f64::max);
+    (low <= high).then_some((low, high))
+}
+
+fn change_color(change: f64) -> &'static str {
+    if change >= 0.0 {
+        "green"
+    } else {
+        "red"
+    }
+}
+
+/// "+1.23 (+0.5%)"
+fn format_change(change: f64, close: f64) -> String {
+    let sign = if change >= 0.0 { "+" } else { "" };
+    let previous = close - change;
+    if previous == 0.0 {
+        return format!("{}{:.2}", sign, change);
+    }
+    format!(
+        "{}{:.2} ({}{:.1}%)",
+        sign,
+        change,
+        sign,
+        change / previous * 100.0
+    )
+}
+
+/// The closes over the chart window as a line in the box `(x, y, width, height)`, in the
+/// color of the change across it
+fn sparkline_svg(points: &[StockPoint], (x, y, width, height): (i32, i32, i32, i32)) -> String {
+    let points = &points[points.len().saturating_sub(CHART_DAYS)..];
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return String::new();
+    };
+    if points.len() < 2 {
+        return String::new();
+    }
+    let min = points.iter().map(|p| p.close).fold(f64::INFINITY, f64::min);
+    let max = points
+        .iter()
+        .map(|p| p.close)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+    let line: Vec<String> = points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            format!(
+                "{},{}",
+                x + (width * i as i32) / (points.len() - 1) as i32,
+                y + height - ((p.close - min) / range * height as f64) as i32
+            )
+        })
+        .collect();
+    format!(
+        r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+        line.join(" "),
+        change_color(last.close - first.close)
+    )
+}
+
+/// One symbol of a sparkline grid: symbol, price and change above its sparkline
+pub fn sparkline_cell_svg(stock: &StockData, x: i32, y: i32, width: i32, height: i32) -> String {
+    let mut svg = format!(
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white" stroke="black" stroke-width="2"/>"#,
+        x, y, width, height
+    );
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" font-size="18" font-weight="bold" fill="black">{}</text>"#,
+        x + 6,
+        y + 22,
+        svg_common::escape_xml_text(&stock.symbol)
+    ));
+    let Some((close, change)) = day_change(&stock.points) else {
+        return svg;
+    };
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" text-anchor="end" font-size="14" fill="black">${:.2}</text>"#,
+        x + width - 6,
+        y + 21,
+        close
+    ));
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" text-anchor="end" font-size="12" fill="{}">{}</text>"#,
+        x + width - 6,
+        y + 38,
+        change_color(change),
+        format_change(change, close)
+    ));
+    svg.push_str(&sparkline_svg(
+        &stock.points,
+        (x + 6, y + 46, width - 12, height - 54),
+    ));
+    svg
+}
+
+/// A row per symbol in the box `(x, y)`, `width` wide; `height` should fit
+/// `watchlist_rows(height)` of them
+pub fn watchlist_svg(stocks: &[StockData], x: i32, y: i32, width: i32, height: i32) -> String {
+    // Column edges: symbol, price (right-aligned), change (right-aligned), range bar,
+    // sparkline
+    let price_right = x + width * 27 / 100;
+    let change_right = x + width * 48 / 100;
+    let (range_left, range_right) = (x + width * 52 / 100, x + width * 72 / 100);
+    let spark_left = x + width * 76 / 100;
+
+    let mut svg = String::new();
+    let heading = |x: i32, anchor: &str, text: &str| {
+        format!(
+            r#"<text x="{}" y="{}" text-anchor="{}" font-size="12" fill="black">{}</text>"#,
+            x,
+            y + 13,
+            anchor,
+            text
+        )
+    };
+    svg.push_str(&heading(x, "start", "Symbol"));
+    svg.push_str(&heading(price_right, "end", "Last"));
+    svg.push_str(&heading(change_right, "end", "Change"));
+    svg.push_str(&heading(
+        (range_left + range_right) / 2,
+        "middle",
+        "52-week range",
+    ));
+    svg.push_str(&heading(x + width, "end", &format!("{} days", CHART_DAYS)));
+    svg.push_str(&format!(
+        r#"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="black" stroke-width="2"/>"#,
+        x,
+        x + width,
+        y = y + HEADING_HEIGHT - 2
+    ));
+
+    for (i, stock) in stocks.iter().take(watchlist_rows(height)).enumerate() {
+        let top = y + HEADING_HEIGHT + i as i32 * ROW_HEIGHT;
+        let baseline = top + ROW_HEIGHT / 2 + 6;
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" font-size="17" font-weight="bold" fill="black">{}</text>"#,
+            x,
+            baseline,
+            svg_common::escape_xml_text(&stock.symbol)
+        ));
+        if let Some((close, change)) = day_change(&stock.points) {
+            svg.push_str(&format!(
+                r#"<text x="{}" y="{}" text-anchor="end" font-size="16" fill="black">${:.2}</text>"#,
+                price_right, baseline, close
+            ));
+            svg.push_str(&format!(
+                r#"<text x="{}" y="{}" text-anchor="end" font-size="14" fill="{}">{}</text>"#,
+                change_right,
+                baseline,
+                change_color(change),
+                format_change(change, close)
+            ));
+            if let Some((low, high)) = year_range(&stock.points) {
+                // The range as a bar, with a marker at the last close
+                let bar_y = top + ROW_HEIGHT / 2 - 4;
+                let span = if high > low { high - low } else { 1.0 };
+                let marker =
+                    range_left + ((close - low) / span * (range_right - range_left) as f64) as i32;
+                svg.push_str(&format!(
+                    r#"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="black" stroke-width="2"/>"#,
+                    range_left,
+                    range_right,
+                    y = bar_y
+                ));
+                svg.push_str(&format!(
+                    r#"<rect x="{}" y="{}" width="6" height="12" fill="blue"/>"#,
+                    marker - 3,
+                    bar_y - 6
+                ));
+                svg.push_str(&format!(
+                    r#"<text x="{}" y="{ly}" font-size="10" fill="black">{:.0}</text><text x="{}" y="{ly}" text-anchor="end" font-size="10" fill="black">{:.0}</text>"#,
+                    range_left,
+                    low,
+                    range_right,
+                    high,
+                    ly = bar_y + 16
+                ));
+            }
+        }
+        svg.push_str(&sparkline_svg(
+            &stock.points,
+            (spark_left, top + 5, x + width - spark_left, ROW_HEIGHT - 10),
+        ));
+        svg.push_str(&format!(
+            r##"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="#888888" stroke-width="1"/>"##,
+            x,
+            x + width,
+            y = top + ROW_HEIGHT
+        ));
+    }
+    svg
+}
+
+/// One page of symbols for a small panel: a line each with the last close and change,
+/// or for a single symbol the close as the headline with its change and 52-week range
+pub fn card_svg(
+    stocks: &[StockData],
+    page: usize,
+    pages: usize,
+    battery_pct: Option<u8>,
+    canvas: Canvas,
+) -> String {
+    let title = if pages > 1 {
+        format!("Stocks {}/{}", page + 1, pages)
+    } else {
+        "Stocks".to_string()
+    };
+    let mut card = CompactCard::new(title, battery_pct);
+    if let [stock] = stocks {
+        card.title = stock.symbol.clone();
+        if let Some((close, change)) = day_change(&stock.points) {
+            card.headline = Some(CardLine::new(format!("${:.2}", close)));
+            card.lines
+                .push(CardLine::new(format_change(change, close)).colored(Ink::for_change(change)));
+        }
+        if let Some((low, high)) = year_range(&stock.points) {
+            card.lines.push(
+                CardLine::new("52-week range").with_value(format!("{:.2} – {:.2}", low, high)),
+            );
+        }
+        return card.svg(canvas);
+    }
+    for stock in stocks {
+        let line = CardLine::new(stock.symbol.as_str());
+        card.lines.push(match day_change(&stock.points) {
+            Some((close, change)) => line
+                .with_value(format!("${:.2} {}", close, format_change(change, close)))
+                .colored(Ink::for_change(change)),
+            None => line,
+        });
+    }
+    card.svg(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::year_range;
+    use crate::stocks::StockPoint;
+
+    #[test]
+    fn year_range_covers_the_last_52_weeks() {
+        let bar = |date: &str, low: f64, high: f64| StockPoint {
+            date: date.to_string(),
+            open: low,
+            high,
+            low,
+            close: high,
+            volume: None,
+        };
+        let points = [
+            bar("2024-03-01", 1.0, 500.0),
+            bar("2024-03-08", 90.0, 120.0),
+            bar("2024-11-15", 80.0, 110.0),
+            bar("2025-03-07", 100.0, 130.0),
+        ];
+        assert_eq!(year_range(&points), Some((80.0, 130.0)));
+        assert_eq!(year_range(&[]), None);
+    }
+}
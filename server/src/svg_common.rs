@@ -2,6 +2,271 @@
 //! These modules otherwise duplicated this markup (battery indicator, axis labels)
 //! nearly verbatim in half a dozen places.
 
+/// SVG user-space size a generator lays out for. Comes from the target panel's
+/// layout size (see `Panel::canvas`); 800x480 for the reTerminal E1002.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canvas {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 480,
+        }
+    }
+}
+
+impl Canvas {
+    /// Small panels (the 4.2" and 2.9" ones) have no room for the 800x480 layouts'
+    /// charts and small print; generators draw a `CompactCard` on them instead.
+    pub fn is_compact(&self) -> bool {
+        self.width < 600 || self.height < 360
+    }
+}
+
+/// Average glyph advance of the sans-serif font, in ems. A little generous, so text
+/// cut to fit by character count doesn't run off the edge.
+const CHAR_WIDTH_EM: f64 = 0.58;
+
+/// Text colors of a `CompactCard`. Dark shades, so that on the black-and-white panels
+/// they land on black instead of dithering away, and on red where the panel has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ink {
+    Black,
+    Red,
+    Green,
+    Blue,
+}
+
+impl Ink {
+    /// Green for a gain, red for a loss
+    pub fn for_change(change: f64) -> Self {
+        if change >= 0.0 {
+            Ink::Green
+        } else {
+            Ink::Red
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Ink::Black => "black",
+            Ink::Red => "#a00000",
+            Ink::Green => "#004000",
+            Ink::Blue => "#000090",
+        }
+    }
+}
+
+/// One row of a `CompactCard`: text on the left and an optional right-aligned value
+pub struct CardLine {
+    pub text: String,
+    pub value: Option<String>,
+    pub ink: Ink,
+}
+
+impl CardLine {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            value: None,
+            ink: Ink::Black,
+        }
+    }
+
+    pub fn with_value(mut self, value: impl Into<String>) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    pub fn colored(mut self, ink: Ink) -> Self {
+        self.ink = ink;
+        self
+    }
+}
+
+/// Text-only layout for small panels: a title row with the battery level, an optional
+/// large headline (with an icon to its left) and as many detail lines as fit below it.
+/// Font sizes follow the canvas height, so the same card reads on 296x128 and 400x300.
+pub struct CompactCard {
+    pub title: String,
+    pub headline: Option<CardLine>,
+    /// Image data URI drawn left of the headline
+    pub icon: Option<String>,
+    pub lines: Vec<CardLine>,
+    pub battery_pct: Option<u8>,
+}
+
+/// Font sizes and margin of a `CompactCard` on a given canvas
+struct CardMetrics {
+    margin: f64,
+    title: f64,
+    headline: f64,
+    line: f64,
+}
+
+impl CardMetrics {
+    fn new(canvas: Canvas) -> Self {
+        let height = canvas.height as f64;
+        Self {
+            margin: (height / 32.0).clamp(4.0, 12.0),
+            title: (height / 10.0).clamp(12.0, 22.0),
+            headline: (height / 4.5).clamp(24.0, 56.0),
+            line: (height / 12.0).clamp(11.0, 20.0),
+        }
+    }
+
+    fn line_height(&self) -> f64 {
+        self.line * 1.25
+    }
+
+    /// Baseline of the first detail line
+    fn lines_top(&self, headline: bool) -> f64 {
+        let mut y = self.margin + self.title * 1.2;
+        if headline {
+            y += self.headline * 1.1;
+        }
+        y + self.line
+    }
+}
+
+/// Cuts `text` to what fits in `width` units at `font_size`, marking the cut with "..."
+fn fit_text(text: &str, width: f64, font_size: f64) -> String {
+    let max_chars = (width / (font_size * CHAR_WIDTH_EM)).floor().max(3.0) as usize;
+    if text.chars().count() > max_chars {
+        format!(
+            "{}...",
+            text.chars()
+                .take(max_chars - 3)
+                .collect::<String>()
+                .trim_end()
+        )
+    } else {
+        text.to_string()
+    }
+}
+
+impl CompactCard {
+    pub fn new(title: impl Into<String>, battery_pct: Option<u8>) -> Self {
+        Self {
+            title: title.into(),
+            headline: None,
+            icon: None,
+            lines: Vec::new(),
+            battery_pct,
+        }
+    }
+
+    /// How many detail lines fit on `canvas`, below the headline if there is one
+    pub fn rows(canvas: Canvas, headline: bool) -> usize {
+        let metrics = CardMetrics::new(canvas);
+        let top = metrics.lines_top(headline) - metrics.line;
+        ((canvas.height as f64 - metrics.margin - top) / metrics.line_height()).max(0.0) as usize
+    }
+
+    /// How many characters of running text fit on one detail line, for wrapping
+    pub fn line_chars(canvas: Canvas) -> usize {
+        let metrics = CardMetrics::new(canvas);
+        let width = canvas.width as f64 - metrics.margin * 2.0;
+        (width / (metrics.line * CHAR_WIDTH_EM)).floor() as usize
+    }
+
+    pub fn svg(&self, canvas: Canvas) -> String {
+        let metrics = CardMetrics::new(canvas);
+        let width = canvas.width as f64;
+        let (left, right) = (metrics.margin, width - metrics.margin);
+        let mut svg = format!(
+            r#"<svg width="{}" height="{}" xmlns="http://www.w3.org/2000/svg" font-family="sans-serif"><rect width="100%" height="100%" fill="white"/>"#,
+            canvas.width, canvas.height
+        );
+
+        let title_baseline = metrics.margin + metrics.title;
+        let mut title_width = right - left;
+        if let Some(pct) = self.battery_pct {
+            let battery = format!("{}%", pct);
+            title_width -= (battery.len() + 1) as f64 * metrics.title * CHAR_WIDTH_EM;
+            svg.push_str(&format!(
+                r#"<text x="{}" y="{}" text-anchor="end" font-size="{}" fill="{}">{}</text>"#,
+                right,
+                title_baseline,
+                metrics.title,
+                if pct < 20 { Ink::Red } else { Ink::Black }.color(),
+                battery
+            ));
+        }
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" font-size="{}" font-weight="bold" fill="black">{}</text>"#,
+            left,
+            title_baseline,
+            metrics.title,
+            escape_xml_text(&fit_text(&self.title, title_width, metrics.title))
+        ));
+        let rule_y = metrics.margin + metrics.title * 1.2;
+        svg.push_str(&format!(
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="1"/>"#,
+            left, rule_y, right, rule_y
+        ));
+
+        if let Some(headline) = &self.headline {
+            let mut x = left;
+            let icon_size = metrics.headline * 1.1;
+            if let Some(icon) = &self.icon {
+                svg.push_str(&format!(
+                    r#"<image x="{}" y="{}" width="{}" height="{}" href="{}"/>"#,
+                    x, rule_y, icon_size, icon_size, icon
+                ));
+                x += icon_size + metrics.margin;
+            }
+            let text = headline.text.as_str();
+            let available = right - x;
+            // Long headlines shrink before they're cut, down to the title size
+            let size = (available / (text.chars().count().max(1) as f64 * CHAR_WIDTH_EM))
+                .min(metrics.headline)
+                .max(metrics.title);
+            svg.push_str(&format!(
+                r#"<text x="{}" y="{}" font-size="{:.1}" font-weight="bold" fill="{}">{}</text>"#,
+                x,
+                rule_y + metrics.headline * 0.95,
+                size,
+                headline.ink.color(),
+                escape_xml_text(&fit_text(text, available, size))
+            ));
+        }
+
+        let mut y = metrics.lines_top(self.headline.is_some());
+        let rows = Self::rows(canvas, self.headline.is_some());
+        for line in self.lines.iter().take(rows) {
+            let mut text_width = right - left;
+            if let Some(value) = &line.value {
+                text_width -= (value.chars().count() + 1) as f64 * metrics.line * CHAR_WIDTH_EM;
+                svg.push_str(&format!(
+                    r#"<text x="{}" y="{}" text-anchor="end" font-size="{}" fill="{}">{}</text>"#,
+                    right,
+                    y,
+                    metrics.line,
+                    line.ink.color(),
+                    escape_xml_text(value)
+                ));
+            }
+            svg.push_str(&format!(
+                r#"<text x="{}" y="{}" font-size="{}" fill="{}">{}</text>"#,
+                left,
+                y,
+                metrics.line,
+                line.ink.color(),
+                escape_xml_text(&fit_text(&line.text, text_width, metrics.line))
+            ));
+            y += metrics.line_height();
+        }
+
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
 /// `<linearGradient>` definition for the battery bar fill (red at 0% -> green at 100%).
 /// Callers must emit this once inside their `<defs>` block before calling `battery_bar_svg`.
 pub const BATTERY_GRADIENT_DEF: &str = r#"<linearGradient id="batteryGradient" x1="0%" y1="0%" x2="100%" y2="0%"><stop offset="0%" style="stop-color:red;stop-opacity:1" /><stop offset="100%" style="stop-color:green;stop-opacity:1" /></linearGradient>"#;
@@ -66,3 +331,104 @@ pub fn axis_minmax_labels(
         min_label = min_label,
     )
 }
+
+pub fn escape_xml_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Condenses an upstream error for display on the panel. Request URLs are dropped
+/// because they carry API keys, and the result is cut to fit the footer.
+pub fn short_error_reason(error: &str) -> String {
+    const MAX_CHARS: usize = 60;
+    let mut reason = error.to_string();
+    while let Some(start) = reason.find("(http") {
+        let end = reason[start..]
+            .find(')')
+            .map(|i| start + i + 1)
+            .unwrap_or(reason.len());
+        reason.replace_range(start..end, "");
+    }
+    let reason = reason.split_whitespace().collect::<Vec<_>>().join(" ");
+    if reason.chars().count() > MAX_CHARS {
+        format!(
+            "{}...",
+            reason.chars().take(MAX_CHARS - 3).collect::<String>()
+        )
+    } else {
+        reason
+    }
+}
+
+/// Overlays a "Stale since HH:MM" banner at the top and the failure reason in the footer.
+/// Used when an upstream fetch failed and the last good data is re-rendered instead.
+pub fn with_stale_banner(
+    svg: &str,
+    canvas: Canvas,
+    since: chrono::DateTime<chrono::Local>,
+    reason: &str,
+) -> String {
+    let center = canvas.width as f64 / 2.0;
+    let footer_width = (canvas.width as f64).min(300.0);
+    let banner = format!(
+        r#"<rect x="{}" y="0" width="220" height="22" fill="red"/><text x="{}" y="16" text-anchor="middle" font-size="14" font-weight="bold" fill="white">Stale since {}</text><rect x="{}" y="{}" width="{}" height="18" fill="white"/><text x="{}" y="{}" text-anchor="middle" font-size="11" fill="red">{}</text>"#,
+        center - 110.0,
+        center,
+        since.format("%H:%M"),
+        center - footer_width / 2.0,
+        canvas.height as f64 - 22.0,
+        footer_width,
+        center,
+        canvas.height as f64 - 9.0,
+        escape_xml_text(&short_error_reason(reason))
+    );
+    match svg.rfind("</svg>") {
+        Some(end) => format!("{}{}{}", &svg[..end], banner, &svg[end..]),
+        None => svg.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{short_error_reason, Canvas, CardLine, CompactCard};
+
+    #[test]
+    fn compact_card_fits_the_small_panels() {
+        let mono_2in9 = Canvas {
+            width: 296,
+            height: 128,
+        };
+        let mono_4in2 = Canvas {
+            width: 400,
+            height: 300,
+        };
+        assert!(mono_2in9.is_compact() && mono_4in2.is_compact());
+        assert!(!Canvas::default().is_compact());
+        assert_eq!(CompactCard::rows(mono_2in9, true), 5);
+        assert_eq!(CompactCard::rows(mono_2in9, false), 7);
+        assert_eq!(CompactCard::rows(mono_4in2, true), 7);
+
+        let mut card = CompactCard::new("Weather", Some(80));
+        card.headline = Some(CardLine::new("72°"));
+        card.lines = (0..10)
+            .map(|i| CardLine::new(format!("Line {} {}", i, "x".repeat(80))))
+            .collect();
+        let svg = card.svg(mono_2in9);
+        // Lines that don't fit are dropped and long ones are cut
+        assert_eq!(svg.matches("Line ").count(), 5);
+        assert!(svg.contains("x..."));
+    }
+
+    #[test]
+    fn short_error_reason_strips_urls_and_truncates() {
+        assert_eq!(
+            short_error_reason(
+                "error sending request for url (https://api.example.com/x?appid=SECRET)"
+            ),
+            "error sending request for url"
+        );
+        let long = "x".repeat(100);
+        assert_eq!(short_error_reason(&long).chars().count(), 60);
+    }
+}
@@ -0,0 +1,422 @@
+//! Device check-in log. Clients send their battery level, raw voltage, RSSI, wake reason,
+//! firmware version and last download time as query parameters on each bitmap request;
+//! requests that identify the device (`X-Device-Id` or a device route) are appended to
+//! a JSON-lines file under the state directory.
+//!
+//! The `battery` screen and `/telemetry/{id}` chart the discharge curve from this log and
+//! estimate when each panel runs flat.
+
+use crate::json_lines::{JsonLinesStore, LogRecord};
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Check-ins older than this are dropped
+const RETENTION: Duration = Duration::days(MAX_DAYS as i64);
+/// Longest history there can be to show
+pub const MAX_DAYS: usize = 90;
+/// Default history shown by the screen and the JSON endpoint
+pub const DEFAULT_DAYS: usize = 30;
+/// A rise of more than this many points between check-ins means the battery was charged
+const CHARGE_JUMP: f64 = 5.0;
+/// Shortest stretch of discharge an estimate is made from
+const MIN_SPAN: Duration = Duration::hours(12);
+
+/// Line colors for the devices on the chart, in order
+const COLORS: [&str; 5] = ["black", "red", "blue", "green", "orange"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckIn {
+    pub time: DateTime<Utc>,
+    pub device: String,
+    /// Screen served on this check-in
+    pub screen: String,
+    pub battery_pct: Option<u8>,
+    pub battery_mv: Option<u32>,
+    pub rssi: Option<i32>,
+    /// ESP32 wake cause, e.g. "timer" or "button"
+    pub wake_reason: Option<String>,
+    pub firmware: Option<String>,
+    /// Time the device took to download its previous bitmap
+    pub download_ms: Option<u32>,
+}
+
+/// Discharge since the last charge, fitted with a straight line
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatteryEstimate {
+    pub pct_per_day: f64,
+    pub days_left: f64,
+    pub empty_at: DateTime<Utc>,
+}
+
+/// One device's recent check-ins, for the JSON endpoint and the battery screen
+#[derive(Debug, Serialize)]
+pub struct DeviceHealth {
+    pub device: String,
+    pub estimate: Option<BatteryEstimate>,
+    /// Oldest first; never empty
+    pub check_ins: Vec<CheckIn>,
+}
+
+impl DeviceHealth {
+    pub fn last(&self) -> &CheckIn {
+        self.check_ins
+            .last()
+            .expect("device health without check-ins")
+    }
+}
+
+impl LogRecord for CheckIn {
+    fn keep(&self, now: DateTime<Utc>) -> bool {
+        self.time >= now - RETENTION
+    }
+}
+
+#[derive(Default)]
+pub struct TelemetryStore {
+    log: JsonLinesStore<CheckIn>,
+}
+
+impl TelemetryStore {
+    /// Store backed by a JSON-lines file
+    pub fn persistent(path: impl Into<PathBuf>) -> Self {
+        Self {
+            log: JsonLinesStore::persistent(path),
+        }
+    }
+
+    pub fn record(&self, check_in: CheckIn) {
+        self.log.record(check_in, |check_ins, check_in| {
+            check_ins.push(check_in);
+            true
+        });
+    }
+
+    /// Every device with a check-in in the last `days` days, by ID
+    pub fn health(&self, days: usize) -> Vec<DeviceHealth> {
+        let since = Utc::now() - Duration::days(days as i64);
+        let check_ins = self.log.records();
+        let mut devices: Vec<&str> = check_ins
+            .iter()
+            .filter(|c| c.time >= since)
+            .map(|c| c.device.as_str())
+            .collect();
+        devices.sort_unstable();
+        devices.dedup();
+        devices
+            .into_iter()
+            .map(|device| {
+                let history: Vec<CheckIn> = check_ins
+                    .iter()
+                    .filter(|c| c.device == device && c.time >= since)
+                    .cloned()
+                    .collect();
+                DeviceHealth {
+                    device: device.to_string(),
+                    estimate: estimate(&history),
+                    check_ins: history,
+                }
+            })
+            .collect()
+    }
+
+    pub fn device_health(&self, device: &str, days: usize) -> Option<DeviceHealth> {
+        self.health(days).into_iter().find(|h| h.device == device)
+    }
+}
+
+/// Least-squares fit of battery percentage over the check-ins since the last charge.
+/// `None` while the battery is charging or there is too little history to tell.
+pub fn estimate(check_ins: &[CheckIn]) -> Option<BatteryEstimate> {
+    let points: Vec<(DateTime<Utc>, f64)> = check_ins
+        .iter()
+        .filter_map(|c| Some((c.time, c.battery_pct? as f64)))
+        .collect();
+    let start = points
+        .windows(2)
+        .rposition(|pair| pair[1].1 - pair[0].1 > CHARGE_JUMP)
+        .map_or(0, |i| i + 1);
+    let segment = &points[start..];
+    let (first, last) = (segment.first()?, segment.last()?);
+    if segment.len() < 3 || last.0 - first.0 < MIN_SPAN {
+        return None;
+    }
+
+    let days = |time: DateTime<Utc>| (time - first.0).num_seconds() as f64 / 86400.0;
+    let n = segment.len() as f64;
+    let mean_x = segment.iter().map(|p| days(p.0)).sum::<f64>() / n;
+    let mean_y = segment.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut sxy, mut sxx) = (0.0, 0.0);
+    for &(time, pct) in segment {
+        let dx = days(time) - mean_x;
+        sxy += dx * (pct - mean_y);
+        sxx += dx * dx;
+    }
+    let slope = sxy / sxx;
+    if slope >= 0.0 {
+        return None;
+    }
+
+    let fitted_now = (mean_y + slope * (days(last.0) - mean_x)).max(0.0);
+    let days_left = fitted_now / -slope;
+    Some(BatteryEstimate {
+        pct_per_day: -slope,
+        days_left,
+        empty_at: last.0 + Duration::seconds((days_left * 86400.0) as i64),
+    })
+}
+
+fn status_line(health: &DeviceHealth) -> String {
+    let last = health.last();
+    let mut parts = vec![health.device.clone()];
+    if let Some(pct) = last.battery_pct {
+        parts.push(format!("{}%", pct));
+    }
+    if let Some(mv) = last.battery_mv {
+        parts.push(format!("{:.2} V", mv as f64 / 1000.0));
+    }
+    if let Some(rssi) = last.rssi {
+        parts.push(format!("{} dBm", rssi));
+    }
+    if let Some(firmware) = &last.firmware {
+        parts.push(format!("fw {}", firmware));
+    }
+    parts.push(match &health.estimate {
+        Some(estimate) => format!(
+            "~{:.0} days left ({})",
+            estimate.days_left,
+            estimate.empty_at.with_timezone(&Local).format("%b %-d")
+        ),
+        None => "no estimate".to_string(),
+    });
+    parts.push(format!(
+        "seen {}",
+        last.time.with_timezone(&Local).format("%b %-d %H:%M")
+    ));
+    svg_common::escape_xml_text(&parts.join("  ·  "))
+}
+
+/// A line per device with its battery level and days left, red when it is nearly empty
+fn compact_battery_svg(devices: &[DeviceHealth], days: usize, canvas: Canvas) -> String {
+    let mut card = CompactCard::new("Device Battery", None);
+    if devices.is_empty() {
+        card.lines.push(CardLine::new(format!(
+            "No check-ins in the last {} days",
+            days
+        )));
+    }
+    for health in devices {
+        let last = health.last();
+        let mut parts = Vec::new();
+        if let Some(pct) = last.battery_pct {
+            parts.push(format!("{}%", pct));
+        }
+        if let Some(estimate) = &health.estimate {
+            parts.push(format!("~{:.0} days", estimate.days_left));
+        }
+        let low = last.battery_pct.is_some_and(|pct| pct < 20)
+            || health.estimate.as_ref().is_some_and(|e| e.days_left < 14.0);
+        let line = CardLine::new(health.device.as_str()).with_value(parts.join(" · "));
+        card.lines
+            .push(if low { line.colored(Ink::Red) } else { line });
+    }
+    card.svg(canvas)
+}
+
+/// Battery level of every reporting device over the last `days` days, with a status line
+/// and days-until-empty estimate for each
+pub fn generate_battery_svg(devices: &[DeviceHealth], days: usize, canvas: Canvas) -> String {
+    let width = canvas.width;
+    let height = canvas.height;
+    if canvas.is_compact() {
+        return compact_battery_svg(devices, days, canvas);
+    }
+    let mut svg = String::new();
+
+    svg.push_str(&format!(
+        r#"<svg viewBox="0 0 {} {}" xmlns="http://www.w3.org/2000/svg">"#,
+        width, height
+    ));
+    svg.push_str(&format!(
+        r#"<rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+    svg.push_str(&format!(
+        r#"<text x="{}" y="28" text-anchor="middle" font-size="22" font-weight="bold" fill="black">Device Battery</text>"#,
+        width / 2
+    ));
+
+    if devices.is_empty() {
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" text-anchor="middle" font-size="18" fill="black">No check-ins in the last {} days</text>"#,
+            width / 2,
+            height / 2,
+            days
+        ));
+        svg.push_str("</svg>");
+        return svg;
+    }
+
+    // One status line per device under the chart, then the footer
+    let legend_rows = devices.len().min(COLORS.len()) as i32;
+    let chart_x = 50;
+    let chart_y = 45;
+    let chart_w = width - chart_x - 15;
+    let chart_h = height - chart_y - 40 - legend_rows * 20;
+
+    svg.push_str(&format!(
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white" stroke="black" stroke-width="2"/>"#,
+        chart_x, chart_y, chart_w, chart_h
+    ));
+    for pct in [25, 50, 75] {
+        let y = chart_y + chart_h - chart_h * pct / 100;
+        svg.push_str(&format!(
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="1" stroke-dasharray="4,4" shape-rendering="crispEdges"/>"#,
+            chart_x,
+            y,
+            chart_x + chart_w,
+            y
+        ));
+    }
+    svg.push_str(&svg_common::axis_minmax_labels(
+        (chart_x - 5) as f64,
+        (chart_y + 10) as f64,
+        (chart_y + chart_h) as f64,
+        "100%",
+        "0%",
+    ));
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" font-size="10" fill="black">{} days ago</text><text x="{}" y="{}" text-anchor="end" font-size="10" fill="black">now</text>"#,
+        chart_x,
+        chart_y + chart_h + 12,
+        days,
+        chart_x + chart_w,
+        chart_y + chart_h + 12
+    ));
+
+    let now = Utc::now();
+    let window = (days as f64 * 86400.0).max(1.0);
+    for (health, color) in devices.iter().zip(COLORS) {
+        let points: Vec<String> = health
+            .check_ins
+            .iter()
+            .filter_map(|c| {
+                let pct = c.battery_pct? as f64;
+                let age = (now - c.time).num_seconds() as f64;
+                let x = chart_x as f64 + chart_w as f64 * (1.0 - age / window);
+                let y = (chart_y + chart_h) as f64 - chart_h as f64 * pct.min(100.0) / 100.0;
+                Some(format!("{:.1},{:.1}", x, y))
+            })
+            .collect();
+        if points.len() > 1 {
+            svg.push_str(&format!(
+                r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="3"/>"#,
+                points.join(" "),
+                color
+            ));
+        } else if let Some(point) = points.first() {
+            let (x, y) = point.split_once(',').unwrap_or(("0", "0"));
+            svg.push_str(&format!(
+                r#"<circle cx="{}" cy="{}" r="4" fill="{}"/>"#,
+                x, y, color
+            ));
+        }
+    }
+
+    for (i, (health, color)) in devices.iter().zip(COLORS).enumerate() {
+        let y = chart_y + chart_h + 32 + i as i32 * 20;
+        svg.push_str(&format!(
+            r#"<rect x="{}" y="{}" width="16" height="10" fill="{}"/>"#,
+            chart_x,
+            y - 10,
+            color
+        ));
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" font-size="14" fill="black">{}</text>"#,
+            chart_x + 24,
+            y,
+            status_line(health)
+        ));
+    }
+
+    let updated = Local::now().format("Last updated: %H:%M:%S");
+    svg.push_str(&format!(
+        r#"<text x="10" y="{}" font-size="12" fill="black">{}</text>"#,
+        height - 10,
+        updated
+    ));
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate, CheckIn, TelemetryStore};
+    use chrono::{Duration, Utc};
+
+    fn check_in(hours_ago: i64, battery_pct: u8) -> CheckIn {
+        CheckIn {
+            time: Utc::now() - Duration::hours(hours_ago),
+            device: "kitchen".to_string(),
+            screen: "weather".to_string(),
+            battery_pct: Some(battery_pct),
+            battery_mv: None,
+            rssi: Some(-60),
+            wake_reason: Some("timer".to_string()),
+            firmware: None,
+            download_ms: None,
+        }
+    }
+
+    #[test]
+    fn estimate_uses_discharge_since_last_charge() {
+        // Charged from 30% to 100% four days ago, then 5% a day
+        let check_ins = vec![
+            check_in(24 * 6, 40),
+            check_in(24 * 5, 30),
+            check_in(24 * 4, 100),
+            check_in(24 * 3, 95),
+            check_in(24 * 2, 90),
+            check_in(24, 85),
+            check_in(0, 80),
+        ];
+        let estimate = estimate(&check_ins).unwrap();
+        assert!((estimate.pct_per_day - 5.0).abs() < 0.01, "{:?}", estimate);
+        assert!((estimate.days_left - 16.0).abs() < 0.01, "{:?}", estimate);
+
+        // Just charged: not enough history since
+        assert!(super::estimate(&check_ins[..3]).is_none());
+        // Level rising slowly (e.g. on USB power): no estimate
+        assert!(super::estimate(&[check_in(48, 80), check_in(24, 82), check_in(0, 84)]).is_none());
+    }
+
+    #[test]
+    fn store_persists_and_groups_by_device() {
+        let path =
+            std::env::temp_dir().join(format!("iot-image-telemetry-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = TelemetryStore::persistent(&path);
+        store.record(check_in(24 * 100, 90));
+        store.record(check_in(2, 70));
+        store.record(CheckIn {
+            device: "hallway".to_string(),
+            ..check_in(1, 50)
+        });
+        assert_eq!(store.health(30).len(), 2);
+        assert_eq!(
+            store.device_health("kitchen", 30).unwrap().check_ins.len(),
+            1
+        );
+
+        // The check-in past the retention period is dropped as soon as it's recorded
+        let reloaded = TelemetryStore::persistent(&path);
+        let kitchen = reloaded.device_health("kitchen", 365).unwrap();
+        assert_eq!(kitchen.check_ins.len(), 1);
+        assert_eq!(kitchen.last().battery_pct, Some(70));
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+}
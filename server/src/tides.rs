@@ -0,0 +1,344 @@
+//! Tide predictions from a station's harmonic constituents, as published by NOAA
+//! CO-OPS (`https://api.tidesandcurrents.noaa.gov/mdapi/prod/webapi/stations/{id}/harcon.json`).
+//! The height is the sum of one cosine per constituent, with the equilibrium arguments
+//! and nodal corrections of Schureman's "Manual of Harmonic Analysis and Prediction of
+//! Tides"; the minor constituents borrow the nodal corrections of their nearest major one.
+
+use chrono::{DateTime, Duration, Timelike, Utc};
+use serde::Deserialize;
+use std::error::Error;
+use std::path::Path;
+
+/// Nodal correction groups, after Schureman
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    M2,
+    O1,
+    J1,
+    Oo1,
+    Mf,
+    Mm,
+    K1,
+    K2,
+    M3,
+}
+
+struct Constituent {
+    name: &'static str,
+    /// Multiples of T (mean solar hour angle), s, h, p and p1 in the equilibrium argument
+    doodson: [i8; 5],
+    /// Constant part of the argument, in degrees
+    offset: f64,
+    /// Nodal groups and their powers: f is the product of each factor to `|power|`,
+    /// u the sum of `power` times each angle
+    node: &'static [(Node, i8)],
+}
+
+const fn constituent(
+    name: &'static str,
+    doodson: [i8; 5],
+    offset: f64,
+    node: &'static [(Node, i8)],
+) -> Constituent {
+    Constituent {
+        name,
+        doodson,
+        offset,
+        node,
+    }
+}
+
+/// The 37 constituents NOAA publishes
+const CONSTITUENTS: [Constituent; 37] = [
+    constituent("M2", [2, -2, 2, 0, 0], 0.0, &[(Node::M2, 1)]),
+    constituent("S2", [2, 0, 0, 0, 0], 0.0, &[]),
+    constituent("N2", [2, -3, 2, 1, 0], 0.0, &[(Node::M2, 1)]),
+    constituent("K1", [1, 0, 1, 0, 0], -90.0, &[(Node::K1, 1)]),
+    constituent("M4", [4, -4, 4, 0, 0], 0.0, &[(Node::M2, 2)]),
+    constituent("O1", [1, -2, 1, 0, 0], 90.0, &[(Node::O1, 1)]),
+    constituent("M6", [6, -6, 6, 0, 0], 0.0, &[(Node::M2, 3)]),
+    constituent(
+        "MK3",
+        [3, -2, 3, 0, 0],
+        -90.0,
+        &[(Node::M2, 1), (Node::K1, 1)],
+    ),
+    constituent("S4", [4, 0, 0, 0, 0], 0.0, &[]),
+    constituent("MN4", [4, -5, 4, 1, 0], 0.0, &[(Node::M2, 2)]),
+    constituent("NU2", [2, -3, 4, -1, 0], 0.0, &[(Node::M2, 1)]),
+    constituent("S6", [6, 0, 0, 0, 0], 0.0, &[]),
+    constituent("MU2", [2, -4, 4, 0, 0], 0.0, &[(Node::M2, 1)]),
+    constituent("2N2", [2, -4, 2, 2, 0], 0.0, &[(Node::M2, 1)]),
+    constituent("OO1", [1, 2, 1, 0, 0], -90.0, &[(Node::Oo1, 1)]),
+    constituent("LAM2", [2, -1, 0, 1, 0], 180.0, &[(Node::M2, 1)]),
+    constituent("S1", [1, 0, 0, 0, 0], 0.0, &[]),
+    constituent("M1", [1, -1, 1, 1, 0], -90.0, &[(Node::O1, 1)]),
+    constituent("J1", [1, 1, 1, -1, 0], -90.0, &[(Node::J1, 1)]),
+    constituent("MM", [0, 1, 0, -1, 0], 0.0, &[(Node::Mm, 1)]),
+    constituent("SSA", [0, 0, 2, 0, 0], 0.0, &[]),
+    constituent("SA", [0, 0, 1, 0, 0], 0.0, &[]),
+    constituent("MSF", [0, 2, -2, 0, 0], 0.0, &[(Node::M2, -1)]),
+    constituent("MF", [0, 2, 0, 0, 0], 0.0, &[(Node::Mf, 1)]),
+    constituent("RHO", [1, -3, 3, -1, 0], 90.0, &[(Node::O1, 1)]),
+    constituent("Q1", [1, -3, 1, 1, 0], 90.0, &[(Node::O1, 1)]),
+    constituent("T2", [2, 0, -1, 0, 1], 0.0, &[]),
+    constituent("R2", [2, 0, 1, 0, -1], 180.0, &[]),
+    constituent("2Q1", [1, -4, 1, 2, 0], 90.0, &[(Node::O1, 1)]),
+    constituent("P1", [1, 0, -1, 0, 0], 90.0, &[]),
+    constituent("2SM2", [2, 2, -2, 0, 0], 0.0, &[(Node::M2, -1)]),
+    constituent("M3", [3, -3, 3, 0, 0], 0.0, &[(Node::M3, 1)]),
+    constituent("L2", [2, -1, 2, -1, 0], 180.0, &[(Node::M2, 1)]),
+    constituent(
+        "2MK3",
+        [3, -4, 3, 0, 0],
+        90.0,
+        &[(Node::M2, 2), (Node::K1, -1)],
+    ),
+    constituent("K2", [2, 0, 2, 0, 0], 0.0, &[(Node::K2, 1)]),
+    constituent("M8", [8, -8, 8, 0, 0], 0.0, &[(Node::M2, 4)]),
+    constituent("MS4", [4, -2, 2, 0, 0], 0.0, &[(Node::M2, 1)]),
+];
+
+fn sin_deg(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos_deg(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
+
+/// Mean longitudes at `time`, in degrees: T, s, h, p and p1, then the moon's node N
+fn astronomical_arguments(time: DateTime<Utc>) -> ([f64; 5], f64) {
+    let t = (time.timestamp() as f64 / 86400.0 + 2440587.5 - 2451545.0) / 36525.0;
+    let hours = time.num_seconds_from_midnight() as f64 / 3600.0;
+    let arguments = [
+        180.0 + 15.0 * hours,
+        218.3164477 + 481267.88123421 * t,
+        280.46646 + 36000.76983 * t,
+        83.3532465 + 4069.0137287 * t,
+        282.93768 + 1.71946 * t,
+    ];
+    (arguments, 125.04452 - 1934.136261 * t)
+}
+
+/// Node factor f and angle u, in degrees, for each group at the node longitude `n`
+fn node_correction(node: Node, n: f64) -> (f64, f64) {
+    let i = (0.91370 - 0.03569 * cos_deg(n)).acos().to_degrees();
+    let nu = (0.08968 * sin_deg(n) / sin_deg(i)).asin().to_degrees();
+    let xi = n - 2.0 * (0.64412 * (n / 2.0).to_radians().tan()).atan().to_degrees() - nu;
+    match node {
+        Node::M2 => (cos_deg(i / 2.0).powi(4) / 0.9154, 2.0 * xi - 2.0 * nu),
+        Node::O1 => (
+            sin_deg(i) * cos_deg(i / 2.0).powi(2) / 0.3800,
+            2.0 * xi - nu,
+        ),
+        Node::J1 => (sin_deg(2.0 * i) / 0.7214, -nu),
+        Node::Oo1 => (
+            sin_deg(i) * sin_deg(i / 2.0).powi(2) / 0.0164,
+            -2.0 * xi - nu,
+        ),
+        Node::Mf => (sin_deg(i).powi(2) / 0.1578, -2.0 * xi),
+        Node::Mm => ((2.0 / 3.0 - sin_deg(i).powi(2)) / 0.5021, 0.0),
+        Node::K1 => {
+            let f = (0.8965 * sin_deg(2.0 * i).powi(2)
+                + 0.6001 * sin_deg(2.0 * i) * cos_deg(nu)
+                + 0.1006)
+                .sqrt();
+            let nu1 = (sin_deg(2.0 * i) * sin_deg(nu))
+                .atan2(sin_deg(2.0 * i) * cos_deg(nu) + 0.3347)
+                .to_degrees();
+            (f, -nu1)
+        }
+        Node::K2 => {
+            let f = (19.0444 * sin_deg(i).powi(4)
+                + 2.7702 * sin_deg(i).powi(2) * cos_deg(2.0 * nu)
+                + 0.0981)
+                .sqrt();
+            let nu2 = (sin_deg(i).powi(2) * sin_deg(2.0 * nu))
+                .atan2(sin_deg(i).powi(2) * cos_deg(2.0 * nu) + 0.0727)
+                .to_degrees();
+            (f, -nu2)
+        }
+        Node::M3 => (cos_deg(i / 2.0).powi(6) / 0.8758, 3.0 * xi - 3.0 * nu),
+    }
+}
+
+/// NOAA's `harcon.json`
+#[derive(Deserialize)]
+struct HarconFile {
+    /// "feet" or "metric"
+    units: String,
+    #[serde(rename = "HarmonicConstituents")]
+    constituents: Vec<HarconRecord>,
+}
+
+#[derive(Deserialize)]
+struct HarconRecord {
+    name: String,
+    amplitude: f64,
+    /// Greenwich phase lag, in degrees
+    #[serde(rename = "phase_GMT")]
+    phase: f64,
+}
+
+/// A station's constituents, ready to predict from
+pub struct Harmonics {
+    /// "ft" or "m"
+    pub units: &'static str,
+    /// Added to every height, to refer them to chart datum rather than mean sea level
+    mean_sea_level: f64,
+    /// Constituent, amplitude and phase lag
+    constituents: Vec<(&'static Constituent, f64, f64)>,
+}
+
+/// A high or low water
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extreme {
+    pub time: DateTime<Utc>,
+    pub height: f64,
+    pub high: bool,
+}
+
+/// Sampling step for finding highs and lows; each is then refined with a parabola
+const EXTREME_STEP: Duration = Duration::minutes(6);
+
+impl Harmonics {
+    /// Reads a NOAA `harcon.json`. Constituents with zero amplitude are dropped.
+    pub fn load(path: &Path, mean_sea_level: f64) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let file: HarconFile = serde_json::from_str(&text)?;
+        let constituents = file
+            .constituents
+            .iter()
+            .filter(|record| record.amplitude != 0.0)
+            .map(|record| {
+                CONSTITUENTS
+                    .iter()
+                    .find(|c| c.name.eq_ignore_ascii_case(&record.name))
+                    .map(|c| (c, record.amplitude, record.phase))
+                    .ok_or_else(|| format!("Unknown tidal constituent {}", record.name))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            units: if file.units == "feet" { "ft" } else { "m" },
+            mean_sea_level,
+            constituents,
+        })
+    }
+
+    /// Predicted water level at `time`
+    pub fn height(&self, time: DateTime<Utc>) -> f64 {
+        let (arguments, node_longitude) = astronomical_arguments(time);
+        self.mean_sea_level
+            + self
+                .constituents
+                .iter()
+                .map(|&(constituent, amplitude, phase)| {
+                    let v: f64 = constituent
+                        .doodson
+                        .iter()
+                        .zip(arguments)
+                        .map(|(&multiple, argument)| multiple as f64 * argument)
+                        .sum::<f64>()
+                        + constituent.offset;
+                    let (f, u) =
+                        constituent
+                            .node
+                            .iter()
+                            .fold((1.0, 0.0), |(f, u), &(node, power)| {
+                                let (node_f, node_u) = node_correction(node, node_longitude);
+                                (
+                                    f * node_f.powi(power.abs() as i32),
+                                    u + power as f64 * node_u,
+                                )
+                            });
+                    f * amplitude * cos_deg(v + u - phase)
+                })
+                .sum::<f64>()
+    }
+
+    /// Highs and lows in `[start, end)`
+    pub fn extremes(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Extreme> {
+        let step_hours = EXTREME_STEP.num_seconds() as f64 / 3600.0;
+        let mut found = Vec::new();
+        let mut time = start;
+        let (mut previous, mut current) = (self.height(time - EXTREME_STEP), self.height(time));
+        while time < end {
+            let next = self.height(time + EXTREME_STEP);
+            let high = current >= previous && current > next;
+            let low = current <= previous && current < next;
+            if high || low {
+                // Vertex of the parabola through the three samples
+                let curvature = previous - 2.0 * current + next;
+                let shift = 0.5 * (previous - next) / curvature;
+                found.push(Extreme {
+                    time: time + Duration::seconds((shift * step_hours * 3600.0) as i64),
+                    height: current - 0.25 * (previous - next) * shift,
+                    high,
+                });
+            }
+            previous = current;
+            current = next;
+            time += EXTREME_STEP;
+        }
+        found
+    }
+
+    /// Height with its units, e.g. "5.4 ft"
+    pub fn format_height(&self, height: f64) -> String {
+        format!("{:.1} {}", height, self.units)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Harmonics, CONSTITUENTS};
+    use chrono::{Duration, TimeZone, Utc};
+    use std::path::Path;
+
+    fn only(name: &str) -> Harmonics {
+        Harmonics {
+            units: "m",
+            mean_sea_level: 1.0,
+            constituents: vec![(
+                CONSTITUENTS.iter().find(|c| c.name == name).unwrap(),
+                0.5,
+                0.0,
+            )],
+        }
+    }
+
+    #[test]
+    fn predictions_follow_the_constituents() {
+        // Every constituent's argument advances at NOAA's published speed
+        let start = Utc.with_ymd_and_hms(2025, 3, 5, 0, 0, 0).unwrap();
+        let m2 = only("M2");
+        let highs: Vec<_> = m2
+            .extremes(start, start + Duration::days(2))
+            .into_iter()
+            .filter(|extreme| extreme.high)
+            .collect();
+        assert!(highs.len() >= 3);
+        let period = (highs[1].time - highs[0].time).num_seconds() as f64 / 3600.0;
+        assert!((period - 360.0 / 28.9841042).abs() < 0.01, "{}", period);
+        // Mean sea level plus the amplitude, reduced by M2's node factor
+        assert!(highs[0].height > 1.45 && highs[0].height < 1.5);
+
+        // S2 has no nodal correction and peaks at Greenwich noon and midnight
+        let s2 = only("S2");
+        let extremes = s2.extremes(start, start + Duration::days(1));
+        assert_eq!(extremes.len(), 4);
+        assert!(extremes[0].high && !extremes[1].high);
+        assert_eq!(extremes[0].time, start);
+        assert!((s2.height(start + Duration::hours(12)) - 1.5).abs() < 1e-9);
+
+        // The zero-amplitude constituents are dropped
+        let recorded = Harmonics::load(Path::new("tests/fixtures/tides/harcon.json"), 3.1).unwrap();
+        assert_eq!(recorded.units, "ft");
+        assert_eq!(recorded.constituents.len(), 8);
+        let days = recorded.extremes(start, start + Duration::days(2));
+        assert!(days.len() >= 7);
+        assert!(days.windows(2).all(|pair| pair[0].high != pair[1].high));
+    }
+}
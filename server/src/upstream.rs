@@ -0,0 +1,386 @@
+//! The HTTP client shared by every upstream fetch: weather, air quality, stocks and
+//! FRED. Connections are reused across requests and every request has a timeout.
+//! Timeouts, connection failures, 429s and 5xx responses are retried with jittered
+//! exponential backoff. Each API's requests also go through its own `RateLimit`, so
+//! fetching many symbols at once stays inside the API's quota.
+
+use crate::config::{ApiSource, Sources};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::{sleep, Instant};
+
+pub type FetchError = Box<dyn Error + Send + Sync>;
+
+/// For the whole request, including reading the body
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Tries per request, including the first
+const ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubling for each one after
+const BACKOFF: Duration = Duration::from_millis(500);
+/// Longest `Retry-After` honored; a longer one fails the request instead of stalling it
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+/// Requests in flight at once to one API
+const MAX_CONCURRENT: usize = 4;
+
+#[derive(Clone)]
+pub struct Upstream {
+    client: reqwest::Client,
+    attempts: u32,
+    backoff: Duration,
+}
+
+impl Upstream {
+    pub fn new(timeout: Duration) -> Self {
+        Self::with_retries(timeout, ATTEMPTS, BACKOFF)
+    }
+
+    fn with_retries(timeout: Duration, attempts: u32, backoff: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .connect_timeout(CONNECT_TIMEOUT.min(timeout))
+            .build()
+            .expect("HTTP client with default TLS settings");
+        Self {
+            client,
+            attempts: attempts.max(1),
+            backoff,
+        }
+    }
+
+    /// GETs `url` and parses the JSON body
+    pub async fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        limit: &RateLimit,
+    ) -> Result<T, FetchError> {
+        Ok(serde_json::from_str(&self.get_text(url, limit).await?)?)
+    }
+
+    /// Like `get_json`, for APIs that want to know who is calling
+    pub async fn get_json_as<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        user_agent: &str,
+        limit: &RateLimit,
+    ) -> Result<T, FetchError> {
+        let request = || {
+            self.client
+                .get(url)
+                .header(reqwest::header::USER_AGENT, user_agent)
+        };
+        Ok(serde_json::from_str(&self.send(request, limit).await?)?)
+    }
+
+    /// GETs `url` within `limit`, retrying transient failures, and returns the body. An
+    /// HTTP error status that isn't worth retrying fails straight away.
+    pub async fn get_text(&self, url: &str, limit: &RateLimit) -> Result<String, FetchError> {
+        self.send(|| self.client.get(url), limit).await
+    }
+
+    async fn send(
+        &self,
+        request: impl Fn() -> RequestBuilder,
+        limit: &RateLimit,
+    ) -> Result<String, FetchError> {
+        let mut attempt = 1;
+        loop {
+            let (error, retry_after) = {
+                let _permit = limit.acquire().await;
+                match request().send().await {
+                    Ok(response) if retryable(response.status()) => {
+                        let retry_after = response
+                            .headers()
+                            .get(reqwest::header::RETRY_AFTER)
+                            .and_then(|value| value.to_str().ok()?.parse().ok())
+                            .map(Duration::from_secs);
+                        (format!("status {}", response.status()), retry_after)
+                    }
+                    Ok(response) => match response.error_for_status() {
+                        Ok(response) => match response.text().await {
+                            Ok(text) => return Ok(text),
+                            Err(e) if e.is_timeout() => (e.to_string(), None),
+                            Err(e) => return Err(e.into()),
+                        },
+                        Err(e) => return Err(e.into()),
+                    },
+                    Err(e) if e.is_timeout() || e.is_connect() => (e.to_string(), None),
+                    Err(e) => return Err(e.into()),
+                }
+            };
+            let delay = match retry_after {
+                Some(delay) if delay > MAX_RETRY_AFTER => None,
+                Some(delay) => Some(delay),
+                None => Some(jittered(self.backoff * 2u32.pow(attempt - 1))),
+            };
+            let Some(delay) = delay.filter(|_| attempt < self.attempts) else {
+                return Err(
+                    format!("{} failed after {} tries: {}", limit.name, attempt, error).into(),
+                );
+            };
+            eprintln!(
+                "{} request failed ({}), retrying in {:.1}s",
+                limit.name,
+                error,
+                delay.as_secs_f64()
+            );
+            sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Somewhere between half and all of `delay`, so that requests which failed together
+/// don't retry together
+fn jittered(delay: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    delay.mul_f64(0.5 + 0.5 * (random as f64 / u64::MAX as f64))
+}
+
+/// At most `requests` in any `window`, and at most a few in flight at once. Callers
+/// queue in the order they arrive.
+pub struct RateLimit {
+    /// The API, for logs and errors
+    pub name: &'static str,
+    requests: usize,
+    window: Duration,
+    /// When each request in the current window was sent, oldest first
+    sent: Mutex<VecDeque<Instant>>,
+    in_flight: Semaphore,
+}
+
+impl RateLimit {
+    pub fn new(name: &'static str, requests: usize, window: Duration, concurrent: usize) -> Self {
+        Self {
+            name,
+            requests: requests.max(1),
+            window,
+            sent: Mutex::new(VecDeque::new()),
+            in_flight: Semaphore::new(concurrent.max(1)),
+        }
+    }
+
+    pub fn per_minute(name: &'static str, requests: u32) -> Self {
+        Self::new(
+            name,
+            requests as usize,
+            Duration::from_secs(60),
+            MAX_CONCURRENT,
+        )
+    }
+
+    /// Waits for a slot in the window, and holds one of the in-flight places until the
+    /// permit is dropped
+    async fn acquire(&self) -> SemaphorePermit<'_> {
+        let permit = self
+            .in_flight
+            .acquire()
+            .await
+            .expect("rate limit semaphore is never closed");
+        loop {
+            let wait = {
+                let now = Instant::now();
+                let mut sent = self.sent.lock().unwrap();
+                while sent
+                    .front()
+                    .is_some_and(|&time| now.duration_since(time) >= self.window)
+                {
+                    sent.pop_front();
+                }
+                if sent.len() < self.requests {
+                    sent.push_back(now);
+                    return permit;
+                }
+                sent[0] + self.window - now
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+/// The rate limit of each API, shared by every screen that uses it
+pub struct Limits {
+    pub openweather: Arc<RateLimit>,
+    pub open_meteo: Arc<RateLimit>,
+    pub nws: Arc<RateLimit>,
+    pub twelve_data: Arc<RateLimit>,
+    pub alpha_vantage: Arc<RateLimit>,
+    pub stooq: Arc<RateLimit>,
+    pub fred: Arc<RateLimit>,
+}
+
+impl Limits {
+    /// Each source's `requests_per_minute`, or its free tier's limit
+    pub fn new(sources: &Sources) -> Self {
+        let limit = |name, source: &Option<ApiSource>, default| {
+            let requests = source
+                .as_ref()
+                .and_then(|s| s.requests_per_minute)
+                .unwrap_or(default);
+            Arc::new(RateLimit::per_minute(name, requests))
+        };
+        Self {
+            openweather: limit("OpenWeather", &sources.openweather, 60),
+            open_meteo: limit("Open-Meteo", &None, 600),
+            // Unpublished; the API asks for modest use
+            nws: limit("NWS", &None, 60),
+            twelve_data: limit("Twelve Data", &sources.twelve_data, 8),
+            alpha_vantage: limit("Alpha Vantage", &sources.alpha_vantage, 5),
+            // Unpublished; kept modest since it needs no key
+            stooq: limit("Stooq", &None, 30),
+            fred: limit("FRED", &sources.fred, 120),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::new(&Sources::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimit, Upstream};
+    use axum::http::header::USER_AGENT;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use futures_util::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    /// Serves `app` on a free local port and returns its address
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried_and_others_are_not() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/flaky",
+                get({
+                    let calls = calls.clone();
+                    move || async move {
+                        // Unavailable twice, then fine
+                        match calls.fetch_add(1, Ordering::SeqCst) {
+                            0 | 1 => (StatusCode::SERVICE_UNAVAILABLE, "busy"),
+                            _ => (StatusCode::OK, "ok"),
+                        }
+                    }
+                }),
+            )
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+            .route(
+                "/agent",
+                get(|headers: HeaderMap| async move {
+                    let agent = headers.get(USER_AGENT).and_then(|v| v.to_str().ok());
+                    format!("{:?}", agent.unwrap_or_default())
+                }),
+            )
+            .route(
+                "/hang",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "late"
+                }),
+            );
+        let base = serve(app).await;
+        let limit = RateLimit::new("Test", 100, Duration::from_secs(1), 4);
+        let upstream =
+            Upstream::with_retries(Duration::from_millis(200), 3, Duration::from_millis(10));
+
+        let body = upstream.get_text(&format!("{}/flaky", base), &limit).await;
+        assert_eq!(body.unwrap(), "ok");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        calls.store(0, Ordering::SeqCst);
+        let once = Upstream::with_retries(Duration::from_millis(200), 2, Duration::from_millis(10));
+        let error = once.get_text(&format!("{}/flaky", base), &limit).await;
+        assert!(error.unwrap_err().to_string().contains("after 2 tries"));
+
+        assert!(upstream
+            .get_text(&format!("{}/missing", base), &limit)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("404"));
+
+        let agent: String = upstream
+            .get_json_as(
+                &format!("{}/agent", base),
+                "iot-image (me@example.com)",
+                &limit,
+            )
+            .await
+            .unwrap();
+        assert_eq!(agent, "iot-image (me@example.com)");
+
+        let started = Instant::now();
+        assert!(upstream
+            .get_text(&format!("{}/hang", base), &limit)
+            .await
+            .is_err());
+        // Three tries of 200ms, not the 5s the server takes
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_stay_within_the_limit() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new().route(
+            "/quote",
+            get({
+                let (in_flight, most_in_flight, arrivals) =
+                    (in_flight.clone(), most_in_flight.clone(), arrivals.clone());
+                move || async move {
+                    arrivals.lock().unwrap().push(Instant::now());
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_in_flight.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    "ok"
+                }
+            }),
+        );
+        let base = serve(app).await;
+        // Three a window, two at a time
+        let window = Duration::from_millis(300);
+        let limit = RateLimit::new("Test", 3, window, 2);
+        let upstream = Upstream::new(Duration::from_secs(5));
+        let url = format!("{}/quote", base);
+
+        let started = Instant::now();
+        let bodies = join_all((0..7).map(|_| upstream.get_text(&url, &limit))).await;
+        assert!(bodies.iter().all(|body| body.as_deref().ok() == Some("ok")));
+        assert!(most_in_flight.load(Ordering::SeqCst) <= 2);
+
+        // The 4th waits for the 1st to leave the window, and the 7th for the 4th
+        let arrivals = arrivals.lock().unwrap();
+        assert_eq!(arrivals.len(), 7);
+        for i in 3..7 {
+            assert!(arrivals[i] - arrivals[i - 3] >= window - Duration::from_millis(5));
+        }
+        assert!(started.elapsed() >= window * 2);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::svg_common;
+use crate::locale::{Locale, Units};
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use crate::upstream::{FetchError, RateLimit, Upstream};
 use base64::{engine::general_purpose, Engine as _};
 use chrono::prelude::*;
 use chrono::Timelike;
@@ -6,13 +8,25 @@ use reverse_geocoder::ReverseGeocoder;
 use serde::{Deserialize, Serialize};
 use std::fs;
 
+/// A forecast in the provider-neutral form the screens draw, as returned by a
+/// `WeatherProvider`. Temperatures and wind speeds are in the units it was fetched
+/// with (°F and mph for imperial, °C and m/s for metric; see `Units::api_units`);
+/// rain is always in millimetres.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WeatherData {
     pub lat: f32,
     pub lon: f32,
+    /// Seconds east of UTC at the forecast location
     pub timezone_offset: i32,
-    pub current: CurrentWeather,
+    /// Today first
     pub daily: Vec<DailyWeather>,
+    /// At least the next 48 hours, starting at or before the current hour
+    #[serde(default)]
+    pub hourly: Vec<HourlyWeather>,
+    /// The next hour of precipitation, in one-minute steps (15 from Open-Meteo); NWS
+    /// has none
+    #[serde(default)]
+    pub minutely: Vec<MinutelyPrecipitation>,
     #[serde(default)]
     pub alerts: Vec<WeatherAlert>,
 }
@@ -30,107 +44,185 @@ pub struct WeatherOverviewData {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WeatherAlert {
     pub event: String,
+    /// Issuing office, e.g. "NWS San Francisco CA"
+    #[serde(default)]
+    pub sender: String,
     pub description: String,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Hazard types, e.g. "Wind"; OpenWeatherMap only
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub start: i64,
     pub end: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CurrentWeather {
-    pub dt: i64,
-    pub temp: f32,
-    pub humidity: i32,
-    pub weather: Vec<Weather>,
+/// How dangerous an alert is, least first
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    #[default]
+    Minor,
+    Moderate,
+    Severe,
+    Extreme,
+}
+
+impl Severity {
+    /// Looks up a CAP severity name as NWS reports it, e.g. "Severe"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "minor" => Some(Severity::Minor),
+            "moderate" => Some(Severity::Moderate),
+            "severe" => Some(Severity::Severe),
+            "extreme" => Some(Severity::Extreme),
+            _ => None,
+        }
+    }
+
+    /// Judged from a US-style event name when the source gives no severity: warnings
+    /// are severe, watches and advisories moderate, anything else minor
+    pub fn from_event(event: &str) -> Self {
+        let event = event.to_ascii_lowercase();
+        if event.contains("warning") {
+            Severity::Severe
+        } else if event.contains("watch") || event.contains("advisory") {
+            Severity::Moderate
+        } else {
+            Severity::Minor
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DailyWeather {
+    /// A time during the day, in Unix seconds
     pub dt: i64,
-    pub temp: TempRange,
+    pub temp_min: f32,
+    pub temp_max: f32,
     pub feels_like: FeelsLike,
+    /// Relative humidity, %
     pub humidity: i32,
+    /// Highest sustained wind speed
     pub wind_speed: f32,
     pub wind_gust: Option<f32>,
-    pub sunrise: i64,
-    pub sunset: i64,
-    pub weather: Vec<Weather>,
-    #[serde(default)]
+    /// Not every provider has sunrise and sunset times
+    pub sunrise: Option<i64>,
+    pub sunset: Option<i64>,
+    pub condition: Condition,
+    /// Chance of precipitation, 0.0 to 1.0
     pub pop: f32,
-    #[serde(default)]
+    /// Expected rain, mm
     pub rain: f32,
     pub uvi: Option<f32>,
+    /// Cloud cover, %
     pub clouds: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct TempRange {
-    pub day: f32,
-    pub min: f32,
-    pub max: f32,
-    pub morn: f32,
-    pub night: f32,
+pub struct HourlyWeather {
+    /// The hour, in Unix seconds
+    pub dt: i64,
+    pub temp: f32,
+    pub feels_like: f32,
+    /// Chance of precipitation, 0.0 to 1.0
+    pub pop: f32,
+    /// Rain in the hour, mm
+    pub rain: f32,
+    /// Snow in the hour, mm of water
+    pub snow: f32,
+    pub wind_speed: f32,
+    /// Direction the wind blows from, degrees clockwise from north
+    pub wind_deg: Option<f32>,
+    pub condition: Condition,
+}
+
+/// Precipitation intensity from `dt` until the next step
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MinutelyPrecipitation {
+    /// Start of the step, in Unix seconds
+    pub dt: i64,
+    /// mm/h
+    pub precipitation: f32,
 }
 
+/// Apparent temperature in the morning, afternoon and evening
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FeelsLike {
+    pub morn: f32,
     pub day: f32,
-    pub night: f32,
     pub eve: f32,
-    pub morn: f32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Weather {
-    pub description: String,
-    pub main: String,
-    pub icon: String,
+/// The day's weather, normalized from each provider's condition codes to the local
+/// icon set. Ordered from fair to most severe.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Condition {
+    Clear,
+    PartlyCloudy,
+    Cloudy,
+    Overcast,
+    Fog,
+    Drizzle,
+    /// Scattered showers
+    Showers,
+    /// Steady rain
+    Rain,
+    Sleet,
+    Snow,
+    Thunderstorm,
 }
 
-/// Maps OpenWeatherMap icon codes to local SVG icon filenames
-fn map_weather_icon(icon_code: &str) -> &'static str {
-    match icon_code {
-        "01d" => "clear-day.svg",
-        "01n" => "clear-night.svg",
-        "02d" => "partly-cloudy-day.svg",
-        "02n" => "partly-cloudy-night.svg",
-        "03d" | "03n" => "cloudy.svg",
-        "04d" | "04n" => "overcast-day.svg",
-        "09d" | "09n" => "rain.svg",
-        "10d" => "overcast-day-rain.svg",
-        "10n" => "overcast-night-rain.svg",
-        "11d" => "thunderstorms-day.svg",
-        "11n" => "thunderstorms-night.svg",
-        "13d" => "snow.svg",
-        "13n" => "snow.svg",
-        "50d" | "50n" => "fog.svg",
-        _ => "cloudy.svg", // default fallback
+impl Condition {
+    /// Local SVG icon filename, in its night version when there is one
+    fn icon_file(self, night: bool) -> &'static str {
+        match (self, night) {
+            (Condition::Clear, false) => "clear-day.svg",
+            (Condition::Clear, true) => "clear-night.svg",
+            (Condition::PartlyCloudy, false) => "partly-cloudy-day.svg",
+            (Condition::PartlyCloudy, true) => "partly-cloudy-night.svg",
+            (Condition::Cloudy, _) => "cloudy.svg",
+            (Condition::Overcast, false) => "overcast-day.svg",
+            (Condition::Overcast, true) => "overcast-night.svg",
+            (Condition::Fog, _) => "fog.svg",
+            (Condition::Drizzle, _) => "drizzle.svg",
+            (Condition::Showers, _) => "rain.svg",
+            (Condition::Rain, false) => "overcast-day-rain.svg",
+            (Condition::Rain, true) => "overcast-night-rain.svg",
+            (Condition::Sleet, _) => "sleet.svg",
+            (Condition::Snow, _) => "snow.svg",
+            (Condition::Thunderstorm, false) => "thunderstorms-day.svg",
+            (Condition::Thunderstorm, true) => "thunderstorms-night.svg",
+        }
     }
 }
 
 const RAIN_ICON_POP_CUTOFF: f32 = 0.25; // 25% chance of rain
 const RAIN_ICON_AMOUNT_CUTOFF: f32 = 2.54; // 0.1 inch in mm
 
-fn display_icon_for_daily_weather(icon_code: &str, pop: f32, rain: f32) -> &str {
+fn display_condition_for_daily_weather(condition: Condition, pop: f32, rain: f32) -> Condition {
     println!(
-        "Determining icon for code {}, pop {}, rain {}",
-        icon_code, pop, rain
+        "Determining icon for condition {:?}, pop {}, rain {}",
+        condition, pop, rain
     );
     if pop < RAIN_ICON_POP_CUTOFF || rain < RAIN_ICON_AMOUNT_CUTOFF {
-        match icon_code {
-            "09d" => "03d",
-            "09n" => "03n",
-            "10d" => "04d",
-            "10n" => "04n",
-            _ => icon_code,
+        match condition {
+            Condition::Showers => Condition::Cloudy,
+            Condition::Rain => Condition::Overcast,
+            _ => condition,
         }
     } else {
-        icon_code
+        condition
     }
 }
 
 /// Loads an SVG icon and returns its content as a base64-encoded data URI
-fn load_weather_icon_as_data_uri(icon_code: &str) -> Result<String, std::io::Error> {
-    let icon_filename = map_weather_icon(icon_code);
+pub fn load_weather_icon_as_data_uri(
+    condition: Condition,
+    night: bool,
+) -> Result<String, std::io::Error> {
+    let icon_filename = condition.icon_file(night);
     let icon_path = format!("assets/static/fill-svg-static/{}", icon_filename);
     let svg_content = fs::read(&icon_path)?;
 
@@ -143,10 +235,11 @@ struct BarData {
     fill_percent: f32, // 0.0 to 100.0
 }
 
-fn temperature_bar(temp: f32) -> BarData {
-    // Map temperature range 32°F to 100°F to 0-100% fill
+fn temperature_bar(temp: f32, units: Units) -> BarData {
+    // Map temperature range 32°F to 100°F (0°C to 38°C) to 0-100% fill
     let lowest = 32.0;
     let highest = 100.0;
+    let temp = units.to_fahrenheit(temp);
     let fill_percent = ((temp - lowest) / (highest - lowest) * 100.0).clamp(0.0, 100.0);
     BarData { fill_percent }
 }
@@ -157,9 +250,9 @@ fn humidity_bar(humidity: i32, _temp: f32) -> BarData {
     BarData { fill_percent }
 }
 
-fn wind_bar(wind_speed: f32) -> BarData {
-    // Map wind speed 0-60 mph to 0-100% fill
-    let fill_percent = (wind_speed / 60.0 * 100.0).clamp(0.0, 100.0);
+fn wind_bar(wind_speed: f32, units: Units) -> BarData {
+    // Map wind speed 0-60 mph (0-27 m/s) to 0-100% fill
+    let fill_percent = (units.to_mph(wind_speed) / 60.0 * 100.0).clamp(0.0, 100.0);
     BarData { fill_percent }
 }
 
@@ -176,9 +269,9 @@ fn uvi_bar(uvi: f32) -> BarData {
     BarData { fill_percent }
 }
 
-/// Converts wind speed in mph to Beaufort scale (0-12)
-fn wind_speed_to_beaufort(wind_speed: f32) -> u8 {
-    match wind_speed as i32 {
+/// Converts wind speed in the fetched units to Beaufort scale (0-12)
+fn wind_speed_to_beaufort(wind_speed: f32, units: Units) -> u8 {
+    match units.to_mph(wind_speed) as i32 {
         0 => 0,
         1..=3 => 1,
         4..=7 => 2,
@@ -204,50 +297,58 @@ fn load_beaufort_icon_as_data_uri(beaufort: u8) -> Result<String, std::io::Error
     Ok(format!("data:image/svg+xml;base64,{}", encoded))
 }
 
-/// Fetches weather data from OpenWeatherMap One Call API (3.0)
-///
-/// # Arguments
-/// * `lat` - Latitude coordinate
-/// * `lon` - Longitude coordinate
-/// * `key` - OpenWeatherMap API key
-///
-/// # Returns
-/// Result containing WeatherData on success, or error message on failure
-pub async fn fetch_weather(
-    lat: &str,
-    lon: &str,
-    key: &str,
-) -> Result<WeatherData, Box<dyn std::error::Error>> {
-    let url = format!(
-        "https://api.openweathermap.org/data/3.0/onecall?lat={}&lon={}&units=imperial&exclude=minutely,hourly&appid={}",
-        lat, lon, key
-    );
-
-    let client = reqwest::Client::new();
-    let response = client.get(&url).send().await?;
-    let weather_data = response.json::<WeatherData>().await?;
-
-    Ok(weather_data)
-}
-
+/// Fetches the AI-written daily summary, which quotes temperatures in `units`
 pub async fn fetch_weather_overview(
+    upstream: &Upstream,
+    limit: &RateLimit,
     lat: &str,
     lon: &str,
     key: &str,
-) -> Result<WeatherOverviewData, Box<dyn std::error::Error>> {
+    units: Units,
+) -> Result<WeatherOverviewData, FetchError> {
     let url = format!(
-        "https://api.openweathermap.org/data/3.0/onecall/overview?lat={}&lon={}&units=imperial&appid={}",
-        lat, lon, key
+        "https://api.openweathermap.org/data/3.0/onecall/overview?lat={}&lon={}&units={}&appid={}",
+        lat,
+        lon,
+        units.api_units(),
+        key
     );
+    upstream.get_json(&url, limit).await
+}
 
-    let client = reqwest::Client::new();
-    let response = client.get(&url).send().await?;
-    let weather_overview_data = response.json::<WeatherOverviewData>().await?;
+/// When an alert is in effect, in whole hours, e.g. "3/5 10am - 10pm"
+pub fn alert_time_range(
+    alert: &WeatherAlert,
+    tz_offset: FixedOffset,
+    locale: Locale,
+) -> Option<String> {
+    let start_time = DateTime::from_timestamp(alert.start, 0)?.with_timezone(&tz_offset);
+    let mut end_time = DateTime::from_timestamp(alert.end, 0)?.with_timezone(&tz_offset);
+
+    // Round start down (use hour as-is), round end up (add hour if has minutes)
+    if end_time.minute() > 0 || end_time.second() > 0 {
+        end_time += chrono::Duration::hours(1);
+    }
 
-    Ok(weather_overview_data)
+    // The end date only when it differs, to leave room for 24-hour times
+    let end = if end_time.date_naive() == start_time.date_naive() {
+        locale.hour(end_time.hour())
+    } else {
+        format!(
+            "{} {}",
+            locale.short_date(&end_time),
+            locale.hour(end_time.hour())
+        )
+    };
+    Some(format!(
+        "{} {} - {}",
+        locale.short_date(&start_time),
+        locale.hour(start_time.hour()),
+        end
+    ))
 }
 
-fn wrap_text_lines(text: &str, max_chars: usize) -> Vec<String> {
+pub fn wrap_text_lines(text: &str, max_chars: usize) -> Vec<String> {
     let mut lines = Vec::new();
     let mut current = String::new();
 
@@ -274,19 +375,28 @@ fn wrap_text_lines(text: &str, max_chars: usize) -> Vec<String> {
     lines
 }
 
-fn escape_xml_text(text: &str) -> String {
-    text.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-}
-
 pub fn generate_weather_overview_svg(
     weather: &WeatherOverviewData,
     battery_pct: Option<u8>,
     geocoder: &ReverseGeocoder,
+    locale: Locale,
+    canvas: Canvas,
 ) -> String {
-    let mut svg = String::from(
-        r#"<svg xmlns="http://www.w3.org/2000/svg" width="800" height="480" viewBox="0 0 800 480">"#,
+    let (width, height) = (canvas.width, canvas.height);
+    let coords = (weather.lat as f64, weather.lon as f64);
+    let search_result = geocoder.search(coords);
+    if canvas.is_compact() {
+        let mut card = CompactCard::new(search_result.record.name.as_str(), battery_pct);
+        card.lines = wrap_text_lines(&weather.weather_overview, CompactCard::line_chars(canvas))
+            .into_iter()
+            .map(CardLine::new)
+            .collect();
+        return card.svg(canvas);
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
     );
     svg.push('\n');
 
@@ -297,37 +407,48 @@ pub fn generate_weather_overview_svg(
     svg.push_str(r#"  </defs>"#);
     svg.push('\n');
 
-    svg.push_str(r#"  <rect width="800" height="480" fill="white"/>"#);
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
     svg.push('\n');
 
-    let coords = (weather.lat as f64, weather.lon as f64);
-    let search_result = geocoder.search(coords);
+    // The API sends the date as YYYY-MM-DD
+    let date = NaiveDate::parse_from_str(&weather.date, "%Y-%m-%d")
+        .map(|date| locale.long_date(&date))
+        .unwrap_or_else(|_| weather.date.clone());
     svg.push_str(r#"  <text x="20" y="38" font-family="Arial" font-size="30" font-weight="bold" fill="black">Weather Overview</text>"#);
     svg.push('\n');
     svg.push_str(&format!(
         r#"  <text x="20" y="62" font-family="Arial" font-size="16" fill="black">{}, {} ({})</text>"#,
-        escape_xml_text(&search_result.record.name),
-        escape_xml_text(&weather.date),
-        escape_xml_text(&weather.tz)
+        svg_common::escape_xml_text(&search_result.record.name),
+        svg_common::escape_xml_text(&date),
+        svg_common::escape_xml_text(&weather.tz)
     ));
     svg.push('\n');
-    svg.push_str(r#"  <line x1="20" y1="76" x2="780" y2="76" stroke="black" stroke-width="1"/>"#);
+    svg.push_str(&format!(
+        r#"  <line x1="20" y1="76" x2="{}" y2="76" stroke="black" stroke-width="1"/>"#,
+        width - 20
+    ));
     svg.push('\n');
 
-    let lines = wrap_text_lines(&weather.weather_overview, 60);
+    // 60 characters and 14 lines fill the 800x480 layout
     let line_height = 26;
+    let max_chars = (60 * (width - 40) / 760) as usize;
+    let max_lines = ((height - 142) / line_height + 1) as usize;
+    let lines = wrap_text_lines(&weather.weather_overview, max_chars);
     let mut y = 108;
-    for line in lines.iter().take(14) {
+    for line in lines.iter().take(max_lines) {
         svg.push_str(&format!(
             r#"  <text x="30" y="{}" font-family="Arial" font-size="24" fill="black">{}</text>"#,
             y,
-            escape_xml_text(line)
+            svg_common::escape_xml_text(line)
         ));
         svg.push('\n');
         y += line_height;
     }
 
-    let footer_y = 470;
+    let footer_y = height - 10;
     let pct = battery_pct.unwrap_or(50);
     let battery_x = 75.0;
     let battery_y = footer_y as f64 - 10.0;
@@ -356,9 +477,10 @@ pub fn generate_weather_overview_svg(
         now.second()
     );
     svg.push_str(&format!(
-        r#"  <text x="790" y="{}" text-anchor="end" font-size="12" fill="black">{}</text>"#,
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">{}</text>"#,
+        width - 10,
         footer_y,
-        escape_xml_text(&timestamp)
+        svg_common::escape_xml_text(&timestamp)
     ));
     svg.push('\n');
 
@@ -371,27 +493,110 @@ pub fn generate_weather_overview_svg(
 /// # Arguments
 /// * `weather` - The weather data to display
 /// * `battery_pct` - Optional battery percentage to display
+/// * `geocoder` - Used to name the city at the forecast's coordinates
+/// * `units` - Units the data was fetched for
+/// * `locale` - Language for dates and the clock format for times
+/// * `canvas` - Size to lay out for
 ///
 /// # Returns
 /// A String containing the SVG markup
+/// Today's high and low under the condition icon, then the details and the coming days
+fn compact_weather_svg(
+    weather: &WeatherData,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let tz_offset =
+        chrono::FixedOffset::east_opt(weather.timezone_offset).unwrap_or_else(|| chrono::Utc.fix());
+    let local_date = |dt: i64| {
+        Utc.timestamp_opt(dt, 0)
+            .single()
+            .unwrap_or_else(Utc::now)
+            .with_timezone(&tz_offset)
+    };
+    let today = &weather.daily[0];
+    let city = &geocoder
+        .search((weather.lat as f64, weather.lon as f64))
+        .record
+        .name;
+
+    let mut card = CompactCard::new(
+        format!("{} · {}", locale.long_date(&local_date(today.dt)), city),
+        battery_pct,
+    );
+    card.headline = Some(CardLine::new(format!(
+        "{:.0}° / {:.0}°",
+        today.temp_max, today.temp_min
+    )));
+    let condition = display_condition_for_daily_weather(today.condition, today.pop, today.rain);
+    card.icon = load_weather_icon_as_data_uri(condition, false).ok();
+    card.lines
+        .push(CardLine::new("Feels like").with_value(format!(
+            "{:.0}° {:.0}° {:.0}°",
+            today.feels_like.morn, today.feels_like.day, today.feels_like.eve
+        )));
+    let rain = format!(
+        "{:.0}% · {}",
+        today.pop * 100.0,
+        units.format_rain(today.rain)
+    );
+    card.lines.push(if today.pop >= RAIN_ICON_POP_CUTOFF {
+        CardLine::new("Rain").with_value(rain).colored(Ink::Blue)
+    } else {
+        CardLine::new("Rain").with_value(rain)
+    });
+    card.lines
+        .push(CardLine::new("Wind").with_value(units.format_wind(today.wind_speed)));
+    card.lines
+        .push(CardLine::new("Humidity").with_value(format!("{}%", today.humidity)));
+    for day in weather.daily.iter().skip(1) {
+        card.lines.push(
+            CardLine::new(locale.weekday(local_date(day.dt).weekday())).with_value(format!(
+                "{:.0}° / {:.0}° · {:.0}%",
+                day.temp_max,
+                day.temp_min,
+                day.pop * 100.0
+            )),
+        );
+    }
+    card.svg(canvas)
+}
+
 pub fn generate_weather_svg(
     weather: &WeatherData,
     battery_pct: Option<u8>,
     geocoder: &ReverseGeocoder,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
 ) -> String {
+    let (width, height) = (canvas.width, canvas.height);
     if weather.daily.is_empty() {
-        return r#"<svg xmlns="http://www.w3.org/2000/svg" width="800" height="480">
-    <text x="400" y="240" text-anchor="middle" font-size="20">Error: weather.daily is empty</text>
-</svg>"#
-            .to_string();
+        return format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">
+    <text x="{}" y="{}" text-anchor="middle" font-size="20">Error: weather.daily is empty</text>
+</svg>"#,
+            width,
+            height,
+            width / 2,
+            height / 2
+        );
+    }
+
+    if canvas.is_compact() {
+        return compact_weather_svg(weather, battery_pct, geocoder, units, locale, canvas);
     }
 
     // Create timezone offset from the weather data
     let tz_offset =
         chrono::FixedOffset::east_opt(weather.timezone_offset).unwrap_or_else(|| chrono::Utc.fix());
 
-    let mut svg = String::from(
-        r#"<svg xmlns="http://www.w3.org/2000/svg" width="800" height="480" viewBox="0 0 800 480">"#,
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
     );
     svg.push('\n');
 
@@ -469,11 +674,14 @@ pub fn generate_weather_svg(
     svg.push('\n');
 
     // Background
-    svg.push_str(r#"  <rect width="800" height="480" fill="white"/>"#);
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
     svg.push('\n');
 
     // Left section: Today's detailed forecast (takes ~60% of width)
-    let left_width = 480.0;
+    let left_width = (width as f32 * 0.6).round();
     let today = &weather.daily[0];
 
     // Date header
@@ -484,7 +692,7 @@ pub fn generate_weather_svg(
         .with_timezone(&tz_offset);
     svg.push_str(&format!(
         r#"  <text x="20" y="35" font-family="Arial" font-size="28" font-weight="bold" fill="black">{}</text>"#,
-        today_time.format("%A, %b %e")
+        svg_common::escape_xml_text(&locale.long_date(&today_time))
     ));
     svg.push('\n');
 
@@ -493,21 +701,19 @@ pub fn generate_weather_svg(
     let search_result = geocoder.search(coords);
     svg.push_str(&format!(
         r#"  <text x="20" y="58" font-family="Arial" font-size="16" fill="black">{}</text>"#,
-        escape_xml_text(&search_result.record.name)
+        svg_common::escape_xml_text(&search_result.record.name)
     ));
     svg.push('\n');
 
     // Weather icon (large, centered in left section)
-    if let Some(w) = today.weather.first() {
-        // Embed weather icon as a data URI
-        let icon_code = display_icon_for_daily_weather(&w.icon, today.pop, today.rain);
-        if let Ok(data_uri) = load_weather_icon_as_data_uri(icon_code) {
-            svg.push_str(&format!(
-                r#"  <image x="350" y="2" width="80" height="80" href="{}"/>"#,
-                data_uri
-            ));
-            svg.push('\n');
-        }
+    // Embed weather icon as a data URI
+    let condition = display_condition_for_daily_weather(today.condition, today.pop, today.rain);
+    if let Ok(data_uri) = load_weather_icon_as_data_uri(condition, false) {
+        svg.push_str(&format!(
+            r#"  <image x="350" y="2" width="80" height="80" href="{}"/>"#,
+            data_uri
+        ));
+        svg.push('\n');
     }
 
     // Morning/Day/Eve temperatures in a row
@@ -521,7 +727,7 @@ pub fn generate_weather_svg(
     ));
     svg.push('\n');
 
-    let morn_bar = temperature_bar(today.feels_like.morn);
+    let morn_bar = temperature_bar(today.feels_like.morn, units);
     let bar_width = 100.0;
     let bar_height = 20.0;
     let bar_inset = 2.0; // Inset to avoid covering border
@@ -566,7 +772,7 @@ pub fn generate_weather_svg(
     ));
     svg.push('\n');
 
-    let day_bar = temperature_bar(today.feels_like.day);
+    let day_bar = temperature_bar(today.feels_like.day, units);
     let fill_width = (bar_width - bar_inset * 2.0) * (day_bar.fill_percent / 100.0);
 
     // Background (container) rectangle
@@ -608,7 +814,7 @@ pub fn generate_weather_svg(
     ));
     svg.push('\n');
 
-    let eve_bar = temperature_bar(today.feels_like.eve);
+    let eve_bar = temperature_bar(today.feels_like.eve, units);
     let fill_width = (bar_width - bar_inset * 2.0) * (eve_bar.fill_percent / 100.0);
 
     // Background (container) rectangle
@@ -651,7 +857,7 @@ pub fn generate_weather_svg(
     ));
     svg.push('\n');
 
-    let hum_bar = humidity_bar(today.humidity, today.temp.day);
+    let hum_bar = humidity_bar(today.humidity, today.temp_max);
     let hum_bar_width = 150.0;
     let hum_fill_width = (hum_bar_width - bar_inset * 2.0) * (hum_bar.fill_percent / 100.0);
 
@@ -694,7 +900,8 @@ pub fn generate_weather_svg(
     ));
     svg.push('\n');
 
-    let wind_bar = wind_bar(today.wind_gust.unwrap_or(today.wind_speed));
+    let wind_speed = today.wind_gust.unwrap_or(today.wind_speed);
+    let wind_bar = wind_bar(wind_speed, units);
     let wind_fill_width = (hum_bar_width - bar_inset * 2.0) * (wind_bar.fill_percent / 100.0);
 
     // Background rectangle
@@ -729,20 +936,15 @@ pub fn generate_weather_svg(
         wind_clip_id
     ));
     svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="18" fill="black">{}</text>"#,
+        180.0 + hum_bar_width,
+        detail_y + 35.0,
+        units.format_wind(wind_speed)
+    ));
+    svg.push('\n');
 
-    // Sunrise/sunset times (calculated once, used conditionally below)
-    let sunrise_time = Utc
-        .timestamp_opt(today.sunrise, 0)
-        .single()
-        .unwrap_or_else(Utc::now)
-        .with_timezone(&tz_offset);
-    let sunset_time = Utc
-        .timestamp_opt(today.sunset, 0)
-        .single()
-        .unwrap_or_else(Utc::now)
-        .with_timezone(&tz_offset);
-
-    // Always show cloudiness/UVI/sunrise/sunset, plus warning titles if present
+    // Always show cloudiness/UVI/rain, plus sunrise/sunset and warning titles if present
 
     // Cloudiness bar
     svg.push_str(&format!(
@@ -832,77 +1034,49 @@ pub fn generate_weather_svg(
     ));
     svg.push('\n');
 
-    // Sunrise text (moved down)
+    // Expected rain and its chance
     svg.push_str(&format!(
-        r#"  <text x="40" y="{}" font-family="Arial" font-size="20" fill="black">Sunrise: {}</text>"#,
-        detail_y + 160.0, sunrise_time.format("%l:%M %P")
+        r#"  <text x="40" y="{}" font-family="Arial" font-size="20" fill="black">Rain: {} ({:.0}%)</text>"#,
+        detail_y + 135.0,
+        units.format_rain(today.rain),
+        today.pop * 100.0
     ));
     svg.push('\n');
 
-    // Sunset text (moved down)
-    svg.push_str(&format!(
-        r#"  <text x="280" y="{}" font-family="Arial" font-size="20" fill="black">Sunset: {}</text>"#,
-        detail_y + 160.0, sunset_time.format("%l:%M %P")
-    ));
-    svg.push('\n');
+    // Sunrise/sunset, when the provider has them
+    let local_time = |timestamp: Option<i64>| {
+        timestamp
+            .and_then(|t| Utc.timestamp_opt(t, 0).single())
+            .map(|t| locale.time(&t.with_timezone(&tz_offset)))
+    };
+    if let Some(sunrise) = local_time(today.sunrise) {
+        svg.push_str(&format!(
+            r#"  <text x="40" y="{}" font-family="Arial" font-size="20" fill="black">Sunrise: {}</text>"#,
+            detail_y + 165.0, sunrise
+        ));
+        svg.push('\n');
+    }
+    if let Some(sunset) = local_time(today.sunset) {
+        svg.push_str(&format!(
+            r#"  <text x="280" y="{}" font-family="Arial" font-size="20" fill="black">Sunset: {}</text>"#,
+            detail_y + 165.0, sunset
+        ));
+        svg.push('\n');
+    }
 
     // Weather warning titles (if any)
     if !weather.alerts.is_empty() {
-        let mut alert_y = detail_y + 190.0;
+        let mut alert_y = detail_y + 195.0;
         for alert in &weather.alerts {
-            let (Some(start_utc), Some(end_utc)) = (
-                chrono::DateTime::from_timestamp(alert.start, 0),
-                chrono::DateTime::from_timestamp(alert.end, 0),
-            ) else {
+            let Some(time_range) = alert_time_range(alert, tz_offset, locale) else {
                 continue;
             };
-            let start_time = start_utc.with_timezone(&tz_offset);
-            let mut end_time = end_utc.with_timezone(&tz_offset);
-
-            // Round start down (use hour as-is), round end up (add hour if has minutes)
-            let start_hour = start_time.hour();
-            if end_time.minute() > 0 || end_time.second() > 0 {
-                end_time += chrono::Duration::hours(1);
-            }
-            let end_hour = end_time.hour();
-
-            // Convert to 12-hour format with am/pm
-            let (start_12h, start_ampm) = if start_hour == 0 {
-                (12, "am")
-            } else if start_hour < 12 {
-                (start_hour, "am")
-            } else if start_hour == 12 {
-                (12, "pm")
-            } else {
-                (start_hour - 12, "pm")
-            };
-
-            let (end_12h, end_ampm) = if end_hour == 0 {
-                (12, "am")
-            } else if end_hour < 12 {
-                (end_hour, "am")
-            } else if end_hour == 12 {
-                (12, "pm")
-            } else {
-                (end_hour - 12, "pm")
-            };
-
-            let time_range = format!(
-                "({}/{} {}{} - {}/{} {}{})",
-                start_time.month(),
-                start_time.day(),
-                start_12h,
-                start_ampm,
-                end_time.month(),
-                end_time.day(),
-                end_12h,
-                end_ampm
-            );
+            let time_range = format!("({})", time_range);
 
             // Event name in bold red
             svg.push_str(&format!(
                 r#"  <text x="40" y="{}" font-family="Arial" font-size="16" font-weight="bold" fill="red">{}</text>"#,
-                alert_y, escape_xml_text(&alert.event)
+                alert_y, svg_common::escape_xml_text(&alert.event)
             ));
             svg.push('\n');
 
@@ -918,8 +1092,10 @@ pub fn generate_weather_svg(
 
     // Vertical divider
     svg.push_str(&format!(
-        r#"  <line x1="{}" y1="20" x2="{}" y2="460" stroke="black" stroke-width="2"/>"#,
-        left_width, left_width
+        r#"  <line x1="{}" y1="20" x2="{}" y2="{}" stroke="black" stroke-width="2"/>"#,
+        left_width,
+        left_width,
+        height - 20
     ));
     svg.push('\n');
 
@@ -932,7 +1108,8 @@ pub fn generate_weather_svg(
 
     let right_x = left_width + 20.0;
     let forecast_start_y = 70.0;
-    let row_height = 80.0;
+    // 80 on the 800x480 layout; taller panels spread the rows out
+    let row_height = (height as f32 - 80.0) / 5.0;
 
     for (idx, day) in weather.daily.iter().skip(1).take(5).enumerate() {
         let y = forecast_start_y + (idx as f32 * row_height);
@@ -942,15 +1119,7 @@ pub fn generate_weather_svg(
             .single()
             .unwrap_or_else(Utc::now)
             .with_timezone(&tz_offset);
-        let day_name = [
-            "Monday",
-            "Tuesday",
-            "Wednesday",
-            "Thursday",
-            "Friday",
-            "Saturday",
-            "Sunday",
-        ][day_time.weekday() as usize];
+        let day_name = locale.weekday(day_time.weekday());
 
         // Day name and date
         svg.push_str(&format!(
@@ -960,24 +1129,22 @@ pub fn generate_weather_svg(
         svg.push('\n');
 
         // Weather icon (small)
-        if let Some(w) = day.weather.first() {
-            // Embed small weather icon as a data URI
-            let icon_code = display_icon_for_daily_weather(&w.icon, day.pop, day.rain);
-            if let Ok(data_uri) = load_weather_icon_as_data_uri(icon_code) {
-                svg.push_str(&format!(
-                    r#"  <image x="{}" y="{}" width="80" height="80" href="{}"/>"#,
-                    right_x + 150.0,
-                    y - 20.0,
-                    data_uri
-                ));
-                svg.push('\n');
-            }
+        // Embed small weather icon as a data URI
+        let condition = display_condition_for_daily_weather(day.condition, day.pop, day.rain);
+        if let Ok(data_uri) = load_weather_icon_as_data_uri(condition, false) {
+            svg.push_str(&format!(
+                r#"  <image x="{}" y="{}" width="80" height="80" href="{}"/>"#,
+                right_x + 150.0,
+                y - 20.0,
+                data_uri
+            ));
+            svg.push('\n');
         }
 
         // Temperature bar indicator
         // Calculate positions for min and max temperatures on the bar
-        let min_bar = temperature_bar(day.temp.min);
-        let max_bar = temperature_bar(day.temp.max);
+        let min_bar = temperature_bar(day.temp_min, units);
+        let max_bar = temperature_bar(day.temp_max, units);
         let forecast_bar_width = 125.0; // 5 pixels longer
         let forecast_bar_height = 16.0;
         let forecast_inset = 2.0;
@@ -1033,7 +1200,7 @@ pub fn generate_weather_svg(
         svg.push('\n');
 
         // Wind indicator (Beaufort scale icon, color-coded by danger level)
-        let beaufort = wind_speed_to_beaufort(day.wind_gust.unwrap_or(day.wind_speed));
+        let beaufort = wind_speed_to_beaufort(day.wind_gust.unwrap_or(day.wind_speed), units);
         if let Ok(beaufort_icon_uri) = load_beaufort_icon_as_data_uri(beaufort) {
             svg.push_str(&format!(
                 r#"  <image x="{}" y="{}" width="80" height="80" href="{}"/>"#,
@@ -1046,7 +1213,7 @@ pub fn generate_weather_svg(
     }
 
     // Footer with battery and last updated
-    let footer_y = 470;
+    let footer_y = height - 10;
 
     // Battery bar (if provided) - now on the left
     let pct = battery_pct.unwrap_or(50);
@@ -1080,8 +1247,10 @@ pub fn generate_weather_svg(
         now.second()
     );
     svg.push_str(&format!(
-        r#"  <text x="790" y="{}" text-anchor="end" font-size="12" fill="black">{}</text>"#,
-        footer_y, timestamp
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">{}</text>"#,
+        width - 10,
+        footer_y,
+        timestamp
     ));
     svg.push('\n');
 
@@ -1091,18 +1260,35 @@ pub fn generate_weather_svg(
 
 #[cfg(test)]
 mod tests {
-    use super::display_icon_for_daily_weather;
+    use super::{
+        display_condition_for_daily_weather, temperature_bar, wind_speed_to_beaufort, Condition,
+    };
+    use crate::locale::Units;
+
+    #[test]
+    fn bars_and_beaufort_scale_the_same_in_every_unit() {
+        let fill = |temp, units| temperature_bar(temp, units).fill_percent;
+        assert_eq!(fill(66.0, Units::Imperial), 50.0);
+        assert!((fill(18.89, Units::Metric) - 50.0).abs() < 0.1);
+        assert_eq!(fill(-5.0, Units::Uk), 0.0);
+
+        // 20 mph and 9 m/s are both a fresh breeze
+        assert_eq!(wind_speed_to_beaufort(20.0, Units::Imperial), 5);
+        assert_eq!(wind_speed_to_beaufort(9.0, Units::Metric), 5);
+        assert_eq!(wind_speed_to_beaufort(9.0, Units::Uk), 5);
+        assert_eq!(wind_speed_to_beaufort(0.2, Units::Metric), 0);
+    }
 
     #[test]
     fn low_pop_or_low_rain_rain_icons_are_replaced_with_clouds() {
-        assert_eq!(display_icon_for_daily_weather("09d", 0.24, 1.0), "03d");
-        assert_eq!(display_icon_for_daily_weather("09n", 0.24, 1.0), "03n");
-        assert_eq!(display_icon_for_daily_weather("10d", 0.24, 1.0), "04d");
-        assert_eq!(display_icon_for_daily_weather("10n", 0.24, 1.0), "04n");
+        let display = display_condition_for_daily_weather;
+        assert_eq!(display(Condition::Showers, 0.24, 1.0), Condition::Cloudy);
+        assert_eq!(display(Condition::Rain, 0.24, 1.0), Condition::Overcast);
         // pop >= cutoff, rain below 2.54mm cutoff → replace with clouds
-        assert_eq!(display_icon_for_daily_weather("10d", 0.25, 2.53), "04d");
+        assert_eq!(display(Condition::Rain, 0.25, 2.53), Condition::Overcast);
         // pop >= cutoff, rain at/above 2.54mm cutoff → keep rain icon
-        assert_eq!(display_icon_for_daily_weather("10d", 0.25, 2.54), "10d");
-        assert_eq!(display_icon_for_daily_weather("01d", 0.10, 0.0), "01d");
+        assert_eq!(display(Condition::Rain, 0.25, 2.54), Condition::Rain);
+        assert_eq!(display(Condition::Clear, 0.10, 0.0), Condition::Clear);
+        assert_eq!(display(Condition::Snow, 0.10, 0.0), Condition::Snow);
     }
 }
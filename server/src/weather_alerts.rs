@@ -0,0 +1,435 @@
+//! Every current weather alert in full: event, issuing office, time range, the wrapped
+//! description and tags, colored red for severe alerts and yellow for the rest. Alerts
+//! that do not fit continue on further pages.
+
+use crate::locale::Locale;
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use crate::weather::{alert_time_range, wrap_text_lines, Severity, WeatherAlert, WeatherData};
+use chrono::prelude::*;
+use reverse_geocoder::ReverseGeocoder;
+
+const CONTENT_TOP: f64 = 80.0;
+/// Average glyph width of the description font, for wrapping
+const CHAR_WIDTH: f64 = 8.0;
+
+/// One row of the alert list
+enum Line<'a> {
+    /// The colored bar with the event name, repeated when an alert spans pages
+    Heading {
+        alert: &'a WeatherAlert,
+        continued: bool,
+    },
+    /// Sender and severity, or the tags
+    Detail(String),
+    Text(String),
+    Gap,
+}
+
+impl Line<'_> {
+    fn height(&self) -> f64 {
+        match self {
+            Line::Heading { .. } => 34.0,
+            Line::Detail(_) => 20.0,
+            Line::Text(_) => 19.0,
+            Line::Gap => 12.0,
+        }
+    }
+}
+
+/// Whether a severe or extreme alert is in effect at `now`
+pub fn severe_alert_active(alerts: &[WeatherAlert], now: i64) -> bool {
+    alerts
+        .iter()
+        .any(|alert| alert.severity >= Severity::Severe && alert.start <= now && now < alert.end)
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Minor => "Minor",
+        Severity::Moderate => "Moderate",
+        Severity::Severe => "Severe",
+        Severity::Extreme => "Extreme",
+    }
+}
+
+/// Current and upcoming alerts at `now`, the most severe first
+fn listed_alerts(alerts: &[WeatherAlert], now: i64) -> Vec<&WeatherAlert> {
+    let mut alerts: Vec<&WeatherAlert> = alerts.iter().filter(|a| a.end > now).collect();
+    alerts.sort_by_key(|alert| (std::cmp::Reverse(alert.severity), alert.start));
+    alerts
+}
+
+/// The alert list split into pages for a full-size `canvas`
+fn canvas_pages<'a>(alerts: &[&'a WeatherAlert], canvas: Canvas) -> Vec<Vec<Line<'a>>> {
+    let wrap_chars = ((canvas.width as f64 - 50.0) / CHAR_WIDTH) as usize;
+    paginate(
+        alerts,
+        wrap_chars,
+        canvas.height as f64 - CONTENT_TOP - 30.0,
+    )
+}
+
+/// Number of pages the alerts at `now` take on `canvas`, at least one
+pub fn alert_pages(alerts: &[WeatherAlert], canvas: Canvas, now: i64) -> usize {
+    let alerts = listed_alerts(alerts, now);
+    if canvas.is_compact() {
+        alerts.len().max(1)
+    } else {
+        canvas_pages(&alerts, canvas).len()
+    }
+}
+
+/// Splits the alerts into pages of at most `page_height`, most severe first
+fn paginate<'a>(
+    alerts: &[&'a WeatherAlert],
+    wrap_chars: usize,
+    page_height: f64,
+) -> Vec<Vec<Line<'a>>> {
+    let mut pages = vec![Vec::new()];
+    let mut used = 0.0;
+    for &alert in alerts {
+        let mut lines = vec![
+            Line::Heading {
+                alert,
+                continued: false,
+            },
+            Line::Detail(if alert.sender.is_empty() {
+                format!("Severity: {}", severity_name(alert.severity))
+            } else {
+                format!(
+                    "{} · Severity: {}",
+                    alert.sender,
+                    severity_name(alert.severity)
+                )
+            }),
+        ];
+        lines.extend(
+            wrap_text_lines(&alert.description, wrap_chars)
+                .into_iter()
+                .map(Line::Text),
+        );
+        if !alert.tags.is_empty() {
+            lines.push(Line::Detail(format!("Tags: {}", alert.tags.join(", "))));
+        }
+        lines.push(Line::Gap);
+
+        for line in lines {
+            // Keep a heading with at least two lines under it
+            let needed = match line {
+                Line::Heading { .. } => line.height() + 40.0,
+                _ => line.height(),
+            };
+            let page = pages.last_mut().expect("at least one page");
+            if used + needed > page_height && !page.is_empty() {
+                if matches!(line, Line::Gap) {
+                    continue;
+                }
+                pages.push(Vec::new());
+                used = 0.0;
+                if !matches!(line, Line::Heading { .. }) {
+                    let heading = Line::Heading {
+                        alert,
+                        continued: true,
+                    };
+                    used += heading.height();
+                    pages.last_mut().expect("just pushed").push(heading);
+                }
+            }
+            used += line.height();
+            pages.last_mut().expect("at least one page").push(line);
+        }
+    }
+    pages
+}
+
+/// One alert per page: the event as the headline, then its times, severity and as
+/// much of the description as fits
+fn compact_alerts_svg(
+    alerts: &[&WeatherAlert],
+    city: &str,
+    battery_pct: Option<u8>,
+    tz_offset: FixedOffset,
+    locale: Locale,
+    canvas: Canvas,
+    page: usize,
+) -> String {
+    let Some(alert) = alerts.get(page % alerts.len().max(1)) else {
+        let mut card = CompactCard::new(format!("Alerts · {}", city), battery_pct);
+        card.lines
+            .push(CardLine::new("No weather alerts in effect"));
+        return card.svg(canvas);
+    };
+    let title = if alerts.len() > 1 {
+        format!(
+            "Alert {}/{} · {}",
+            page % alerts.len() + 1,
+            alerts.len(),
+            city
+        )
+    } else {
+        format!("Alert · {}", city)
+    };
+    let mut card = CompactCard::new(title, battery_pct);
+    let ink = if alert.severity >= Severity::Severe {
+        Ink::Red
+    } else {
+        Ink::Black
+    };
+    card.headline = Some(CardLine::new(alert.event.as_str()).colored(ink));
+    if let Some(time_range) = alert_time_range(alert, tz_offset, locale) {
+        card.lines.push(CardLine::new(time_range));
+    }
+    card.lines.push(CardLine::new(format!(
+        "Severity: {}",
+        severity_name(alert.severity)
+    )));
+    card.lines.extend(
+        wrap_text_lines(&alert.description, CompactCard::line_chars(canvas))
+            .into_iter()
+            .map(CardLine::new),
+    );
+    // Mark a description that runs past the bottom
+    let rows = CompactCard::rows(canvas, true);
+    if card.lines.len() > rows {
+        card.lines.truncate(rows);
+        if let Some(last) = card.lines.last_mut() {
+            last.text.push_str("...");
+        }
+    }
+    card.svg(canvas)
+}
+
+/// Generates an SVG of the current alerts
+///
+/// # Arguments
+/// * `weather` - Forecast with its alerts
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `geocoder` - Reverse geocoder for the city name
+/// * `locale` - Date and clock format for the time ranges
+/// * `canvas` - Size to lay out for
+/// * `page` - Zero-based page, wrapping around past the last
+///
+/// # Returns
+/// A String containing the SVG markup
+pub fn generate_weather_alerts_svg(
+    weather: &WeatherData,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    locale: Locale,
+    canvas: Canvas,
+    page: usize,
+) -> String {
+    let (width, height) = (canvas.width, canvas.height);
+    let now = Utc::now().timestamp();
+    let tz_offset =
+        chrono::FixedOffset::east_opt(weather.timezone_offset).unwrap_or_else(|| chrono::Utc.fix());
+    let coords = (weather.lat as f64, weather.lon as f64);
+
+    let alerts = listed_alerts(&weather.alerts, now);
+
+    if canvas.is_compact() {
+        return compact_alerts_svg(
+            &alerts,
+            &geocoder.search(coords).record.name,
+            battery_pct,
+            tz_offset,
+            locale,
+            canvas,
+            page,
+        );
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    );
+    svg.push('\n');
+    svg.push_str("  <defs>");
+    svg.push_str(svg_common::BATTERY_GRADIENT_DEF);
+    svg.push_str("</defs>\n");
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+    svg.push('\n');
+
+    svg.push_str(
+        r#"  <text x="20" y="35" font-family="Arial" font-size="28" font-weight="bold" fill="black">Weather Alerts</text>"#,
+    );
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <text x="20" y="58" font-family="Arial" font-size="16" fill="black">{}</text>"#,
+        svg_common::escape_xml_text(&geocoder.search(coords).record.name)
+    ));
+    svg.push('\n');
+
+    if alerts.is_empty() {
+        svg.push_str(&format!(
+            r#"  <text x="{}" y="{}" text-anchor="middle" font-family="Arial" font-size="22" fill="black">No weather alerts in effect</text>"#,
+            width / 2,
+            height / 2
+        ));
+        svg.push('\n');
+    } else {
+        let pages = canvas_pages(&alerts, canvas);
+        let page = page % pages.len();
+        if pages.len() > 1 {
+            svg.push_str(&format!(
+                r#"  <text x="{}" y="35" text-anchor="end" font-family="Arial" font-size="16" fill="black">Page {} of {}</text>"#,
+                width - 20,
+                page + 1,
+                pages.len()
+            ));
+            svg.push('\n');
+        }
+
+        let mut y = CONTENT_TOP;
+        for line in &pages[page] {
+            match line {
+                Line::Heading { alert, continued } => {
+                    let (fill, text_fill) = if alert.severity >= Severity::Severe {
+                        ("red", "white")
+                    } else {
+                        ("yellow", "black")
+                    };
+                    svg.push_str(&format!(
+                        r#"  <rect x="15" y="{}" width="{}" height="28" fill="{}" stroke="black" stroke-width="1"/>"#,
+                        y,
+                        width - 30,
+                        fill
+                    ));
+                    svg.push_str(&format!(
+                        r#"<text x="25" y="{}" font-family="Arial" font-size="18" font-weight="bold" fill="{}">{}{}</text>"#,
+                        y + 20.0,
+                        text_fill,
+                        svg_common::escape_xml_text(&alert.event),
+                        if *continued { " (continued)" } else { "" }
+                    ));
+                    if let Some(time_range) = alert_time_range(alert, tz_offset, locale) {
+                        svg.push_str(&format!(
+                            r#"<text x="{}" y="{}" text-anchor="end" font-family="Arial" font-size="16" fill="{}">{}</text>"#,
+                            width - 25,
+                            y + 20.0,
+                            text_fill,
+                            time_range
+                        ));
+                    }
+                }
+                Line::Detail(text) => {
+                    svg.push_str(&format!(
+                        r#"  <text x="25" y="{}" font-family="Arial" font-size="13" font-style="italic" fill="black">{}</text>"#,
+                        y + 14.0,
+                        svg_common::escape_xml_text(text)
+                    ));
+                }
+                Line::Text(text) => {
+                    svg.push_str(&format!(
+                        r#"  <text x="25" y="{}" font-family="Arial" font-size="15" fill="black">{}</text>"#,
+                        y + 14.0,
+                        svg_common::escape_xml_text(text)
+                    ));
+                }
+                Line::Gap => {}
+            }
+            svg.push('\n');
+            y += line.height();
+        }
+    }
+
+    // Footer with battery and last updated
+    let footer_y = height - 10;
+    let pct = battery_pct.unwrap_or(50);
+    svg.push_str(&svg_common::battery_label_svg(
+        10.0,
+        footer_y as f64,
+        "start",
+        12,
+    ));
+    svg.push_str(&svg_common::battery_bar_svg(
+        75.0,
+        footer_y as f64 - 10.0,
+        pct,
+        2.0,
+        "batteryClip",
+    ));
+    svg.push('\n');
+    let updated = Local::now();
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">Last updated: {:02}:{:02}:{:02}</text>"#,
+        width - 10,
+        footer_y,
+        updated.hour(),
+        updated.minute(),
+        updated.second()
+    ));
+    svg.push('\n');
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alert_pages, paginate, severe_alert_active, Line};
+    use crate::svg_common::Canvas;
+    use crate::weather::{Severity, WeatherAlert};
+
+    fn alert(event: &str, words: usize) -> WeatherAlert {
+        WeatherAlert {
+            event: event.to_string(),
+            sender: "NWS San Francisco CA".to_string(),
+            description: vec!["wind"; words].join(" "),
+            severity: Severity::from_event(event),
+            tags: Vec::new(),
+            start: 1000,
+            end: 5000,
+        }
+    }
+
+    #[test]
+    fn long_alerts_continue_on_the_next_page() {
+        // 20 words of 4 letters wrap to 4 lines of 25 characters
+        let short = alert("Wind Advisory", 20);
+        let long = alert("High Wind Warning", 100);
+        let pages = paginate(&[&short, &long], 25, 400.0);
+        assert_eq!(pages.len(), 2);
+        let headings: Vec<(&str, bool)> = pages
+            .iter()
+            .flatten()
+            .filter_map(|line| match line {
+                Line::Heading { alert, continued } => Some((alert.event.as_str(), *continued)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            headings,
+            [
+                ("Wind Advisory", false),
+                ("High Wind Warning", false),
+                ("High Wind Warning", true)
+            ]
+        );
+        for page in &pages {
+            assert!(page.iter().map(Line::height).sum::<f64>() <= 400.0);
+        }
+
+        assert!(severe_alert_active(&[long], 2000));
+        assert!(!severe_alert_active(&[short], 2000));
+        assert!(!severe_alert_active(&[alert("Tornado Warning", 1)], 6000));
+    }
+
+    #[test]
+    fn page_count_matches_the_layout() {
+        let alerts = [alert("Wind Advisory", 20), alert("High Wind Warning", 600)];
+        let mono_4in2 = Canvas {
+            width: 400,
+            height: 300,
+        };
+        // One card per alert on a small panel; the long one continues on a full one
+        assert_eq!(alert_pages(&alerts, mono_4in2, 2000), 2);
+        assert_eq!(alert_pages(&alerts, Canvas::default(), 2000), 3);
+        // Expired alerts take no pages, but there's always the empty one
+        assert_eq!(alert_pages(&alerts, mono_4in2, 6000), 1);
+        assert_eq!(alert_pages(&alerts, Canvas::default(), 6000), 1);
+    }
+}
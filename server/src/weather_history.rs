@@ -0,0 +1,923 @@
+//! Daily weather history and the comparison screen built on it. Each forecast fetched
+//! records today's high, low and rain for its location in a JSON-lines file under the
+//! state directory; the last fetch of the day wins, so by evening the record is close
+//! to what was observed. Years of history can be imported from a NOAA daily summaries
+//! CSV (`--import-history`) to start with.
+//!
+//! The screen sets today's forecast against the location's climate normals (a NOAA
+//! daily normals CSV), the same day last year, the records for the date and the rain
+//! so far this month.
+
+use crate::json_lines::{JsonLinesStore, LogRecord};
+use crate::locale::{Locale, Units};
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use crate::weather::WeatherData;
+use chrono::prelude::*;
+use chrono::Duration;
+use reverse_geocoder::ReverseGeocoder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Days of history charted along the bottom, today included
+const CHART_DAYS: i64 = 30;
+const MM_PER_INCH: f32 = 25.4;
+
+/// One day at one location
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyObservation {
+    /// See `location_key`
+    pub location: String,
+    pub date: NaiveDate,
+    /// °F
+    pub high: f32,
+    /// °F
+    pub low: f32,
+    /// mm; imported history can lack it
+    pub precip: Option<f32>,
+}
+
+/// Coordinates rounded to about a kilometre, so that small differences between
+/// screens for the same place share a history
+pub fn location_key(lat: f64, lon: f64) -> String {
+    format!("{:.2},{:.2}", lat, lon)
+}
+
+/// Today's high, low and rain from a forecast, in the store's units
+pub fn forecast_observation(
+    weather: &WeatherData,
+    location: &str,
+    units: Units,
+) -> Option<DailyObservation> {
+    let today = weather.daily.first()?;
+    let tz_offset = FixedOffset::east_opt(weather.timezone_offset)?;
+    Some(DailyObservation {
+        location: location.to_string(),
+        date: DateTime::from_timestamp(today.dt, 0)?
+            .with_timezone(&tz_offset)
+            .date_naive(),
+        high: units.to_fahrenheit(today.temp_max),
+        low: units.to_fahrenheit(today.temp_min),
+        precip: Some(today.rain),
+    })
+}
+
+impl LogRecord for DailyObservation {
+    /// History is kept for good
+    fn keep(&self, _now: DateTime<Utc>) -> bool {
+        true
+    }
+}
+
+fn day_key(day: &DailyObservation) -> (&str, NaiveDate) {
+    (&day.location, day.date)
+}
+
+/// Adds `day` in order of location and date, replacing what was recorded for it before.
+/// False if it was already recorded as it is.
+fn insert_day(days: &mut Vec<DailyObservation>, day: DailyObservation) -> bool {
+    match days.binary_search_by(|d| day_key(d).cmp(&day_key(&day))) {
+        Ok(i) if days[i] == day => false,
+        Ok(i) => {
+            days[i] = day;
+            true
+        }
+        Err(i) => {
+            days.insert(i, day);
+            true
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ObservationStore {
+    log: JsonLinesStore<DailyObservation>,
+}
+
+impl ObservationStore {
+    /// Store backed by a JSON-lines file. A day recorded more than once keeps its last
+    /// line, and the file is rewritten without the earlier ones.
+    pub fn persistent(path: impl Into<PathBuf>) -> Self {
+        let log = JsonLinesStore::persistent(path);
+        let replaced = {
+            let mut days = log.records();
+            let count = days.len();
+            // A stable sort of the lines in reverse puts each day's last line first
+            days.reverse();
+            days.sort_by(|a, b| day_key(a).cmp(&day_key(b)));
+            days.dedup_by(|a, b| day_key(a) == day_key(b));
+            days.len() < count
+        };
+        if replaced {
+            log.rewrite();
+        }
+        Self { log }
+    }
+
+    /// Records a day, replacing what was recorded for it before. Unchanged days are not
+    /// written again, so this can run on every request.
+    pub fn record(&self, day: DailyObservation) {
+        self.log.record(day, insert_day);
+    }
+
+    /// Adds imported days, replacing any recorded for the same dates
+    pub fn import(&self, imported: Vec<DailyObservation>) -> usize {
+        let count = imported.len();
+        {
+            let mut days = self.log.records();
+            for day in imported {
+                insert_day(&mut days, day);
+            }
+        }
+        self.log.rewrite();
+        count
+    }
+
+    /// Every day recorded for a location, oldest first
+    pub fn history(&self, location: &str) -> Vec<DailyObservation> {
+        self.log
+            .records()
+            .iter()
+            .filter(|day| day.location == location)
+            .cloned()
+            .collect()
+    }
+}
+
+/// A row of a NOAA daily summaries CSV (Climate Data Online), or a plain
+/// `date,high,low,precip` file
+#[derive(Debug, Deserialize)]
+struct HistoryRecord {
+    #[serde(rename = "DATE", alias = "date")]
+    date: String,
+    #[serde(rename = "TMAX", alias = "high")]
+    high: Option<f32>,
+    #[serde(rename = "TMIN", alias = "low")]
+    low: Option<f32>,
+    #[serde(rename = "PRCP", alias = "precip")]
+    precip: Option<f32>,
+}
+
+/// Reads daily history for `location`, in °F and inches for imperial `units` (NOAA's
+/// standard units) or °C and mm otherwise. Days without both a high and a low are
+/// skipped.
+pub fn read_history_csv(
+    path: &Path,
+    location: &str,
+    units: Units,
+) -> Result<Vec<DailyObservation>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file);
+    let mut days = Vec::new();
+    for result in rdr.deserialize() {
+        let record: HistoryRecord = result?;
+        let (Some(high), Some(low)) = (record.high, record.low) else {
+            continue;
+        };
+        let date = NaiveDate::parse_from_str(&record.date, "%Y-%m-%d")
+            .map_err(|e| format!("Bad date {}: {}", record.date, e))?;
+        days.push(DailyObservation {
+            location: location.to_string(),
+            date,
+            high: units.to_fahrenheit(high),
+            low: units.to_fahrenheit(low),
+            precip: record.precip.map(|precip| match units {
+                Units::Imperial => precip * MM_PER_INCH,
+                Units::Metric | Units::Uk => precip,
+            }),
+        });
+    }
+    Ok(days)
+}
+
+/// A row of NOAA's 1991–2020 daily normals CSV, or a plain
+/// `date,high,low,precip_mtd` file with `MM-DD` dates
+#[derive(Debug, Deserialize)]
+struct NormalsRecord {
+    #[serde(rename = "DATE", alias = "date")]
+    date: String,
+    #[serde(rename = "DLY-TMAX-NORMAL", alias = "high")]
+    high: Option<f32>,
+    #[serde(rename = "DLY-TMIN-NORMAL", alias = "low")]
+    low: Option<f32>,
+    #[serde(rename = "MTD-PRCP-NORMAL", alias = "precip_mtd")]
+    precip_mtd: Option<f32>,
+}
+
+/// The normals for one calendar day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normal {
+    /// °F
+    pub high: f32,
+    /// °F
+    pub low: f32,
+    /// Rain from the 1st of the month through this day, mm
+    pub precip_mtd: Option<f32>,
+}
+
+/// Climate normals by month and day
+#[derive(Debug, Default)]
+pub struct Normals {
+    days: HashMap<(u32, u32), Normal>,
+}
+
+impl Normals {
+    /// Reads a normals CSV in °F and inches. NOAA marks missing values with -9999 and
+    /// trace precipitation with -7777.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(file);
+        let mut days = HashMap::new();
+        for result in rdr.deserialize() {
+            let record: NormalsRecord = result?;
+            let present = |value: Option<f32>| value.filter(|&v| v > -7000.0);
+            let (Some(high), Some(low)) = (present(record.high), present(record.low)) else {
+                continue;
+            };
+            let (month, day) = record
+                .date
+                .split_once('-')
+                .and_then(|(month, day)| Some((month.parse().ok()?, day.parse().ok()?)))
+                .ok_or_else(|| format!("Bad normals date {}", record.date))?;
+            let precip_mtd = record.precip_mtd.and_then(|precip| {
+                if precip == -7777.0 {
+                    Some(0.0)
+                } else {
+                    (precip >= 0.0).then_some(precip * MM_PER_INCH)
+                }
+            });
+            days.insert(
+                (month, day),
+                Normal {
+                    high,
+                    low,
+                    precip_mtd,
+                },
+            );
+        }
+        Ok(Self { days })
+    }
+
+    /// February 29th falls back to the 28th where the file has no leap day
+    pub fn get(&self, date: NaiveDate) -> Option<Normal> {
+        self.days
+            .get(&(date.month(), date.day()))
+            .or_else(|| {
+                (date.month() == 2 && date.day() == 29)
+                    .then(|| self.days.get(&(2, 28)))
+                    .flatten()
+            })
+            .copied()
+    }
+}
+
+/// Today set against the history and normals
+#[derive(Debug, PartialEq)]
+struct Comparison {
+    normal: Option<Normal>,
+    last_year: Option<DailyObservation>,
+    /// Highest high and its year, from earlier years
+    record_high: Option<(f32, i32)>,
+    /// Lowest low and its year, from earlier years
+    record_low: Option<(f32, i32)>,
+    /// Earlier years with a record for the date
+    years: usize,
+    /// Rain recorded this month through today, mm
+    month_precip: f32,
+}
+
+fn compare(
+    today: &DailyObservation,
+    history: &[DailyObservation],
+    normals: &Normals,
+) -> Comparison {
+    let date = today.date;
+    let same_day: Vec<&DailyObservation> = history
+        .iter()
+        .filter(|day| {
+            day.date.year() < date.year()
+                && day.date.month() == date.month()
+                && day.date.day() == date.day()
+        })
+        .collect();
+    let year_ago = date
+        .with_year(date.year() - 1)
+        .or_else(|| NaiveDate::from_ymd_opt(date.year() - 1, 2, 28));
+    let month_precip = history
+        .iter()
+        .filter(|day| {
+            day.date.year() == date.year() && day.date.month() == date.month() && day.date < date
+        })
+        .chain(std::iter::once(today))
+        .filter_map(|day| day.precip)
+        .sum();
+    Comparison {
+        normal: normals.get(date),
+        last_year: history
+            .iter()
+            .find(|day| Some(day.date) == year_ago)
+            .cloned(),
+        record_high: same_day
+            .iter()
+            .max_by(|a, b| a.high.total_cmp(&b.high))
+            .map(|day| (day.high, day.date.year())),
+        record_low: same_day
+            .iter()
+            .min_by(|a, b| a.low.total_cmp(&b.low))
+            .map(|day| (day.low, day.date.year())),
+        years: same_day.len(),
+        month_precip,
+    }
+}
+
+/// A °F difference in whole degrees of `units`
+fn scaled_difference(difference: f32, units: Units) -> f32 {
+    match units {
+        Units::Imperial => difference,
+        Units::Metric | Units::Uk => difference * 5.0 / 9.0,
+    }
+    .round()
+}
+
+/// e.g. "8°F above normal"
+fn format_difference(difference: f32, units: Units) -> String {
+    let scaled = scaled_difference(difference, units);
+    if scaled == 0.0 {
+        return "At normal".to_string();
+    }
+    format!(
+        "{:.0}{} {} normal",
+        scaled.abs(),
+        units.temperature_symbol(),
+        if scaled > 0.0 { "above" } else { "below" }
+    )
+}
+
+/// e.g. "3°F warmer"
+fn format_warmer(difference: f32, units: Units) -> String {
+    let scaled = scaled_difference(difference, units);
+    if scaled == 0.0 {
+        return "the same".to_string();
+    }
+    format!(
+        "{:.0}{} {}",
+        scaled.abs(),
+        units.temperature_symbol(),
+        if scaled > 0.0 { "warmer" } else { "cooler" }
+    )
+}
+
+/// A °F temperature in `units`, e.g. "72°"
+fn format_temp(temp: f32, units: Units) -> String {
+    format!("{:.0}°", units.fahrenheit_to_units(temp))
+}
+
+/// Recorded highs and lows over the last `CHART_DAYS` as bars, with the normal high and
+/// low as lines, in the box `(x, y, width, height)`
+fn chart_svg(
+    days: &[&DailyObservation],
+    today: NaiveDate,
+    normals: &Normals,
+    units: Units,
+    locale: Locale,
+    (x, y, width, height): (f64, f64, f64, f64),
+) -> String {
+    let mut svg = format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="16" font-weight="bold" fill="black">Last {} days</text>"#,
+        x,
+        y - 8.0,
+        CHART_DAYS
+    );
+    svg.push('\n');
+    let first = today - Duration::days(CHART_DAYS - 1);
+    let normal_days: Vec<(i64, Normal)> = (0..CHART_DAYS)
+        .filter_map(|i| Some((i, normals.get(first + Duration::days(i))?)))
+        .collect();
+    let temps = days
+        .iter()
+        .flat_map(|day| [day.high, day.low])
+        .chain(normal_days.iter().flat_map(|(_, n)| [n.high, n.low]));
+    let (low, high) = temps.fold((f32::MAX, f32::MIN), |(low, high), t| {
+        (low.min(t), high.max(t))
+    });
+    if low > high {
+        return svg;
+    }
+    let (low, high) = (low - 3.0, high + 3.0);
+
+    let chart_x = x + 30.0;
+    let chart_width = width - 30.0;
+    let slot = chart_width / CHART_DAYS as f64;
+    let day_x = |date: NaiveDate| chart_x + ((date - first).num_days() as f64 + 0.5) * slot;
+    let temp_y = |t: f32| y + height - ((t - low) / (high - low)) as f64 * height;
+
+    svg.push_str(&format!(
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="1"/>"#,
+        chart_x, y, chart_width, height
+    ));
+    svg.push('\n');
+    svg.push_str(&svg_common::axis_minmax_labels(
+        chart_x - 4.0,
+        y + 8.0,
+        y + height,
+        &format_temp(high, units),
+        &format_temp(low, units),
+    ));
+    svg.push('\n');
+
+    for day in days {
+        svg.push_str(&format!(
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"/>"#,
+            day_x(day.date) - slot * 0.3,
+            temp_y(day.high),
+            slot * 0.6,
+            (temp_y(day.low) - temp_y(day.high)).max(1.0),
+            if day.date == today { "green" } else { "black" }
+        ));
+    }
+    svg.push('\n');
+
+    for (color, value) in [
+        ("red", (|n: &Normal| n.high) as fn(&Normal) -> f32),
+        ("blue", |n: &Normal| n.low),
+    ] {
+        let points: Vec<String> = normal_days
+            .iter()
+            .map(|(i, normal)| {
+                format!(
+                    "{:.1},{:.1}",
+                    day_x(first + Duration::days(*i)),
+                    temp_y(value(normal))
+                )
+            })
+            .collect();
+        if points.len() > 1 {
+            svg.push_str(&format!(
+                r#"  <polyline points="{}" fill="none" stroke="{}" stroke-width="2" stroke-dasharray="6,3"/>"#,
+                points.join(" "),
+                color
+            ));
+            svg.push('\n');
+        }
+    }
+
+    // A date label each week, ending today
+    for i in (0..CHART_DAYS).rev().step_by(7) {
+        let date = first + Duration::days(i);
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{}" text-anchor="middle" font-size="10" fill="black">{}</text>"#,
+            day_x(date),
+            y + height + 12.0,
+            locale.short_date(&date)
+        ));
+    }
+    svg.push('\n');
+    svg
+}
+
+/// Today's forecast as the headline, then how it compares, without the chart
+#[allow(clippy::too_many_arguments)]
+fn compact_history_svg(
+    weather: &WeatherData,
+    location: &str,
+    history: &[DailyObservation],
+    normals: Option<&Normals>,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let city = &geocoder
+        .search((weather.lat as f64, weather.lon as f64))
+        .record
+        .name;
+    let Some(today) = forecast_observation(weather, location, units) else {
+        let mut card = CompactCard::new(format!("History · {}", city), battery_pct);
+        card.lines.push(CardLine::new("No forecast for today"));
+        return card.svg(canvas);
+    };
+    let empty = Normals::default();
+    let normals = normals.unwrap_or(&empty);
+    let comparison = compare(&today, history, normals);
+
+    let mut card = CompactCard::new(
+        format!("{} · {}", locale.long_date(&today.date), city),
+        battery_pct,
+    );
+    card.headline = Some(CardLine::new(format!(
+        "{} / {}",
+        format_temp(today.high, units),
+        format_temp(today.low, units)
+    )));
+    if let Some(normal) = comparison.normal {
+        let difference = today.high - normal.high;
+        let line = CardLine::new(format_difference(difference, units)).with_value(format!(
+            "{} / {}",
+            format_temp(normal.high, units),
+            format_temp(normal.low, units)
+        ));
+        card.lines.push(if difference >= 5.0 {
+            line.colored(Ink::Red)
+        } else if difference <= -5.0 {
+            line.colored(Ink::Blue)
+        } else {
+            line
+        });
+    }
+    if let Some(day) = &comparison.last_year {
+        card.lines.push(
+            CardLine::new(format!(
+                "Last year · {}",
+                format_warmer(today.high - day.high, units)
+            ))
+            .with_value(format!(
+                "{} / {}",
+                format_temp(day.high, units),
+                format_temp(day.low, units)
+            )),
+        );
+    }
+    for (label, record, beaten) in [
+        (
+            "Record high",
+            comparison.record_high,
+            comparison.record_high.is_some_and(|(t, _)| today.high > t),
+        ),
+        (
+            "Record low",
+            comparison.record_low,
+            comparison.record_low.is_some_and(|(t, _)| today.low < t),
+        ),
+    ] {
+        if let Some((temp, year)) = record {
+            let line =
+                CardLine::new(label).with_value(format!("{} ({})", format_temp(temp, units), year));
+            card.lines
+                .push(if beaten { line.colored(Ink::Red) } else { line });
+        }
+    }
+    let mut rain = units.format_rain(comparison.month_precip);
+    if let Some(normal) = comparison.normal.and_then(|n| n.precip_mtd) {
+        rain.push_str(&format!(" / {}", units.format_rain(normal)));
+    }
+    card.lines
+        .push(CardLine::new("Rain this month").with_value(rain));
+    card.svg(canvas)
+}
+
+/// Generates an SVG comparing today's forecast with the recorded history
+///
+/// # Arguments
+/// * `weather` - Forecast for today
+/// * `location` - The forecast's key in the history (see `location_key`)
+/// * `history` - Every recorded day for the location, oldest first
+/// * `normals` - Climate normals, if the screen has them
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `geocoder` - Reverse geocoder for the city name
+/// * `units` - Units the forecast was fetched in, and to show
+/// * `locale` - Date format
+/// * `canvas` - Size to lay out for
+///
+/// # Returns
+/// A String containing the SVG markup
+#[allow(clippy::too_many_arguments)]
+pub fn generate_weather_history_svg(
+    weather: &WeatherData,
+    location: &str,
+    history: &[DailyObservation],
+    normals: Option<&Normals>,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let (width, height) = (canvas.width, canvas.height);
+    if canvas.is_compact() {
+        return compact_history_svg(
+            weather,
+            location,
+            history,
+            normals,
+            battery_pct,
+            geocoder,
+            units,
+            locale,
+            canvas,
+        );
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    );
+    svg.push('\n');
+    svg.push_str("  <defs>");
+    svg.push_str(svg_common::BATTERY_GRADIENT_DEF);
+    svg.push_str("</defs>\n");
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+    svg.push('\n');
+
+    svg.push_str(
+        r#"  <text x="20" y="35" font-family="Arial" font-size="28" font-weight="bold" fill="black">Weather History</text>"#,
+    );
+    svg.push('\n');
+
+    let Some(today) = forecast_observation(weather, location, units) else {
+        svg.push_str(&format!(
+            r#"  <text x="{}" y="{}" text-anchor="middle" font-family="Arial" font-size="22" fill="black">No forecast for today</text>"#,
+            width / 2,
+            height / 2
+        ));
+        svg.push_str("\n</svg>");
+        return svg;
+    };
+    let coords = (weather.lat as f64, weather.lon as f64);
+    svg.push_str(&format!(
+        r#"  <text x="20" y="58" font-family="Arial" font-size="16" fill="black">{} · {}</text>"#,
+        locale.long_date(&today.date),
+        svg_common::escape_xml_text(&geocoder.search(coords).record.name)
+    ));
+    svg.push('\n');
+
+    let empty = Normals::default();
+    let normals = normals.unwrap_or(&empty);
+    let comparison = compare(&today, history, normals);
+
+    // Today against normal and last year
+    svg.push_str(&format!(
+        r#"  <text x="20" y="90" font-family="Arial" font-size="14" fill="black">Today's forecast</text><text x="20" y="132" font-family="Arial" font-size="44" font-weight="bold" fill="black">{} <tspan font-size="28" font-weight="normal">/ {}</tspan></text>"#,
+        format_temp(today.high, units),
+        format_temp(today.low, units)
+    ));
+    svg.push('\n');
+    match comparison.normal {
+        Some(normal) => {
+            let difference = today.high - normal.high;
+            let color = if difference >= 5.0 {
+                "red"
+            } else if difference <= -5.0 {
+                "blue"
+            } else {
+                "black"
+            };
+            svg.push_str(&format!(
+                r#"  <text x="20" y="162" font-family="Arial" font-size="20" font-weight="bold" fill="{}">{}</text><text x="20" y="186" font-family="Arial" font-size="15" fill="black">Normal {} / {}</text>"#,
+                color,
+                format_difference(difference, units),
+                format_temp(normal.high, units),
+                format_temp(normal.low, units)
+            ));
+        }
+        None => svg.push_str(
+            r#"  <text x="20" y="162" font-family="Arial" font-size="15" font-style="italic" fill="black">No normals for today</text>"#,
+        ),
+    }
+    svg.push('\n');
+    let last_year = match &comparison.last_year {
+        Some(day) => format!(
+            "Last year {} / {} · {}",
+            format_temp(day.high, units),
+            format_temp(day.low, units),
+            format_warmer(today.high - day.high, units)
+        ),
+        None => "Last year: not recorded".to_string(),
+    };
+    svg.push_str(&format!(
+        r#"  <text x="20" y="210" font-family="Arial" font-size="15" fill="black">{}</text>"#,
+        last_year
+    ));
+    svg.push('\n');
+
+    // Records for the date
+    let right_x = (width / 2) as f64 + 20.0;
+    let mut y = 90.0;
+    for (label, record, beaten) in [
+        (
+            "Record high",
+            comparison.record_high,
+            comparison.record_high.is_some_and(|(t, _)| today.high > t),
+        ),
+        (
+            "Record low",
+            comparison.record_low,
+            comparison.record_low.is_some_and(|(t, _)| today.low < t),
+        ),
+    ] {
+        let value = match record {
+            Some((temp, year)) => format!(
+                r#"{} <tspan font-size="16" font-weight="normal">({})</tspan>"#,
+                format_temp(temp, units),
+                year
+            ),
+            None => "—".to_string(),
+        };
+        svg.push_str(&format!(
+            r#"  <text x="{x}" y="{}" font-family="Arial" font-size="14" fill="black">{}{}</text><text x="{x}" y="{}" font-family="Arial" font-size="26" font-weight="bold" fill="black">{}</text>"#,
+            y,
+            label,
+            if beaten { r#" <tspan fill="red" font-weight="bold">· forecast beats it</tspan>"# } else { "" },
+            y + 28.0,
+            value,
+            x = right_x
+        ));
+        svg.push('\n');
+        y += 54.0;
+    }
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="12" font-style="italic" fill="black">From {} earlier year{} of records</text>"#,
+        right_x,
+        y - 12.0,
+        comparison.years,
+        if comparison.years == 1 { "" } else { "s" }
+    ));
+    svg.push('\n');
+
+    // Rain this month against normal, on a scale with room for both
+    let bar_y = y + 14.0;
+    let bar_width = width as f64 - right_x - 20.0;
+    let normal_precip = comparison.normal.and_then(|n| n.precip_mtd);
+    let scale = (comparison.month_precip.max(normal_precip.unwrap_or(0.0)) * 1.2).max(1.0);
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" font-family="Arial" font-size="14" fill="black">Rain this month</text>"#,
+        right_x,
+        bar_y - 4.0
+    ));
+    svg.push_str(&format!(
+        r#"<rect x="{}" y="{}" width="{}" height="20" fill="white" stroke="black" stroke-width="2" rx="3"/><rect x="{}" y="{}" width="{:.1}" height="16" fill="blue" rx="2"/>"#,
+        right_x,
+        bar_y,
+        bar_width,
+        right_x + 2.0,
+        bar_y + 2.0,
+        ((comparison.month_precip / scale) as f64 * (bar_width - 4.0)).max(0.0)
+    ));
+    let mut rain_label = units.format_rain(comparison.month_precip);
+    if let Some(normal) = normal_precip {
+        let normal_x = right_x + 2.0 + (normal / scale) as f64 * (bar_width - 4.0);
+        svg.push_str(&format!(
+            r#"<line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="red" stroke-width="3"/>"#,
+            bar_y - 3.0,
+            bar_y + 23.0,
+            x = normal_x
+        ));
+        rain_label.push_str(&format!(" · normal {}", units.format_rain(normal)));
+    }
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" font-family="Arial" font-size="13" fill="black">{}</text>"#,
+        right_x,
+        bar_y + 36.0,
+        rain_label
+    ));
+    svg.push('\n');
+
+    // The last weeks, today from the forecast
+    let first = today.date - Duration::days(CHART_DAYS - 1);
+    let chart_days: Vec<&DailyObservation> = history
+        .iter()
+        .filter(|day| day.date >= first && day.date < today.date)
+        .chain(std::iter::once(&today))
+        .collect();
+    let chart_top = 290.0;
+    svg.push_str(&chart_svg(
+        &chart_days,
+        today.date,
+        normals,
+        units,
+        locale,
+        (
+            20.0,
+            chart_top,
+            width as f64 - 40.0,
+            (height as f64 - chart_top - 40.0).max(40.0),
+        ),
+    ));
+
+    // Footer with battery and last updated
+    let footer_y = height - 10;
+    let pct = battery_pct.unwrap_or(50);
+    svg.push_str(&svg_common::battery_label_svg(
+        10.0,
+        footer_y as f64,
+        "start",
+        12,
+    ));
+    svg.push_str(&svg_common::battery_bar_svg(
+        75.0,
+        footer_y as f64 - 10.0,
+        pct,
+        2.0,
+        "batteryClip",
+    ));
+    svg.push('\n');
+    let updated = Local::now();
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">Last updated: {:02}:{:02}:{:02}</text>"#,
+        width - 10,
+        footer_y,
+        updated.hour(),
+        updated.minute(),
+        updated.second()
+    ));
+    svg.push('\n');
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compare, format_difference, format_warmer, read_history_csv, DailyObservation, Normals,
+        ObservationStore,
+    };
+    use crate::locale::Units;
+    use chrono::NaiveDate;
+    use std::path::Path;
+
+    fn day(date: &str, high: f32, low: f32, precip: f32) -> DailyObservation {
+        DailyObservation {
+            location: "37.77,-122.42".to_string(),
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            high,
+            low,
+            precip: Some(precip),
+        }
+    }
+
+    #[test]
+    fn history_is_recorded_imported_and_reloaded() {
+        let path = std::env::temp_dir().join(format!(
+            "iot-image-observations-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let store = ObservationStore::persistent(&path);
+        store.record(day("2025-03-05", 60.0, 48.0, 0.0));
+        store.record(day("2025-03-05", 62.0, 47.0, 2.0));
+        store.record(day("2025-03-05", 62.0, 47.0, 2.0));
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        // CDO daily summaries, in °F and inches; the day without TMIN is skipped
+        let imported = read_history_csv(
+            Path::new("tests/fixtures/history/daily-summaries.csv"),
+            "37.77,-122.42",
+            Units::Imperial,
+        )
+        .unwrap();
+        assert_eq!(imported.len(), 4);
+        assert_eq!(imported[0].precip, Some(0.5 * 25.4));
+        assert_eq!(imported[3].precip, None);
+        store.import(imported);
+
+        // The later record of the day wins, and the file is compacted on load
+        let reloaded = ObservationStore::persistent(&path);
+        let history = reloaded.history("37.77,-122.42");
+        assert_eq!(history.len(), 5);
+        assert_eq!(history.last(), Some(&day("2025-03-05", 62.0, 47.0, 2.0)));
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 5);
+        assert!(reloaded.history("40.71,-74.01").is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn today_is_compared_with_records_normals_and_last_year() {
+        let normals = Normals::load(Path::new("tests/fixtures/history/normals.csv")).unwrap();
+        let history = vec![
+            day("2022-03-05", 71.0, 41.0, 0.0),
+            day("2023-03-05", 58.0, 39.0, 0.0),
+            day("2024-03-05", 64.0, 45.0, 0.0),
+            day("2025-03-01", 60.0, 50.0, 3.0),
+            day("2025-03-04", 61.0, 49.0, 4.5),
+        ];
+        let today = day("2025-03-05", 72.0, 50.0, 1.0);
+        let comparison = compare(&today, &history, &normals);
+        let normal = comparison.normal.unwrap();
+        assert_eq!((normal.high, normal.low), (64.1, 48.6));
+        assert!((normal.precip_mtd.unwrap() - 0.61 * 25.4).abs() < 1e-3);
+        assert_eq!(comparison.last_year, Some(history[2].clone()));
+        assert_eq!(comparison.record_high, Some((71.0, 2022)));
+        assert_eq!(comparison.record_low, Some((39.0, 2023)));
+        assert_eq!(comparison.years, 3);
+        assert_eq!(comparison.month_precip, 8.5);
+
+        assert_eq!(
+            format_difference(72.0 - 64.1, Units::Imperial),
+            "8°F above normal"
+        );
+        assert_eq!(format_difference(-9.0, Units::Metric), "5°C below normal");
+        assert_eq!(format_difference(0.3, Units::Imperial), "At normal");
+        assert_eq!(format_warmer(72.0 - 64.0, Units::Uk), "4°C warmer");
+    }
+}
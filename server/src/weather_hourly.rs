@@ -0,0 +1,444 @@
+//! The next 48 hours from the hourly forecast: temperature and feels-like lines,
+//! chance and amount of precipitation, wind direction and speed, with the nights
+//! shaded when the provider gives sunrise and sunset times.
+
+use crate::locale::{Locale, Units};
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use crate::weather::{load_weather_icon_as_data_uri, DailyWeather, HourlyWeather, WeatherData};
+use chrono::prelude::*;
+use reverse_geocoder::ReverseGeocoder;
+
+const HOURS: usize = 48;
+/// Smallest full-scale precipitation amount, so a drizzle doesn't fill the chart
+const MIN_AMOUNT_SCALE_MM: f32 = 5.0;
+
+/// Sunrise to sunset of each day that has both, in Unix seconds
+fn daylight(daily: &[DailyWeather]) -> Vec<(i64, i64)> {
+    daily
+        .iter()
+        .filter_map(|day| Some((day.sunrise?, day.sunset?)))
+        .collect()
+}
+
+/// The dark parts of `start..end`, or nothing when there are no daylight times
+fn night_spans(daylight: &[(i64, i64)], start: i64, end: i64) -> Vec<(i64, i64)> {
+    if daylight.is_empty() {
+        return Vec::new();
+    }
+    let mut spans = Vec::new();
+    let mut dark_from = start;
+    for &(sunrise, sunset) in daylight {
+        if sunset <= start || sunrise >= end {
+            continue;
+        }
+        if sunrise > dark_from {
+            spans.push((dark_from, sunrise));
+        }
+        dark_from = dark_from.max(sunset);
+    }
+    if dark_from < end {
+        spans.push((dark_from, end));
+    }
+    spans
+}
+
+fn is_night(daylight: &[(i64, i64)], time: i64) -> bool {
+    !daylight.is_empty()
+        && !daylight
+            .iter()
+            .any(|&(sunrise, sunset)| sunrise <= time && time < sunset)
+}
+
+/// Generates an SVG of the next 48 hours of the forecast
+///
+/// # Arguments
+/// * `weather` - Forecast with hourly data
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `geocoder` - Reverse geocoder for the city name
+/// * `units` - Units the data was fetched for
+/// * `locale` - Language for weekdays and the clock format for hours
+/// * `canvas` - Size to lay out for
+///
+/// # Returns
+/// A String containing the SVG markup
+pub fn generate_weather_hourly_svg(
+    weather: &WeatherData,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let (width, height) = (canvas.width, canvas.height);
+
+    // From the current hour on
+    let now = Utc::now().timestamp();
+    let hours: Vec<&HourlyWeather> = weather
+        .hourly
+        .iter()
+        .filter(|hour| hour.dt + 3600 > now)
+        .take(HOURS)
+        .collect();
+    if hours.len() < 2 {
+        return format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">
+    <text x="{}" y="{}" text-anchor="middle" font-size="20">Error: no hourly forecast</text>
+</svg>"#,
+            width,
+            height,
+            width / 2,
+            height / 2
+        );
+    }
+
+    let coords = (weather.lat as f64, weather.lon as f64);
+    let tz_offset =
+        chrono::FixedOffset::east_opt(weather.timezone_offset).unwrap_or_else(|| chrono::Utc.fix());
+    let local = |dt: i64| {
+        Utc.timestamp_opt(dt, 0)
+            .single()
+            .unwrap_or_else(Utc::now)
+            .with_timezone(&tz_offset)
+    };
+
+    if canvas.is_compact() {
+        // The current hour, then every third hour
+        let today = local(hours[0].dt).date_naive();
+        let night = is_night(&daylight(&weather.daily), hours[0].dt);
+        let mut card = CompactCard::new(
+            format!("Next hours · {}", geocoder.search(coords).record.name),
+            battery_pct,
+        );
+        card.headline = Some(CardLine::new(format!("{:.0}°", hours[0].temp)));
+        card.icon = load_weather_icon_as_data_uri(hours[0].condition, night).ok();
+        card.lines = hours
+            .iter()
+            .skip(3)
+            .step_by(3)
+            .map(|hour| {
+                let time = local(hour.dt);
+                let label = if time.date_naive() == today {
+                    locale.hour(time.hour())
+                } else {
+                    format!(
+                        "{} {}",
+                        locale.weekday(time.weekday()),
+                        locale.hour(time.hour())
+                    )
+                };
+                let line = CardLine::new(label).with_value(format!(
+                    "{:.0}° · {:.0}% · {}",
+                    hour.temp,
+                    hour.pop * 100.0,
+                    units.format_wind(hour.wind_speed)
+                ));
+                if hour.rain + hour.snow > 0.0 {
+                    line.colored(Ink::Blue)
+                } else {
+                    line
+                }
+            })
+            .collect();
+        return card.svg(canvas);
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    );
+    svg.push('\n');
+    svg.push_str("  <defs>");
+    svg.push_str(svg_common::BATTERY_GRADIENT_DEF);
+    svg.push_str("</defs>\n");
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+    svg.push('\n');
+
+    // Header: title and city, legend on the right
+    svg.push_str(
+        r#"  <text x="20" y="35" font-family="Arial" font-size="28" font-weight="bold" fill="black">Next 48 Hours</text>"#,
+    );
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <text x="20" y="58" font-family="Arial" font-size="16" fill="black">{}</text>"#,
+        svg_common::escape_xml_text(&geocoder.search(coords).record.name)
+    ));
+    svg.push('\n');
+    let legend_x = (width - 270) as f64;
+    svg.push_str(&format!(
+        r#"  <line x1="{x}" y1="25" x2="{}" y2="25" stroke="red" stroke-width="3"/><text x="{}" y="29" font-size="12" fill="black">Temperature</text><line x1="{}" y1="25" x2="{}" y2="25" stroke="black" stroke-width="2" stroke-dasharray="5,3"/><text x="{}" y="29" font-size="12" fill="black">Feels like</text>"#,
+        legend_x + 20.0,
+        legend_x + 25.0,
+        legend_x + 110.0,
+        legend_x + 130.0,
+        legend_x + 135.0,
+        x = legend_x
+    ));
+    svg.push_str(&format!(
+        r#"  <rect x="{x}" y="40" width="20" height="10" fill="lightblue"/><text x="{}" y="49" font-size="12" fill="black">Chance</text><rect x="{}" y="40" width="20" height="10" fill="blue"/><text x="{}" y="49" font-size="12" fill="black">Rain</text><rect x="{}" y="40" width="20" height="10" fill="purple"/><text x="{}" y="49" font-size="12" fill="black">Snow</text>"#,
+        legend_x + 25.0,
+        legend_x + 110.0,
+        legend_x + 135.0,
+        legend_x + 190.0,
+        legend_x + 215.0,
+        x = legend_x
+    ));
+    svg.push('\n');
+
+    // Layout: icons, temperatures, precipitation, wind, then the time labels
+    let chart_x = 50.0;
+    let chart_w = width as f64 - chart_x - 50.0;
+    let icons_y = 66.0;
+    let temp_y = 104.0;
+    let temp_h = (height as f64 * 0.38).round();
+    let precip_y = temp_y + temp_h + 10.0;
+    let precip_h = (height as f64 * 0.16).round();
+    let wind_y = precip_y + precip_h + 6.0;
+    let labels_y = wind_y + 48.0;
+
+    let start = hours[0].dt;
+    let end = hours[hours.len() - 1].dt + 3600;
+    let slot_w = chart_w / hours.len() as f64;
+    let x_at = |time: i64| chart_x + (time - start) as f64 / 3600.0 * slot_w;
+
+    // Nights, behind everything else
+    let daylight = daylight(&weather.daily);
+    for (dark_from, dark_to) in night_spans(&daylight, start, end) {
+        svg.push_str(&format!(
+            r##"  <rect x="{:.1}" y="{}" width="{:.1}" height="{}" fill="#d8d8d8"/>"##,
+            x_at(dark_from),
+            temp_y,
+            x_at(dark_to) - x_at(dark_from),
+            wind_y + 36.0 - temp_y
+        ));
+        svg.push('\n');
+    }
+
+    // Time gridlines every six hours, solid at midnight, with icons and labels
+    for hour in &hours {
+        let time = local(hour.dt);
+        if time.hour() % 6 != 0 {
+            continue;
+        }
+        let x = x_at(hour.dt);
+        let midnight = time.hour() == 0;
+        svg.push_str(&format!(
+            r#"  <line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="black" stroke-width="1"{} shape-rendering="crispEdges"/>"#,
+            temp_y,
+            wind_y + 36.0,
+            if midnight { "" } else { r#" stroke-dasharray="2,3""# },
+            x = x
+        ));
+        let label = if midnight {
+            locale.weekday(time.weekday()).to_string()
+        } else {
+            locale.hour(time.hour())
+        };
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{}" text-anchor="middle" font-size="12"{} fill="black">{}</text>"#,
+            x,
+            labels_y,
+            if midnight { r#" font-weight="bold""# } else { "" },
+            svg_common::escape_xml_text(&label)
+        ));
+        if x + 16.0 <= chart_x + chart_w {
+            let night = is_night(&daylight, hour.dt);
+            if let Ok(data_uri) = load_weather_icon_as_data_uri(hour.condition, night) {
+                svg.push_str(&format!(
+                    r#"<image x="{:.1}" y="{}" width="36" height="36" href="{}"/>"#,
+                    x - 18.0,
+                    icons_y,
+                    data_uri
+                ));
+            }
+        }
+        svg.push('\n');
+    }
+
+    // Temperature and feels-like lines
+    let (temp_min, temp_max) = hours
+        .iter()
+        .flat_map(|hour| [hour.temp, hour.feels_like])
+        .fold((f32::MAX, f32::MIN), |(lo, hi), t| (lo.min(t), hi.max(t)));
+    let (temp_min, temp_max) = ((temp_min - 2.0).floor(), (temp_max + 2.0).ceil());
+    let temp_to_y =
+        |t: f32| temp_y + temp_h - temp_h * ((t - temp_min) / (temp_max - temp_min)) as f64;
+    svg.push_str(&format!(
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="2"/>"#,
+        chart_x, temp_y, chart_w, temp_h
+    ));
+    svg.push('\n');
+    let line = |value: fn(&HourlyWeather) -> f32| {
+        hours
+            .iter()
+            .enumerate()
+            .map(|(i, hour)| {
+                format!(
+                    "{}{:.1},{:.1}",
+                    if i == 0 { "M" } else { " L" },
+                    chart_x + (i as f64 + 0.5) * slot_w,
+                    temp_to_y(value(hour))
+                )
+            })
+            .collect::<String>()
+    };
+    svg.push_str(&format!(
+        r#"  <path d="{}" fill="none" stroke="black" stroke-width="2" stroke-dasharray="5,3"/>"#,
+        line(|hour| hour.feels_like)
+    ));
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <path d="{}" fill="none" stroke="red" stroke-width="3"/>"#,
+        line(|hour| hour.temp)
+    ));
+    svg.push('\n');
+    svg.push_str(&svg_common::axis_minmax_labels(
+        chart_x - 5.0,
+        temp_y + 10.0,
+        temp_y + temp_h,
+        &format!("{:.0}°", temp_max),
+        &format!("{:.0}°", temp_min),
+    ));
+    svg.push('\n');
+
+    // Chance of precipitation, with the amounts as narrower bars in front
+    let amount_scale = hours
+        .iter()
+        .map(|hour| hour.rain + hour.snow)
+        .fold(MIN_AMOUNT_SCALE_MM, f32::max);
+    svg.push_str(&format!(
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="2"/>"#,
+        chart_x, precip_y, chart_w, precip_h
+    ));
+    svg.push('\n');
+    for (i, hour) in hours.iter().enumerate() {
+        let x = chart_x + i as f64 * slot_w;
+        let bottom = precip_y + precip_h;
+        let pop_h = precip_h * hour.pop.clamp(0.0, 1.0) as f64;
+        if pop_h > 0.0 {
+            svg.push_str(&format!(
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="lightblue"/>"#,
+                x + 1.0,
+                bottom - pop_h,
+                slot_w - 2.0,
+                pop_h
+            ));
+        }
+        let rain_h = precip_h * (hour.rain / amount_scale) as f64;
+        let snow_h = precip_h * (hour.snow / amount_scale) as f64;
+        let bar_x = x + slot_w * 0.25;
+        if rain_h > 0.0 {
+            svg.push_str(&format!(
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="blue"/>"#,
+                bar_x,
+                bottom - rain_h,
+                slot_w * 0.5,
+                rain_h
+            ));
+        }
+        if snow_h > 0.0 {
+            svg.push_str(&format!(
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="purple"/>"#,
+                bar_x,
+                bottom - rain_h - snow_h,
+                slot_w * 0.5,
+                snow_h
+            ));
+        }
+    }
+    svg.push('\n');
+    svg.push_str(&svg_common::axis_minmax_labels(
+        chart_x - 5.0,
+        precip_y + 10.0,
+        precip_y + precip_h,
+        "100%",
+        "0%",
+    ));
+    svg.push_str(&format!(
+        r#"<text x="{x}" y="{}" font-size="10" fill="black">{}</text><text x="{x}" y="{}" font-size="10" fill="black">0</text>"#,
+        precip_y + 10.0,
+        units.format_rain(amount_scale),
+        precip_y + precip_h,
+        x = chart_x + chart_w + 5.0
+    ));
+    svg.push('\n');
+
+    // Wind: an arrow the way it blows, every three hours
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="10" fill="black">Wind</text>"#,
+        chart_x - 5.0,
+        wind_y + 18.0
+    ));
+    for (i, hour) in hours.iter().enumerate().step_by(3) {
+        let x = chart_x + (i as f64 + 0.5) * slot_w;
+        if let Some(deg) = hour.wind_deg {
+            svg.push_str(&format!(
+                r#"<path d="M0,-9 L6,5 L0,1 L-6,5 Z" fill="black" transform="translate({:.1},{}) rotate({:.0})"/>"#,
+                x,
+                wind_y + 10.0,
+                deg + 180.0
+            ));
+        }
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{}" text-anchor="middle" font-size="9" fill="black">{}</text>"#,
+            x,
+            wind_y + 32.0,
+            units.format_wind(hour.wind_speed)
+        ));
+    }
+    svg.push('\n');
+
+    // Footer with battery and last updated
+    let footer_y = height - 10;
+    let pct = battery_pct.unwrap_or(50);
+    svg.push_str(&svg_common::battery_label_svg(
+        10.0,
+        footer_y as f64,
+        "start",
+        12,
+    ));
+    svg.push_str(&svg_common::battery_bar_svg(
+        75.0,
+        footer_y as f64 - 10.0,
+        pct,
+        2.0,
+        "batteryClip",
+    ));
+    svg.push('\n');
+    let now = Local::now();
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">Last updated: {:02}:{:02}:{:02}</text>"#,
+        width - 10,
+        footer_y,
+        now.hour(),
+        now.minute(),
+        now.second()
+    ));
+    svg.push('\n');
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_night, night_spans};
+
+    #[test]
+    fn nights_fall_between_sunset_and_the_next_sunrise() {
+        let daylight = [(600, 1800), (3000, 4200)];
+        assert_eq!(
+            night_spans(&daylight, 0, 5000),
+            vec![(0, 600), (1800, 3000), (4200, 5000)]
+        );
+        // Starting in daylight
+        assert_eq!(night_spans(&daylight, 1000, 3500), vec![(1800, 3000)]);
+        assert!(is_night(&daylight, 2000));
+        assert!(!is_night(&daylight, 600));
+        // No sunrise and sunset times: no shading, day icons
+        assert!(night_spans(&[], 0, 5000).is_empty());
+        assert!(!is_night(&[], 2000));
+    }
+}
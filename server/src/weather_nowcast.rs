@@ -0,0 +1,398 @@
+//! The next hour of precipitation from the minute-by-minute nowcast, with a sentence
+//! such as "Rain starting in 12 min, stopping at 10:40".
+
+use crate::locale::{Locale, Units};
+use crate::svg_common::{self, Canvas, CardLine, CompactCard, Ink};
+use crate::weather::{wrap_text_lines, MinutelyPrecipitation, WeatherData};
+use chrono::prelude::*;
+use reverse_geocoder::ReverseGeocoder;
+
+/// Lighter than this counts as dry, mm/h
+const WET_MM_H: f32 = 0.1;
+/// Light to moderate and moderate to heavy rain, mm/h
+const MODERATE_MM_H: f32 = 2.5;
+const HEAVY_MM_H: f32 = 7.6;
+/// Top of the chart; heavier rain is drawn at full height
+const CHART_MAX_MM_H: f32 = 10.0;
+const HORIZON_SECS: i64 = 3600;
+
+/// What the next hour holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outlook {
+    /// No nowcast for this location
+    Unavailable,
+    Dry,
+    /// Precipitation starts at `start`, and stops at `stop` if that is within the hour
+    Starting {
+        start: i64,
+        stop: Option<i64>,
+    },
+    /// Precipitation is falling, and stops at `stop` if that is within the hour
+    Falling {
+        stop: Option<i64>,
+    },
+}
+
+impl Outlook {
+    pub fn expects_precipitation(self) -> bool {
+        matches!(self, Outlook::Starting { .. } | Outlook::Falling { .. })
+    }
+
+    /// One line for the top of the screen, times in the forecast location's time zone
+    pub fn summary(self, now: i64, locale: Locale, tz: FixedOffset) -> String {
+        let time = |t: i64| {
+            let local = Utc
+                .timestamp_opt(t, 0)
+                .single()
+                .unwrap_or_else(Utc::now)
+                .with_timezone(&tz);
+            locale.time(&local)
+        };
+        match self {
+            Outlook::Unavailable => "No minute-by-minute forecast here".to_string(),
+            Outlook::Dry => "No rain in the next hour".to_string(),
+            Outlook::Starting { start, stop } => {
+                let minutes = ((start - now) as f64 / 60.0).round().max(1.0);
+                match stop {
+                    Some(stop) => format!(
+                        "Rain starting in {} min, stopping at {}",
+                        minutes,
+                        time(stop)
+                    ),
+                    None => format!("Rain starting in {} min", minutes),
+                }
+            }
+            Outlook::Falling { stop: Some(stop) } => format!("Rain stopping at {}", time(stop)),
+            Outlook::Falling { stop: None } => "Rain for at least the next hour".to_string(),
+        }
+    }
+}
+
+/// Start, end and intensity of each step overlapping the next hour
+fn upcoming(minutely: &[MinutelyPrecipitation], now: i64) -> Vec<(i64, i64, f32)> {
+    minutely
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            // The last step is as long as the one before it
+            let end = match (minutely.get(i + 1), i.checked_sub(1)) {
+                (Some(next), _) => next.dt,
+                (None, Some(prev)) => step.dt * 2 - minutely[prev].dt,
+                (None, None) => step.dt + 60,
+            };
+            (step.dt, end, step.precipitation)
+        })
+        .filter(|&(start, end, _)| end > now && start < now + HORIZON_SECS)
+        .collect()
+}
+
+/// Intensity category of a precipitation rate, as the chart bands are labelled
+fn intensity_name(mm_h: f32) -> &'static str {
+    if mm_h < WET_MM_H {
+        "Dry"
+    } else if mm_h < MODERATE_MM_H {
+        "Light"
+    } else if mm_h < HEAVY_MM_H {
+        "Moderate"
+    } else {
+        "Heavy"
+    }
+}
+
+pub fn outlook(minutely: &[MinutelyPrecipitation], now: i64) -> Outlook {
+    let steps = upcoming(minutely, now);
+    let wet = |&&(_, _, mm_h): &&(i64, i64, f32)| mm_h >= WET_MM_H;
+    let Some(first) = steps.first() else {
+        return Outlook::Unavailable;
+    };
+    if first.0 <= now && wet(&first) {
+        let stop = steps.iter().find(|step| !wet(step)).map(|step| step.0);
+        return Outlook::Falling { stop };
+    }
+    match steps.iter().position(|step| wet(&step)) {
+        Some(i) => Outlook::Starting {
+            start: steps[i].0.max(now),
+            stop: steps[i..].iter().find(|step| !wet(step)).map(|step| step.0),
+        },
+        None => Outlook::Dry,
+    }
+}
+
+/// Generates an SVG of the next hour's precipitation
+///
+/// # Arguments
+/// * `weather` - Forecast with the minute-by-minute nowcast
+/// * `battery_pct` - Optional battery percentage (0-100)
+/// * `geocoder` - Reverse geocoder for the city name
+/// * `_units` - Unused; the chart is labelled by intensity category
+/// * `locale` - Clock format for times
+/// * `canvas` - Size to lay out for
+///
+/// # Returns
+/// A String containing the SVG markup
+pub fn generate_weather_nowcast_svg(
+    weather: &WeatherData,
+    battery_pct: Option<u8>,
+    geocoder: &ReverseGeocoder,
+    _units: Units,
+    locale: Locale,
+    canvas: Canvas,
+) -> String {
+    let (width, height) = (canvas.width, canvas.height);
+    let now = Utc::now().timestamp();
+    let tz_offset =
+        chrono::FixedOffset::east_opt(weather.timezone_offset).unwrap_or_else(|| chrono::Utc.fix());
+    let coords = (weather.lat as f64, weather.lon as f64);
+    let outlook = outlook(&weather.minutely, now);
+
+    if canvas.is_compact() {
+        // The outlook sentence, then the intensity every quarter hour
+        let ink = if outlook.expects_precipitation() {
+            Ink::Blue
+        } else {
+            Ink::Black
+        };
+        let mut card = CompactCard::new(
+            format!("Next hour · {}", geocoder.search(coords).record.name),
+            battery_pct,
+        );
+        card.lines = wrap_text_lines(
+            &outlook.summary(now, locale, tz_offset),
+            CompactCard::line_chars(canvas),
+        )
+        .into_iter()
+        .map(|line| CardLine::new(line).colored(ink))
+        .collect();
+        let steps = upcoming(&weather.minutely, now);
+        for quarter in 0..4 {
+            let t = now + quarter * 15 * 60;
+            let Some(&(_, _, mm_h)) = steps.iter().find(|&&(start, end, _)| start <= t && t < end)
+            else {
+                continue;
+            };
+            let label = if quarter == 0 {
+                "Now".to_string()
+            } else {
+                let local = Utc
+                    .timestamp_opt(t, 0)
+                    .single()
+                    .unwrap_or_else(Utc::now)
+                    .with_timezone(&tz_offset);
+                locale.time(&local)
+            };
+            card.lines
+                .push(CardLine::new(label).with_value(intensity_name(mm_h)));
+        }
+        return card.svg(canvas);
+    }
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, width, height
+    );
+    svg.push('\n');
+    svg.push_str("  <defs>");
+    svg.push_str(svg_common::BATTERY_GRADIENT_DEF);
+    svg.push_str("</defs>\n");
+    svg.push_str(&format!(
+        r#"  <rect width="{}" height="{}" fill="white"/>"#,
+        width, height
+    ));
+    svg.push('\n');
+
+    // Header and the outlook sentence
+    svg.push_str(
+        r#"  <text x="20" y="35" font-family="Arial" font-size="28" font-weight="bold" fill="black">Next Hour</text>"#,
+    );
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <text x="20" y="58" font-family="Arial" font-size="16" fill="black">{}</text>"#,
+        svg_common::escape_xml_text(&geocoder.search(coords).record.name)
+    ));
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <text x="20" y="105" font-family="Arial" font-size="26" font-weight="bold" fill="{}">{}</text>"#,
+        if outlook.expects_precipitation() {
+            "blue"
+        } else {
+            "black"
+        },
+        svg_common::escape_xml_text(&outlook.summary(now, locale, tz_offset))
+    ));
+    svg.push('\n');
+
+    // Intensity chart, banded light/moderate/heavy
+    let chart_x = 80.0;
+    let chart_y = 130.0;
+    let chart_w = width as f64 - chart_x - 30.0;
+    let chart_h = height as f64 - chart_y - 70.0;
+    let x_at = |t: i64| chart_x + chart_w * (t - now) as f64 / HORIZON_SECS as f64;
+    let y_at = |mm_h: f32| {
+        chart_y + chart_h - chart_h * (mm_h.min(CHART_MAX_MM_H) / CHART_MAX_MM_H) as f64
+    };
+
+    for (start, end, mm_h) in upcoming(&weather.minutely, now) {
+        if mm_h < WET_MM_H {
+            continue;
+        }
+        let (x1, x2) = (x_at(start.max(now)), x_at(end.min(now + HORIZON_SECS)));
+        svg.push_str(&format!(
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="blue"/>"#,
+            x1,
+            y_at(mm_h),
+            x2 - x1,
+            chart_y + chart_h - y_at(mm_h)
+        ));
+    }
+    svg.push('\n');
+    svg.push_str(&format!(
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="2"/>"#,
+        chart_x, chart_y, chart_w, chart_h
+    ));
+    svg.push('\n');
+    for (bottom, top, label) in [
+        (0.0, MODERATE_MM_H, "Light"),
+        (MODERATE_MM_H, HEAVY_MM_H, "Moderate"),
+        (HEAVY_MM_H, CHART_MAX_MM_H, "Heavy"),
+    ] {
+        if bottom > 0.0 {
+            svg.push_str(&format!(
+                r#"<line x1="{}" y1="{:.1}" x2="{}" y2="{:.1}" stroke="black" stroke-width="1" stroke-dasharray="4,4" shape-rendering="crispEdges"/>"#,
+                chart_x,
+                y_at(bottom),
+                chart_x + chart_w,
+                y_at(bottom)
+            ));
+        }
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{:.1}" text-anchor="end" font-size="12" fill="black">{}</text>"#,
+            chart_x - 6.0,
+            (y_at(bottom) + y_at(top)) / 2.0 + 4.0,
+            label
+        ));
+    }
+    svg.push('\n');
+
+    // Quarter-hour marks
+    for quarter in 0..=4 {
+        let t = now + quarter * 15 * 60;
+        let x = x_at(t);
+        if quarter > 0 && quarter < 4 {
+            svg.push_str(&format!(
+                r#"<line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="black" stroke-width="1" stroke-dasharray="2,3" shape-rendering="crispEdges"/>"#,
+                chart_y,
+                chart_y + chart_h,
+                x = x
+            ));
+        }
+        let label = if quarter == 0 {
+            "Now".to_string()
+        } else {
+            let local = Utc
+                .timestamp_opt(t, 0)
+                .single()
+                .unwrap_or_else(Utc::now)
+                .with_timezone(&tz_offset);
+            locale.time(&local)
+        };
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{}" text-anchor="middle" font-size="14" fill="black">{}</text>"#,
+            x,
+            chart_y + chart_h + 20.0,
+            label
+        ));
+    }
+    svg.push('\n');
+
+    // Footer with battery and last updated
+    let footer_y = height - 10;
+    let pct = battery_pct.unwrap_or(50);
+    svg.push_str(&svg_common::battery_label_svg(
+        10.0,
+        footer_y as f64,
+        "start",
+        12,
+    ));
+    svg.push_str(&svg_common::battery_bar_svg(
+        75.0,
+        footer_y as f64 - 10.0,
+        pct,
+        2.0,
+        "batteryClip",
+    ));
+    svg.push('\n');
+    let updated = Local::now();
+    svg.push_str(&format!(
+        r#"  <text x="{}" y="{}" text-anchor="end" font-size="12" fill="black">Last updated: {:02}:{:02}:{:02}</text>"#,
+        width - 10,
+        footer_y,
+        updated.hour(),
+        updated.minute(),
+        updated.second()
+    ));
+    svg.push('\n');
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{outlook, Outlook};
+    use crate::locale::Locale;
+    use crate::weather::MinutelyPrecipitation;
+    use chrono::FixedOffset;
+
+    fn minutes(mm_h: &[f32]) -> Vec<MinutelyPrecipitation> {
+        mm_h.iter()
+            .enumerate()
+            .map(|(i, &precipitation)| MinutelyPrecipitation {
+                dt: 1_741_190_400 + i as i64 * 60,
+                precipitation,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn outlook_finds_when_rain_starts_and_stops() {
+        let now = 1_741_190_400 + 30;
+        let pacific = FixedOffset::west_opt(8 * 3600).unwrap();
+
+        let mut mm_h = vec![0.0; 60];
+        mm_h[12..40].fill(1.5);
+        let starting = outlook(&minutes(&mm_h), now);
+        assert_eq!(
+            starting,
+            Outlook::Starting {
+                start: 1_741_190_400 + 12 * 60,
+                stop: Some(1_741_190_400 + 40 * 60)
+            }
+        );
+        // Now is 08:00 Pacific
+        assert_eq!(
+            starting.summary(now, Locale::EnGb, pacific),
+            "Rain starting in 12 min, stopping at 08:40"
+        );
+
+        mm_h[..5].fill(0.5);
+        mm_h[5..12].fill(0.0);
+        assert_eq!(
+            outlook(&minutes(&mm_h), now),
+            Outlook::Falling {
+                stop: Some(1_741_190_400 + 5 * 60)
+            }
+        );
+        assert_eq!(
+            outlook(&minutes(&[2.0; 60]), now).summary(now, Locale::EnUs, pacific),
+            "Rain for at least the next hour"
+        );
+        // A trace is not rain
+        assert_eq!(outlook(&minutes(&[0.05; 60]), now), Outlook::Dry);
+        assert_eq!(outlook(&[], now), Outlook::Unavailable);
+        // A stale nowcast has nothing left for the coming hour
+        assert_eq!(
+            outlook(&minutes(&[1.0; 60]), now + 7200),
+            Outlook::Unavailable
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Weather forecast sources. Each provider fetches from its own API and returns the
+//! provider-neutral `WeatherData` the weather screen draws, with its condition codes
+//! normalized to `Condition`.
+//!
+//! Parsing is kept separate from fetching so each provider can be tested against
+//! recorded responses in `tests/fixtures/weather/`.
+
+mod nws;
+mod open_meteo;
+mod openweather;
+
+use crate::locale::Units;
+use crate::upstream::FetchError;
+use crate::weather::WeatherData;
+use serde::Deserialize;
+use std::future::Future;
+use std::pin::Pin;
+
+pub use nws::Nws;
+pub use open_meteo::OpenMeteo;
+pub use openweather::OpenWeather;
+
+pub type FetchFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<WeatherData, FetchError>> + Send + 'a>>;
+
+/// A source of daily forecasts
+pub trait WeatherProvider: Send + Sync {
+    /// Today's forecast and the days after it for a location, in `units`
+    fn fetch<'a>(&'a self, lat: &'a str, lon: &'a str, units: Units) -> FetchFuture<'a>;
+}
+
+/// Which provider a weather screen uses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    /// OpenWeatherMap One Call 3.0; needs `[sources.openweather]`
+    #[default]
+    #[serde(rename = "openweather")]
+    OpenWeather,
+    /// Open-Meteo, which needs no key
+    OpenMeteo,
+    /// US National Weather Service, for US locations only
+    Nws,
+}
+
+impl Provider {
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::OpenWeather => "openweather",
+            Provider::OpenMeteo => "open-meteo",
+            Provider::Nws => "nws",
+        }
+    }
+}
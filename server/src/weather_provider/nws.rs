@@ -0,0 +1,558 @@
+//! US National Weather Service API (api.weather.gov), for US locations only.
+//!
+//! `/points` gives the location's forecast gridpoint and time zone. The raw gridpoint
+//! data is a set of time series in SI units, each value covering an ISO 8601 interval,
+//! which are split into hours and aggregated into local days. There are no sunrise,
+//! sunset or UV index values.
+
+use super::{FetchFuture, WeatherProvider};
+use crate::locale::Units;
+use crate::upstream::{FetchError, RateLimit, Upstream};
+use crate::weather::{
+    Condition, DailyWeather, FeelsLike, HourlyWeather, Severity, WeatherAlert, WeatherData,
+};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, Offset, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Days of forecast to keep; the gridpoint data runs about a week out
+const FORECAST_DAYS: usize = 7;
+/// Local hours whose weather sets the day's condition
+const DAYTIME: std::ops::Range<u32> = 6..18;
+/// Snowfall depth per depth of water, roughly
+const SNOW_DEPTH_PER_WATER: f32 = 10.0;
+
+pub struct Nws {
+    /// The API rejects requests without a User-Agent, and asks for contact details in it
+    pub user_agent: String,
+    pub upstream: Upstream,
+    pub limit: Arc<RateLimit>,
+}
+
+impl WeatherProvider for Nws {
+    fn fetch<'a>(&'a self, lat: &'a str, lon: &'a str, units: Units) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let (upstream, user_agent) = (&self.upstream, self.user_agent.as_str());
+            let points: Points = upstream
+                .get_json_as(
+                    &format!("https://api.weather.gov/points/{},{}", lat, lon),
+                    user_agent,
+                    &self.limit,
+                )
+                .await?;
+            let grid: Gridpoint = upstream
+                .get_json_as(
+                    &points.properties.forecast_grid_data,
+                    user_agent,
+                    &self.limit,
+                )
+                .await?;
+            let alerts: Alerts = upstream
+                .get_json_as(
+                    &format!(
+                        "https://api.weather.gov/alerts/active?point={},{}",
+                        lat, lon
+                    ),
+                    user_agent,
+                    &self.limit,
+                )
+                .await?;
+            forecast(&points, &grid, &alerts, units)
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Points {
+    geometry: PointGeometry,
+    properties: PointProperties,
+}
+
+#[derive(Deserialize)]
+struct PointGeometry {
+    /// Longitude, latitude
+    coordinates: (f32, f32),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PointProperties {
+    forecast_grid_data: String,
+    time_zone: String,
+}
+
+#[derive(Deserialize)]
+struct Gridpoint {
+    properties: GridProperties,
+}
+
+/// The series used here: temperatures in °C, speeds in km/h, precipitation in mm
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GridProperties {
+    temperature: Series<f32>,
+    #[serde(default)]
+    apparent_temperature: Series<f32>,
+    #[serde(default)]
+    relative_humidity: Series<f32>,
+    #[serde(default)]
+    wind_speed: Series<f32>,
+    #[serde(default)]
+    wind_gust: Series<f32>,
+    #[serde(default)]
+    probability_of_precipitation: Series<f32>,
+    #[serde(default)]
+    quantitative_precipitation: Series<f32>,
+    #[serde(default)]
+    wind_direction: Series<f32>,
+    #[serde(default)]
+    snowfall_amount: Series<f32>,
+    #[serde(default)]
+    sky_cover: Series<f32>,
+    #[serde(default)]
+    weather: Series<Vec<WeatherValue>>,
+}
+
+#[derive(Deserialize, Default)]
+struct Series<T> {
+    values: Vec<SeriesValue<T>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SeriesValue<T> {
+    /// Start and duration, e.g. "2025-03-05T14:00:00+00:00/PT6H"
+    valid_time: String,
+    value: Option<T>,
+}
+
+#[derive(Deserialize, Clone)]
+struct WeatherValue {
+    coverage: Option<String>,
+    weather: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Alerts {
+    features: Vec<AlertFeature>,
+}
+
+#[derive(Deserialize)]
+struct AlertFeature {
+    properties: AlertProperties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlertProperties {
+    event: String,
+    sender_name: Option<String>,
+    severity: Option<String>,
+    description: Option<String>,
+    onset: Option<DateTime<FixedOffset>>,
+    effective: Option<DateTime<FixedOffset>>,
+    ends: Option<DateTime<FixedOffset>>,
+    expires: Option<DateTime<FixedOffset>>,
+}
+
+/// Whole hours in an ISO 8601 duration such as "PT6H" or "P1DT12H"
+fn duration_hours(duration: &str) -> Option<i64> {
+    let rest = duration.strip_prefix('P')?;
+    let (days, time) = rest.split_once('T').unwrap_or((rest, ""));
+    let days = match days.strip_suffix('D') {
+        Some(days) => days.parse::<i64>().ok()?,
+        None if days.is_empty() => 0,
+        None => return None,
+    };
+    let hours = match time.strip_suffix('H') {
+        Some(hours) => hours.parse::<i64>().ok()?,
+        None if time.is_empty() => 0,
+        None => return None,
+    };
+    Some(days * 24 + hours)
+}
+
+impl<T: Clone> Series<T> {
+    /// Start, length in hours and value of each interval that has a value
+    fn intervals(&self) -> impl Iterator<Item = (DateTime<Utc>, i64, T)> + '_ {
+        self.values.iter().filter_map(|v| {
+            let (start, duration) = v.valid_time.split_once('/')?;
+            let start = DateTime::parse_from_rfc3339(start)
+                .ok()?
+                .with_timezone(&Utc);
+            Some((start, duration_hours(duration)?, v.value.clone()?))
+        })
+    }
+
+    /// One value per hour
+    fn hourly(&self) -> Vec<(DateTime<Utc>, T)> {
+        self.intervals()
+            .flat_map(|(start, hours, value)| {
+                (0..hours).map(move |h| (start + Duration::hours(h), value.clone()))
+            })
+            .collect()
+    }
+}
+
+impl Series<f32> {
+    /// An amount over each interval, shared equally between its hours
+    fn hourly_share(&self) -> HashMap<DateTime<Utc>, f32> {
+        self.intervals()
+            .flat_map(|(start, hours, amount)| {
+                (0..hours).map(move |h| (start + Duration::hours(h), amount / hours as f32))
+            })
+            .collect()
+    }
+}
+
+/// Local hour and value of the hours on `day`
+fn on_day<T: Clone>(hourly: &[(DateTime<Utc>, T)], tz: Tz, day: NaiveDate) -> Vec<(u32, T)> {
+    hourly
+        .iter()
+        .map(|(t, v)| (t.with_timezone(&tz), v))
+        .filter(|(t, _)| t.date_naive() == day)
+        .map(|(t, v)| (t.hour(), v.clone()))
+        .collect()
+}
+
+fn max(values: &[(u32, f32)]) -> Option<f32> {
+    values.iter().map(|&(_, v)| v).reduce(f32::max)
+}
+
+fn min(values: &[(u32, f32)]) -> Option<f32> {
+    values.iter().map(|&(_, v)| v).reduce(f32::min)
+}
+
+fn mean(values: &[(u32, f32)]) -> Option<f32> {
+    let sum: f32 = values.iter().map(|&(_, v)| v).sum();
+    (!values.is_empty()).then(|| sum / values.len() as f32)
+}
+
+fn at_hour(values: &[(u32, f32)], hour: u32) -> Option<f32> {
+    values.iter().find(|&&(h, _)| h == hour).map(|&(_, v)| v)
+}
+
+fn temperature(celsius: f32, units: Units) -> f32 {
+    match units {
+        Units::Imperial => celsius * 9.0 / 5.0 + 32.0,
+        Units::Metric | Units::Uk => celsius,
+    }
+}
+
+fn wind_speed(km_h: f32, units: Units) -> f32 {
+    match units {
+        Units::Imperial => km_h / 1.609_344,
+        Units::Metric | Units::Uk => km_h / 3.6,
+    }
+}
+
+/// Maps NWS weather types to conditions; haze, smoke and the like have none
+fn weather_condition(weather: &str) -> Option<Condition> {
+    match weather {
+        "thunderstorms" => Some(Condition::Thunderstorm),
+        "snow" | "snow_showers" | "blowing_snow" => Some(Condition::Snow),
+        "sleet" | "freezing_rain" | "freezing_drizzle" => Some(Condition::Sleet),
+        "rain" => Some(Condition::Rain),
+        "rain_showers" => Some(Condition::Showers),
+        "drizzle" => Some(Condition::Drizzle),
+        "fog" | "freezing_fog" | "ice_fog" => Some(Condition::Fog),
+        _ => None,
+    }
+}
+
+/// The most severe weather, ignoring a slight chance of it, or otherwise the sky cover
+fn condition<'a>(
+    weather: impl IntoIterator<Item = &'a WeatherValue>,
+    sky_cover: Option<f32>,
+) -> Condition {
+    let worst = weather
+        .into_iter()
+        .filter(|v| v.coverage.as_deref() != Some("slight_chance"))
+        .filter_map(|v| v.weather.as_deref().and_then(weather_condition))
+        .max();
+    if let Some(condition) = worst {
+        return condition;
+    }
+    match sky_cover {
+        Some(cover) if cover < 25.0 => Condition::Clear,
+        Some(cover) if cover < 50.0 => Condition::PartlyCloudy,
+        Some(cover) if cover < 88.0 => Condition::Cloudy,
+        Some(_) => Condition::Overcast,
+        None => Condition::Cloudy,
+    }
+}
+
+/// The day's condition from its daytime hours
+fn day_condition(weather: &[(u32, Vec<WeatherValue>)], sky_cover: &[(u32, f32)]) -> Condition {
+    let daytime_sky: Vec<(u32, f32)> = sky_cover
+        .iter()
+        .copied()
+        .filter(|(hour, _)| DAYTIME.contains(hour))
+        .collect();
+    condition(
+        weather
+            .iter()
+            .filter(|(hour, _)| DAYTIME.contains(hour))
+            .flat_map(|(_, values)| values),
+        mean(&daytime_sky).or_else(|| mean(sky_cover)),
+    )
+}
+
+/// Precipitation less the water in any snow, in mm
+fn rain<K>(precipitation: &[(K, f32)], snowfall: &[(K, f32)]) -> f32 {
+    let total = |amounts: &[(K, f32)]| amounts.iter().map(|(_, mm)| mm).sum::<f32>();
+    (total(precipitation) - total(snowfall) / SNOW_DEPTH_PER_WATER).max(0.0)
+}
+
+fn hourly_forecast(
+    g: &GridProperties,
+    temperatures: &[(DateTime<Utc>, f32)],
+    sky_cover: &[(DateTime<Utc>, f32)],
+    weather: &[(DateTime<Utc>, Vec<WeatherValue>)],
+    units: Units,
+) -> Vec<HourlyWeather> {
+    let by_hour = |hourly: Vec<(DateTime<Utc>, f32)>| hourly.into_iter().collect::<HashMap<_, _>>();
+    let apparent = by_hour(g.apparent_temperature.hourly());
+    let pops = by_hour(g.probability_of_precipitation.hourly());
+    let winds = by_hour(g.wind_speed.hourly());
+    let directions = by_hour(g.wind_direction.hourly());
+    let sky_cover: HashMap<_, _> = sky_cover.iter().copied().collect();
+    let weather: HashMap<_, _> = weather.iter().map(|(t, w)| (*t, w)).collect();
+    let precipitation = g.quantitative_precipitation.hourly_share();
+    let snowfall = g.snowfall_amount.hourly_share();
+    temperatures
+        .iter()
+        .map(|&(hour, temp)| {
+            let snow = snowfall.get(&hour).copied().unwrap_or(0.0);
+            let precipitation = precipitation.get(&hour).copied().unwrap_or(0.0);
+            HourlyWeather {
+                dt: hour.timestamp(),
+                temp: temperature(temp, units),
+                feels_like: temperature(apparent.get(&hour).copied().unwrap_or(temp), units),
+                pop: pops.get(&hour).copied().unwrap_or(0.0) / 100.0,
+                rain: rain(&[(hour, precipitation)], &[(hour, snow)]),
+                snow: snow / SNOW_DEPTH_PER_WATER,
+                wind_speed: wind_speed(winds.get(&hour).copied().unwrap_or(0.0), units),
+                wind_deg: directions.get(&hour).copied(),
+                condition: condition(
+                    weather.get(&hour).into_iter().copied().flatten(),
+                    sky_cover.get(&hour).copied(),
+                ),
+            }
+        })
+        .collect()
+}
+
+fn forecast(
+    points: &Points,
+    grid: &Gridpoint,
+    alerts: &Alerts,
+    units: Units,
+) -> Result<WeatherData, FetchError> {
+    let tz: Tz = points
+        .properties
+        .time_zone
+        .parse()
+        .map_err(|_| format!("Unknown time zone {}", points.properties.time_zone))?;
+    let g = &grid.properties;
+    let temperatures = g.temperature.hourly();
+    let first = temperatures
+        .first()
+        .ok_or("Gridpoint data has no temperatures")?
+        .0;
+    let (apparent, humidity) = (
+        g.apparent_temperature.hourly(),
+        g.relative_humidity.hourly(),
+    );
+    let (winds, gusts) = (g.wind_speed.hourly(), g.wind_gust.hourly());
+    let (pops, sky_cover) = (
+        g.probability_of_precipitation.hourly(),
+        g.sky_cover.hourly(),
+    );
+    let weather = g.weather.hourly();
+    let by_start = |series: &Series<f32>| -> Vec<(DateTime<Utc>, f32)> {
+        series
+            .intervals()
+            .map(|(start, _, mm)| (start, mm))
+            .collect()
+    };
+    let (precipitation, snowfall) = (
+        by_start(&g.quantitative_precipitation),
+        by_start(&g.snowfall_amount),
+    );
+
+    let first_day = first.with_timezone(&tz).date_naive();
+    let daily = first_day
+        .iter_days()
+        .take(FORECAST_DAYS)
+        .map_while(|day| {
+            let temps = on_day(&temperatures, tz, day);
+            let (temp_min, temp_max) = (min(&temps)?, max(&temps)?);
+            let apparent = on_day(&apparent, tz, day);
+            let feels_like = |hour, default| at_hour(&apparent, hour).unwrap_or(default);
+            let sky_cover = on_day(&sky_cover, tz, day);
+            let noon = tz
+                .from_local_datetime(&day.and_hms_opt(12, 0, 0)?)
+                .earliest()?;
+            Some(DailyWeather {
+                dt: noon.timestamp(),
+                temp_min: temperature(temp_min, units),
+                temp_max: temperature(temp_max, units),
+                feels_like: FeelsLike {
+                    morn: temperature(feels_like(6, temp_min), units),
+                    day: temperature(feels_like(12, temp_max), units),
+                    eve: temperature(feels_like(18, temp_max), units),
+                },
+                humidity: mean(&on_day(&humidity, tz, day)).unwrap_or(0.0).round() as i32,
+                wind_speed: wind_speed(max(&on_day(&winds, tz, day)).unwrap_or(0.0), units),
+                wind_gust: max(&on_day(&gusts, tz, day)).map(|gust| wind_speed(gust, units)),
+                sunrise: None,
+                sunset: None,
+                condition: day_condition(&on_day(&weather, tz, day), &sky_cover),
+                pop: max(&on_day(&pops, tz, day)).unwrap_or(0.0) / 100.0,
+                rain: rain(
+                    &on_day(&precipitation, tz, day),
+                    &on_day(&snowfall, tz, day),
+                ),
+                uvi: None,
+                clouds: mean(&sky_cover).map(|cover| cover.round() as i32),
+            })
+        })
+        .collect();
+
+    let hourly = hourly_forecast(g, &temperatures, &sky_cover, &weather, units);
+
+    let alerts = alerts
+        .features
+        .iter()
+        .filter_map(|feature| {
+            let alert = &feature.properties;
+            Some(WeatherAlert {
+                event: alert.event.clone(),
+                sender: alert.sender_name.clone().unwrap_or_default(),
+                severity: alert
+                    .severity
+                    .as_deref()
+                    .and_then(Severity::from_name)
+                    .unwrap_or_else(|| Severity::from_event(&alert.event)),
+                tags: Vec::new(),
+                description: alert.description.clone().unwrap_or_default(),
+                start: alert.onset.or(alert.effective)?.timestamp(),
+                end: alert.ends.or(alert.expires)?.timestamp(),
+            })
+        })
+        .collect();
+
+    let (lon, lat) = points.geometry.coordinates;
+    Ok(WeatherData {
+        lat,
+        lon,
+        timezone_offset: tz
+            .offset_from_utc_datetime(&first.naive_utc())
+            .fix()
+            .local_minus_utc(),
+        daily,
+        hourly,
+        // No nowcast
+        minutely: Vec::new(),
+        alerts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{duration_hours, forecast, Alerts, Gridpoint, Points};
+    use crate::locale::Units;
+    use crate::weather::{Condition, Severity, WeatherData};
+
+    fn recorded(units: Units) -> WeatherData {
+        let points: Points =
+            serde_json::from_str(include_str!("../../tests/fixtures/weather/nws_points.json"))
+                .unwrap();
+        let grid: Gridpoint = serde_json::from_str(include_str!(
+            "../../tests/fixtures/weather/nws_gridpoint.json"
+        ))
+        .unwrap();
+        let alerts: Alerts =
+            serde_json::from_str(include_str!("../../tests/fixtures/weather/nws_alerts.json"))
+                .unwrap();
+        forecast(&points, &grid, &alerts, units).unwrap()
+    }
+
+    #[test]
+    fn aggregates_recorded_gridpoint_series_into_local_days() {
+        let weather = recorded(Units::Metric);
+        assert_eq!(weather.timezone_offset, -28800);
+        assert_eq!((weather.lat, weather.lon), (37.7749, -122.4194));
+        assert_eq!(weather.daily.len(), 2);
+
+        let today = &weather.daily[0];
+        // Noon Pacific
+        assert_eq!(today.dt, 1741204800);
+        assert_eq!((today.temp_min, today.temp_max), (9.4, 16.1));
+        assert_eq!(
+            (
+                today.feels_like.morn,
+                today.feels_like.day,
+                today.feels_like.eve
+            ),
+            (7.8, 15.0, 11.1)
+        );
+        assert_eq!(today.humidity, 87);
+        // 36 and 72 km/h
+        assert!((today.wind_speed - 10.0).abs() < 0.001);
+        assert!((today.wind_gust.unwrap() - 20.0).abs() < 0.001);
+        assert_eq!(today.pop, 0.8);
+        // The evening's rain counts for the day it starts on
+        assert!((today.rain - 13.97).abs() < 0.001);
+        // A slight chance of thunderstorms does not count
+        assert_eq!(today.condition, Condition::Rain);
+        assert_eq!(today.sunrise, None);
+
+        let tomorrow = &weather.daily[1];
+        // Overnight showers are not daytime weather: the sky cover decides
+        assert_eq!(tomorrow.condition, Condition::PartlyCloudy);
+        assert_eq!(tomorrow.clouds, Some(45));
+        assert_eq!(tomorrow.wind_gust, None);
+        assert_eq!(tomorrow.feels_like.day, tomorrow.temp_max);
+        assert_eq!(tomorrow.rain, 0.0);
+
+        assert_eq!(weather.alerts.len(), 2);
+        assert_eq!(weather.alerts[0].event, "Wind Advisory");
+        assert_eq!(
+            (weather.alerts[0].start, weather.alerts[0].end),
+            (1741197600, 1741240800)
+        );
+        assert_eq!(weather.alerts[0].sender, "NWS San Francisco CA");
+        assert_eq!(weather.alerts[1].severity, Severity::Minor);
+        // No end time: the alert's expiry
+        assert_eq!(weather.alerts[1].end, 1741215600);
+
+        // One entry per hour of temperatures, 06:00 on the 5th to midnight on the 7th
+        assert_eq!(weather.hourly.len(), 34);
+        let first = &weather.hourly[0];
+        assert_eq!(first.dt, 1741183200);
+        assert_eq!(first.condition, Condition::Rain);
+        assert_eq!(first.wind_deg, Some(190.0));
+        // 5.08 mm over six hours
+        assert!((first.rain - 0.8467).abs() < 0.001);
+        // 07:00 on the 6th: 2 cm of snow, no weather listed and 30% sky cover
+        let morning = &weather.hourly[25];
+        assert!((morning.snow - 2.0).abs() < 0.001);
+        assert_eq!(morning.rain, 0.0);
+        assert_eq!(morning.condition, Condition::PartlyCloudy);
+    }
+
+    #[test]
+    fn converts_to_imperial_and_parses_durations() {
+        let today = &recorded(Units::Imperial).daily[0];
+        assert!((today.temp_max - 60.98).abs() < 0.01);
+        assert!((today.wind_speed - 22.37).abs() < 0.01);
+        assert_eq!(duration_hours("PT6H"), Some(6));
+        assert_eq!(duration_hours("P1DT12H"), Some(36));
+        assert_eq!(duration_hours("P2D"), Some(48));
+        assert_eq!(duration_hours("PT30M"), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! Open-Meteo forecast API, which needs no key. It has no weather alerts.
+
+use super::{FetchFuture, WeatherProvider};
+use crate::locale::Units;
+use crate::upstream::{RateLimit, Upstream};
+use crate::weather::{
+    Condition, DailyWeather, FeelsLike, HourlyWeather, MinutelyPrecipitation, WeatherData,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const DAILY_VARIABLES: &str = "weather_code,temperature_2m_max,temperature_2m_min,sunrise,sunset,\
+precipitation_probability_max,rain_sum,showers_sum,wind_speed_10m_max,wind_gusts_10m_max,\
+uv_index_max,relative_humidity_2m_mean,cloud_cover_mean";
+const HOURLY_VARIABLES: &str = "temperature_2m,apparent_temperature,precipitation_probability,\
+rain,showers,snowfall,weather_code,wind_speed_10m,wind_direction_10m";
+
+pub struct OpenMeteo {
+    pub upstream: Upstream,
+    pub limit: Arc<RateLimit>,
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn fetch<'a>(&'a self, lat: &'a str, lon: &'a str, units: Units) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let (temperature_unit, wind_speed_unit) = match units {
+                Units::Imperial => ("fahrenheit", "mph"),
+                Units::Metric | Units::Uk => ("celsius", "ms"),
+            };
+            let url = format!(
+                "https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}&daily={}&hourly={}&minutely_15=precipitation&forecast_minutely_15=8&temperature_unit={}&wind_speed_unit={}&timezone=auto&timeformat=unixtime&forecast_days=7",
+                lat, lon, DAILY_VARIABLES, HOURLY_VARIABLES, temperature_unit, wind_speed_unit
+            );
+            let forecast: Forecast = self.upstream.get_json(&url, &self.limit).await?;
+            Ok(forecast.into())
+        })
+    }
+}
+
+/// Response with `timeformat=unixtime`: daily times are local midnights. Any value
+/// can be null, usually at the end of the forecast.
+#[derive(Deserialize)]
+struct Forecast {
+    latitude: f32,
+    longitude: f32,
+    utc_offset_seconds: i32,
+    hourly: Hourly,
+    /// Only where Open-Meteo has a 15-minute model
+    minutely_15: Option<Minutely15>,
+    daily: Daily,
+}
+
+/// Precipitation is mm in the preceding 15 minutes
+#[derive(Deserialize)]
+struct Minutely15 {
+    time: Vec<i64>,
+    precipitation: Vec<Option<f32>>,
+}
+
+/// Rain and showers are mm in the preceding hour; snowfall is cm of snow
+#[derive(Deserialize)]
+struct Hourly {
+    time: Vec<i64>,
+    temperature_2m: Vec<Option<f32>>,
+    apparent_temperature: Vec<Option<f32>>,
+    precipitation_probability: Vec<Option<f32>>,
+    rain: Vec<Option<f32>>,
+    showers: Vec<Option<f32>>,
+    snowfall: Vec<Option<f32>>,
+    weather_code: Vec<Option<u8>>,
+    wind_speed_10m: Vec<Option<f32>>,
+    wind_direction_10m: Vec<Option<f32>>,
+}
+
+#[derive(Deserialize)]
+struct Daily {
+    time: Vec<i64>,
+    weather_code: Vec<Option<u8>>,
+    temperature_2m_max: Vec<Option<f32>>,
+    temperature_2m_min: Vec<Option<f32>>,
+    sunrise: Vec<Option<i64>>,
+    sunset: Vec<Option<i64>>,
+    precipitation_probability_max: Vec<Option<f32>>,
+    rain_sum: Vec<Option<f32>>,
+    showers_sum: Vec<Option<f32>>,
+    wind_speed_10m_max: Vec<Option<f32>>,
+    wind_gusts_10m_max: Vec<Option<f32>>,
+    uv_index_max: Vec<Option<f32>>,
+    relative_humidity_2m_mean: Vec<Option<f32>>,
+    cloud_cover_mean: Vec<Option<f32>>,
+}
+
+/// Maps WMO weather interpretation codes to conditions
+fn condition(code: u8) -> Condition {
+    match code {
+        0 | 1 => Condition::Clear,
+        2 => Condition::PartlyCloudy,
+        3 => Condition::Overcast,
+        45 | 48 => Condition::Fog,
+        51 | 53 | 55 => Condition::Drizzle,
+        56 | 57 | 66 | 67 => Condition::Sleet,
+        61 | 63 | 65 => Condition::Rain,
+        71 | 73 | 75 | 77 | 85 | 86 => Condition::Snow,
+        80..=82 => Condition::Showers,
+        95 | 96 | 99 => Condition::Thunderstorm,
+        _ => Condition::Cloudy,
+    }
+}
+
+fn at<T: Copy>(values: &[Option<T>], i: usize) -> Option<T> {
+    values.get(i).copied().flatten()
+}
+
+impl Hourly {
+    fn apparent_temperature_at(&self, time: i64) -> Option<f32> {
+        let index = self.time.iter().position(|&t| t == time)?;
+        at(&self.apparent_temperature, index)
+    }
+
+    fn hours(&self) -> Vec<HourlyWeather> {
+        self.time
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &time)| {
+                let temp = at(&self.temperature_2m, i)?;
+                Some(HourlyWeather {
+                    dt: time,
+                    temp,
+                    feels_like: at(&self.apparent_temperature, i).unwrap_or(temp),
+                    pop: at(&self.precipitation_probability, i).unwrap_or(0.0) / 100.0,
+                    rain: at(&self.rain, i).unwrap_or(0.0) + at(&self.showers, i).unwrap_or(0.0),
+                    // About 7 cm of fresh snow per 10 mm of water
+                    snow: at(&self.snowfall, i).unwrap_or(0.0) * 10.0 / 7.0,
+                    wind_speed: at(&self.wind_speed_10m, i).unwrap_or(0.0),
+                    wind_deg: at(&self.wind_direction_10m, i),
+                    condition: at(&self.weather_code, i).map_or(Condition::Cloudy, condition),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Minutely15 {
+    fn steps(&self) -> Vec<MinutelyPrecipitation> {
+        self.time
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &end)| {
+                Some(MinutelyPrecipitation {
+                    dt: end - 15 * 60,
+                    precipitation: at(&self.precipitation, i)? * 4.0,
+                })
+            })
+            .collect()
+    }
+}
+
+impl From<Forecast> for WeatherData {
+    fn from(forecast: Forecast) -> Self {
+        let d = &forecast.daily;
+        let daily = d
+            .time
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &midnight)| {
+                // Days without temperatures are past the end of the forecast
+                let temp_min = at(&d.temperature_2m_min, i)?;
+                let temp_max = at(&d.temperature_2m_max, i)?;
+                let feels_like = |hour: i64, default: f32| {
+                    forecast
+                        .hourly
+                        .apparent_temperature_at(midnight + hour * 3600)
+                        .unwrap_or(default)
+                };
+                Some(DailyWeather {
+                    dt: midnight + 12 * 3600,
+                    temp_min,
+                    temp_max,
+                    feels_like: FeelsLike {
+                        morn: feels_like(6, temp_min),
+                        day: feels_like(12, temp_max),
+                        eve: feels_like(18, temp_max),
+                    },
+                    humidity: at(&d.relative_humidity_2m_mean, i).unwrap_or(0.0).round() as i32,
+                    wind_speed: at(&d.wind_speed_10m_max, i).unwrap_or(0.0),
+                    wind_gust: at(&d.wind_gusts_10m_max, i),
+                    sunrise: at(&d.sunrise, i),
+                    sunset: at(&d.sunset, i),
+                    condition: at(&d.weather_code, i).map_or(Condition::Cloudy, condition),
+                    pop: at(&d.precipitation_probability_max, i).unwrap_or(0.0) / 100.0,
+                    rain: at(&d.rain_sum, i).unwrap_or(0.0) + at(&d.showers_sum, i).unwrap_or(0.0),
+                    uvi: at(&d.uv_index_max, i),
+                    clouds: at(&d.cloud_cover_mean, i).map(|c| c.round() as i32),
+                })
+            })
+            .collect();
+        WeatherData {
+            lat: forecast.latitude,
+            lon: forecast.longitude,
+            timezone_offset: forecast.utc_offset_seconds,
+            daily,
+            hourly: forecast.hourly.hours(),
+            minutely: forecast
+                .minutely_15
+                .as_ref()
+                .map_or_else(Vec::new, Minutely15::steps),
+            alerts: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Forecast;
+    use crate::weather::{Condition, WeatherData};
+
+    #[test]
+    fn parses_recorded_forecast() {
+        let forecast: Forecast =
+            serde_json::from_str(include_str!("../../tests/fixtures/weather/open_meteo.json"))
+                .unwrap();
+        let weather = WeatherData::from(forecast);
+        assert_eq!(weather.timezone_offset, -28800);
+        // The last day has no temperatures yet
+        assert_eq!(weather.daily.len(), 2);
+
+        let today = &weather.daily[0];
+        // Noon local time
+        assert_eq!(today.dt, 1741204800);
+        assert_eq!(today.condition, Condition::Rain);
+        assert_eq!(today.feels_like.morn, 46.8);
+        assert_eq!(today.feels_like.day, 55.4);
+        assert_eq!(today.feels_like.eve, 52.1);
+        assert_eq!(today.pop, 0.9);
+        assert!((today.rain - 15.3).abs() < 0.001);
+        assert_eq!(today.humidity, 85);
+        assert_eq!(today.sunrise, Some(1741184820));
+
+        let tomorrow = &weather.daily[1];
+        assert_eq!(tomorrow.condition, Condition::PartlyCloudy);
+        // Hourly data ends before the evening: falls back to the high
+        assert_eq!(tomorrow.feels_like.eve, tomorrow.temp_max);
+        assert_eq!(tomorrow.clouds, Some(38));
+        assert!(weather.alerts.is_empty());
+
+        // The last hour has no temperature
+        assert_eq!(weather.hourly.len(), 6);
+        let hour = &weather.hourly[1];
+        assert_eq!(hour.dt, 1741183200);
+        assert_eq!(hour.condition, Condition::Rain);
+        assert_eq!(hour.pop, 0.7);
+        assert!((hour.rain - 2.2).abs() < 0.001);
+        assert!((weather.hourly[4].snow - 1.0).abs() < 0.001);
+        assert_eq!(weather.hourly[4].wind_deg, Some(270.0));
+
+        // 15-minute sums become intensities from the start of each step
+        assert_eq!(weather.minutely.len(), 3);
+        assert_eq!(weather.minutely[0].dt, 1741189500);
+        assert_eq!(weather.minutely[1].precipitation, 1.2);
+    }
+}
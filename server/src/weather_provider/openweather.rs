@@ -0,0 +1,236 @@
+//! OpenWeatherMap One Call API (3.0)
+
+use super::{FetchFuture, WeatherProvider};
+use crate::locale::Units;
+use crate::upstream::{RateLimit, Upstream};
+use crate::weather::{
+    Condition, DailyWeather, FeelsLike, HourlyWeather, MinutelyPrecipitation, Severity,
+    WeatherAlert, WeatherData,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub struct OpenWeather {
+    pub api_key: String,
+    pub upstream: Upstream,
+    pub limit: Arc<RateLimit>,
+}
+
+impl WeatherProvider for OpenWeather {
+    fn fetch<'a>(&'a self, lat: &'a str, lon: &'a str, units: Units) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let url = format!(
+                "https://api.openweathermap.org/data/3.0/onecall?lat={}&lon={}&units={}&appid={}",
+                lat,
+                lon,
+                units.api_units(),
+                self.api_key
+            );
+            let one_call: OneCall = self.upstream.get_json(&url, &self.limit).await?;
+            Ok(one_call.into())
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct OneCall {
+    lat: f32,
+    lon: f32,
+    timezone_offset: i32,
+    daily: Vec<Daily>,
+    #[serde(default)]
+    hourly: Vec<Hourly>,
+    /// Not available everywhere
+    #[serde(default)]
+    minutely: Vec<MinutelyPrecipitation>,
+    #[serde(default)]
+    alerts: Vec<Alert>,
+}
+
+#[derive(Deserialize)]
+struct Daily {
+    dt: i64,
+    temp: Temp,
+    feels_like: FeelsLike,
+    humidity: i32,
+    wind_speed: f32,
+    wind_gust: Option<f32>,
+    sunrise: i64,
+    sunset: i64,
+    weather: Vec<Weather>,
+    #[serde(default)]
+    pop: f32,
+    #[serde(default)]
+    rain: f32,
+    uvi: Option<f32>,
+    clouds: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct Hourly {
+    dt: i64,
+    temp: f32,
+    feels_like: f32,
+    #[serde(default)]
+    pop: f32,
+    rain: Option<LastHour>,
+    snow: Option<LastHour>,
+    wind_speed: f32,
+    wind_deg: Option<f32>,
+    weather: Vec<Weather>,
+}
+
+#[derive(Deserialize)]
+struct LastHour {
+    #[serde(rename = "1h")]
+    amount: f32,
+}
+
+#[derive(Deserialize)]
+struct Temp {
+    min: f32,
+    max: f32,
+}
+
+#[derive(Deserialize)]
+struct Weather {
+    icon: String,
+}
+
+#[derive(Deserialize)]
+struct Alert {
+    #[serde(default)]
+    sender_name: String,
+    event: String,
+    description: String,
+    start: i64,
+    end: i64,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Maps OpenWeatherMap icon codes ("10d") to conditions; day and night share one
+fn condition(icon_code: &str) -> Condition {
+    match icon_code.get(..2).unwrap_or_default() {
+        "01" => Condition::Clear,
+        "02" => Condition::PartlyCloudy,
+        "03" => Condition::Cloudy,
+        "04" => Condition::Overcast,
+        "09" => Condition::Showers,
+        "10" => Condition::Rain,
+        "11" => Condition::Thunderstorm,
+        "13" => Condition::Snow,
+        "50" => Condition::Fog,
+        _ => Condition::Cloudy,
+    }
+}
+
+impl From<OneCall> for WeatherData {
+    fn from(one_call: OneCall) -> Self {
+        let daily = one_call
+            .daily
+            .into_iter()
+            .map(|day| DailyWeather {
+                dt: day.dt,
+                temp_min: day.temp.min,
+                temp_max: day.temp.max,
+                feels_like: day.feels_like,
+                humidity: day.humidity,
+                wind_speed: day.wind_speed,
+                wind_gust: day.wind_gust,
+                sunrise: Some(day.sunrise),
+                sunset: Some(day.sunset),
+                condition: day
+                    .weather
+                    .first()
+                    .map_or(Condition::Cloudy, |w| condition(&w.icon)),
+                pop: day.pop,
+                rain: day.rain,
+                uvi: day.uvi,
+                clouds: day.clouds,
+            })
+            .collect();
+        let hourly = one_call
+            .hourly
+            .into_iter()
+            .map(|hour| HourlyWeather {
+                dt: hour.dt,
+                temp: hour.temp,
+                feels_like: hour.feels_like,
+                pop: hour.pop,
+                rain: hour.rain.map_or(0.0, |rain| rain.amount),
+                snow: hour.snow.map_or(0.0, |snow| snow.amount),
+                wind_speed: hour.wind_speed,
+                wind_deg: hour.wind_deg,
+                condition: hour
+                    .weather
+                    .first()
+                    .map_or(Condition::Cloudy, |w| condition(&w.icon)),
+            })
+            .collect();
+        let alerts = one_call
+            .alerts
+            .into_iter()
+            .map(|alert| WeatherAlert {
+                severity: Severity::from_event(&alert.event),
+                event: alert.event,
+                sender: alert.sender_name,
+                description: alert.description,
+                tags: alert.tags,
+                start: alert.start,
+                end: alert.end,
+            })
+            .collect();
+        WeatherData {
+            lat: one_call.lat,
+            lon: one_call.lon,
+            timezone_offset: one_call.timezone_offset,
+            daily,
+            hourly,
+            minutely: one_call.minutely,
+            alerts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OneCall;
+    use crate::weather::{Condition, Severity, WeatherData};
+
+    #[test]
+    fn parses_recorded_one_call_response() {
+        let one_call: OneCall = serde_json::from_str(include_str!(
+            "../../tests/fixtures/weather/openweather.json"
+        ))
+        .unwrap();
+        let weather = WeatherData::from(one_call);
+        assert_eq!(weather.timezone_offset, -28800);
+        assert_eq!(weather.daily.len(), 3);
+
+        let today = &weather.daily[0];
+        assert_eq!(today.temp_max, 61.2);
+        assert_eq!(today.feels_like.morn, 47.1);
+        assert_eq!(today.condition, Condition::Rain);
+        assert_eq!(today.sunrise, Some(1741184853));
+        assert_eq!(weather.daily[1].condition, Condition::PartlyCloudy);
+        // No rain or gusts reported: defaults
+        assert_eq!(weather.daily[2].rain, 0.0);
+        assert_eq!(weather.daily[2].wind_gust, None);
+
+        assert_eq!(weather.hourly.len(), 3);
+        assert_eq!(weather.hourly[0].rain, 1.02);
+        assert_eq!(weather.hourly[1].snow, 0.0);
+        assert_eq!(weather.hourly[2].condition, Condition::Overcast);
+        assert_eq!(weather.hourly[2].wind_deg, Some(200.0));
+
+        assert_eq!(weather.minutely.len(), 4);
+        assert_eq!(weather.minutely[2].precipitation, 1.2);
+
+        assert_eq!(weather.alerts.len(), 1);
+        assert_eq!(weather.alerts[0].event, "Wind Advisory");
+        assert_eq!(weather.alerts[0].sender, "NWS San Francisco CA");
+        assert_eq!(weather.alerts[0].severity, Severity::Moderate);
+        assert_eq!(weather.alerts[0].tags, ["Wind"]);
+    }
+}
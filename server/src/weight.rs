@@ -1,6 +1,6 @@
-use crate::svg_common;
+use crate::svg_common::{self, Canvas, CardLine, CompactCard};
 use chrono::{DateTime, Duration, NaiveDate, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fs::File;
 use std::path::Path;
@@ -9,13 +9,13 @@ use std::path::Path;
 // Layer 1: Data Structures
 // ============================================================================
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WeightReading {
     pub timestamp: DateTime<Utc>,
     pub weight_lbs: f64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KalmanState {
     pub timestamp: DateTime<Utc>,
     pub weight_lbs: f64,
@@ -28,13 +28,13 @@ pub struct KalmanState {
     pub velocity_variance: f64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectionPoint {
     pub timestamp: DateTime<Utc>,
     pub weight_lbs: f64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DecayProjection {
     pub lookback_days: i64,
     pub points: Vec<ProjectionPoint>,
@@ -43,7 +43,7 @@ pub struct DecayProjection {
     pub color: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WeightData {
     pub raw_readings: Vec<WeightReading>,
     pub kalman_states: Vec<KalmanState>,
@@ -350,14 +350,77 @@ pub async fn fetch_weight_data(csv_path: &Path) -> Result<WeightData, Box<dyn Er
     })
 }
 
+// ============================================================================
+// Layer 3: SVG Generation - Small Panels
+// ============================================================================
+
+/// Both weight screens on a small panel: the trend weight, or its velocity for the
+/// velocity screen, as the headline, then the last reading and the projections
+fn compact_weight_svg(
+    data: &WeightData,
+    battery_pct: Option<u8>,
+    canvas: Canvas,
+    velocity: bool,
+) -> String {
+    let title = if velocity {
+        "Weight Velocity"
+    } else {
+        "Weight Forecast"
+    };
+    let mut card = CompactCard::new(title, battery_pct);
+    let Some(state) = data.kalman_states.last() else {
+        card.lines.push(CardLine::new("No weight readings"));
+        return card.svg(canvas);
+    };
+    let weekly = format!("{:+.2} lbs/week", state.velocity_lbs_per_day * 7.0);
+    let trend = format!("{:.1} lbs", state.weight_lbs);
+    if velocity {
+        card.headline = Some(CardLine::new(weekly));
+        card.lines
+            .push(CardLine::new("Trend weight").with_value(trend));
+    } else {
+        card.headline = Some(CardLine::new(trend));
+        card.lines
+            .push(CardLine::new("Velocity").with_value(weekly));
+    }
+    if let Some(reading) = data.raw_readings.last() {
+        card.lines.push(
+            CardLine::new(format!(
+                "Last reading {}",
+                reading.timestamp.format("%b %d")
+            ))
+            .with_value(format!("{:.1} lbs", reading.weight_lbs)),
+        );
+    }
+    if let Some(point) = data.linear_projection.last() {
+        card.lines.push(
+            CardLine::new(format!("Linear to {}", point.timestamp.format("%b %d")))
+                .with_value(format!("{:.1} lbs", point.weight_lbs)),
+        );
+    }
+    for projection in &data.decay_projections {
+        if let Some(weight) = projection.stall_weight {
+            card.lines.push(
+                CardLine::new(format!("{}d decay stalls at", projection.lookback_days))
+                    .with_value(format!("{:.1} lbs", weight)),
+            );
+        }
+    }
+    card.svg(canvas)
+}
+
 // ============================================================================
 // Layer 3: SVG Generation - Forecast Chart
 // ============================================================================
 
-pub fn generate_forecast_svg(data: &WeightData, battery_pct: Option<u8>) -> String {
+pub fn generate_forecast_svg(data: &WeightData, battery_pct: Option<u8>, canvas: Canvas) -> String {
+    if canvas.is_compact() {
+        return compact_weight_svg(data, battery_pct, canvas, false);
+    }
+
     // Chart dimensions
-    let width = 800;
-    let height = 480;
+    let width = canvas.width;
+    let height = canvas.height;
     let margin_left = 60;
     let margin_right = 40;
     let margin_top = 50;
@@ -751,10 +814,14 @@ pub fn generate_forecast_svg(data: &WeightData, battery_pct: Option<u8>) -> Stri
 // Layer 3: SVG Generation - Velocity Chart
 // ============================================================================
 
-pub fn generate_velocity_svg(data: &WeightData, battery_pct: Option<u8>) -> String {
+pub fn generate_velocity_svg(data: &WeightData, battery_pct: Option<u8>, canvas: Canvas) -> String {
+    if canvas.is_compact() {
+        return compact_weight_svg(data, battery_pct, canvas, true);
+    }
+
     // Chart dimensions
-    let width = 800;
-    let height = 480;
+    let width = canvas.width;
+    let height = canvas.height;
     let margin_left = 60;
     let margin_right = 40;
     let margin_top = 40;